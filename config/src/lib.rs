@@ -3,18 +3,267 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use common_macros::hash_map;
 use serde::Deserialize;
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
 
+/// Relational database used by a dataset controller
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Postgres,
+    Sqlite,
+}
+
+impl Backend {
+    /// Deduce the backend from a database url, like the diesel cli does, anything
+    /// that isn't a postgres url is taken as a sqlite file
+    pub fn from_url(url: &str) -> Self {
+        if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            Self::Postgres
+        } else {
+            Self::Sqlite
+        }
+    }
+}
+
+/// A dataset stored in postgres (along with mongo) or in a single sqlite file,
+/// mongo isn't used at all with the sqlite backend
 #[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct DatabaseEntry {
+    /// Controller used to serve this entry (ex. "movie-lens"), the name of the
+    /// entry is used when it isn't given
+    #[serde(default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub backend: Backend,
+    #[serde(default)]
     pub psql_url: String,
+    #[serde(default)]
+    pub sqlite_path: String,
+    #[serde(default)]
     pub mongo_url: String,
+    #[serde(default)]
     pub mongo_db: String,
+    #[serde(default)]
     pub users_ratings_mongo: bool,
+    #[serde(default)]
     pub users_who_rated_mongo: bool,
+    /// Path to the description of the dataset, only used by the `dataset` kind
+    #[serde(default)]
+    pub dataset: Option<String>,
+}
+
+impl DatabaseEntry {
+    /// Controller kind of this entry, `name` is the name of the entry in the config
+    pub fn kind_or<'a>(&'a self, name: &'a str) -> &'a str {
+        self.kind.as_deref().unwrap_or(name)
+    }
+
+    /// Url (or path) of the relational database for the configured backend
+    pub fn database_url(&self) -> &str {
+        match self.backend {
+            Backend::Postgres => &self.psql_url,
+            Backend::Sqlite => &self.sqlite_path,
+        }
+    }
+
+    /// Point this entry to another database, the backend is deduced from the url
+    pub fn set_database_url(&mut self, url: &str) {
+        self.backend = Backend::from_url(url);
+
+        match self.backend {
+            Backend::Postgres => self.psql_url = url.into(),
+            Backend::Sqlite => self.sqlite_path = url.into(),
+        }
+    }
+}
+
+/// A column in a csv file, referenced by its position or by its header name
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum CsvColumn {
+    Index(usize),
+    Name(String),
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_has_headers() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct CsvRatingsFile {
+    pub path: String,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    #[serde(default = "default_has_headers")]
+    pub has_headers: bool,
+    pub user_id: CsvColumn,
+    pub item_id: CsvColumn,
+    pub score: CsvColumn,
+    /// Column with the time (seconds since the unix epoch) the rating was given
+    pub time: Option<CsvColumn>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct CsvEntitiesFile {
+    pub path: String,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    #[serde(default = "default_has_headers")]
+    pub has_headers: bool,
+    pub id: CsvColumn,
+    pub name: Option<CsvColumn>,
+    #[serde(default)]
+    pub data: HashMap<String, CsvColumn>,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct CsvEntry {
+    pub score_range: (f64, f64),
+    /// Step between two consecutive scores, any score within the range is valid
+    /// if it isn't given
+    pub score_step: Option<f64>,
+    pub ratings: CsvRatingsFile,
+    pub users: Option<CsvEntitiesFile>,
+    pub items: Option<CsvEntitiesFile>,
+}
+
+/// Type of the ids of a dataset table, integer ids are generated by the database
+/// when inserting and text ones must be given
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdType {
+    #[default]
+    Integer,
+    Text,
+}
+
+/// Type of a data column of a dataset table
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    Integer,
+    Float,
+    Text,
+}
+
+fn default_id_column() -> String {
+    "id".into()
+}
+
+fn default_score_column() -> String {
+    "score".into()
+}
+
+/// The users or items table of a dataset
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct EntityTable {
+    pub table: String,
+    #[serde(default = "default_id_column")]
+    pub id: String,
+    #[serde(default)]
+    pub id_type: IdType,
+    /// Column searched by name, if there's one
+    pub name: Option<String>,
+    /// Other columns along with their types
+    #[serde(default)]
+    pub data: BTreeMap<String, ColumnType>,
+    /// Data columns that must be given to insert a new entry
+    #[serde(default)]
+    pub required: Vec<String>,
+    /// File the rows are loaded from, its data keys are the data columns
+    pub csv: Option<CsvEntitiesFile>,
+}
+
+/// The ratings table of a dataset
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct RatingsTable {
+    pub table: String,
+    #[serde(default = "default_id_column")]
+    pub id: String,
+    pub user_id: String,
+    pub item_id: String,
+    #[serde(default = "default_score_column")]
+    pub score: String,
+    /// Column with the time (seconds since the unix epoch) the rating was given
+    pub time: Option<String>,
+    /// Outbox table used to keep the mongo documents, its columns are `id`, the user
+    /// and item columns of the ratings table, `score` and `rated_at`
+    pub outbox: Option<String>,
+    /// File the ratings are loaded from
+    pub csv: Option<CsvRatingsFile>,
+}
+
+/// Tables, columns and score range of a dataset, any dataset described this way
+/// is served by the generic `dataset` controller
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct DatasetDescription {
+    pub score_range: (f64, f64),
+    pub score_step: Option<f64>,
+    /// Score given to implicit interactions, if the dataset records them
+    pub implicit_score: Option<f64>,
+    pub users: EntityTable,
+    pub items: EntityTable,
+    pub ratings: RatingsTable,
+    /// Tables with the users means, the items means and the global mean, they're
+    /// kept up to date by triggers. Means are computed from the ratings otherwise.
+    /// Their columns aren't described, they're always `user_id` (or `item_id`,
+    /// or `id` for the global mean), `val` and `score_number`
+    pub means: Option<String>,
+    pub item_means: Option<String>,
+    pub global_mean: Option<String>,
+}
+
+impl DatasetDescription {
+    /// Load a dataset description, paths of its csv files are relative to the
+    /// directory of the description
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        let mut parsed: Self = toml::from_str(&contents)?;
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let relative = |file: &mut String| {
+            if Path::new(file.as_str()).is_relative() {
+                *file = dir.join(file.as_str()).to_string_lossy().into_owned();
+            }
+        };
+
+        if let Some(csv) = &mut parsed.users.csv {
+            relative(&mut csv.path);
+        }
+
+        if let Some(csv) = &mut parsed.items.csv {
+            relative(&mut csv.path);
+        }
+
+        if let Some(csv) = &mut parsed.ratings.csv {
+            relative(&mut csv.path);
+        }
+
+        Ok(parsed)
+    }
+
+    /// The csv files of the dataset, if its ratings file is given
+    pub fn csv_entry(&self) -> Option<CsvEntry> {
+        Some(CsvEntry {
+            score_range: self.score_range,
+            score_step: self.score_step,
+            ratings: self.ratings.csv.clone()?,
+            users: self.users.csv.clone(),
+            items: self.items.csv.clone(),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, PartialEq)]
@@ -24,9 +273,80 @@ pub struct MatrixConfig {
     pub allow_chunk_optimization: bool,
 }
 
+/// How the engine treats implicit interactions (ratings without a score given
+/// by the user), explicit and binary ratings are always used as they are
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImplicitRatings {
+    /// Use their scores like any other rating
+    #[default]
+    Include,
+
+    /// Leave them out of distances, predictions and means
+    Exclude,
+
+    /// Replace their scores by a fraction of the score range, 0.0 being the
+    /// min score and 1.0 the max one (other weights are refused on load)
+    Weight(f64),
+}
+
+/// Hyperparameters of the matrix factorization model (biased MF trained with SGD)
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FactorizationConfig {
+    /// Latent factors of every user and item
+    pub factors: usize,
+    pub learning_rate: f64,
+    pub regularization: f64,
+    pub epochs: usize,
+    /// Seed of the initial factors and of the order ratings are visited in
+    pub seed: u64,
+    /// Ratings are read by chunks of this many users, all at once if it isn't given
+    pub users_chunk_size: Option<usize>,
+}
+
+impl Default for FactorizationConfig {
+    fn default() -> Self {
+        Self {
+            factors: 20,
+            learning_rate: 0.005,
+            regularization: 0.02,
+            epochs: 20,
+            seed: 0,
+            users_chunk_size: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, PartialEq)]
 pub struct EngineConfig {
     pub partial_users_chunk_size: usize,
+    #[serde(default)]
+    pub implicit_ratings: ImplicitRatings,
+    /// Round predictions to the closest score of the controller rating scale
+    #[serde(default)]
+    pub snap_predictions: bool,
+    #[serde(default)]
+    pub factorization: FactorizationConfig,
+}
+
+/// Capacities (in entries) of the controller read caches, 0 disables a cache
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub user_ratings_capacity: usize,
+    pub users_who_rated_capacity: usize,
+    pub users_means_capacity: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            user_ratings_capacity: 1024,
+            users_who_rated_capacity: 1024,
+            users_means_capacity: 4096,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, PartialEq)]
@@ -41,7 +361,11 @@ pub struct Config {
     pub system: SystemConfig,
     pub engine: EngineConfig,
     pub matrix: MatrixConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
     pub databases: HashMap<String, DatabaseEntry>,
+    #[serde(default)]
+    pub csv: HashMap<String, CsvEntry>,
 }
 
 impl Default for Config {
@@ -54,57 +378,106 @@ impl Default for Config {
             },
             engine: EngineConfig {
                 partial_users_chunk_size: 10000,
+                implicit_ratings: ImplicitRatings::Include,
+                snap_predictions: false,
+                factorization: FactorizationConfig::default(),
             },
             matrix: MatrixConfig {
                 chunk_size_threshold: 0.3,
                 partial_users_chunk_size: 10000,
                 allow_chunk_optimization: true,
             },
+            cache: CacheConfig::default(),
             databases: hash_map! {
                 "simple-movie".into() => DatabaseEntry {
+                    kind: None,
+                    backend: Backend::Postgres,
+                    sqlite_path: String::new(),
                     users_ratings_mongo: false,
                     users_who_rated_mongo: true,
+                    dataset: None,
                     psql_url: "postgres://postgres:@localhost/simple-movie".into(),
                     mongo_url: "mongodb://localhost:27017".into(),
                     mongo_db: "simple-movie".into()
                 },
                 "books".into() => DatabaseEntry {
+                    kind: None,
+                    backend: Backend::Postgres,
+                    sqlite_path: String::new(),
                     users_ratings_mongo: false,
                     users_who_rated_mongo: true,
+                    dataset: None,
                     psql_url: "postgres://postgres:@localhost/books".into(),
                     mongo_url: "mongodb://localhost:27017".into(),
                     mongo_db: "books".into()
                 },
                 "shelves".into() => DatabaseEntry {
+                    kind: None,
+                    backend: Backend::Postgres,
+                    sqlite_path: String::new(),
                     users_ratings_mongo: false,
                     users_who_rated_mongo: true,
+                    dataset: None,
                     psql_url: "postgres://postgres:@localhost/shelves".into(),
                     mongo_url: "mongodb://localhost:27017".into(),
                     mongo_db: "shelves".into(),
                 },
                 "movie-lens".into() => DatabaseEntry {
+                    kind: None,
+                    backend: Backend::Postgres,
+                    sqlite_path: String::new(),
                     users_ratings_mongo: false,
                     users_who_rated_mongo: true,
+                    dataset: None,
                     psql_url: "postgres://postgres:@localhost/movie-lens".into(),
                     mongo_url: "mongodb://localhost:27017".into(),
                     mongo_db: "movie-lens".into(),
                 },
                 "movie-lens-small".into() => DatabaseEntry {
+                    kind: None,
+                    backend: Backend::Postgres,
+                    sqlite_path: String::new(),
                     users_ratings_mongo: false,
                     users_who_rated_mongo: true,
+                    dataset: None,
                     psql_url: "postgres://postgres:@localhost/movie-lens-small".into(),
                     mongo_url: "mongodb://localhost:27017".into(),
                     mongo_db: "movie-lens-small".into(),
                 }
             },
+            csv: HashMap::new(),
         }
     }
 }
 
 impl Config {
+    /// Load a config file, relative paths to dataset descriptions are resolved
+    /// against the directory of the config file
     pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
         let contents = std::fs::read_to_string(path)?;
-        let parsed: Self = toml::from_str(&contents)?;
+        let mut parsed: Self = toml::from_str(&contents)?;
+
+        if let ImplicitRatings::Weight(weight) = parsed.engine.implicit_ratings {
+            if !(0. ..=1.).contains(&weight) {
+                return Err(anyhow!(
+                    "The weight of implicit ratings must be between 0 and 1, got {}",
+                    weight
+                ));
+            }
+        }
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for dataset in parsed
+            .databases
+            .values_mut()
+            .filter_map(|db| db.dataset.as_mut())
+        {
+            if Path::new(dataset.as_str()).is_relative() {
+                *dataset = dir.join(dataset.as_str()).to_string_lossy().into_owned();
+            }
+        }
+
         Ok(parsed)
     }
 }
@@ -125,19 +498,95 @@ mod tests {
             },
             engine: EngineConfig {
                 partial_users_chunk_size: 10000,
+                implicit_ratings: ImplicitRatings::Weight(0.5),
+                snap_predictions: true,
+                factorization: FactorizationConfig {
+                    factors: 10,
+                    epochs: 30,
+                    users_chunk_size: Some(500),
+                    ..FactorizationConfig::default()
+                },
             },
             matrix: MatrixConfig {
                 chunk_size_threshold: 0.3,
                 partial_users_chunk_size: 10000,
                 allow_chunk_optimization: true,
             },
+            cache: CacheConfig {
+                user_ratings_capacity: 512,
+                users_who_rated_capacity: 0,
+                users_means_capacity: 4096,
+            },
             databases: hash_map! {
                 "some-database".into() => DatabaseEntry {
+                    kind: None,
+                    backend: Backend::Postgres,
+                    sqlite_path: String::new(),
                     users_ratings_mongo: false,
                     users_who_rated_mongo: true,
+                    dataset: None,
                     psql_url: "postgres://postgres:@localhost/some-database".into(),
                     mongo_url: "mongodb://localhost:27017".into(),
                     mongo_db: "some-database".into(),
+                },
+                "some-sqlite".into() => DatabaseEntry {
+                    kind: None,
+                    backend: Backend::Sqlite,
+                    sqlite_path: "some-sqlite.db".into(),
+                    users_ratings_mongo: false,
+                    users_who_rated_mongo: false,
+                    dataset: None,
+                    psql_url: String::new(),
+                    mongo_url: String::new(),
+                    mongo_db: String::new(),
+                },
+                "my-movielens-25m".into() => DatabaseEntry {
+                    kind: Some("movie-lens".into()),
+                    backend: Backend::Postgres,
+                    sqlite_path: String::new(),
+                    users_ratings_mongo: false,
+                    users_who_rated_mongo: true,
+                    dataset: None,
+                    psql_url: "postgres://postgres:@localhost/movie-lens-25m".into(),
+                    mongo_url: "mongodb://localhost:27017".into(),
+                    mongo_db: "movie-lens-25m".into(),
+                },
+                "some-dataset".into() => DatabaseEntry {
+                    kind: Some("dataset".into()),
+                    backend: Backend::Sqlite,
+                    sqlite_path: "some-dataset.db".into(),
+                    users_ratings_mongo: false,
+                    users_who_rated_mongo: false,
+                    dataset: Some("example-dataset.toml".into()),
+                    psql_url: String::new(),
+                    mongo_url: String::new(),
+                    mongo_db: String::new(),
+                }
+            },
+            csv: hash_map! {
+                "some-csv".into() => CsvEntry {
+                    score_range: (0.5, 5.),
+                    score_step: Some(0.5),
+                    ratings: CsvRatingsFile {
+                        path: "data/ratings.csv".into(),
+                        delimiter: ',',
+                        has_headers: true,
+                        user_id: CsvColumn::Name("userId".into()),
+                        item_id: CsvColumn::Name("movieId".into()),
+                        score: CsvColumn::Index(2),
+                        time: Some(CsvColumn::Index(3)),
+                    },
+                    users: None,
+                    items: Some(CsvEntitiesFile {
+                        path: "data/movies.csv".into(),
+                        delimiter: ',',
+                        has_headers: true,
+                        id: CsvColumn::Index(0),
+                        name: Some(CsvColumn::Index(1)),
+                        data: hash_map! {
+                            "genres".into() => CsvColumn::Index(2),
+                        },
+                    }),
                 }
             },
         };
@@ -145,6 +594,72 @@ mod tests {
         let loaded = Config::load("example.toml")?;
         assert_eq!(expected, loaded);
 
+        assert_eq!(
+            loaded.databases["some-database"].kind_or("some-database"),
+            "some-database"
+        );
+        assert_eq!(
+            loaded.databases["my-movielens-25m"].kind_or("my-movielens-25m"),
+            "movie-lens"
+        );
+
+        let nested = Config::load("../config/example.toml")?;
+        let dataset = nested.databases["some-dataset"].dataset.as_ref().unwrap();
+        assert_eq!(
+            Path::new(dataset),
+            Path::new("../config/example-dataset.toml")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn implicit_weight_out_of_range() -> Result<(), Error> {
+        let example = std::fs::read_to_string("example.toml")?;
+        let path = std::env::temp_dir().join("implicit-weight.toml");
+
+        for weight in &["1.5", "-0.1", "nan"] {
+            let config = example.replace(
+                "implicit_ratings = { weight = 0.5 }",
+                &format!("implicit_ratings = {{ weight = {} }}", weight),
+            );
+            std::fs::write(&path, config)?;
+            let error = Config::load(&path).err().unwrap();
+            assert!(error.to_string().contains("between 0 and 1"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_dataset_description() -> Result<(), Error> {
+        let description = DatasetDescription::load("example-dataset.toml")?;
+
+        assert_eq!(description.implicit_score, Some(0.));
+        assert_eq!(description.users.id, "id");
+        assert_eq!(description.users.id_type, IdType::Integer);
+        assert_eq!(description.users.data["age"], ColumnType::Integer);
+        assert_eq!(description.items.id_type, IdType::Text);
+        assert_eq!(description.items.name.as_deref(), Some("title"));
+        assert_eq!(description.ratings.score, "score");
+        assert_eq!(description.ratings.time, None);
+        assert_eq!(description.means.as_deref(), Some("means"));
+        assert_eq!(description.item_means, None);
+
+        let csv = description.csv_entry().unwrap();
+        assert_eq!(csv.score_range, (0., 10.));
+        assert_eq!(csv.score_step, Some(1.));
+        assert_eq!(csv.ratings.path, "data/ratings.csv");
+        assert_eq!(csv.items.unwrap().delimiter, ';');
+        assert!(csv.users.is_none());
+
+        let nested = DatasetDescription::load("../config/example-dataset.toml")?;
+        let ratings = nested.ratings.csv.unwrap();
+        assert_eq!(
+            Path::new(&ratings.path),
+            Path::new("../config/data/ratings.csv")
+        );
+
         Ok(())
     }
 }
@@ -0,0 +1,107 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::searchby::FUZZY_THRESHOLD;
+use anyhow::Error;
+use config::Backend;
+use diesel::pg::{Pg, PgConnection};
+use diesel::sql_types::{BigInt, Float, Integer, Text};
+use diesel::{connection::SimpleConnection, sqlite::SqliteConnection, Connection};
+
+/// Connection to the relational database of a dataset, queries are written once
+/// and dispatched to the concrete connection with `with_conn!`
+pub enum DbConnection {
+    Postgres(PgConnection),
+    Sqlite(SqliteConnection),
+}
+
+impl DbConnection {
+    pub fn establish(backend: Backend, url: &str) -> Result<Self, Error> {
+        match backend {
+            Backend::Postgres => {
+                let conn = PgConnection::establish(url)?;
+                // Fuzzy searches by name (`<%`) keep the threshold of the ones in memory
+                conn.batch_execute(&format!(
+                    "SET pg_trgm.word_similarity_threshold = {}",
+                    FUZZY_THRESHOLD
+                ))?;
+
+                Ok(Self::Postgres(conn))
+            }
+            Backend::Sqlite => Ok(Self::Sqlite(SqliteConnection::establish(url)?)),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self {
+            Self::Postgres(_) => Backend::Postgres,
+            Self::Sqlite(_) => Backend::Sqlite,
+        }
+    }
+}
+
+sql_function! {
+    /// Lowercase a text column, available in both postgres and sqlite
+    fn lower(x: Text) -> Text;
+}
+
+sql_function! {
+    /// Number of characters of a text column, available in both postgres and sqlite
+    fn length(x: Text) -> Integer;
+}
+
+sql_function! {
+    /// Greatest similarity between the trigrams of `x` and the ones of a part of
+    /// `y`, from 0 to 1. Only available in postgres, with `pg_trgm`
+    fn word_similarity(x: Text, y: Text) -> Float;
+}
+
+// Rowid of the last row inserted through the connection, only available in sqlite
+no_arg_sql_function!(last_insert_rowid, BigInt);
+
+// `x <% y` holds when `word_similarity(x, y)` reaches `pg_trgm.word_similarity_threshold`,
+// unlike the function it can use a trigram index on `y`
+diesel_infix_operator!(WordSimilar, " <% ", backend: Pg);
+
+/// Evaluate an expression with the concrete connection bound to `$conn`, the
+/// expression is type checked once per backend
+#[macro_export]
+macro_rules! with_conn {
+    ($db:expr, $conn:ident => $body:expr) => {
+        match $db {
+            $crate::backend::DbConnection::Postgres($conn) => $body,
+            $crate::backend::DbConnection::Sqlite($conn) => $body,
+        }
+    };
+}
+
+/// Run an insert statement and return the inserted row, diesel can't use
+/// `RETURNING` with sqlite so there the row is read back by its key within the
+/// same transaction. The key is the given one, or the rowid sqlite gave to the
+/// row (`last_insert_rowid()`) when the table has an integer key generated on insert
+#[macro_export]
+macro_rules! insert_returning {
+    ($db:expr, $insert:expr, $table:path, key = $key:expr) => {
+        match $db {
+            $crate::backend::DbConnection::Postgres(conn) => $insert.get_result(conn),
+            $crate::backend::DbConnection::Sqlite(conn) => conn.transaction(|| {
+                $insert.execute(conn)?;
+                $table.find($key).first(conn)
+            }),
+        }
+    };
+
+    ($db:expr, $insert:expr, $table:path) => {
+        match $db {
+            $crate::backend::DbConnection::Postgres(conn) => $insert.get_result(conn),
+            $crate::backend::DbConnection::Sqlite(conn) => conn.transaction(|| {
+                $insert.execute(conn)?;
+                let rowid: i64 =
+                    diesel::select($crate::backend::last_insert_rowid).get_result(conn)?;
+                $table.find(rowid as i32).first(conn)
+            }),
+        }
+    };
+}
@@ -0,0 +1,579 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::{
+    counts, eid, maped_ratings, means, ratings, Controller, Entity, Field, Histogram, ItemFeatures,
+    RatingKind, RatingScale, Result, SearchBy, TimedScore, Timestamp, Value,
+};
+use config::Config;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+/// A bounded map that evicts the least recently used entry when full
+struct Lru<K, V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<K, (V, u64)>,
+    order: BTreeMap<u64, K>,
+}
+
+impl<K, V> Lru<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let (value, last_used) = self.entries.get_mut(key)?;
+
+        self.tick += 1;
+        self.order.remove(last_used);
+        self.order.insert(self.tick, key.clone());
+        *last_used = self.tick;
+
+        Some(value)
+    }
+
+    fn put(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.remove(&key);
+        if self.entries.len() == self.capacity {
+            let oldest = self.order.keys().next().copied();
+            if let Some(key) = oldest.and_then(|tick| self.order.remove(&tick)) {
+                self.entries.remove(&key);
+            }
+        }
+
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some((_, last_used)) = self.entries.remove(key) {
+            self.order.remove(&last_used);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+/// Decorator that caches the ratings and means read through it, any rating
+/// written through it invalidates the cached values of its user and item.
+/// Removing an user (or an item) drops every cached value its ratings could
+/// have been part of.
+///
+/// Writes that don't go through this controller aren't seen until the cached
+/// values are evicted.
+#[allow(clippy::type_complexity)]
+pub struct CachedController<C>
+where
+    C: Controller,
+{
+    controller: C,
+
+    user_ratings: RefCell<Lru<eid!(C::User), ratings!(C::Item)>>,
+    users_who_rated: RefCell<Lru<eid!(C::Item), Option<ratings!(C::User)>>>,
+    users_means: RefCell<Lru<eid!(C::User), Option<f64>>>,
+}
+
+impl<C, U, I> CachedController<C>
+where
+    C: Controller<User = U, Item = I>,
+    U: Entity + Clone,
+    I: Entity + Clone,
+    eid!(U): Hash + Eq + Clone,
+    eid!(I): Hash + Eq + Clone,
+{
+    pub fn new(
+        controller: C,
+        user_ratings_capacity: usize,
+        users_who_rated_capacity: usize,
+        users_means_capacity: usize,
+    ) -> Self {
+        Self {
+            controller,
+            user_ratings: RefCell::new(Lru::new(user_ratings_capacity)),
+            users_who_rated: RefCell::new(Lru::new(users_who_rated_capacity)),
+            users_means: RefCell::new(Lru::new(users_means_capacity)),
+        }
+    }
+
+    /// Use the capacities of the `[cache]` section
+    pub fn from_config(controller: C, config: &Config) -> Self {
+        let cache = &config.cache;
+
+        Self::new(
+            controller,
+            cache.user_ratings_capacity,
+            cache.users_who_rated_capacity,
+            cache.users_means_capacity,
+        )
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.controller
+    }
+
+    /// Drop every cached value
+    pub fn clear(&self) {
+        self.user_ratings.borrow_mut().clear();
+        self.users_who_rated.borrow_mut().clear();
+        self.users_means.borrow_mut().clear();
+    }
+
+    /// Ids of the items a user rated, from the cache if it's there
+    fn rated_items(&self, user_id: &eid!(U)) -> Result<Vec<eid!(I)>> {
+        if let Some(ratings) = self.user_ratings.borrow_mut().get(user_id) {
+            return Ok(ratings.keys().cloned().collect());
+        }
+
+        let mut rated = Vec::new();
+        for user in self
+            .controller
+            .create_partial_users(std::slice::from_ref(user_id))?
+        {
+            rated.extend(self.controller.user_ratings(&user)?.keys().cloned());
+        }
+
+        Ok(rated)
+    }
+
+    fn invalidate(&self, user_id: &eid!(U), item_id: &eid!(I)) {
+        self.user_ratings.borrow_mut().remove(user_id);
+        self.users_means.borrow_mut().remove(user_id);
+        self.users_who_rated.borrow_mut().remove(item_id);
+    }
+}
+
+impl<C, U, I, R> Controller for CachedController<C>
+where
+    C: Controller<User = U, Item = I, Rating = R>,
+    U: Entity + Clone,
+    I: Entity + Clone,
+    R: Entity,
+    eid!(U): Hash + Eq + Clone,
+    eid!(I): Hash + Eq + Clone,
+{
+    type User = U;
+    type Item = I;
+    type Rating = R;
+
+    fn users(&self) -> Result<Vec<U>> {
+        self.controller.users()
+    }
+
+    fn users_by(&self, by: &SearchBy) -> Result<Vec<U>> {
+        self.controller.users_by(by)
+    }
+
+    fn users_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<U>> {
+        self.controller.users_offset_limit(offset, limit)
+    }
+
+    fn users_after(&self, after: Option<&eid!(U)>, limit: usize) -> Result<Vec<U>> {
+        self.controller.users_after(after, limit)
+    }
+
+    fn items(&self) -> Result<Vec<I>> {
+        self.controller.items()
+    }
+
+    fn items_by(&self, by: &SearchBy) -> Result<Vec<I>> {
+        self.controller.items_by(by)
+    }
+
+    fn items_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<I>> {
+        self.controller.items_offset_limit(offset, limit)
+    }
+
+    fn items_after(&self, after: Option<&eid!(I)>, limit: usize) -> Result<Vec<I>> {
+        self.controller.items_after(after, limit)
+    }
+
+    fn create_partial_users(&self, user_ids: &[eid!(U)]) -> Result<Vec<U>> {
+        self.controller.create_partial_users(user_ids)
+    }
+
+    fn create_partial_items(&self, item_ids: &[eid!(I)]) -> Result<Vec<I>> {
+        self.controller.create_partial_items(item_ids)
+    }
+
+    fn users_who_rated(&self, items: &[I]) -> Result<maped_ratings!(I => U)> {
+        let mut users_who_rated = HashMap::new();
+        let mut missing = Vec::new();
+
+        {
+            let mut cache = self.users_who_rated.borrow_mut();
+            for item in items {
+                match cache.get(&item.get_id()) {
+                    Some(Some(ratings)) => {
+                        users_who_rated.insert(item.get_id(), ratings.clone());
+                    }
+                    Some(None) => {}
+                    None => missing.push(item.clone()),
+                }
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(users_who_rated);
+        }
+
+        let mut fetched = self.controller.users_who_rated(&missing)?;
+        let mut cache = self.users_who_rated.borrow_mut();
+        for item in missing {
+            let item_id = item.get_id();
+            let ratings = fetched.remove(&item_id);
+            cache.put(item_id.clone(), ratings.clone());
+
+            if let Some(ratings) = ratings {
+                users_who_rated.insert(item_id, ratings);
+            }
+        }
+
+        Ok(users_who_rated)
+    }
+
+    fn user_ratings(&self, user: &U) -> Result<ratings!(I)> {
+        let user_id = user.get_id();
+        if let Some(ratings) = self.user_ratings.borrow_mut().get(&user_id) {
+            return Ok(ratings.clone());
+        }
+
+        let ratings = self.controller.user_ratings(user)?;
+        self.user_ratings.borrow_mut().put(user_id, ratings.clone());
+
+        Ok(ratings)
+    }
+
+    fn user_timed_ratings(&self, user: &U) -> Result<ratings!(I, TimedScore)> {
+        self.controller.user_timed_ratings(user)
+    }
+
+    fn users_ratings_since(&self, since: Timestamp) -> Result<maped_ratings!(U => I, TimedScore)> {
+        self.controller.users_ratings_since(since)
+    }
+
+    fn all_users_ratings(&self) -> Result<maped_ratings!(U => I)> {
+        self.controller.all_users_ratings()
+    }
+
+    fn users_ratings(&self, users: &[U]) -> Result<maped_ratings!(U => I)> {
+        self.controller.users_ratings(users)
+    }
+
+    fn users_ratings_except(&self, user: &U) -> Result<maped_ratings!(U => I)> {
+        self.controller.users_ratings_except(user)
+    }
+
+    fn users_means(&self, users: &[U]) -> Result<means!(U)> {
+        let mut means = HashMap::new();
+        let mut missing = Vec::new();
+
+        {
+            let mut cache = self.users_means.borrow_mut();
+            for user in users {
+                match cache.get(&user.get_id()) {
+                    Some(Some(mean)) => {
+                        means.insert(user.get_id(), *mean);
+                    }
+                    Some(None) => {}
+                    None => missing.push(user.clone()),
+                }
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(means);
+        }
+
+        let fetched = self.controller.users_means(&missing)?;
+        let mut cache = self.users_means.borrow_mut();
+        for user in missing {
+            let user_id = user.get_id();
+            let mean = fetched.get(&user_id).copied();
+            cache.put(user_id.clone(), mean);
+
+            if let Some(mean) = mean {
+                means.insert(user_id, mean);
+            }
+        }
+
+        Ok(means)
+    }
+
+    fn users_count(&self) -> Result<usize> {
+        self.controller.users_count()
+    }
+
+    fn items_count(&self) -> Result<usize> {
+        self.controller.items_count()
+    }
+
+    fn ratings_count(&self) -> Result<usize> {
+        self.controller.ratings_count()
+    }
+
+    fn users_ratings_count(&self, users: &[U]) -> Result<counts!(U)> {
+        self.controller.users_ratings_count(users)
+    }
+
+    fn items_ratings_count(&self, items: &[I]) -> Result<counts!(I)> {
+        self.controller.items_ratings_count(items)
+    }
+
+    fn items_means(&self, items: &[I]) -> Result<means!(I)> {
+        self.controller.items_means(items)
+    }
+
+    fn global_mean(&self) -> Result<Option<f64>> {
+        self.controller.global_mean()
+    }
+
+    fn user_histogram(&self, user: &U) -> Result<Histogram> {
+        self.controller.user_histogram(user)
+    }
+
+    fn item_histogram(&self, item: &I) -> Result<Histogram> {
+        self.controller.item_histogram(item)
+    }
+
+    fn most_rated_items(&self, limit: usize) -> Result<Vec<(eid!(I), usize)>> {
+        self.controller.most_rated_items(limit)
+    }
+
+    fn items_features(&self, items: &[I]) -> Result<ItemFeatures<eid!(I)>> {
+        self.controller.items_features(items)
+    }
+
+    fn score_range(&self) -> (f64, f64) {
+        self.controller.score_range()
+    }
+
+    fn rating_scale(&self) -> RatingScale {
+        self.controller.rating_scale()
+    }
+
+    fn rating_kind(&self, score: f64) -> RatingKind {
+        self.controller.rating_kind(score)
+    }
+
+    fn check_score(&self, score: f64) -> Result<()> {
+        self.controller.check_score(score)
+    }
+
+    fn fields_for_users(&self) -> Vec<Field<'_>> {
+        self.controller.fields_for_users()
+    }
+
+    fn fields_for_items(&self) -> Vec<Field<'_>> {
+        self.controller.fields_for_items()
+    }
+
+    fn custom_keys_for_users(&self) -> Vec<String> {
+        self.controller.custom_keys_for_users()
+    }
+
+    fn custom_keys_for_items(&self) -> Vec<String> {
+        self.controller.custom_keys_for_items()
+    }
+
+    fn insert_user(&self, proto: HashMap<&str, Value>) -> Result<U> {
+        let user = self.controller.insert_user(proto)?;
+        self.user_ratings.borrow_mut().remove(&user.get_id());
+        self.users_means.borrow_mut().remove(&user.get_id());
+        Ok(user)
+    }
+
+    fn insert_item(&self, proto: HashMap<&str, Value>) -> Result<I> {
+        let item = self.controller.insert_item(proto)?;
+        self.users_who_rated.borrow_mut().remove(&item.get_id());
+        Ok(item)
+    }
+
+    fn update_user(&self, user_id: &eid!(U), proto: HashMap<&str, Value>) -> Result<U> {
+        self.controller.update_user(user_id, proto)
+    }
+
+    fn update_item(&self, item_id: &eid!(I), proto: HashMap<&str, Value>) -> Result<I> {
+        self.controller.update_item(item_id, proto)
+    }
+
+    fn remove_user(&self, user_id: &eid!(U)) -> Result<U> {
+        // The user is only among the raters of the items it rated, if those can't
+        // be read every cached rater is dropped
+        let rated = self.rated_items(user_id);
+        let user = self.controller.remove_user(user_id);
+        self.user_ratings.borrow_mut().remove(user_id);
+        self.users_means.borrow_mut().remove(user_id);
+
+        let mut users_who_rated = self.users_who_rated.borrow_mut();
+        match rated {
+            Ok(items) => items
+                .iter()
+                .for_each(|item_id| users_who_rated.remove(item_id)),
+            Err(_) => users_who_rated.clear(),
+        }
+
+        user
+    }
+
+    fn remove_item(&self, item_id: &eid!(I)) -> Result<I> {
+        let item = self.controller.remove_item(item_id);
+        self.users_who_rated.borrow_mut().remove(item_id);
+        self.user_ratings.borrow_mut().clear();
+        self.users_means.borrow_mut().clear();
+        item
+    }
+
+    fn insert_rating(&self, user_id: &eid!(U), item_id: &eid!(I), score: f64) -> Result<R> {
+        // Invalidate even on errors, the write may have been partially applied
+        let rating = self.controller.insert_rating(user_id, item_id, score);
+        self.invalidate(user_id, item_id);
+        rating
+    }
+
+    fn remove_rating(&self, user_id: &eid!(U), item_id: &eid!(I)) -> Result<R> {
+        let rating = self.controller.remove_rating(user_id, item_id);
+        self.invalidate(user_id, item_id);
+        rating
+    }
+
+    fn update_rating(&self, user_id: &eid!(U), item_id: &eid!(I), score: f64) -> Result<R> {
+        let rating = self.controller.update_rating(user_id, item_id, score);
+        self.invalidate(user_id, item_id);
+        rating
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{memory::MemoryItem, MemoryController};
+    use assert_approx_eq::*;
+
+    fn controller() -> CachedController<MemoryController> {
+        let controller = MemoryController::new((1., 5.));
+
+        controller.add_rating(1, 10, 4.);
+        controller.add_rating(1, 20, 2.);
+        controller.add_rating(2, 10, 5.);
+        controller.add_item(MemoryItem::new(30));
+
+        CachedController::new(controller, 2, 2, 2)
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let mut lru = Lru::new(2);
+        lru.put(1, "a");
+        lru.put(2, "b");
+        lru.get(&1);
+        lru.put(3, "c");
+
+        assert_eq!(lru.get(&1), Some(&"a"));
+        assert_eq!(lru.get(&2), None);
+        assert_eq!(lru.get(&3), Some(&"c"));
+
+        let mut disabled = Lru::new(0);
+        disabled.put(1, "a");
+        assert_eq!(disabled.get(&1), None);
+    }
+
+    #[test]
+    fn cached_reads() -> Result<()> {
+        let controller = controller();
+        let users = controller.create_partial_users(&[1, 2])?;
+
+        assert_eq!(controller.user_ratings(&users[0])?.len(), 2);
+        assert_approx_eq!(controller.users_means(&users)?[&2], 5.);
+
+        // Written behind the cache, the cached values are still served
+        controller.inner().add_rating(1, 30, 3.);
+        controller.inner().add_rating(2, 20, 1.);
+        assert_eq!(controller.user_ratings(&users[0])?.len(), 2);
+        assert_approx_eq!(controller.users_means(&users)?[&2], 5.);
+
+        let items = controller.create_partial_items(&[30])?;
+        assert_eq!(controller.users_who_rated(&items)?[&30].len(), 1);
+
+        controller.clear();
+        assert_eq!(controller.user_ratings(&users[0])?.len(), 3);
+        assert_approx_eq!(controller.users_means(&users)?[&2], 3.);
+
+        Ok(())
+    }
+
+    #[test]
+    fn writes_invalidate() -> Result<()> {
+        let controller = controller();
+        let users = controller.create_partial_users(&[2])?;
+        let items = controller.create_partial_items(&[10, 30])?;
+
+        assert!(!controller.users_who_rated(&items)?.contains_key(&30));
+        assert_approx_eq!(controller.users_means(&users)?[&2], 5.);
+
+        controller.insert_rating(&2, &30, 3.)?;
+        assert_eq!(controller.users_who_rated(&items)?[&30].len(), 1);
+        assert_approx_eq!(controller.users_means(&users)?[&2], 4.);
+        assert_eq!(controller.user_ratings(&users[0])?.len(), 2);
+
+        controller.update_rating(&2, &10, 1.)?;
+        assert_approx_eq!(controller.users_who_rated(&items)?[&10][&2], 1.);
+        assert_approx_eq!(controller.users_means(&users)?[&2], 2.);
+
+        controller.remove_rating(&2, &30)?;
+        assert!(!controller.users_who_rated(&items)?.contains_key(&30));
+        assert_eq!(controller.user_ratings(&users[0])?.len(), 1);
+
+        controller.remove_item(&10)?;
+        assert!(controller.user_ratings(&users[0])?.is_empty());
+        assert!(controller.users_means(&users)?.is_empty());
+
+        let items = controller.create_partial_items(&[20])?;
+        assert!(controller.users_who_rated(&items)?.contains_key(&20));
+        controller.remove_user(&1)?;
+        assert!(!controller.users_who_rated(&items)?.contains_key(&20));
+
+        Ok(())
+    }
+
+    #[test]
+    fn removed_user_evicts_its_items() -> Result<()> {
+        let controller = controller();
+        let items = controller.create_partial_items(&[20, 30])?;
+        assert!(controller.users_who_rated(&items)?.contains_key(&20));
+
+        // Item 30 wasn't rated by the removed user, its cached raters are kept
+        controller.inner().add_rating(2, 30, 3.);
+        controller.remove_user(&1)?;
+
+        let users_who_rated = controller.users_who_rated(&items)?;
+        assert!(!users_who_rated.contains_key(&20));
+        assert!(!users_who_rated.contains_key(&30));
+
+        controller.clear();
+        assert!(controller.users_who_rated(&items)?.contains_key(&30));
+
+        Ok(())
+    }
+}
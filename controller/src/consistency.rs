@@ -0,0 +1,183 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::MapedRatings;
+use std::{collections::BTreeSet, fmt, hash::Hash};
+
+/// How a rating stored in a document differs from the one in the ratings table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Drift {
+    /// The rating is in the ratings table but not in the document
+    Missing { expected: f64 },
+
+    /// The rating is only in the document
+    Extra { found: f64 },
+
+    /// Both have the rating but with different scores
+    Mismatch { expected: f64, found: f64 },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::Missing { expected } => write!(f, "missing (expected {})", expected),
+            Drift::Extra { found } => write!(f, "extra (found {})", found),
+            Drift::Mismatch { expected, found } => {
+                write!(f, "mismatch (expected {}, found {})", expected, found)
+            }
+        }
+    }
+}
+
+/// A single drifted rating, `key` is the owner of the document (an user for
+/// `users_ratings`, an item for `users_who_rated`) and `other` the rated side
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discrepancy<K, O> {
+    pub key: K,
+    pub other: O,
+    pub drift: Drift,
+}
+
+/// Drift found between the ratings table and the rating documents
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsistencyReport<U, I> {
+    /// Discrepancies on documents by user (`users_ratings`)
+    pub users: Vec<Discrepancy<U, I>>,
+
+    /// Discrepancies on documents by item (`users_who_rated`)
+    pub items: Vec<Discrepancy<I, U>>,
+}
+
+impl<U, I> Default for ConsistencyReport<U, I> {
+    fn default() -> Self {
+        Self {
+            users: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+}
+
+impl<U, I> ConsistencyReport<U, I>
+where
+    U: Ord + Clone,
+    I: Ord + Clone,
+{
+    pub fn is_consistent(&self) -> bool {
+        self.users.is_empty() && self.items.is_empty()
+    }
+
+    /// Users whose document has at least one discrepancy
+    pub fn drifted_users(&self) -> BTreeSet<U> {
+        self.users.iter().map(|d| d.key.clone()).collect()
+    }
+
+    /// Items whose document has at least one discrepancy
+    pub fn drifted_items(&self) -> BTreeSet<I> {
+        self.items.iter().map(|d| d.key.clone()).collect()
+    }
+}
+
+/// Diff the ratings found in documents against the expected ones (the ratings
+/// table), discrepancies are ordered by key and then by the rated side
+pub fn diff_ratings<K, O>(
+    expected: &MapedRatings<K, O>,
+    found: &MapedRatings<K, O>,
+) -> Vec<Discrepancy<K, O>>
+where
+    K: Hash + Eq + Ord + Clone,
+    O: Hash + Eq + Ord + Clone,
+{
+    let mut discrepancies = Vec::new();
+
+    for (key, expected_ratings) in expected {
+        for (other, &expected) in expected_ratings {
+            let drift = match found.get(key).and_then(|ratings| ratings.get(other)) {
+                None => Drift::Missing { expected },
+                Some(&found) if found != expected => Drift::Mismatch { expected, found },
+                Some(_) => continue,
+            };
+
+            discrepancies.push(Discrepancy {
+                key: key.clone(),
+                other: other.clone(),
+                drift,
+            });
+        }
+    }
+
+    for (key, found_ratings) in found {
+        for (other, &found) in found_ratings {
+            let is_expected = expected
+                .get(key)
+                .and_then(|ratings| ratings.get(other))
+                .is_some();
+
+            if !is_expected {
+                discrepancies.push(Discrepancy {
+                    key: key.clone(),
+                    other: other.clone(),
+                    drift: Drift::Extra { found },
+                });
+            }
+        }
+    }
+
+    discrepancies.sort_by(|a, b| (&a.key, &a.other).cmp(&(&b.key, &b.other)));
+    discrepancies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn diff_finds_every_kind_of_drift() {
+        let mut expected: MapedRatings<i32, i32> = HashMap::new();
+        expected.entry(1).or_default().insert(10, 4.);
+        expected.entry(1).or_default().insert(20, 2.);
+        expected.entry(2).or_default().insert(10, 5.);
+
+        let mut found = expected.clone();
+        assert!(diff_ratings(&expected, &found).is_empty());
+
+        found.get_mut(&1).unwrap().remove(&20);
+        found.get_mut(&2).unwrap().insert(10, 1.);
+        found.entry(3).or_default().insert(30, 3.);
+
+        let discrepancies = diff_ratings(&expected, &found);
+        let drifts: Vec<_> = discrepancies
+            .iter()
+            .map(|d| (d.key, d.other, d.drift))
+            .collect();
+
+        assert_eq!(
+            drifts,
+            vec![
+                (1, 20, Drift::Missing { expected: 2. }),
+                (
+                    2,
+                    10,
+                    Drift::Mismatch {
+                        expected: 5.,
+                        found: 1.
+                    }
+                ),
+                (3, 30, Drift::Extra { found: 3. }),
+            ]
+        );
+
+        let report = ConsistencyReport {
+            users: discrepancies,
+            items: Vec::new(),
+        };
+
+        assert!(!report.is_consistent());
+        assert_eq!(
+            report.drifted_users().into_iter().collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+    }
+}
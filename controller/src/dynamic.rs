@@ -0,0 +1,777 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::{
+    eid, error::ErrorKind, memory::parse_id, Controller, Counts, Data, Entity, Field, Histogram,
+    ItemFeatures, MapedRatings, Means, RatingKind, RatingScale, Ratings, Result, SearchBy,
+    TimedScore, Timestamp, Value,
+};
+use std::{collections::HashMap, fmt::Display, hash::Hash, str::FromStr};
+
+/// An user, item or rating coming from a type erased controller, ids are
+/// kept as strings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DynEntity {
+    pub id: String,
+    pub data: Data,
+}
+
+impl DynEntity {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.into(),
+            data: Data::new(),
+        }
+    }
+
+    fn erase<E>(entity: &E) -> Self
+    where
+        E: Entity,
+        eid!(E): ToString,
+    {
+        Self {
+            id: entity.get_id().to_string(),
+            data: entity.get_data(),
+        }
+    }
+}
+
+impl Entity for DynEntity {
+    type Id = String;
+
+    fn get_id(&self) -> Self::Id {
+        self.id.clone()
+    }
+
+    fn get_data(&self) -> Data {
+        self.data.clone()
+    }
+}
+
+/// Object safe version of `Controller`, every entity is a `DynEntity` and ids are
+/// strings, so any dataset can be picked at runtime (ex. from the config).
+pub trait DynController {
+    /// Get all users
+    fn users(&self) -> Result<Vec<DynEntity>>;
+
+    /// Get users that matched the search criteria by id, name or custom (if implemented)
+    fn users_by(&self, by: &SearchBy) -> Result<Vec<DynEntity>>;
+
+    /// Get a chunk of users specified by certain offset and limit, ordered by id
+    fn users_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<DynEntity>>;
+
+    /// Get a chunk of at most `limit` users with an id greater than `after`, ordered by id
+    fn users_after(&self, after: Option<&str>, limit: usize) -> Result<Vec<DynEntity>>;
+
+    /// Get all items
+    fn items(&self) -> Result<Vec<DynEntity>>;
+
+    /// Get items that matched the search criteria by id, name or custom (if implemented)
+    fn items_by(&self, by: &SearchBy) -> Result<Vec<DynEntity>>;
+
+    /// Get a chunk of items specified by certain offset and limit, ordered by id
+    fn items_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<DynEntity>>;
+
+    /// Get a chunk of at most `limit` items with an id greater than `after`, ordered by id
+    fn items_after(&self, after: Option<&str>, limit: usize) -> Result<Vec<DynEntity>>;
+
+    /// Build skeleton/partial users, useful to use in other queries
+    fn create_partial_users(&self, user_ids: &[String]) -> Result<Vec<DynEntity>>;
+
+    /// Build skeleton/partial items, useful to use in other queries
+    fn create_partial_items(&self, item_ids: &[String]) -> Result<Vec<DynEntity>>;
+
+    /// Get an "inverted" MapedRatings, i.e. maps Item::Id => User::Id
+    fn users_who_rated(&self, items: &[DynEntity]) -> Result<MapedRatings<String, String>>;
+
+    /// Get the ratings for the specified user
+    fn user_ratings(&self, user: &DynEntity) -> Result<Ratings<String>>;
+
+    /// Get the ratings for the specified user along with the time they were given
+    fn user_timed_ratings(&self, user: &DynEntity) -> Result<Ratings<String, TimedScore>>;
+
+    /// Get the ratings given at or after `since`, i.e. maps User::Id => Item::Id
+    fn users_ratings_since(
+        &self,
+        since: Timestamp,
+    ) -> Result<MapedRatings<String, String, TimedScore>>;
+
+    /// Get all normal MapedRatings, i.e. maps User::Id => Item::Id
+    fn all_users_ratings(&self) -> Result<MapedRatings<String, String>>;
+
+    /// Get some normal MapedRatings for the specified users, i.e. maps User::Id => Item::Id
+    fn users_ratings(&self, users: &[DynEntity]) -> Result<MapedRatings<String, String>>;
+
+    /// Get all normal MapedRatings except for the specified user, i.e. maps User::Id => Item::Id
+    fn users_ratings_except(&self, user: &DynEntity) -> Result<MapedRatings<String, String>>;
+
+    /// Get means for the specified users, returns a map of User::Id => f64
+    fn users_means(&self, users: &[DynEntity]) -> Result<Means<String>>;
+
+    /// Get the number of users
+    fn users_count(&self) -> Result<usize>;
+
+    /// Get the number of items
+    fn items_count(&self) -> Result<usize>;
+
+    /// Get the number of ratings
+    fn ratings_count(&self) -> Result<usize>;
+
+    /// Get how many ratings has each one of the specified users
+    fn users_ratings_count(&self, users: &[DynEntity]) -> Result<Counts<String>>;
+
+    /// Get how many ratings has each one of the specified items
+    fn items_ratings_count(&self, items: &[DynEntity]) -> Result<Counts<String>>;
+
+    /// Content features of the specified items, along with their vocabulary
+    fn items_features(&self, items: &[DynEntity]) -> Result<ItemFeatures<String>>;
+
+    /// Get means for the specified items
+    fn items_means(&self, items: &[DynEntity]) -> Result<Means<String>>;
+
+    /// Get the mean of every rating, `None` if there are no ratings
+    fn global_mean(&self) -> Result<Option<f64>>;
+
+    /// Get how many times the specified user gave each score
+    fn user_histogram(&self, user: &DynEntity) -> Result<Histogram>;
+
+    /// Get how many times the specified item was given each score
+    fn item_histogram(&self, item: &DynEntity) -> Result<Histogram>;
+
+    /// Get the `limit` most rated items along with their number of ratings
+    fn most_rated_items(&self, limit: usize) -> Result<Vec<(String, usize)>>;
+
+    /// The controller score range, ex. (0.0, 5.0) is (min_rating, max_rating)
+    fn score_range(&self) -> (f64, f64);
+
+    /// The scores accepted by the controller
+    fn rating_scale(&self) -> RatingScale;
+
+    /// The kind of rating a score stands for
+    fn rating_kind(&self, score: f64) -> RatingKind;
+
+    /// Check that a score can be written
+    fn check_score(&self, score: f64) -> Result<()>;
+
+    /// Return a list of fields required to insert a new user
+    fn fields_for_users(&self) -> Vec<Field<'_>>;
+
+    /// Return a list of fields required to insert a new item
+    fn fields_for_items(&self) -> Vec<Field<'_>>;
+
+    /// Return the keys users can be searched by with a custom search
+    fn custom_keys_for_users(&self) -> Vec<String>;
+
+    /// Return the keys items can be searched by with a custom search
+    fn custom_keys_for_items(&self) -> Vec<String>;
+
+    /// Insert a new user frow a prototype
+    fn insert_user(&self, proto: HashMap<&str, Value>) -> Result<DynEntity>;
+
+    /// Insert a new item frow a prototype
+    fn insert_item(&self, proto: HashMap<&str, Value>) -> Result<DynEntity>;
+
+    /// Update an existing user from a prototype
+    fn update_user(&self, user_id: &str, proto: HashMap<&str, Value>) -> Result<DynEntity>;
+
+    /// Update an existing item from a prototype
+    fn update_item(&self, item_id: &str, proto: HashMap<&str, Value>) -> Result<DynEntity>;
+
+    /// Remove an user along with all of its ratings
+    fn remove_user(&self, user_id: &str) -> Result<DynEntity>;
+
+    /// Remove an item along with all of its ratings
+    fn remove_item(&self, item_id: &str) -> Result<DynEntity>;
+
+    /// Createa a rating in user for an item
+    fn insert_rating(&self, user_id: &str, item_id: &str, score: f64) -> Result<DynEntity>;
+
+    /// Remove a rating in user for an item
+    fn remove_rating(&self, user_id: &str, item_id: &str) -> Result<DynEntity>;
+
+    /// Update a rating in user for an item
+    fn update_rating(&self, user_id: &str, item_id: &str, score: f64) -> Result<DynEntity>;
+}
+
+fn erase_all<E>(entities: Vec<E>) -> Vec<DynEntity>
+where
+    E: Entity,
+    eid!(E): ToString,
+{
+    entities.iter().map(DynEntity::erase).collect()
+}
+
+fn erase_keys<K: ToString, V>(map: HashMap<K, V>) -> HashMap<String, V> {
+    map.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+}
+
+fn erase_maped<K: ToString, I: ToString, V>(
+    maped: MapedRatings<K, I, V>,
+) -> MapedRatings<String, String, V> {
+    maped
+        .into_iter()
+        .map(|(k, ratings)| (k.to_string(), erase_keys(ratings)))
+        .collect()
+}
+
+fn parse_ids<K>(ids: &[String]) -> Result<Vec<K>>
+where
+    K: FromStr,
+    K::Err: Display,
+{
+    Ok(ids
+        .iter()
+        .map(|id| parse_id(id))
+        .collect::<std::result::Result<_, _>>()?)
+}
+
+/// Adapter from any `Controller` to `DynController`, ids are parsed back with
+/// `FromStr`. Entities given to the adapter are turned into partial entities.
+pub struct DynAdapter<C>(C);
+
+impl<C> DynAdapter<C> {
+    pub fn new(controller: C) -> Self {
+        Self(controller)
+    }
+
+    pub fn into_inner(self) -> C {
+        self.0
+    }
+}
+
+impl<C, U, I, R> DynAdapter<C>
+where
+    C: Controller<User = U, Item = I, Rating = R> + 'static,
+    U: Entity,
+    I: Entity,
+    R: Entity,
+    eid!(U): ToString + FromStr + Hash + Eq,
+    eid!(I): ToString + FromStr + Hash + Eq,
+    eid!(R): ToString,
+    <eid!(U) as FromStr>::Err: Display,
+    <eid!(I) as FromStr>::Err: Display,
+{
+    /// Erase the controller type
+    pub fn boxed(controller: C) -> Box<dyn DynController> {
+        Box::new(Self::new(controller))
+    }
+
+    fn partial_users(&self, users: &[DynEntity]) -> Result<Vec<U>> {
+        let ids: Vec<_> = users.iter().map(|user| user.id.clone()).collect();
+        self.0.create_partial_users(&parse_ids(&ids)?)
+    }
+
+    fn partial_items(&self, items: &[DynEntity]) -> Result<Vec<I>> {
+        let ids: Vec<_> = items.iter().map(|item| item.id.clone()).collect();
+        self.0.create_partial_items(&parse_ids(&ids)?)
+    }
+
+    fn partial_user(&self, user: &DynEntity) -> Result<U> {
+        self.partial_users(std::slice::from_ref(user))?
+            .pop()
+            .ok_or_else(|| ErrorKind::NotFoundById(user.id.clone()).into())
+    }
+
+    fn partial_item(&self, item: &DynEntity) -> Result<I> {
+        self.partial_items(std::slice::from_ref(item))?
+            .pop()
+            .ok_or_else(|| ErrorKind::NotFoundById(item.id.clone()).into())
+    }
+}
+
+impl<C, U, I, R> DynController for DynAdapter<C>
+where
+    C: Controller<User = U, Item = I, Rating = R> + 'static,
+    U: Entity,
+    I: Entity,
+    R: Entity,
+    eid!(U): ToString + FromStr + Hash + Eq,
+    eid!(I): ToString + FromStr + Hash + Eq,
+    eid!(R): ToString,
+    <eid!(U) as FromStr>::Err: Display,
+    <eid!(I) as FromStr>::Err: Display,
+{
+    fn users(&self) -> Result<Vec<DynEntity>> {
+        Ok(erase_all(self.0.users()?))
+    }
+
+    fn users_by(&self, by: &SearchBy) -> Result<Vec<DynEntity>> {
+        Ok(erase_all(self.0.users_by(by)?))
+    }
+
+    fn users_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<DynEntity>> {
+        Ok(erase_all(self.0.users_offset_limit(offset, limit)?))
+    }
+
+    fn users_after(&self, after: Option<&str>, limit: usize) -> Result<Vec<DynEntity>> {
+        let after = after.map(parse_id).transpose()?;
+        Ok(erase_all(self.0.users_after(after.as_ref(), limit)?))
+    }
+
+    fn items(&self) -> Result<Vec<DynEntity>> {
+        Ok(erase_all(self.0.items()?))
+    }
+
+    fn items_by(&self, by: &SearchBy) -> Result<Vec<DynEntity>> {
+        Ok(erase_all(self.0.items_by(by)?))
+    }
+
+    fn items_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<DynEntity>> {
+        Ok(erase_all(self.0.items_offset_limit(offset, limit)?))
+    }
+
+    fn items_after(&self, after: Option<&str>, limit: usize) -> Result<Vec<DynEntity>> {
+        let after = after.map(parse_id).transpose()?;
+        Ok(erase_all(self.0.items_after(after.as_ref(), limit)?))
+    }
+
+    fn create_partial_users(&self, user_ids: &[String]) -> Result<Vec<DynEntity>> {
+        Ok(erase_all(
+            self.0.create_partial_users(&parse_ids(user_ids)?)?,
+        ))
+    }
+
+    fn create_partial_items(&self, item_ids: &[String]) -> Result<Vec<DynEntity>> {
+        Ok(erase_all(
+            self.0.create_partial_items(&parse_ids(item_ids)?)?,
+        ))
+    }
+
+    fn users_who_rated(&self, items: &[DynEntity]) -> Result<MapedRatings<String, String>> {
+        let items = self.partial_items(items)?;
+        Ok(erase_maped(self.0.users_who_rated(&items)?))
+    }
+
+    fn user_ratings(&self, user: &DynEntity) -> Result<Ratings<String>> {
+        let user = self.partial_user(user)?;
+        Ok(erase_keys(self.0.user_ratings(&user)?))
+    }
+
+    fn user_timed_ratings(&self, user: &DynEntity) -> Result<Ratings<String, TimedScore>> {
+        let user = self.partial_user(user)?;
+        Ok(erase_keys(self.0.user_timed_ratings(&user)?))
+    }
+
+    fn users_ratings_since(
+        &self,
+        since: Timestamp,
+    ) -> Result<MapedRatings<String, String, TimedScore>> {
+        Ok(erase_maped(self.0.users_ratings_since(since)?))
+    }
+
+    fn all_users_ratings(&self) -> Result<MapedRatings<String, String>> {
+        Ok(erase_maped(self.0.all_users_ratings()?))
+    }
+
+    fn users_ratings(&self, users: &[DynEntity]) -> Result<MapedRatings<String, String>> {
+        let users = self.partial_users(users)?;
+        Ok(erase_maped(self.0.users_ratings(&users)?))
+    }
+
+    fn users_ratings_except(&self, user: &DynEntity) -> Result<MapedRatings<String, String>> {
+        let user = self.partial_user(user)?;
+        Ok(erase_maped(self.0.users_ratings_except(&user)?))
+    }
+
+    fn users_means(&self, users: &[DynEntity]) -> Result<Means<String>> {
+        let users = self.partial_users(users)?;
+        Ok(erase_keys(self.0.users_means(&users)?))
+    }
+
+    fn users_count(&self) -> Result<usize> {
+        self.0.users_count()
+    }
+
+    fn items_count(&self) -> Result<usize> {
+        self.0.items_count()
+    }
+
+    fn ratings_count(&self) -> Result<usize> {
+        self.0.ratings_count()
+    }
+
+    fn users_ratings_count(&self, users: &[DynEntity]) -> Result<Counts<String>> {
+        let users = self.partial_users(users)?;
+        Ok(erase_keys(self.0.users_ratings_count(&users)?))
+    }
+
+    fn items_ratings_count(&self, items: &[DynEntity]) -> Result<Counts<String>> {
+        let items = self.partial_items(items)?;
+        Ok(erase_keys(self.0.items_ratings_count(&items)?))
+    }
+
+    fn items_features(&self, items: &[DynEntity]) -> Result<ItemFeatures<String>> {
+        let items = self.partial_items(items)?;
+        let features = self.0.items_features(&items)?;
+        Ok(features.map_ids(|id| id.to_string()))
+    }
+
+    fn items_means(&self, items: &[DynEntity]) -> Result<Means<String>> {
+        let items = self.partial_items(items)?;
+        Ok(erase_keys(self.0.items_means(&items)?))
+    }
+
+    fn global_mean(&self) -> Result<Option<f64>> {
+        self.0.global_mean()
+    }
+
+    fn user_histogram(&self, user: &DynEntity) -> Result<Histogram> {
+        self.0.user_histogram(&self.partial_user(user)?)
+    }
+
+    fn item_histogram(&self, item: &DynEntity) -> Result<Histogram> {
+        self.0.item_histogram(&self.partial_item(item)?)
+    }
+
+    fn most_rated_items(&self, limit: usize) -> Result<Vec<(String, usize)>> {
+        let items = self.0.most_rated_items(limit)?;
+        Ok(items
+            .into_iter()
+            .map(|(id, count)| (id.to_string(), count))
+            .collect())
+    }
+
+    fn score_range(&self) -> (f64, f64) {
+        self.0.score_range()
+    }
+
+    fn rating_scale(&self) -> RatingScale {
+        self.0.rating_scale()
+    }
+
+    fn rating_kind(&self, score: f64) -> RatingKind {
+        self.0.rating_kind(score)
+    }
+
+    fn check_score(&self, score: f64) -> Result<()> {
+        self.0.check_score(score)
+    }
+
+    fn fields_for_users(&self) -> Vec<Field<'_>> {
+        self.0.fields_for_users()
+    }
+
+    fn fields_for_items(&self) -> Vec<Field<'_>> {
+        self.0.fields_for_items()
+    }
+
+    fn custom_keys_for_users(&self) -> Vec<String> {
+        self.0.custom_keys_for_users()
+    }
+
+    fn custom_keys_for_items(&self) -> Vec<String> {
+        self.0.custom_keys_for_items()
+    }
+
+    fn insert_user(&self, proto: HashMap<&str, Value>) -> Result<DynEntity> {
+        Ok(DynEntity::erase(&self.0.insert_user(proto)?))
+    }
+
+    fn insert_item(&self, proto: HashMap<&str, Value>) -> Result<DynEntity> {
+        Ok(DynEntity::erase(&self.0.insert_item(proto)?))
+    }
+
+    fn update_user(&self, user_id: &str, proto: HashMap<&str, Value>) -> Result<DynEntity> {
+        let user = self.0.update_user(&parse_id(user_id)?, proto)?;
+        Ok(DynEntity::erase(&user))
+    }
+
+    fn update_item(&self, item_id: &str, proto: HashMap<&str, Value>) -> Result<DynEntity> {
+        let item = self.0.update_item(&parse_id(item_id)?, proto)?;
+        Ok(DynEntity::erase(&item))
+    }
+
+    fn remove_user(&self, user_id: &str) -> Result<DynEntity> {
+        Ok(DynEntity::erase(&self.0.remove_user(&parse_id(user_id)?)?))
+    }
+
+    fn remove_item(&self, item_id: &str) -> Result<DynEntity> {
+        Ok(DynEntity::erase(&self.0.remove_item(&parse_id(item_id)?)?))
+    }
+
+    fn insert_rating(&self, user_id: &str, item_id: &str, score: f64) -> Result<DynEntity> {
+        let rating = self
+            .0
+            .insert_rating(&parse_id(user_id)?, &parse_id(item_id)?, score)?;
+        Ok(DynEntity::erase(&rating))
+    }
+
+    fn remove_rating(&self, user_id: &str, item_id: &str) -> Result<DynEntity> {
+        let rating = self
+            .0
+            .remove_rating(&parse_id(user_id)?, &parse_id(item_id)?)?;
+        Ok(DynEntity::erase(&rating))
+    }
+
+    fn update_rating(&self, user_id: &str, item_id: &str, score: f64) -> Result<DynEntity> {
+        let rating = self
+            .0
+            .update_rating(&parse_id(user_id)?, &parse_id(item_id)?, score)?;
+        Ok(DynEntity::erase(&rating))
+    }
+}
+
+/// A boxed `DynController` is a regular `Controller`, so the engine and the
+/// matrices can run over a dataset chosen at runtime.
+impl Controller for Box<dyn DynController> {
+    type User = DynEntity;
+    type Item = DynEntity;
+    type Rating = DynEntity;
+
+    fn users(&self) -> Result<Vec<DynEntity>> {
+        self.as_ref().users()
+    }
+
+    fn users_by(&self, by: &SearchBy) -> Result<Vec<DynEntity>> {
+        self.as_ref().users_by(by)
+    }
+
+    fn users_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<DynEntity>> {
+        self.as_ref().users_offset_limit(offset, limit)
+    }
+
+    fn users_after(&self, after: Option<&String>, limit: usize) -> Result<Vec<DynEntity>> {
+        self.as_ref().users_after(after.map(String::as_str), limit)
+    }
+
+    fn items(&self) -> Result<Vec<DynEntity>> {
+        self.as_ref().items()
+    }
+
+    fn items_by(&self, by: &SearchBy) -> Result<Vec<DynEntity>> {
+        self.as_ref().items_by(by)
+    }
+
+    fn items_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<DynEntity>> {
+        self.as_ref().items_offset_limit(offset, limit)
+    }
+
+    fn items_after(&self, after: Option<&String>, limit: usize) -> Result<Vec<DynEntity>> {
+        self.as_ref().items_after(after.map(String::as_str), limit)
+    }
+
+    fn create_partial_users(&self, user_ids: &[String]) -> Result<Vec<DynEntity>> {
+        self.as_ref().create_partial_users(user_ids)
+    }
+
+    fn create_partial_items(&self, item_ids: &[String]) -> Result<Vec<DynEntity>> {
+        self.as_ref().create_partial_items(item_ids)
+    }
+
+    fn users_who_rated(&self, items: &[DynEntity]) -> Result<MapedRatings<String, String>> {
+        self.as_ref().users_who_rated(items)
+    }
+
+    fn user_ratings(&self, user: &DynEntity) -> Result<Ratings<String>> {
+        self.as_ref().user_ratings(user)
+    }
+
+    fn user_timed_ratings(&self, user: &DynEntity) -> Result<Ratings<String, TimedScore>> {
+        self.as_ref().user_timed_ratings(user)
+    }
+
+    fn users_ratings_since(
+        &self,
+        since: Timestamp,
+    ) -> Result<MapedRatings<String, String, TimedScore>> {
+        self.as_ref().users_ratings_since(since)
+    }
+
+    fn all_users_ratings(&self) -> Result<MapedRatings<String, String>> {
+        self.as_ref().all_users_ratings()
+    }
+
+    fn users_ratings(&self, users: &[DynEntity]) -> Result<MapedRatings<String, String>> {
+        self.as_ref().users_ratings(users)
+    }
+
+    fn users_ratings_except(&self, user: &DynEntity) -> Result<MapedRatings<String, String>> {
+        self.as_ref().users_ratings_except(user)
+    }
+
+    fn users_means(&self, users: &[DynEntity]) -> Result<Means<String>> {
+        self.as_ref().users_means(users)
+    }
+
+    fn users_count(&self) -> Result<usize> {
+        self.as_ref().users_count()
+    }
+
+    fn items_count(&self) -> Result<usize> {
+        self.as_ref().items_count()
+    }
+
+    fn ratings_count(&self) -> Result<usize> {
+        self.as_ref().ratings_count()
+    }
+
+    fn users_ratings_count(&self, users: &[DynEntity]) -> Result<Counts<String>> {
+        self.as_ref().users_ratings_count(users)
+    }
+
+    fn items_ratings_count(&self, items: &[DynEntity]) -> Result<Counts<String>> {
+        self.as_ref().items_ratings_count(items)
+    }
+
+    fn items_features(&self, items: &[DynEntity]) -> Result<ItemFeatures<String>> {
+        self.as_ref().items_features(items)
+    }
+
+    fn items_means(&self, items: &[DynEntity]) -> Result<Means<String>> {
+        self.as_ref().items_means(items)
+    }
+
+    fn global_mean(&self) -> Result<Option<f64>> {
+        self.as_ref().global_mean()
+    }
+
+    fn user_histogram(&self, user: &DynEntity) -> Result<Histogram> {
+        self.as_ref().user_histogram(user)
+    }
+
+    fn item_histogram(&self, item: &DynEntity) -> Result<Histogram> {
+        self.as_ref().item_histogram(item)
+    }
+
+    fn most_rated_items(&self, limit: usize) -> Result<Vec<(String, usize)>> {
+        self.as_ref().most_rated_items(limit)
+    }
+
+    fn score_range(&self) -> (f64, f64) {
+        self.as_ref().score_range()
+    }
+
+    fn rating_scale(&self) -> RatingScale {
+        self.as_ref().rating_scale()
+    }
+
+    fn rating_kind(&self, score: f64) -> RatingKind {
+        self.as_ref().rating_kind(score)
+    }
+
+    fn check_score(&self, score: f64) -> Result<()> {
+        self.as_ref().check_score(score)
+    }
+
+    fn fields_for_users(&self) -> Vec<Field<'_>> {
+        self.as_ref().fields_for_users()
+    }
+
+    fn fields_for_items(&self) -> Vec<Field<'_>> {
+        self.as_ref().fields_for_items()
+    }
+
+    fn custom_keys_for_users(&self) -> Vec<String> {
+        self.as_ref().custom_keys_for_users()
+    }
+
+    fn custom_keys_for_items(&self) -> Vec<String> {
+        self.as_ref().custom_keys_for_items()
+    }
+
+    fn insert_user(&self, proto: HashMap<&str, Value>) -> Result<DynEntity> {
+        self.as_ref().insert_user(proto)
+    }
+
+    fn insert_item(&self, proto: HashMap<&str, Value>) -> Result<DynEntity> {
+        self.as_ref().insert_item(proto)
+    }
+
+    fn update_user(&self, user_id: &String, proto: HashMap<&str, Value>) -> Result<DynEntity> {
+        self.as_ref().update_user(user_id, proto)
+    }
+
+    fn update_item(&self, item_id: &String, proto: HashMap<&str, Value>) -> Result<DynEntity> {
+        self.as_ref().update_item(item_id, proto)
+    }
+
+    fn remove_user(&self, user_id: &String) -> Result<DynEntity> {
+        self.as_ref().remove_user(user_id)
+    }
+
+    fn remove_item(&self, item_id: &String) -> Result<DynEntity> {
+        self.as_ref().remove_item(item_id)
+    }
+
+    fn insert_rating(&self, user_id: &String, item_id: &String, score: f64) -> Result<DynEntity> {
+        self.as_ref().insert_rating(user_id, item_id, score)
+    }
+
+    fn remove_rating(&self, user_id: &String, item_id: &String) -> Result<DynEntity> {
+        self.as_ref().remove_rating(user_id, item_id)
+    }
+
+    fn update_rating(&self, user_id: &String, item_id: &String, score: f64) -> Result<DynEntity> {
+        self.as_ref().update_rating(user_id, item_id, score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        memory::{MemoryItem, MemoryUser},
+        MemoryController,
+    };
+    use assert_approx_eq::*;
+
+    fn controller() -> Box<dyn DynController> {
+        let controller: MemoryController = MemoryController::new((1., 5.));
+
+        controller.add_user(MemoryUser::with_name(1, "Patrick C"));
+        controller.add_item(MemoryItem::with_name(10, "Alien"));
+        controller.add_rating(1, 10, 4.);
+        controller.add_rating(1, 20, 2.);
+        controller.add_rating(2, 10, 5.);
+
+        DynAdapter::boxed(controller)
+    }
+
+    #[test]
+    fn erased_queries() -> Result<()> {
+        let controller = controller();
+
+        let users = controller.users_by(&SearchBy::name("Patrick C"))?;
+        assert_eq!(users[0].id, "1");
+        assert_eq!(users[0].data["name"], Value::from("Patrick C"));
+
+        let ratings = controller.user_ratings(&DynEntity::new("1"))?;
+        assert_approx_eq!(ratings["20"], 2.);
+
+        let users_who_rated = controller.users_who_rated(&[DynEntity::new("10")])?;
+        assert_eq!(users_who_rated["10"].len(), 2);
+
+        let ids: Vec<_> = controller
+            .users_by_chunks(1)
+            .map(|chunk| chunk.unwrap()[0].id.clone())
+            .collect();
+        assert_eq!(ids, vec!["1", "2"]);
+
+        assert!(controller.user_ratings(&DynEntity::new("x")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn erased_writes() -> Result<()> {
+        let controller = controller();
+
+        let rating = controller.insert_rating(&"2".to_string(), &"20".to_string(), 1.)?;
+        assert_eq!(rating.data["user_id"], Value::from(2));
+
+        let means = controller.users_means(&[DynEntity::new("2")])?;
+        assert_approx_eq!(means["2"], 3.);
+
+        controller.remove_rating(&"2".to_string(), &"20".to_string())?;
+        assert_eq!(controller.ratings_count()?, 3);
+
+        let user = controller.remove_user(&"1".to_string())?;
+        assert_eq!(user.data["name"], Value::from("Patrick C"));
+        assert_eq!(controller.ratings_count()?, 1);
+        assert!(controller.remove_item(&"x".to_string()).is_err());
+
+        Ok(())
+    }
+}
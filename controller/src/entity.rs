@@ -3,16 +3,62 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
+use crate::Value;
+use indexmap::IndexMap;
 use prettytable::{cell, format::consts::FORMAT_NO_LINESEP, row, table, Table};
+use serde::{ser::SerializeMap, Serialize, Serializer};
 use std::collections::HashMap;
 
+/// Typed attributes of an entity, they keep the order they were inserted in
+pub type Data = IndexMap<String, Value>;
+
+/// Build the `Data` of an entity, anything convertible to a `Value` is accepted
+#[macro_export]
+macro_rules! data {
+    ($($key:expr => $val:expr),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut data = $crate::entity::Data::new();
+        $(data.insert($key.into(), $crate::Value::from($val));)*
+        data
+    }};
+}
+
 pub trait Entity {
     type Id;
 
     fn get_id(&self) -> Self::Id;
-    fn get_data(&self) -> HashMap<String, String> {
+    fn get_data(&self) -> Data {
         Default::default()
     }
+
+    /// A serializable view of this entity, a map with its id followed by its data
+    fn to_record(&self) -> Record<'_, Self>
+    where
+        Self: Sized,
+    {
+        Record(self)
+    }
+}
+
+/// Serializable view of an entity, see `Entity::to_record`
+pub struct Record<'a, E>(pub &'a E);
+
+impl<'a, E> Serialize for Record<'a, E>
+where
+    E: Entity,
+    E::Id: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data = self.0.get_data();
+        let mut map = serializer.serialize_map(Some(data.len() + 1))?;
+
+        map.serialize_entry("id", &self.0.get_id())?;
+        for (key, val) in &data {
+            map.serialize_entry(key, val)?;
+        }
+
+        map.end()
+    }
 }
 
 pub trait ToTable {
@@ -16,6 +16,12 @@ pub enum ErrorKind {
     #[error("Couldn't found entity with {0}({1})")]
     NotFoundByCustom(String, String),
 
+    #[error("Searching by {0} is not supported")]
+    CustomSearchNotSupported(String),
+
+    #[error("Updating field {0} is not supported")]
+    UpdateNotSupported(String),
+
     #[error("Controller function not implemented")]
     NotImplemented,
 
@@ -28,6 +34,15 @@ pub enum ErrorKind {
     #[error("Couldn't cast value to {0}")]
     CastingValue(&'static str),
 
+    #[error("Invalid value for field {0}: {1}")]
+    InvalidField(String, String),
+
+    #[error("Score {0} is out of the range [{1}, {2}]")]
+    ScoreOutOfRange(f64, f64, f64),
+
+    #[error("Score {0} is not on the rating scale, scores go by steps of {1}")]
+    ScoreOffScale(f64, f64),
+
     #[error("Couldn't update rating for user({0}) on item({1})")]
     UpdateRatingFailed(String, String),
 
@@ -37,6 +52,21 @@ pub enum ErrorKind {
     #[error("Couldn't remove rating for user({0}) on item({1})")]
     RemoveRatingFailed(String, String),
 
+    #[error("An entity with id({0}) already exists")]
+    DuplicatedId(String),
+
+    #[error("Couldn't find column {0} in csv file")]
+    CsvColumnNotFound(String),
+
+    #[error("Malformed record at line {1} of {0}: {2}")]
+    MalformedCsvRecord(String, u64, String),
+
+    #[error("Mongo isn't available for this controller")]
+    MongoUnavailable,
+
+    #[error("Unknown controller kind {0} for a database entry")]
+    UnknownControllerKind(String),
+
     #[error("Database config not found for {0}")]
     DbConfigError(String),
 }
@@ -0,0 +1,194 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A content feature of an item, categorical features are one-hot encoded as
+/// `name=value` while numeric ones keep their value under `name`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Feature {
+    Categorical(String, String),
+    Numeric(String, f64),
+}
+
+impl Feature {
+    pub fn categorical(name: &str, value: &str) -> Self {
+        Self::Categorical(name.into(), value.into())
+    }
+
+    pub fn numeric(name: &str, value: f64) -> Self {
+        Self::Numeric(name.into(), value)
+    }
+
+    /// Name of this feature within the vocabulary
+    pub fn key(&self) -> String {
+        match self {
+            Feature::Categorical(name, value) => format!("{}={}", name, value),
+            Feature::Numeric(name, _) => name.clone(),
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        match self {
+            Feature::Categorical(_, _) => 1.,
+            Feature::Numeric(_, value) => *value,
+        }
+    }
+
+    /// A year as the categorical feature of its decade, ex. 1979 is `decade=1970s`,
+    /// so it weighs as much as any other one-hot feature instead of dwarfing them
+    pub fn decade(year: i32) -> Self {
+        Self::categorical("decade", &format!("{}s", year - year.rem_euclid(10)))
+    }
+
+    /// Lowercase words of a text as categorical features, ex. words of a title
+    pub fn words(name: &str, text: &str) -> Vec<Self> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| Self::categorical(name, &word.to_lowercase()))
+            .collect()
+    }
+}
+
+/// Sparse feature vector, index in the vocabulary => value
+pub type FeatureVector = HashMap<usize, f64>;
+
+/// Feature vectors of some items along with the vocabulary they're built on,
+/// the vocabulary only holds features seen on those items
+#[derive(Debug, Clone)]
+pub struct ItemFeatures<K> {
+    vocabulary: Vec<String>,
+    indices: HashMap<String, usize>,
+    vectors: HashMap<K, FeatureVector>,
+}
+
+impl<K> ItemFeatures<K> {
+    pub fn vocabulary(&self) -> &[String] {
+        &self.vocabulary
+    }
+
+    pub fn index_of(&self, feature: &str) -> Option<usize> {
+        self.indices.get(feature).copied()
+    }
+
+    pub fn vectors(&self) -> &HashMap<K, FeatureVector> {
+        &self.vectors
+    }
+
+    /// Number of items with features
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+}
+
+impl<K: Hash + Eq> Default for ItemFeatures<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq> ItemFeatures<K> {
+    pub fn new() -> Self {
+        Self {
+            vocabulary: Vec::new(),
+            indices: HashMap::new(),
+            vectors: HashMap::new(),
+        }
+    }
+
+    /// Add the features of an item, new features are appended to the vocabulary
+    pub fn add(&mut self, item_id: K, features: Vec<Feature>) {
+        let mut vector = FeatureVector::new();
+
+        for feature in features {
+            let key = feature.key();
+            let index = match self.indices.get(&key) {
+                Some(index) => *index,
+                None => {
+                    self.vocabulary.push(key.clone());
+                    self.indices.insert(key, self.vocabulary.len() - 1);
+                    self.vocabulary.len() - 1
+                }
+            };
+
+            vector.insert(index, feature.value());
+        }
+
+        self.vectors.insert(item_id, vector);
+    }
+
+    pub fn vector(&self, item_id: &K) -> Option<&FeatureVector> {
+        self.vectors.get(item_id)
+    }
+
+    /// Dense version of the vector of an item, as long as the vocabulary
+    pub fn dense(&self, item_id: &K) -> Option<Vec<f64>> {
+        let vector = self.vectors.get(item_id)?;
+        let mut dense = vec![0.; self.vocabulary.len()];
+        for (index, value) in vector {
+            dense[*index] = *value;
+        }
+
+        Some(dense)
+    }
+
+    /// Change the type of the item ids, the vocabulary is kept as it is
+    pub fn map_ids<T, F>(self, f: F) -> ItemFeatures<T>
+    where
+        T: Hash + Eq,
+        F: Fn(K) -> T,
+    {
+        ItemFeatures {
+            vocabulary: self.vocabulary,
+            indices: self.indices,
+            vectors: self.vectors.into_iter().map(|(k, v)| (f(k), v)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_vocabulary() {
+        let mut features = ItemFeatures::new();
+        features.add(
+            1,
+            vec![
+                Feature::categorical("genre", "Horror"),
+                Feature::numeric("year", 1979.),
+            ],
+        );
+
+        features.add(
+            2,
+            vec![
+                Feature::categorical("genre", "Comedy"),
+                Feature::categorical("genre", "Horror"),
+            ],
+        );
+
+        assert_eq!(
+            features.vocabulary(),
+            &["genre=Horror", "year", "genre=Comedy"]
+        );
+        assert_eq!(features.dense(&1), Some(vec![1., 1979., 0.]));
+        assert_eq!(features.dense(&2), Some(vec![1., 0., 1.]));
+        assert_eq!(features.index_of("genre=Comedy"), Some(2));
+        assert!(features.vector(&3).is_none());
+
+        assert_eq!(Feature::decade(1979).key(), "decade=1970s");
+        assert_eq!(Feature::decade(2000).key(), "decade=2000s");
+
+        let words = Feature::words("word", "Alien: Resurrection");
+        assert_eq!(words[1], Feature::categorical("word", "resurrection"));
+    }
+}
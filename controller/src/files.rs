@@ -0,0 +1,307 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::{
+    error::ErrorKind,
+    memory::{parse_id, MemoryItem, MemoryUser},
+    MemoryController, Timestamp,
+};
+use anyhow::Error;
+use config::{Config, CsvColumn, CsvEntitiesFile, CsvEntry};
+use csv::{Position, Reader, ReaderBuilder, StringRecord};
+use std::{collections::HashMap, fmt::Display, fs::File, hash::Hash, str::FromStr};
+
+/// A controller that serves the dataset files directly, the files are described
+/// by a `[csv.*]` entry in the config and loaded in memory when connecting. Writes
+/// (inserts, updates and removals) only change the data in memory, the files are
+/// never written to, so they're lost once the controller is dropped.
+pub type CsvController<U = String, I = String> = MemoryController<U, I>;
+
+fn open_csv(path: &str, delimiter: char, has_headers: bool) -> Result<Reader<File>, Error> {
+    if !delimiter.is_ascii() {
+        return Err(
+            ErrorKind::ValueConvert(format!("Invalid csv delimiter '{}'", delimiter)).into(),
+        );
+    }
+
+    Ok(ReaderBuilder::new()
+        .has_headers(has_headers)
+        .delimiter(delimiter as u8)
+        .from_path(path)?)
+}
+
+fn column_index(column: &CsvColumn, headers: Option<&StringRecord>) -> Result<usize, ErrorKind> {
+    match column {
+        CsvColumn::Index(index) => Ok(*index),
+        CsvColumn::Name(name) => headers
+            .and_then(|headers| headers.iter().position(|header| header == name))
+            .ok_or_else(|| ErrorKind::CsvColumnNotFound(name.clone())),
+    }
+}
+
+/// Records of a csv file along with their line, a record that can't be read fails
+/// the whole load, as does one whose fields can't be parsed (see `malformed`)
+pub(crate) fn records<'a>(
+    csv: &'a mut Reader<File>,
+    path: &'a str,
+) -> impl Iterator<Item = Result<(u64, StringRecord), ErrorKind>> + 'a {
+    csv.records().map(move |record| match record {
+        Ok(record) => Ok((record.position().map_or(0, Position::line), record)),
+        Err(e) => {
+            let line = e.position().map_or(0, Position::line);
+            Err(malformed(path, line, e))
+        }
+    })
+}
+
+pub(crate) fn malformed(path: &str, line: u64, reason: impl Display) -> ErrorKind {
+    ErrorKind::MalformedCsvRecord(path.into(), line, reason.to_string())
+}
+
+pub(crate) fn field(record: &StringRecord, index: usize) -> Result<&str, ErrorKind> {
+    record
+        .get(index)
+        .map(str::trim)
+        .ok_or_else(|| ErrorKind::CsvColumnNotFound(index.to_string()))
+}
+
+struct EntityRow<K> {
+    id: K,
+    name: Option<String>,
+    data: HashMap<String, String>,
+}
+
+fn read_entities<K>(file: &CsvEntitiesFile) -> Result<Vec<EntityRow<K>>, Error>
+where
+    K: FromStr,
+    K::Err: Display,
+{
+    let mut csv = open_csv(&file.path, file.delimiter, file.has_headers)?;
+    let headers = if file.has_headers {
+        Some(csv.headers()?.clone())
+    } else {
+        None
+    };
+
+    let id_index = column_index(&file.id, headers.as_ref())?;
+    let name_index = file
+        .name
+        .as_ref()
+        .map(|name| column_index(name, headers.as_ref()))
+        .transpose()?;
+
+    let mut data_indices = Vec::new();
+    for (key, column) in &file.data {
+        data_indices.push((key, column_index(column, headers.as_ref())?));
+    }
+
+    let parse = |record: &StringRecord| -> Result<EntityRow<K>, Error> {
+        let id = parse_id(field(record, id_index)?)?;
+        let name = name_index
+            .map(|index| field(record, index).map(Into::into))
+            .transpose()?;
+
+        let mut data = HashMap::new();
+        for (key, index) in &data_indices {
+            data.insert(key.to_string(), field(record, *index)?.to_string());
+        }
+
+        Ok(EntityRow { id, name, data })
+    };
+
+    let mut rows = Vec::new();
+    for record in records(&mut csv, &file.path) {
+        let (line, record) = record?;
+        rows.push(parse(&record).map_err(|e| malformed(&file.path, line, e))?);
+    }
+
+    Ok(rows)
+}
+
+impl<U, I> MemoryController<U, I>
+where
+    U: Hash + Eq + Ord + Clone + FromStr,
+    I: Hash + Eq + Ord + Clone + FromStr,
+    U::Err: Display,
+    I::Err: Display,
+{
+    /// Load the csv dataset named `name` in the config
+    pub fn from_config(config: &Config, name: &str) -> Result<Self, Error> {
+        let entry = config
+            .csv
+            .get(name)
+            .ok_or_else(|| ErrorKind::DbConfigError(name.into()))?;
+
+        Self::from_csv_entry(entry)
+    }
+
+    /// Load a csv dataset, if users or items files are given the ratings of unknown
+    /// users or items are skipped, otherwise they're created from the ratings
+    pub fn from_csv_entry(entry: &CsvEntry) -> Result<Self, Error> {
+        let mut controller = Self::new(entry.score_range);
+        if let Some(step) = entry.score_step {
+            controller = controller.with_score_step(step);
+        }
+
+        if let Some(users) = &entry.users {
+            for row in read_entities(users)? {
+                controller.add_user(MemoryUser {
+                    id: row.id,
+                    name: row.name,
+                    data: row.data,
+                });
+            }
+        }
+
+        if let Some(items) = &entry.items {
+            for row in read_entities(items)? {
+                controller.add_item(MemoryItem {
+                    id: row.id,
+                    name: row.name,
+                    data: row.data,
+                });
+            }
+        }
+
+        let file = &entry.ratings;
+        let mut csv = open_csv(&file.path, file.delimiter, file.has_headers)?;
+        let headers = if file.has_headers {
+            Some(csv.headers()?.clone())
+        } else {
+            None
+        };
+
+        let user_index = column_index(&file.user_id, headers.as_ref())?;
+        let item_index = column_index(&file.item_id, headers.as_ref())?;
+        let score_index = column_index(&file.score, headers.as_ref())?;
+        let time_index = file
+            .time
+            .as_ref()
+            .map(|time| column_index(time, headers.as_ref()))
+            .transpose()?;
+
+        let parse = |record: &StringRecord| -> Result<(U, I, f64, Option<Timestamp>), Error> {
+            let user_id = parse_id(field(record, user_index)?)?;
+            let item_id = parse_id(field(record, item_index)?)?;
+            let score = field(record, score_index)?.parse()?;
+            let time = match time_index {
+                Some(index) => Some(field(record, index)?.parse()?),
+                None => None,
+            };
+
+            Ok((user_id, item_id, score, time))
+        };
+
+        for record in records(&mut csv, &file.path) {
+            let (line, record) = record?;
+            let (user_id, item_id, score, time) =
+                parse(&record).map_err(|e| malformed(&file.path, line, e))?;
+
+            let unknown_user = entry.users.is_some() && !controller.has_user(&user_id);
+            let unknown_item = entry.items.is_some() && !controller.has_item(&item_id);
+
+            if unknown_user || unknown_item {
+                continue;
+            }
+
+            controller.add_timed_rating(user_id, item_id, score, time);
+        }
+
+        Ok(controller)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Controller, Entity, SearchBy};
+    use anyhow::Error;
+
+    fn movie_lens_small() -> CsvEntry {
+        CsvEntry {
+            score_range: (0.5, 5.),
+            score_step: Some(0.5),
+            ratings: config::CsvRatingsFile {
+                path: "../controllers/movie-lens-small/data/ratings.csv".into(),
+                delimiter: ',',
+                has_headers: true,
+                user_id: CsvColumn::Name("userId".into()),
+                item_id: CsvColumn::Name("movieId".into()),
+                score: CsvColumn::Index(2),
+                time: Some(CsvColumn::Name("timestamp".into())),
+            },
+            users: None,
+            items: Some(CsvEntitiesFile {
+                path: "../controllers/movie-lens-small/data/movies.csv".into(),
+                delimiter: ',',
+                has_headers: true,
+                id: CsvColumn::Index(0),
+                name: Some(CsvColumn::Name("title".into())),
+                data: vec![("genres".to_string(), CsvColumn::Index(2))]
+                    .into_iter()
+                    .collect(),
+            }),
+        }
+    }
+
+    #[test]
+    fn load_movie_lens_small() -> Result<(), Error> {
+        let controller: CsvController = CsvController::from_csv_entry(&movie_lens_small())?;
+
+        let items = controller.items_by(&SearchBy::name("Suture (1993)"))?;
+        assert_eq!(items.len(), 1);
+        assert!(items[0].get_data()["genres"]
+            .to_string()
+            .contains("Film-Noir"));
+
+        let users = controller.users_by(&SearchBy::id("2"))?;
+        assert_eq!(controller.user_ratings(&users[0])?.len(), 29);
+
+        let ratings = controller.user_timed_ratings(&users[0])?;
+        assert_eq!(ratings["318"].time, Some(1_445_714_835));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_column() {
+        let mut entry = movie_lens_small();
+        entry.ratings.score = CsvColumn::Name("score".into());
+
+        let controller: Result<CsvController, _> = CsvController::from_csv_entry(&entry);
+        assert!(controller.is_err());
+    }
+
+    #[test]
+    fn malformed_records() -> Result<(), Error> {
+        let write = |file: &str, contents: &str| -> Result<String, Error> {
+            let path = std::env::temp_dir().join(file);
+            std::fs::write(&path, contents)?;
+            Ok(path.to_string_lossy().into())
+        };
+
+        let mut entry = movie_lens_small();
+        entry.items = None;
+
+        let bad_score = "userId,movieId,rating,timestamp\n1,10,4.0,1\n1,20,four,2\n";
+        entry.ratings.path = write("csv-bad-score.csv", bad_score)?;
+        let controller: Result<CsvController, _> = CsvController::from_csv_entry(&entry);
+        assert!(controller.err().unwrap().to_string().contains("line 3"));
+
+        let short_record = "userId,movieId,rating,timestamp\n1,10,4.0,1\n1,20\n";
+        entry.ratings.path = write("csv-short-record.csv", short_record)?;
+        let controller: Result<CsvController, _> = CsvController::from_csv_entry(&entry);
+        assert!(controller.err().unwrap().to_string().contains("line 3"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_config() {
+        let config = Config::default();
+        let controller: Result<CsvController, _> = CsvController::from_config(&config, "nothing");
+        assert!(controller.is_err());
+    }
+}
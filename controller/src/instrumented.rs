@@ -0,0 +1,429 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::{
+    counts, eid, entity::ToTable, maped_ratings, means, ratings, Controller, Entity, Field,
+    Histogram, ItemFeatures, MapedRatings, RatingKind, RatingScale, Result, SearchBy, TimedScore,
+    Timestamp, Value,
+};
+use anyhow::Error;
+use prettytable::{cell, format::consts::FORMAT_NO_LINESEP, row, Table};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// What has been recorded for a single controller method
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MethodStats {
+    pub calls: usize,
+    pub errors: usize,
+    pub rows: usize,
+    pub total_time: Duration,
+    pub max_time: Duration,
+}
+
+impl MethodStats {
+    pub fn mean_time(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::default()
+        } else {
+            self.total_time / self.calls as u32
+        }
+    }
+}
+
+/// Snapshot of the stats of every called method, ordered by method name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ControllerStats(pub BTreeMap<&'static str, MethodStats>);
+
+impl ControllerStats {
+    /// Write the stats as csv, one row per method (times are in seconds)
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut writer = csv::Writer::from_path(path)?;
+        writer.write_record(["method", "calls", "errors", "rows", "total", "mean", "max"])?;
+
+        for (method, stats) in &self.0 {
+            writer.write_record(&[
+                method.to_string(),
+                stats.calls.to_string(),
+                stats.errors.to_string(),
+                stats.rows.to_string(),
+                stats.total_time.as_secs_f64().to_string(),
+                stats.mean_time().as_secs_f64().to_string(),
+                stats.max_time.as_secs_f64().to_string(),
+            ])?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl ToTable for ControllerStats {
+    fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        table.add_row(row![
+            "method", "calls", "errors", "rows", "total", "mean", "max"
+        ]);
+
+        for (method, stats) in &self.0 {
+            table.add_row(row![
+                method,
+                stats.calls,
+                stats.errors,
+                stats.rows,
+                format!("{:.4}", stats.total_time.as_secs_f64()),
+                format!("{:.4}", stats.mean_time().as_secs_f64()),
+                format!("{:.4}", stats.max_time.as_secs_f64()),
+            ]);
+        }
+
+        table.set_format(*FORMAT_NO_LINESEP);
+        table
+    }
+}
+
+fn maped_len<K, I, V>(maped: &MapedRatings<K, I, V>) -> usize {
+    maped.values().map(HashMap::len).sum()
+}
+
+fn one<T>(_: &T) -> usize {
+    1
+}
+
+/// Decorator that records how many times every method is called, how long it
+/// takes and how many rows (entities, ratings or means) it returns.
+pub struct InstrumentedController<C> {
+    controller: C,
+    stats: RefCell<BTreeMap<&'static str, MethodStats>>,
+}
+
+impl<C> InstrumentedController<C> {
+    pub fn new(controller: C) -> Self {
+        Self {
+            controller,
+            stats: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.controller
+    }
+
+    pub fn stats(&self) -> ControllerStats {
+        ControllerStats(self.stats.borrow().clone())
+    }
+
+    pub fn reset_stats(&self) {
+        self.stats.borrow_mut().clear();
+    }
+
+    fn record<T>(
+        &self,
+        method: &'static str,
+        rows: fn(&T) -> usize,
+        call: impl FnOnce() -> std::result::Result<T, Error>,
+    ) -> Result<T> {
+        let now = Instant::now();
+        let result = call();
+        let elapsed = now.elapsed();
+
+        let mut stats = self.stats.borrow_mut();
+        let stats = stats.entry(method).or_default();
+        stats.calls += 1;
+        stats.total_time += elapsed;
+        stats.max_time = stats.max_time.max(elapsed);
+
+        match &result {
+            Ok(value) => stats.rows += rows(value),
+            Err(_) => stats.errors += 1,
+        }
+
+        result
+    }
+}
+
+impl<C, U, I, R> Controller for InstrumentedController<C>
+where
+    C: Controller<User = U, Item = I, Rating = R>,
+    U: Entity,
+    I: Entity,
+    R: Entity,
+{
+    type User = U;
+    type Item = I;
+    type Rating = R;
+
+    fn users(&self) -> Result<Vec<U>> {
+        self.record("users", Vec::len, || self.controller.users())
+    }
+
+    fn users_by(&self, by: &SearchBy) -> Result<Vec<U>> {
+        self.record("users_by", Vec::len, || self.controller.users_by(by))
+    }
+
+    fn users_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<U>> {
+        self.record("users_offset_limit", Vec::len, || {
+            self.controller.users_offset_limit(offset, limit)
+        })
+    }
+
+    fn users_after(&self, after: Option<&eid!(U)>, limit: usize) -> Result<Vec<U>> {
+        self.record("users_after", Vec::len, || {
+            self.controller.users_after(after, limit)
+        })
+    }
+
+    fn items(&self) -> Result<Vec<I>> {
+        self.record("items", Vec::len, || self.controller.items())
+    }
+
+    fn items_by(&self, by: &SearchBy) -> Result<Vec<I>> {
+        self.record("items_by", Vec::len, || self.controller.items_by(by))
+    }
+
+    fn items_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<I>> {
+        self.record("items_offset_limit", Vec::len, || {
+            self.controller.items_offset_limit(offset, limit)
+        })
+    }
+
+    fn items_after(&self, after: Option<&eid!(I)>, limit: usize) -> Result<Vec<I>> {
+        self.record("items_after", Vec::len, || {
+            self.controller.items_after(after, limit)
+        })
+    }
+
+    fn create_partial_users(&self, user_ids: &[eid!(U)]) -> Result<Vec<U>> {
+        self.record("create_partial_users", Vec::len, || {
+            self.controller.create_partial_users(user_ids)
+        })
+    }
+
+    fn create_partial_items(&self, item_ids: &[eid!(I)]) -> Result<Vec<I>> {
+        self.record("create_partial_items", Vec::len, || {
+            self.controller.create_partial_items(item_ids)
+        })
+    }
+
+    fn users_who_rated(&self, items: &[I]) -> Result<maped_ratings!(I => U)> {
+        self.record("users_who_rated", maped_len, || {
+            self.controller.users_who_rated(items)
+        })
+    }
+
+    fn user_ratings(&self, user: &U) -> Result<ratings!(I)> {
+        self.record("user_ratings", HashMap::len, || {
+            self.controller.user_ratings(user)
+        })
+    }
+
+    fn user_timed_ratings(&self, user: &U) -> Result<ratings!(I, TimedScore)> {
+        self.record("user_timed_ratings", HashMap::len, || {
+            self.controller.user_timed_ratings(user)
+        })
+    }
+
+    fn users_ratings_since(&self, since: Timestamp) -> Result<maped_ratings!(U => I, TimedScore)> {
+        self.record("users_ratings_since", maped_len, || {
+            self.controller.users_ratings_since(since)
+        })
+    }
+
+    fn all_users_ratings(&self) -> Result<maped_ratings!(U => I)> {
+        self.record("all_users_ratings", maped_len, || {
+            self.controller.all_users_ratings()
+        })
+    }
+
+    fn users_ratings(&self, users: &[U]) -> Result<maped_ratings!(U => I)> {
+        self.record("users_ratings", maped_len, || {
+            self.controller.users_ratings(users)
+        })
+    }
+
+    fn users_ratings_except(&self, user: &U) -> Result<maped_ratings!(U => I)> {
+        self.record("users_ratings_except", maped_len, || {
+            self.controller.users_ratings_except(user)
+        })
+    }
+
+    fn users_means(&self, users: &[U]) -> Result<means!(U)> {
+        self.record("users_means", HashMap::len, || {
+            self.controller.users_means(users)
+        })
+    }
+
+    fn users_count(&self) -> Result<usize> {
+        self.record("users_count", one, || self.controller.users_count())
+    }
+
+    fn items_count(&self) -> Result<usize> {
+        self.record("items_count", one, || self.controller.items_count())
+    }
+
+    fn ratings_count(&self) -> Result<usize> {
+        self.record("ratings_count", one, || self.controller.ratings_count())
+    }
+
+    fn users_ratings_count(&self, users: &[U]) -> Result<counts!(U)> {
+        self.record("users_ratings_count", HashMap::len, || {
+            self.controller.users_ratings_count(users)
+        })
+    }
+
+    fn items_ratings_count(&self, items: &[I]) -> Result<counts!(I)> {
+        self.record("items_ratings_count", HashMap::len, || {
+            self.controller.items_ratings_count(items)
+        })
+    }
+
+    fn items_means(&self, items: &[I]) -> Result<means!(I)> {
+        self.record("items_means", HashMap::len, || {
+            self.controller.items_means(items)
+        })
+    }
+
+    fn global_mean(&self) -> Result<Option<f64>> {
+        self.record("global_mean", one, || self.controller.global_mean())
+    }
+
+    fn user_histogram(&self, user: &U) -> Result<Histogram> {
+        self.record("user_histogram", Vec::len, || {
+            self.controller.user_histogram(user)
+        })
+    }
+
+    fn item_histogram(&self, item: &I) -> Result<Histogram> {
+        self.record("item_histogram", Vec::len, || {
+            self.controller.item_histogram(item)
+        })
+    }
+
+    fn most_rated_items(&self, limit: usize) -> Result<Vec<(eid!(I), usize)>> {
+        self.record("most_rated_items", Vec::len, || {
+            self.controller.most_rated_items(limit)
+        })
+    }
+
+    fn items_features(&self, items: &[I]) -> Result<ItemFeatures<eid!(I)>> {
+        self.record("items_features", ItemFeatures::len, || {
+            self.controller.items_features(items)
+        })
+    }
+
+    fn score_range(&self) -> (f64, f64) {
+        self.controller.score_range()
+    }
+
+    fn rating_scale(&self) -> RatingScale {
+        self.controller.rating_scale()
+    }
+
+    fn rating_kind(&self, score: f64) -> RatingKind {
+        self.controller.rating_kind(score)
+    }
+
+    fn check_score(&self, score: f64) -> Result<()> {
+        self.controller.check_score(score)
+    }
+
+    fn fields_for_users(&self) -> Vec<Field<'_>> {
+        self.controller.fields_for_users()
+    }
+
+    fn fields_for_items(&self) -> Vec<Field<'_>> {
+        self.controller.fields_for_items()
+    }
+
+    fn custom_keys_for_users(&self) -> Vec<String> {
+        self.controller.custom_keys_for_users()
+    }
+
+    fn custom_keys_for_items(&self) -> Vec<String> {
+        self.controller.custom_keys_for_items()
+    }
+
+    fn insert_user(&self, proto: HashMap<&str, Value>) -> Result<U> {
+        self.record("insert_user", one, || self.controller.insert_user(proto))
+    }
+
+    fn insert_item(&self, proto: HashMap<&str, Value>) -> Result<I> {
+        self.record("insert_item", one, || self.controller.insert_item(proto))
+    }
+
+    fn update_user(&self, user_id: &eid!(U), proto: HashMap<&str, Value>) -> Result<U> {
+        self.record("update_user", one, || {
+            self.controller.update_user(user_id, proto)
+        })
+    }
+
+    fn update_item(&self, item_id: &eid!(I), proto: HashMap<&str, Value>) -> Result<I> {
+        self.record("update_item", one, || {
+            self.controller.update_item(item_id, proto)
+        })
+    }
+
+    fn remove_user(&self, user_id: &eid!(U)) -> Result<U> {
+        self.record("remove_user", one, || self.controller.remove_user(user_id))
+    }
+
+    fn remove_item(&self, item_id: &eid!(I)) -> Result<I> {
+        self.record("remove_item", one, || self.controller.remove_item(item_id))
+    }
+
+    fn insert_rating(&self, user_id: &eid!(U), item_id: &eid!(I), score: f64) -> Result<R> {
+        self.record("insert_rating", one, || {
+            self.controller.insert_rating(user_id, item_id, score)
+        })
+    }
+
+    fn remove_rating(&self, user_id: &eid!(U), item_id: &eid!(I)) -> Result<R> {
+        self.record("remove_rating", one, || {
+            self.controller.remove_rating(user_id, item_id)
+        })
+    }
+
+    fn update_rating(&self, user_id: &eid!(U), item_id: &eid!(I), score: f64) -> Result<R> {
+        self.record("update_rating", one, || {
+            self.controller.update_rating(user_id, item_id, score)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryController;
+
+    #[test]
+    fn records_calls_and_rows() -> Result<()> {
+        let controller = MemoryController::new((1., 5.));
+        controller.add_rating(1, 10, 4.);
+        controller.add_rating(1, 20, 2.);
+        controller.add_rating(2, 10, 5.);
+
+        let controller = InstrumentedController::new(controller);
+        let users = controller.users()?;
+        controller.users_ratings(&users)?;
+        controller.users_ratings(&users[..1])?;
+        assert!(controller.insert_rating(&1, &10, 3.).is_err());
+
+        let stats = controller.stats();
+        assert_eq!(stats.0["users"].rows, 2);
+        assert_eq!(stats.0["users_ratings"].calls, 2);
+        assert_eq!(stats.0["users_ratings"].rows, 5);
+        assert_eq!(stats.0["insert_rating"].errors, 1);
+        assert!(!stats.0.contains_key("items"));
+
+        controller.reset_stats();
+        assert!(controller.stats().0.is_empty());
+
+        Ok(())
+    }
+}
@@ -4,15 +4,25 @@
 // https://opensource.org/licenses/MIT
 
 use crate::{Controller, Entity};
+use anyhow::Error;
 
+/// Keyset-paginated chunks of users, every chunk resumes from the last id
+/// seen in the previous one so the order is stable (ascending ids).
+///
+/// `nth` is absolute (the n-th chunk from the start), the last id of every
+/// visited chunk is remembered so jumping back to a known chunk is cheap.
+///
+/// A failing query is yielded as an error (the cursor isn't advanced), so a
+/// scan never looks complete when it isn't.
 pub struct LazyUserChunks<'a, C, U>
 where
     C: Controller<User = U>,
     U: Entity,
 {
-    pub(crate) curr_offset: usize,
     pub(crate) chunk_size: usize,
     pub(crate) controller: &'a C,
+    pub(crate) cursors: Vec<U::Id>,
+    pub(crate) curr_chunk: usize,
 }
 
 impl<'a, C, U> Iterator for LazyUserChunks<'a, C, U>
@@ -20,41 +30,50 @@ where
     C: Controller<User = U>,
     U: Entity,
 {
-    type Item = Vec<U>;
+    type Item = Result<Vec<U>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let users = self
-            .controller
-            .users_offset_limit(self.curr_offset, self.chunk_size)
-            .ok();
-
-        self.curr_offset += self.chunk_size;
-        match users {
-            Some(users) => {
-                if users.is_empty() {
-                    None
-                } else {
-                    Some(users)
-                }
-            }
-            None => None,
+        let after = match self.curr_chunk {
+            0 => None,
+            n => Some(self.cursors.get(n - 1)?),
+        };
+
+        let users = match self.controller.users_after(after, self.chunk_size) {
+            Ok(users) => users,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let last = users.last()?.get_id();
+        if self.cursors.len() == self.curr_chunk {
+            self.cursors.push(last);
         }
+
+        self.curr_chunk += 1;
+        Some(Ok(users))
     }
 
     fn nth(&mut self, n: usize) -> Option<Self::Item> {
-        self.curr_offset = n * self.chunk_size;
+        self.curr_chunk = self.cursors.len().min(n);
+        while self.curr_chunk < n {
+            if let Err(e) = self.next()? {
+                return Some(Err(e));
+            }
+        }
+
         self.next()
     }
 }
 
+/// Keyset-paginated chunks of items, see [`LazyUserChunks`].
 pub struct LazyItemChunks<'a, C, I>
 where
     C: Controller<Item = I>,
     I: Entity,
 {
-    pub(crate) curr_offset: usize,
     pub(crate) chunk_size: usize,
     pub(crate) controller: &'a C,
+    pub(crate) cursors: Vec<I::Id>,
+    pub(crate) curr_chunk: usize,
 }
 
 impl<'a, C, I> Iterator for LazyItemChunks<'a, C, I>
@@ -62,29 +81,36 @@ where
     C: Controller<Item = I>,
     I: Entity,
 {
-    type Item = Vec<I>;
+    type Item = Result<Vec<I>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let items = self
-            .controller
-            .items_offset_limit(self.curr_offset, self.chunk_size)
-            .ok();
-
-        self.curr_offset += self.chunk_size;
-        match items {
-            Some(items) => {
-                if items.is_empty() {
-                    None
-                } else {
-                    Some(items)
-                }
-            }
-            None => None,
+        let after = match self.curr_chunk {
+            0 => None,
+            n => Some(self.cursors.get(n - 1)?),
+        };
+
+        let items = match self.controller.items_after(after, self.chunk_size) {
+            Ok(items) => items,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let last = items.last()?.get_id();
+        if self.cursors.len() == self.curr_chunk {
+            self.cursors.push(last);
         }
+
+        self.curr_chunk += 1;
+        Some(Ok(items))
     }
 
     fn nth(&mut self, n: usize) -> Option<Self::Item> {
-        self.curr_offset = n * self.chunk_size;
+        self.curr_chunk = self.cursors.len().min(n);
+        while self.curr_chunk < n {
+            if let Err(e) = self.next()? {
+                return Some(Err(e));
+            }
+        }
+
         self.next()
     }
 }
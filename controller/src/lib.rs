@@ -3,9 +3,26 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
+#[cfg(feature = "diesel")]
+#[macro_use]
+extern crate diesel;
+
+#[cfg(feature = "diesel")]
+pub mod backend;
+pub mod cached;
+pub mod consistency;
+pub mod dynamic;
 pub mod entity;
 pub mod error;
+pub mod features;
+pub mod files;
+pub mod instrumented;
 pub mod lazy;
+pub mod memory;
+#[cfg(feature = "mongodb")]
+pub mod mirror;
+#[cfg(feature = "mongodb")]
+pub mod outbox;
 pub mod searchby;
 pub mod values;
 
@@ -21,13 +38,19 @@ macro_rules! maped_ratings {
     ($u:ty => $v:ty) => {
         $crate::MapedRatings<$crate::eid!($u), $crate::eid!($v)>
     };
+    ($u:ty => $v:ty, $s:ty) => {
+        $crate::MapedRatings<$crate::eid!($u), $crate::eid!($v), $s>
+    };
 }
 
 #[macro_export]
 macro_rules! ratings {
     ($e:ty) => {
         $crate::Ratings<$crate::eid!($e)>
-    }
+    };
+    ($e:ty, $s:ty) => {
+        $crate::Ratings<$crate::eid!($e), $s>
+    };
 }
 
 #[macro_export]
@@ -37,19 +60,171 @@ macro_rules! means {
     }
 }
 
-use anyhow::Error;
-use std::collections::HashMap;
+#[macro_export]
+macro_rules! counts {
+    ($e:ty) => {
+        $crate::Counts<$crate::eid!($e)>
+    }
+}
 
-pub use entity::{Entity, ToTable};
+use anyhow::Error;
+use error::ErrorKind;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "diesel")]
+pub use backend::DbConnection;
+pub use cached::CachedController;
+pub use consistency::{ConsistencyReport, Discrepancy, Drift};
+pub use dynamic::{DynAdapter, DynController, DynEntity};
+pub use entity::{Data, Entity, Record, ToTable};
+pub use features::{Feature, FeatureVector, ItemFeatures};
+pub use files::CsvController;
+pub use instrumented::{ControllerStats, InstrumentedController, MethodStats};
 pub use lazy::{LazyItemChunks, LazyUserChunks};
-pub use searchby::SearchBy;
+pub use memory::MemoryController;
+#[cfg(feature = "mongodb")]
+pub use mirror::MirroredRatings;
+#[cfg(feature = "mongodb")]
+pub use outbox::{RatingCollection, RatingEvent};
+pub use searchby::{NameMatch, SearchBy, DEFAULT_SEARCH_LIMIT};
 pub use values::{Field, Type, Value};
 
 pub type Result<T> = std::result::Result<T, Error>;
 pub type Means<K, Value = f64> = HashMap<K, Value>;
+pub type Counts<K> = HashMap<K, usize>;
 pub type Ratings<I, Value = f64> = HashMap<I, Value>;
 pub type MapedRatings<K, I, Value = f64> = HashMap<K, Ratings<I, Value>>;
 
+/// How many times each score was given, ordered by score
+pub type Histogram = Vec<(f64, usize)>;
+
+/// Build the histogram of some scores
+pub fn histogram(scores: impl IntoIterator<Item = f64>) -> Histogram {
+    let mut histogram: Histogram = Vec::new();
+    let mut scores: Vec<_> = scores.into_iter().collect();
+    scores.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+    for score in scores {
+        match histogram.last_mut() {
+            Some((last, count)) if *last == score => *count += 1,
+            _ => histogram.push((score, 1)),
+        }
+    }
+
+    histogram
+}
+
+/// Seconds since the unix epoch
+pub type Timestamp = i64;
+
+/// The current time, new and updated ratings are stamped with it
+pub fn now() -> Timestamp {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as Timestamp)
+}
+
+/// A score along with the time it was given, if it's known
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedScore {
+    pub score: f64,
+    pub time: Option<Timestamp>,
+}
+
+impl TimedScore {
+    pub fn new(score: f64, time: Option<Timestamp>) -> Self {
+        Self { score, time }
+    }
+}
+
+impl fmt::Display for TimedScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.time {
+            Some(time) => write!(f, "{} (at {})", self.score, time),
+            None => write!(f, "{}", self.score),
+        }
+    }
+}
+
+/// What a score stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RatingKind {
+    /// A score given by the user on the controller score range
+    Explicit,
+
+    /// An interaction (read, shelved, clicked) without a score from the user
+    Implicit,
+}
+
+/// Scores a controller accepts, discrete scales only accept the scores found
+/// every `step` from the min score (ex. 0.5, 1.0, 1.5... for 0.5 stars)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RatingScale {
+    pub min: f64,
+    pub max: f64,
+    pub step: Option<f64>,
+}
+
+impl RatingScale {
+    /// Any score within the range
+    pub fn continuous((min, max): (f64, f64)) -> Self {
+        Self {
+            min,
+            max,
+            step: None,
+        }
+    }
+
+    /// Only the scores every `step` within the range
+    pub fn discrete((min, max): (f64, f64), step: f64) -> Self {
+        Self {
+            min,
+            max,
+            step: Some(step),
+        }
+    }
+
+    pub fn range(&self) -> (f64, f64) {
+        (self.min, self.max)
+    }
+
+    /// Check that a score lies within the range and on a step of the scale
+    pub fn check(&self, score: f64) -> Result<()> {
+        if score.is_nan() || score < self.min || score > self.max {
+            return Err(ErrorKind::ScoreOutOfRange(score, self.min, self.max).into());
+        }
+
+        match self.step {
+            Some(step) if (self.snap(score) - score).abs() > 1e-9 => {
+                Err(ErrorKind::ScoreOffScale(score, step).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The closest score of the scale, ex. a prediction of 3.7 is a 3.5 on a scale
+    /// of 0.5 stars
+    pub fn snap(&self, score: f64) -> f64 {
+        let score = score.max(self.min).min(self.max);
+        match self.step {
+            Some(step) if step > 0. => {
+                let snapped = self.min + ((score - self.min) / step).round() * step;
+                if snapped > self.max {
+                    snapped - step
+                } else {
+                    snapped
+                }
+            }
+            _ => score,
+        }
+    }
+}
+
 pub trait Controller {
     type User: Entity;
     type Item: Entity;
@@ -61,18 +236,27 @@ pub trait Controller {
     /// Get users that matched the search criteria by id, name or custom (if implemented)
     fn users_by(&self, by: &SearchBy) -> Result<Vec<Self::User>>;
 
-    /// Get a chunk of users specified by certain offset and limit
+    /// Get a chunk of users specified by certain offset and limit, ordered by id
     fn users_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<Self::User>>;
 
+    /// Get a chunk of at most `limit` users with an id greater than `after` (or from the
+    /// first one if it's `None`), ordered by id
+    fn users_after(
+        &self,
+        after: Option<&eid!(Self::User)>,
+        limit: usize,
+    ) -> Result<Vec<Self::User>>;
+
     /// Build an iterator that returns all users by chunks
     fn users_by_chunks(&self, chunk_size: usize) -> LazyUserChunks<Self, Self::User>
     where
         Self: Sized,
     {
         LazyUserChunks {
-            curr_offset: 0,
             chunk_size,
             controller: self,
+            cursors: Vec::new(),
+            curr_chunk: 0,
         }
     }
 
@@ -82,18 +266,27 @@ pub trait Controller {
     /// Get items that matched the search criteria by id, name or custom (if implemented)
     fn items_by(&self, by: &SearchBy) -> Result<Vec<Self::Item>>;
 
-    /// Get a chunk of items specified by certain offset and limit
+    /// Get a chunk of items specified by certain offset and limit, ordered by id
     fn items_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<Self::Item>>;
 
+    /// Get a chunk of at most `limit` items with an id greater than `after` (or from the
+    /// first one if it's `None`), ordered by id
+    fn items_after(
+        &self,
+        after: Option<&eid!(Self::Item)>,
+        limit: usize,
+    ) -> Result<Vec<Self::Item>>;
+
     /// Build an iterator that returns all items by chunks
     fn items_by_chunks(&self, chunk_size: usize) -> LazyItemChunks<Self, Self::Item>
     where
         Self: Sized,
     {
         LazyItemChunks {
-            curr_offset: 0,
             chunk_size,
             controller: self,
+            cursors: Vec::new(),
+            curr_chunk: 0,
         }
     }
 
@@ -113,6 +306,17 @@ pub trait Controller {
     /// Get the ratings for the specified user
     fn user_ratings(&self, user: &Self::User) -> Result<ratings!(Self::Item)>;
 
+    /// Get the ratings for the specified user along with the time they were given
+    fn user_timed_ratings(&self, user: &Self::User) -> Result<ratings!(Self::Item, TimedScore)>;
+
+    /// Get the ratings given at `since` or later, i.e. maps User::Id => Item::Id,
+    /// ratings without a known time are left out
+    #[allow(clippy::type_complexity)]
+    fn users_ratings_since(
+        &self,
+        since: Timestamp,
+    ) -> Result<maped_ratings!(Self::User => Self::Item, TimedScore)>;
+
     /// Get all normal MapedRatings, i.e. maps User::Id => Item::Id
     #[allow(clippy::type_complexity)]
     fn all_users_ratings(&self) -> Result<maped_ratings!(Self::User => Self::Item)>;
@@ -134,22 +338,104 @@ pub trait Controller {
     /// Get means for the specified users, returns a map of User::Id => f64
     fn users_means(&self, users: &[Self::User]) -> Result<means!(Self::User)>;
 
+    /// Get the number of users
+    fn users_count(&self) -> Result<usize>;
+
+    /// Get the number of items
+    fn items_count(&self) -> Result<usize>;
+
+    /// Get the number of ratings
+    fn ratings_count(&self) -> Result<usize>;
+
+    /// Get how many ratings has each one of the specified users, returns a map of User::Id => usize
+    fn users_ratings_count(&self, users: &[Self::User]) -> Result<counts!(Self::User)>;
+
+    /// Get how many ratings has each one of the specified items, returns a map of Item::Id => usize
+    fn items_ratings_count(&self, items: &[Self::Item]) -> Result<counts!(Self::Item)>;
+
+    /// Get means for the specified items, returns a map of Item::Id => f64
+    fn items_means(&self, items: &[Self::Item]) -> Result<means!(Self::Item)>;
+
+    /// Get the mean of every rating, `None` if there are no ratings
+    fn global_mean(&self) -> Result<Option<f64>>;
+
+    /// Get how many times the specified user gave each score
+    fn user_histogram(&self, user: &Self::User) -> Result<Histogram>;
+
+    /// Get how many times the specified item was given each score
+    fn item_histogram(&self, item: &Self::Item) -> Result<Histogram>;
+
+    /// Get the `limit` most rated items along with their number of ratings, their
+    /// position is their popularity rank (ties are ordered by id)
+    fn most_rated_items(&self, limit: usize) -> Result<Vec<(eid!(Self::Item), usize)>>;
+
     /// The controller score range, ex. (0.0, 5.0) is (min_rating, max_rating)
     fn score_range(&self) -> (f64, f64);
 
+    /// The scores accepted by the controller, any score within the range by default
+    fn rating_scale(&self) -> RatingScale {
+        RatingScale::continuous(self.score_range())
+    }
+
+    /// The kind of rating a score stands for, every score is explicit unless the
+    /// dataset records other kinds of feedback with special scores
+    fn rating_kind(&self, _score: f64) -> RatingKind {
+        RatingKind::Explicit
+    }
+
+    /// Check that a score can be written before inserting or updating a rating,
+    /// by default it must be on the rating scale
+    fn check_score(&self, score: f64) -> Result<()> {
+        self.rating_scale().check(score)
+    }
+
+    /// Content features of the specified items, along with their vocabulary, unknown
+    /// items are left out. Only datasets that hold item content implement it
+    fn items_features(&self, _items: &[Self::Item]) -> Result<ItemFeatures<eid!(Self::Item)>> {
+        Err(ErrorKind::NotImplemented.into())
+    }
+
     /// Return a list of fields required to insert a new user
     fn fields_for_users(&self) -> Vec<Field>;
 
     /// Return a list of fields required to insert a new item
     fn fields_for_items(&self) -> Vec<Field>;
 
+    /// Return the keys users can be searched by with `SearchBy::Custom`
+    fn custom_keys_for_users(&self) -> Vec<String>;
+
+    /// Return the keys items can be searched by with `SearchBy::Custom`
+    fn custom_keys_for_items(&self) -> Vec<String>;
+
     /// Insert a new user frow a prototype
     fn insert_user<'a>(&self, proto: HashMap<&'a str, Value>) -> Result<Self::User>;
 
     /// Insert a new item frow a prototype
     fn insert_item<'a>(&self, proto: HashMap<&'a str, Value>) -> Result<Self::Item>;
 
-    /// Createa a rating in user for an item
+    /// Update an existing user from a prototype (built with the same fields used
+    /// to insert users), the id of the user never changes
+    fn update_user(
+        &self,
+        user_id: &eid!(Self::User),
+        proto: HashMap<&str, Value>,
+    ) -> Result<Self::User>;
+
+    /// Update an existing item from a prototype (built with the same fields used
+    /// to insert items), the id of the item never changes
+    fn update_item(
+        &self,
+        item_id: &eid!(Self::Item),
+        proto: HashMap<&str, Value>,
+    ) -> Result<Self::Item>;
+
+    /// Remove an user along with all of its ratings
+    fn remove_user(&self, user_id: &eid!(Self::User)) -> Result<Self::User>;
+
+    /// Remove an item along with all of its ratings
+    fn remove_item(&self, item_id: &eid!(Self::Item)) -> Result<Self::Item>;
+
+    /// Createa a rating in user for an item, it's stamped with the current time
     fn insert_rating(
         &self,
         user_id: &eid!(Self::User),
@@ -164,7 +450,7 @@ pub trait Controller {
         item_id: &eid!(Self::Item),
     ) -> Result<Self::Rating>;
 
-    /// Update a rating in user for an item
+    /// Update a rating in user for an item, it's stamped with the current time
     fn update_rating(
         &self,
         user_id: &eid!(Self::User),
@@ -0,0 +1,1521 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::{
+    counts, data, eid,
+    error::ErrorKind,
+    files::{field, malformed, records},
+    histogram, maped_ratings, means, now, ratings, Controller, Data, Entity, Feature, Field,
+    Histogram, ItemFeatures, MapedRatings, RatingScale, SearchBy, TimedScore, Timestamp, Type,
+    Value,
+};
+use anyhow::{anyhow, Error};
+use csv::StringRecord;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    hash::Hash,
+    ops::Bound,
+    path::Path,
+    str::FromStr,
+};
+
+pub(crate) fn parse_id<K>(id: &str) -> Result<K, ErrorKind>
+where
+    K: FromStr,
+    K::Err: Display,
+{
+    id.parse()
+        .map_err(|e: K::Err| ErrorKind::ValueConvert(e.to_string()))
+}
+
+/// Data of an user or item, the name goes first and the other columns follow
+/// sorted by key
+fn named_data(name: &Option<String>, data: &HashMap<String, String>) -> Data {
+    let mut named = data! { "name" => name.clone() };
+    let mut keys: Vec<_> = data.keys().collect();
+    keys.sort();
+
+    for key in keys {
+        named.insert(key.clone(), Value::String(data[key].clone()));
+    }
+
+    named
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryUser<K> {
+    pub id: K,
+    pub name: Option<String>,
+    pub data: HashMap<String, String>,
+}
+
+impl<K> MemoryUser<K> {
+    pub fn new(id: K) -> Self {
+        Self {
+            id,
+            name: None,
+            data: HashMap::new(),
+        }
+    }
+
+    pub fn with_name(id: K, name: &str) -> Self {
+        Self {
+            id,
+            name: Some(name.into()),
+            data: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Clone> Entity for MemoryUser<K> {
+    type Id = K;
+
+    fn get_id(&self) -> Self::Id {
+        self.id.clone()
+    }
+
+    fn get_data(&self) -> Data {
+        named_data(&self.name, &self.data)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryItem<K> {
+    pub id: K,
+    pub name: Option<String>,
+    pub data: HashMap<String, String>,
+}
+
+impl<K> MemoryItem<K> {
+    pub fn new(id: K) -> Self {
+        Self {
+            id,
+            name: None,
+            data: HashMap::new(),
+        }
+    }
+
+    pub fn with_name(id: K, name: &str) -> Self {
+        Self {
+            id,
+            name: Some(name.into()),
+            data: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Clone> Entity for MemoryItem<K> {
+    type Id = K;
+
+    fn get_id(&self) -> Self::Id {
+        self.id.clone()
+    }
+
+    fn get_data(&self) -> Data {
+        named_data(&self.name, &self.data)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryRating<U, I> {
+    pub id: u64,
+    pub user_id: U,
+    pub item_id: I,
+    pub score: f64,
+    pub time: Option<Timestamp>,
+}
+
+impl<U, I> Entity for MemoryRating<U, I>
+where
+    U: Clone + Into<Value>,
+    I: Clone + Into<Value>,
+{
+    type Id = u64;
+
+    fn get_id(&self) -> Self::Id {
+        self.id
+    }
+
+    fn get_data(&self) -> Data {
+        data! {
+            "user_id" => self.user_id.clone().into(),
+            "item_id" => self.item_id.clone().into(),
+            "score" => self.score,
+            "time" => self.time.map_or(Value::Null, Value::Timestamp),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Store<U, I>
+where
+    U: Hash + Eq + Ord,
+    I: Hash + Eq + Ord,
+{
+    // Ordered maps give a stable order for offset/limit queries
+    users: BTreeMap<U, MemoryUser<U>>,
+    items: BTreeMap<I, MemoryItem<I>>,
+
+    users_ratings: MapedRatings<U, I>,
+    users_who_rated: MapedRatings<I, U>,
+    ratings_ids: HashMap<(U, I), u64>,
+    ratings_times: HashMap<(U, I), Timestamp>,
+    next_rating_id: u64,
+}
+
+impl<U, I> Store<U, I>
+where
+    U: Hash + Eq + Ord + Clone,
+    I: Hash + Eq + Ord + Clone,
+{
+    fn rate(
+        &mut self,
+        user_id: U,
+        item_id: I,
+        score: f64,
+        time: Option<Timestamp>,
+    ) -> MemoryRating<U, I> {
+        let key = (user_id.clone(), item_id.clone());
+        let next_rating_id = &mut self.next_rating_id;
+        let id = *self.ratings_ids.entry(key.clone()).or_insert_with(|| {
+            *next_rating_id += 1;
+            *next_rating_id
+        });
+
+        match time {
+            Some(time) => self.ratings_times.insert(key, time),
+            None => self.ratings_times.remove(&key),
+        };
+
+        self.users_ratings
+            .entry(user_id.clone())
+            .or_default()
+            .insert(item_id.clone(), score);
+
+        self.users_who_rated
+            .entry(item_id.clone())
+            .or_default()
+            .insert(user_id.clone(), score);
+
+        MemoryRating {
+            id,
+            user_id,
+            item_id,
+            score,
+            time,
+        }
+    }
+
+    fn unrate(&mut self, user_id: &U, item_id: &I) -> Option<MemoryRating<U, I>> {
+        let key = (user_id.clone(), item_id.clone());
+        let id = self.ratings_ids.remove(&key)?;
+        let time = self.ratings_times.remove(&key);
+
+        let ratings = self.users_ratings.get_mut(user_id)?;
+        let score = ratings.remove(item_id)?;
+        if ratings.is_empty() {
+            self.users_ratings.remove(user_id);
+        }
+
+        if let Some(users) = self.users_who_rated.get_mut(item_id) {
+            users.remove(user_id);
+            if users.is_empty() {
+                self.users_who_rated.remove(item_id);
+            }
+        }
+
+        Some(MemoryRating {
+            id,
+            user_id: key.0,
+            item_id: key.1,
+            score,
+            time,
+        })
+    }
+
+    fn remove_user(&mut self, user_id: &U) -> Option<MemoryUser<U>> {
+        let user = self.users.remove(user_id)?;
+        let item_ids: Vec<_> = self
+            .users_ratings
+            .get(user_id)
+            .map(|ratings| ratings.keys().cloned().collect())
+            .unwrap_or_default();
+
+        for item_id in item_ids {
+            self.unrate(user_id, &item_id);
+        }
+
+        Some(user)
+    }
+
+    fn remove_item(&mut self, item_id: &I) -> Option<MemoryItem<I>> {
+        let item = self.items.remove(item_id)?;
+        let user_ids: Vec<_> = self
+            .users_who_rated
+            .get(item_id)
+            .map(|ratings| ratings.keys().cloned().collect())
+            .unwrap_or_default();
+
+        for user_id in user_ids {
+            self.unrate(&user_id, item_id);
+        }
+
+        Some(item)
+    }
+
+    fn score(&self, user_id: &U, item_id: &I) -> Option<f64> {
+        self.users_ratings.get(user_id)?.get(item_id).copied()
+    }
+
+    fn time(&self, user_id: &U, item_id: &I) -> Option<Timestamp> {
+        let key = (user_id.clone(), item_id.clone());
+        self.ratings_times.get(&key).copied()
+    }
+}
+
+/// A controller that keeps the whole dataset in memory, doesn't need any
+/// database running, useful for tests and small datasets.
+pub struct MemoryController<U = i32, I = i32>
+where
+    U: Hash + Eq + Ord,
+    I: Hash + Eq + Ord,
+{
+    score_range: (f64, f64),
+    score_step: Option<f64>,
+    failing_pages: bool,
+    store: RefCell<Store<U, I>>,
+}
+
+impl<U, I> MemoryController<U, I>
+where
+    U: Hash + Eq + Ord + Clone,
+    I: Hash + Eq + Ord + Clone,
+{
+    pub fn new(score_range: (f64, f64)) -> Self {
+        Self {
+            score_range,
+            score_step: None,
+            failing_pages: false,
+            store: RefCell::new(Store {
+                users: BTreeMap::new(),
+                items: BTreeMap::new(),
+                users_ratings: HashMap::new(),
+                users_who_rated: HashMap::new(),
+                ratings_ids: HashMap::new(),
+                ratings_times: HashMap::new(),
+                next_rating_id: 0,
+            }),
+        }
+    }
+
+    /// Only accept the scores every `step` within the score range
+    pub fn with_score_step(mut self, step: f64) -> Self {
+        self.score_step = Some(step);
+        self
+    }
+
+    /// Make every keyset page but the first one fail (`users_after` and `items_after`
+    /// with an `after` id), to test scans that break in the middle
+    pub fn with_failing_pages(mut self) -> Self {
+        self.failing_pages = true;
+        self
+    }
+
+    /// Build a controller from normal MapedRatings (User::Id => Item::Id), users
+    /// and items are created for every id found in the ratings
+    pub fn from_maped_ratings(score_range: (f64, f64), ratings: MapedRatings<U, I>) -> Self {
+        let controller = Self::new(score_range);
+
+        for (user_id, user_ratings) in ratings {
+            for (item_id, score) in user_ratings {
+                controller.add_rating(user_id.clone(), item_id, score);
+            }
+        }
+
+        controller
+    }
+
+    /// Add (or replace) an user
+    pub fn add_user(&self, user: MemoryUser<U>) {
+        self.store.borrow_mut().users.insert(user.id.clone(), user);
+    }
+
+    /// Add (or replace) an item
+    pub fn add_item(&self, item: MemoryItem<I>) {
+        self.store.borrow_mut().items.insert(item.id.clone(), item);
+    }
+
+    pub(crate) fn has_user(&self, user_id: &U) -> bool {
+        self.store.borrow().users.contains_key(user_id)
+    }
+
+    pub(crate) fn has_item(&self, item_id: &I) -> bool {
+        self.store.borrow().items.contains_key(item_id)
+    }
+
+    /// Add (or replace) a rating, the user and the item are created if they don't exist
+    pub fn add_rating(&self, user_id: U, item_id: I, score: f64) -> MemoryRating<U, I> {
+        self.add_timed_rating(user_id, item_id, score, None)
+    }
+
+    /// Same as `add_rating` but also keeps the time the rating was given
+    pub fn add_timed_rating(
+        &self,
+        user_id: U,
+        item_id: I,
+        score: f64,
+        time: Option<Timestamp>,
+    ) -> MemoryRating<U, I> {
+        let mut store = self.store.borrow_mut();
+
+        store
+            .users
+            .entry(user_id.clone())
+            .or_insert_with(|| MemoryUser::new(user_id.clone()));
+
+        store
+            .items
+            .entry(item_id.clone())
+            .or_insert_with(|| MemoryItem::new(item_id.clone()));
+
+        store.rate(user_id, item_id, score, time)
+    }
+}
+
+impl<U, I> MemoryController<U, I>
+where
+    U: Hash + Eq + Ord + Clone + FromStr,
+    I: Hash + Eq + Ord + Clone + FromStr,
+    U::Err: Display,
+    I::Err: Display,
+{
+    /// Load ratings from a csv file with headers, the first three columns must be
+    /// the user id, the item id and the score (in that order), a fourth column is
+    /// taken as the time the rating was given. A record that can't be read fails
+    /// the load with its line
+    pub fn from_csv(score_range: (f64, f64), path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let name = path.to_string_lossy();
+        let mut csv = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .delimiter(b',')
+            .from_path(path)?;
+
+        let parse = |record: &StringRecord| -> Result<(U, I, f64, Option<Timestamp>), Error> {
+            let user_id = parse_id(field(record, 0)?)?;
+            let item_id = parse_id(field(record, 1)?)?;
+            let score = field(record, 2)?.parse()?;
+            let time = record.get(3).map(|time| time.trim().parse()).transpose()?;
+
+            Ok((user_id, item_id, score, time))
+        };
+
+        let controller = Self::new(score_range);
+        for record in records(&mut csv, &name) {
+            let (line, record) = record?;
+            let (user_id, item_id, score, time) =
+                parse(&record).map_err(|e| malformed(&name, line, e))?;
+
+            controller.add_timed_rating(user_id, item_id, score, time);
+        }
+
+        Ok(controller)
+    }
+}
+
+impl<U, I> Controller for MemoryController<U, I>
+where
+    U: Hash + Eq + Ord + Clone + FromStr + ToString + Into<Value>,
+    I: Hash + Eq + Ord + Clone + FromStr + ToString + Into<Value>,
+    U::Err: Display,
+    I::Err: Display,
+{
+    type User = MemoryUser<U>;
+    type Item = MemoryItem<I>;
+    type Rating = MemoryRating<U, I>;
+
+    fn users(&self) -> Result<Vec<Self::User>, Error> {
+        Ok(self.store.borrow().users.values().cloned().collect())
+    }
+
+    fn users_by(&self, by: &SearchBy) -> Result<Vec<Self::User>, Error> {
+        let store = self.store.borrow();
+
+        match by {
+            SearchBy::Id(id) => {
+                let parsed: U = parse_id(id)?;
+                match store.users.get(&parsed) {
+                    Some(user) => Ok(vec![user.clone()]),
+                    None => Err(ErrorKind::NotFoundById(id.clone()).into()),
+                }
+            }
+
+            SearchBy::Name(name) => {
+                let users: Vec<_> = store
+                    .users
+                    .values()
+                    .filter(|user| user.name.as_ref() == Some(name))
+                    .cloned()
+                    .collect();
+
+                if users.is_empty() {
+                    Err(ErrorKind::NotFoundByName(name.clone()).into())
+                } else {
+                    Ok(users)
+                }
+            }
+
+            SearchBy::Custom(k, v) => {
+                let users: Vec<_> = store
+                    .users
+                    .values()
+                    .filter(|user| user.data.get(k) == Some(v))
+                    .cloned()
+                    .collect();
+
+                if users.is_empty() {
+                    Err(ErrorKind::NotFoundByCustom(k.clone(), v.clone()).into())
+                } else {
+                    Ok(users)
+                }
+            }
+
+            SearchBy::NameLike(mode, name, limit) => {
+                let candidates = store.users.values().cloned().collect();
+                let users = mode.rank(name, candidates, |user| user.name.as_deref(), *limit);
+
+                if users.is_empty() {
+                    Err(ErrorKind::NotFoundByName(name.clone()).into())
+                } else {
+                    Ok(users)
+                }
+            }
+        }
+    }
+
+    fn users_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<Self::User>, Error> {
+        let store = self.store.borrow();
+        let users = store
+            .users
+            .values()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect();
+
+        Ok(users)
+    }
+
+    fn users_after(
+        &self,
+        after: Option<&eid!(Self::User)>,
+        limit: usize,
+    ) -> Result<Vec<Self::User>, Error> {
+        if let (true, Some(after)) = (self.failing_pages, after) {
+            return Err(anyhow!(
+                "Failed to read the users after {}",
+                after.to_string()
+            ));
+        }
+
+        let store = self.store.borrow();
+        let users = match after {
+            Some(after) => store
+                .users
+                .range((Bound::Excluded(after), Bound::Unbounded))
+                .map(|(_, entity)| entity)
+                .take(limit)
+                .cloned()
+                .collect(),
+            None => store.users.values().take(limit).cloned().collect(),
+        };
+
+        Ok(users)
+    }
+
+    fn items(&self) -> Result<Vec<Self::Item>, Error> {
+        Ok(self.store.borrow().items.values().cloned().collect())
+    }
+
+    fn items_by(&self, by: &SearchBy) -> Result<Vec<Self::Item>, Error> {
+        let store = self.store.borrow();
+
+        match by {
+            SearchBy::Id(id) => {
+                let parsed: I = parse_id(id)?;
+                match store.items.get(&parsed) {
+                    Some(item) => Ok(vec![item.clone()]),
+                    None => Err(ErrorKind::NotFoundById(id.clone()).into()),
+                }
+            }
+
+            SearchBy::Name(name) => {
+                let items: Vec<_> = store
+                    .items
+                    .values()
+                    .filter(|item| item.name.as_ref() == Some(name))
+                    .cloned()
+                    .collect();
+
+                if items.is_empty() {
+                    Err(ErrorKind::NotFoundByName(name.clone()).into())
+                } else {
+                    Ok(items)
+                }
+            }
+
+            SearchBy::Custom(k, v) => {
+                let items: Vec<_> = store
+                    .items
+                    .values()
+                    .filter(|item| item.data.get(k) == Some(v))
+                    .cloned()
+                    .collect();
+
+                if items.is_empty() {
+                    Err(ErrorKind::NotFoundByCustom(k.clone(), v.clone()).into())
+                } else {
+                    Ok(items)
+                }
+            }
+
+            SearchBy::NameLike(mode, name, limit) => {
+                let candidates = store.items.values().cloned().collect();
+                let items = mode.rank(name, candidates, |item| item.name.as_deref(), *limit);
+
+                if items.is_empty() {
+                    Err(ErrorKind::NotFoundByName(name.clone()).into())
+                } else {
+                    Ok(items)
+                }
+            }
+        }
+    }
+
+    fn items_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<Self::Item>, Error> {
+        let store = self.store.borrow();
+        let items = store
+            .items
+            .values()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect();
+
+        Ok(items)
+    }
+
+    fn items_after(
+        &self,
+        after: Option<&eid!(Self::Item)>,
+        limit: usize,
+    ) -> Result<Vec<Self::Item>, Error> {
+        if let (true, Some(after)) = (self.failing_pages, after) {
+            return Err(anyhow!(
+                "Failed to read the items after {}",
+                after.to_string()
+            ));
+        }
+
+        let store = self.store.borrow();
+        let items = match after {
+            Some(after) => store
+                .items
+                .range((Bound::Excluded(after), Bound::Unbounded))
+                .map(|(_, entity)| entity)
+                .take(limit)
+                .cloned()
+                .collect(),
+            None => store.items.values().take(limit).cloned().collect(),
+        };
+
+        Ok(items)
+    }
+
+    fn create_partial_users(
+        &self,
+        user_ids: &[eid!(Self::User)],
+    ) -> Result<Vec<Self::User>, Error> {
+        Ok(user_ids.iter().cloned().map(MemoryUser::new).collect())
+    }
+
+    fn create_partial_items(
+        &self,
+        item_ids: &[eid!(Self::Item)],
+    ) -> Result<Vec<Self::Item>, Error> {
+        Ok(item_ids.iter().cloned().map(MemoryItem::new).collect())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn users_who_rated(
+        &self,
+        items: &[Self::Item],
+    ) -> Result<maped_ratings!(Self::Item => Self::User), Error> {
+        let store = self.store.borrow();
+        let items_users = items
+            .iter()
+            .filter_map(|item| {
+                let users = store.users_who_rated.get(&item.id)?;
+                Some((item.id.clone(), users.clone()))
+            })
+            .collect();
+
+        Ok(items_users)
+    }
+
+    fn user_ratings(&self, user: &Self::User) -> Result<ratings!(Self::Item), Error> {
+        let store = self.store.borrow();
+        let ratings = store
+            .users_ratings
+            .get(&user.id)
+            .cloned()
+            .unwrap_or_default();
+
+        Ok(ratings)
+    }
+
+    fn user_timed_ratings(
+        &self,
+        user: &Self::User,
+    ) -> Result<ratings!(Self::Item, TimedScore), Error> {
+        let store = self.store.borrow();
+        let ratings = store
+            .users_ratings
+            .get(&user.id)
+            .map(|ratings| {
+                ratings
+                    .iter()
+                    .map(|(item_id, score)| {
+                        let time = store.time(&user.id, item_id);
+                        (item_id.clone(), TimedScore::new(*score, time))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(ratings)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn users_ratings_since(
+        &self,
+        since: Timestamp,
+    ) -> Result<maped_ratings!(Self::User => Self::Item, TimedScore), Error> {
+        let store = self.store.borrow();
+        let mut maped_ratings: MapedRatings<_, _, TimedScore> = HashMap::new();
+
+        for ((user_id, item_id), time) in &store.ratings_times {
+            if *time < since {
+                continue;
+            }
+
+            if let Some(score) = store.score(user_id, item_id) {
+                maped_ratings
+                    .entry(user_id.clone())
+                    .or_default()
+                    .insert(item_id.clone(), TimedScore::new(score, Some(*time)));
+            }
+        }
+
+        Ok(maped_ratings)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn all_users_ratings(&self) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
+        Ok(self.store.borrow().users_ratings.clone())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn users_ratings(
+        &self,
+        users: &[Self::User],
+    ) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
+        let store = self.store.borrow();
+        let maped_ratings = users
+            .iter()
+            .filter_map(|user| {
+                let ratings = store.users_ratings.get(&user.id)?;
+                Some((user.id.clone(), ratings.clone()))
+            })
+            .collect();
+
+        Ok(maped_ratings)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn users_ratings_except(
+        &self,
+        user: &Self::User,
+    ) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
+        let store = self.store.borrow();
+        let maped_ratings = store
+            .users_ratings
+            .iter()
+            .filter(|(id, _)| *id != &user.id)
+            .map(|(id, ratings)| (id.clone(), ratings.clone()))
+            .collect();
+
+        Ok(maped_ratings)
+    }
+
+    fn users_means(&self, users: &[Self::User]) -> Result<means!(Self::User), Error> {
+        let store = self.store.borrow();
+        let means = users
+            .iter()
+            .filter_map(|user| {
+                let ratings = store.users_ratings.get(&user.id)?;
+                let mean = ratings.values().sum::<f64>() / ratings.len() as f64;
+                Some((user.id.clone(), mean))
+            })
+            .collect();
+
+        Ok(means)
+    }
+
+    fn users_count(&self) -> Result<usize, Error> {
+        Ok(self.store.borrow().users.len())
+    }
+
+    fn items_count(&self) -> Result<usize, Error> {
+        Ok(self.store.borrow().items.len())
+    }
+
+    fn ratings_count(&self) -> Result<usize, Error> {
+        Ok(self.store.borrow().ratings_ids.len())
+    }
+
+    fn users_ratings_count(&self, users: &[Self::User]) -> Result<counts!(Self::User), Error> {
+        let store = self.store.borrow();
+        let counts = users
+            .iter()
+            .map(|user| {
+                let count = store.users_ratings.get(&user.id).map_or(0, HashMap::len);
+                (user.id.clone(), count)
+            })
+            .collect();
+
+        Ok(counts)
+    }
+
+    fn items_ratings_count(&self, items: &[Self::Item]) -> Result<counts!(Self::Item), Error> {
+        let store = self.store.borrow();
+        let counts = items
+            .iter()
+            .map(|item| {
+                let count = store.users_who_rated.get(&item.id).map_or(0, HashMap::len);
+                (item.id.clone(), count)
+            })
+            .collect();
+
+        Ok(counts)
+    }
+
+    fn items_means(&self, items: &[Self::Item]) -> Result<means!(Self::Item), Error> {
+        let store = self.store.borrow();
+        let means = items
+            .iter()
+            .filter_map(|item| {
+                let ratings = store.users_who_rated.get(&item.id)?;
+                let mean = ratings.values().sum::<f64>() / ratings.len() as f64;
+                Some((item.id.clone(), mean))
+            })
+            .collect();
+
+        Ok(means)
+    }
+
+    fn global_mean(&self) -> Result<Option<f64>, Error> {
+        let store = self.store.borrow();
+        let scores: Vec<_> = store
+            .users_ratings
+            .values()
+            .flat_map(|r| r.values())
+            .collect();
+
+        if scores.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(
+                scores.iter().copied().sum::<f64>() / scores.len() as f64,
+            ))
+        }
+    }
+
+    fn user_histogram(&self, user: &Self::User) -> Result<Histogram, Error> {
+        let store = self.store.borrow();
+        let ratings = store.users_ratings.get(&user.id);
+        Ok(histogram(
+            ratings.into_iter().flat_map(|r| r.values().copied()),
+        ))
+    }
+
+    fn item_histogram(&self, item: &Self::Item) -> Result<Histogram, Error> {
+        let store = self.store.borrow();
+        let ratings = store.users_who_rated.get(&item.id);
+        Ok(histogram(
+            ratings.into_iter().flat_map(|r| r.values().copied()),
+        ))
+    }
+
+    fn most_rated_items(&self, limit: usize) -> Result<Vec<(I, usize)>, Error> {
+        let store = self.store.borrow();
+        let mut counts: Vec<_> = store
+            .users_who_rated
+            .iter()
+            .map(|(item_id, ratings)| (item_id.clone(), ratings.len()))
+            .collect();
+
+        counts.sort_by(|(a_id, a), (b_id, b)| b.cmp(a).then_with(|| a_id.cmp(b_id)));
+        counts.truncate(limit);
+        Ok(counts)
+    }
+
+    fn items_features(&self, items: &[Self::Item]) -> Result<ItemFeatures<I>, Error> {
+        let store = self.store.borrow();
+        let mut features = ItemFeatures::new();
+
+        // Unknown items are left out, as a query on the database would do
+        for item in items.iter().filter_map(|item| store.items.get(&item.id)) {
+            // Columns that look like numbers are taken as numeric features
+            let mut data: Vec<_> = item.data.iter().collect();
+            data.sort();
+
+            let item_features = data
+                .into_iter()
+                .map(|(key, val)| match val.parse() {
+                    Ok(val) => Feature::numeric(key, val),
+                    Err(_) => Feature::categorical(key, val),
+                })
+                .collect();
+
+            features.add(item.id.clone(), item_features);
+        }
+
+        Ok(features)
+    }
+
+    fn score_range(&self) -> (f64, f64) {
+        self.score_range
+    }
+
+    fn rating_scale(&self) -> RatingScale {
+        match self.score_step {
+            Some(step) => RatingScale::discrete(self.score_range, step),
+            None => RatingScale::continuous(self.score_range),
+        }
+    }
+
+    fn fields_for_users(&self) -> Vec<Field<'_>> {
+        vec![
+            Field::required("id", Type::String).describe("Unique id of the user"),
+            Field::optional("name", Type::String),
+        ]
+    }
+
+    fn fields_for_items(&self) -> Vec<Field<'_>> {
+        vec![
+            Field::required("id", Type::String).describe("Unique id of the item"),
+            Field::optional("name", Type::String),
+        ]
+    }
+
+    fn custom_keys_for_users(&self) -> Vec<String> {
+        let store = self.store.borrow();
+        let keys: BTreeSet<_> = store
+            .users
+            .values()
+            .flat_map(|user| user.data.keys())
+            .collect();
+
+        keys.into_iter().cloned().collect()
+    }
+
+    fn custom_keys_for_items(&self) -> Vec<String> {
+        let store = self.store.borrow();
+        let keys: BTreeSet<_> = store
+            .items
+            .values()
+            .flat_map(|item| item.data.keys())
+            .collect();
+
+        keys.into_iter().cloned().collect()
+    }
+
+    fn insert_user(&self, proto: HashMap<&str, Value>) -> Result<Self::User, Error> {
+        let id: U = parse_id(proto["id"].as_string()?)?;
+        if self.store.borrow().users.contains_key(&id) {
+            return Err(ErrorKind::DuplicatedId(id.to_string()).into());
+        }
+
+        let user = MemoryUser {
+            id,
+            name: proto
+                .get("name")
+                .map(Value::as_string)
+                .transpose()?
+                .map(Into::into),
+            data: HashMap::new(),
+        };
+
+        self.add_user(user.clone());
+        Ok(user)
+    }
+
+    fn insert_item(&self, proto: HashMap<&str, Value>) -> Result<Self::Item, Error> {
+        let id: I = parse_id(proto["id"].as_string()?)?;
+        if self.store.borrow().items.contains_key(&id) {
+            return Err(ErrorKind::DuplicatedId(id.to_string()).into());
+        }
+
+        let item = MemoryItem {
+            id,
+            name: proto
+                .get("name")
+                .map(Value::as_string)
+                .transpose()?
+                .map(Into::into),
+            data: HashMap::new(),
+        };
+
+        self.add_item(item.clone());
+        Ok(item)
+    }
+
+    fn update_user(
+        &self,
+        user_id: &eid!(Self::User),
+        proto: HashMap<&str, Value>,
+    ) -> Result<Self::User, Error> {
+        let mut store = self.store.borrow_mut();
+        let user = store
+            .users
+            .get_mut(user_id)
+            .ok_or_else(|| ErrorKind::NotFoundById(user_id.to_string()))?;
+
+        // Fields left out of the prototype keep their value
+        if let Some(name) = proto.get("name") {
+            user.name = name
+                .non_null()
+                .map(Value::as_string)
+                .transpose()?
+                .map(Into::into);
+        }
+
+        Ok(user.clone())
+    }
+
+    fn update_item(
+        &self,
+        item_id: &eid!(Self::Item),
+        proto: HashMap<&str, Value>,
+    ) -> Result<Self::Item, Error> {
+        let mut store = self.store.borrow_mut();
+        let item = store
+            .items
+            .get_mut(item_id)
+            .ok_or_else(|| ErrorKind::NotFoundById(item_id.to_string()))?;
+
+        // Fields left out of the prototype keep their value
+        if let Some(name) = proto.get("name") {
+            item.name = name
+                .non_null()
+                .map(Value::as_string)
+                .transpose()?
+                .map(Into::into);
+        }
+
+        Ok(item.clone())
+    }
+
+    fn remove_user(&self, user_id: &eid!(Self::User)) -> Result<Self::User, Error> {
+        self.store
+            .borrow_mut()
+            .remove_user(user_id)
+            .ok_or_else(|| ErrorKind::NotFoundById(user_id.to_string()).into())
+    }
+
+    fn remove_item(&self, item_id: &eid!(Self::Item)) -> Result<Self::Item, Error> {
+        self.store
+            .borrow_mut()
+            .remove_item(item_id)
+            .ok_or_else(|| ErrorKind::NotFoundById(item_id.to_string()).into())
+    }
+
+    fn insert_rating(
+        &self,
+        user_id: &eid!(Self::User),
+        item_id: &eid!(Self::Item),
+        score: f64,
+    ) -> Result<Self::Rating, Error> {
+        self.check_score(score)?;
+
+        let mut store = self.store.borrow_mut();
+
+        if !store.users.contains_key(user_id) {
+            return Err(ErrorKind::NotFoundById(user_id.to_string()).into());
+        }
+
+        if !store.items.contains_key(item_id) {
+            return Err(ErrorKind::NotFoundById(item_id.to_string()).into());
+        }
+
+        if store.score(user_id, item_id).is_some() {
+            return Err(
+                ErrorKind::InsertRatingFailed(user_id.to_string(), item_id.to_string()).into(),
+            );
+        }
+
+        Ok(store.rate(user_id.clone(), item_id.clone(), score, Some(now())))
+    }
+
+    fn remove_rating(
+        &self,
+        user_id: &eid!(Self::User),
+        item_id: &eid!(Self::Item),
+    ) -> Result<Self::Rating, Error> {
+        self.store
+            .borrow_mut()
+            .unrate(user_id, item_id)
+            .ok_or_else(|| {
+                ErrorKind::RemoveRatingFailed(user_id.to_string(), item_id.to_string()).into()
+            })
+    }
+
+    fn update_rating(
+        &self,
+        user_id: &eid!(Self::User),
+        item_id: &eid!(Self::Item),
+        score: f64,
+    ) -> Result<Self::Rating, Error> {
+        self.check_score(score)?;
+
+        let mut store = self.store.borrow_mut();
+
+        if store.score(user_id, item_id).is_none() {
+            return Err(
+                ErrorKind::UpdateRatingFailed(user_id.to_string(), item_id.to_string()).into(),
+            );
+        }
+
+        Ok(store.rate(user_id.clone(), item_id.clone(), score, Some(now())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NameMatch;
+    use anyhow::Error;
+    use assert_approx_eq::*;
+
+    fn controller() -> MemoryController {
+        let controller = MemoryController::new((1., 5.));
+
+        controller.add_user(MemoryUser::with_name(1, "Patrick C"));
+        controller.add_user(MemoryUser::with_name(2, "Josh"));
+        controller.add_item(MemoryItem::with_name(10, "Alien"));
+        controller.add_item(MemoryItem::with_name(20, "Blade Runner"));
+
+        controller.add_rating(1, 10, 4.);
+        controller.add_rating(1, 20, 2.);
+        controller.add_rating(2, 10, 5.);
+        controller.add_rating(3, 30, 3.);
+
+        controller
+    }
+
+    #[test]
+    fn search_users_and_items() -> Result<(), Error> {
+        let controller = controller();
+
+        let users = controller.users_by(&SearchBy::id("2"))?;
+        assert_eq!(users[0].name.as_deref(), Some("Josh"));
+
+        let items = controller.items_by(&SearchBy::name("Blade Runner"))?;
+        assert_eq!(items[0].get_id(), 20);
+
+        assert!(controller.users_by(&SearchBy::name("Nobody")).is_err());
+        assert!(controller.items_by(&SearchBy::id("x")).is_err());
+
+        let items =
+            controller.items_by(&SearchBy::name_like(NameMatch::Fuzzy, "blade runer", 5))?;
+        assert_eq!(items[0].get_id(), 20);
+        let users = controller.users_by(&SearchBy::name_like(NameMatch::Prefix, "JO", 5))?;
+        assert_eq!(users[0].get_id(), 2);
+        assert!(controller
+            .users_by(&SearchBy::name_like(NameMatch::Contains, "nobody", 5))
+            .is_err());
+
+        controller.add_item(MemoryItem {
+            id: 40,
+            name: Some("Heat".into()),
+            data: vec![("genre".to_string(), "Crime".to_string())]
+                .into_iter()
+                .collect(),
+        });
+
+        let items = controller.items_by(&SearchBy::custom("genre", "Crime"))?;
+        assert_eq!(items[0].get_id(), 40);
+        assert_eq!(
+            controller.custom_keys_for_items(),
+            vec!["genre".to_string()]
+        );
+        assert!(controller.custom_keys_for_users().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn chunked_users() -> Result<(), Error> {
+        let controller = controller();
+        let mut chunks = controller.users_by_chunks(2);
+
+        let ids: Vec<_> = chunks.next().unwrap()?.iter().map(|u| u.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+
+        let ids: Vec<_> = chunks.next().unwrap()?.iter().map(|u| u.id).collect();
+        assert_eq!(ids, vec![3]);
+
+        assert!(chunks.next().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn keyset_chunks() -> Result<(), Error> {
+        let controller = controller();
+        controller.add_item(MemoryItem::with_name(40, "Heat"));
+
+        let ids: Vec<_> = controller
+            .users_after(Some(&1), 5)?
+            .iter()
+            .map(|u| u.id)
+            .collect();
+        assert_eq!(ids, vec![2, 3]);
+
+        let mut chunks = controller.items_by_chunks(2);
+        let ids: Vec<_> = chunks.next().unwrap()?.iter().map(|i| i.id).collect();
+        assert_eq!(ids, vec![10, 20]);
+
+        // Inserting before the cursor mustn't shift the following chunks
+        controller.add_item(MemoryItem::with_name(5, "Jaws"));
+        let ids: Vec<_> = chunks.next().unwrap()?.iter().map(|i| i.id).collect();
+        assert_eq!(ids, vec![30, 40]);
+        assert!(chunks.next().is_none());
+
+        let ids: Vec<_> = chunks.nth(1).unwrap()?.iter().map(|i| i.id).collect();
+        assert_eq!(ids, vec![30, 40]);
+        let ids: Vec<_> = controller
+            .items_by_chunks(2)
+            .next()
+            .unwrap()?
+            .iter()
+            .map(|i| i.id)
+            .collect();
+        assert_eq!(ids, vec![5, 10]);
+        assert!(chunks.nth(3).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn ratings_and_means() -> Result<(), Error> {
+        let controller = controller();
+        let items = controller.items_by(&SearchBy::id("10"))?;
+
+        let users_who_rated = controller.users_who_rated(&items)?;
+        assert_eq!(users_who_rated[&10].len(), 2);
+
+        let users = controller.users_by(&SearchBy::id("1"))?;
+        let means = controller.users_means(&users)?;
+        assert_approx_eq!(means[&1], 3.);
+
+        let except = controller.users_ratings_except(&users[0])?;
+        assert!(!except.contains_key(&1));
+        assert_eq!(except.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn counts() -> Result<(), Error> {
+        let controller = controller();
+
+        assert_eq!(controller.users_count()?, 3);
+        assert_eq!(controller.items_count()?, 3);
+        assert_eq!(controller.ratings_count()?, 4);
+
+        let users = controller.users_by(&SearchBy::id("1"))?;
+        assert_eq!(controller.users_ratings_count(&users)?[&1], 2);
+
+        let items = controller.create_partial_items(&[10, 40])?;
+        let counts = controller.items_ratings_count(&items)?;
+        assert_eq!(counts[&10], 2);
+        assert_eq!(counts[&40], 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn insert_update_remove_rating() -> Result<(), Error> {
+        let controller = controller();
+
+        assert!(controller.insert_rating(&1, &10, 3.).is_err());
+        assert!(controller.insert_rating(&1, &99, 3.).is_err());
+        assert!(controller.insert_rating(&2, &20, 7.).is_err());
+        assert!(controller.insert_rating(&2, &20, f64::NAN).is_err());
+
+        let rating = controller.insert_rating(&2, &20, 1.)?;
+        assert_approx_eq!(rating.score, 1.);
+
+        assert!(controller.update_rating(&2, &20, 0.).is_err());
+        let updated = controller.update_rating(&2, &20, 3.)?;
+        assert_eq!(updated.id, rating.id);
+        assert!(updated.time >= rating.time);
+
+        let users = controller.users_by(&SearchBy::id("2"))?;
+        assert_approx_eq!(controller.users_means(&users)?[&2], 4.);
+
+        let removed = controller.remove_rating(&2, &20)?;
+        assert_approx_eq!(removed.score, 3.);
+        assert!(controller.remove_rating(&2, &20).is_err());
+        assert!(controller.update_rating(&2, &20, 2.).is_err());
+
+        let items = controller.create_partial_items(&[20])?;
+        assert!(!controller.users_who_rated(&items)?[&20].contains_key(&2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rating_scale() -> Result<(), Error> {
+        let controller = controller().with_score_step(0.5);
+        let scale = controller.rating_scale();
+        assert_eq!(scale, RatingScale::discrete((1., 5.), 0.5));
+
+        assert!(controller.insert_rating(&2, &20, 3.7).is_err());
+        assert!(controller.insert_rating(&2, &20, 6.).is_err());
+        controller.insert_rating(&2, &20, 3.5)?;
+        assert!(controller.update_rating(&2, &20, 4.25).is_err());
+        controller.update_rating(&2, &20, 4.5)?;
+
+        assert_approx_eq!(scale.snap(3.7), 3.5);
+        assert_approx_eq!(scale.snap(3.8), 4.);
+        assert_approx_eq!(scale.snap(0.2), 1.);
+        assert_approx_eq!(scale.snap(7.), 5.);
+        assert_approx_eq!(RatingScale::continuous((1., 5.)).snap(3.7), 3.7);
+        assert_approx_eq!(RatingScale::discrete((0., 5.), 2.).snap(4.9), 4.);
+
+        Ok(())
+    }
+
+    #[test]
+    fn items_content_features() -> Result<(), Error> {
+        let controller = controller();
+        let mut heat = MemoryItem::with_name(40, "Heat");
+        heat.data.insert("genre".into(), "Crime".into());
+        heat.data.insert("year".into(), "1995".into());
+        controller.add_item(heat);
+
+        let items = controller.create_partial_items(&[40, 10])?;
+        let features = controller.items_features(&items)?;
+
+        assert_eq!(features.vocabulary(), &["genre=Crime", "year"]);
+        assert_eq!(features.dense(&40), Some(vec![1., 1995.]));
+        assert_eq!(features.dense(&10), Some(vec![0., 0.]));
+
+        let items = controller.create_partial_items(&[40, 99])?;
+        let features = controller.items_features(&items)?;
+        assert_eq!(features.len(), 1);
+        assert!(features.vector(&99).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn typed_entity_data() -> Result<(), Error> {
+        let controller = controller();
+        let mut user = controller.users_by(&SearchBy::id("1"))?.remove(0);
+        user.data.insert("zip".into(), "15001".into());
+        user.data.insert("city".into(), "Lima".into());
+
+        let keys: Vec<_> = user.get_data().keys().cloned().collect();
+        assert_eq!(keys, vec!["name", "city", "zip"]);
+
+        let json = serde_json::to_string(&user.to_record())?;
+        assert_eq!(
+            json,
+            r#"{"id":1,"name":"Patrick C","city":"Lima","zip":"15001"}"#
+        );
+
+        let rating = MemoryRating {
+            id: 3,
+            user_id: 1,
+            item_id: 10,
+            score: 4.5,
+            time: None,
+        };
+
+        let json = serde_json::to_string(&rating.to_record())?;
+        assert_eq!(
+            json,
+            r#"{"id":3,"user_id":1,"item_id":10,"score":4.5,"time":null}"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn timed_ratings() -> Result<(), Error> {
+        let controller = controller();
+        controller.add_timed_rating(2, 20, 3., Some(100));
+        controller.add_timed_rating(3, 30, 1., Some(200));
+
+        let user = MemoryUser::new(1);
+        let ratings = controller.user_timed_ratings(&user)?;
+        assert_eq!(ratings[&10], TimedScore::new(4., None));
+
+        let since = controller.users_ratings_since(150)?;
+        assert_eq!(since.len(), 1);
+        assert_eq!(since[&3][&30], TimedScore::new(1., Some(200)));
+        assert_eq!(controller.users_ratings_since(0)?[&2].len(), 1);
+
+        let rating = controller.update_rating(&3, &30, 2.)?;
+        assert!(rating.time.unwrap() > 200);
+        controller.remove_rating(&3, &30)?;
+        assert!(controller.users_ratings_since(150)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn update_and_remove_entities() -> Result<(), Error> {
+        let controller = controller();
+
+        let mut proto = HashMap::new();
+        proto.insert("id", Value::String("10".into()));
+        proto.insert("name", Value::String("Aliens".into()));
+
+        let item = controller.update_item(&10, proto.clone())?;
+        assert_eq!(item.name.as_deref(), Some("Aliens"));
+        assert_eq!(controller.items_by(&SearchBy::name("Aliens"))?[0].id, 10);
+        assert!(controller.update_item(&99, proto).is_err());
+
+        // A prototype without a name keeps the current one
+        let item = controller.update_item(&10, HashMap::new())?;
+        assert_eq!(item.name.as_deref(), Some("Aliens"));
+        let user = controller.update_user(&1, HashMap::new())?;
+        assert_eq!(user.name.as_deref(), Some("Patrick C"));
+
+        let removed = controller.remove_user(&1)?;
+        assert_eq!(removed.name.as_deref(), Some("Patrick C"));
+        assert!(controller.remove_user(&1).is_err());
+        assert_eq!(controller.ratings_count()?, 2);
+        assert!(controller.users_means(&[removed])?.is_empty());
+
+        let items = controller.create_partial_items(&[10])?;
+        assert!(!controller.users_who_rated(&items)?[&10].contains_key(&1));
+
+        controller.remove_item(&10)?;
+        assert_eq!(controller.ratings_count()?, 1);
+        let users = controller.users()?;
+        assert!(!controller.users_ratings(&users)?.contains_key(&2));
+        assert!(controller.insert_rating(&2, &10, 3.).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn aggregate_stats() -> Result<(), Error> {
+        let controller = controller();
+        let items = controller.create_partial_items(&[10, 20])?;
+
+        let means = controller.items_means(&items)?;
+        assert_approx_eq!(means[&10], 4.5);
+        assert_approx_eq!(means[&20], 2.);
+        assert_approx_eq!(controller.global_mean()?.unwrap(), 3.5);
+
+        let users = controller.create_partial_users(&[1])?;
+        assert_eq!(
+            controller.user_histogram(&users[0])?,
+            vec![(2., 1), (4., 1)]
+        );
+        assert_eq!(
+            controller.item_histogram(&items[0])?,
+            vec![(4., 1), (5., 1)]
+        );
+        assert_eq!(controller.most_rated_items(2)?, vec![(10, 2), (20, 1)]);
+
+        assert_eq!(
+            MemoryController::<u32, u32>::new((1., 5.)).global_mean()?,
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_maped_ratings() -> Result<(), Error> {
+        let mut ratings = HashMap::new();
+        ratings.insert("Angelica".to_string(), HashMap::new());
+        ratings
+            .get_mut("Angelica")
+            .unwrap()
+            .insert("Norah Jones".to_string(), 4.5);
+
+        let controller = MemoryController::from_maped_ratings((1., 5.), ratings);
+        assert_eq!(controller.users()?.len(), 1);
+        assert_eq!(controller.items_by(&SearchBy::id("Norah Jones"))?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_csv() -> Result<(), Error> {
+        let controller: MemoryController = MemoryController::from_csv(
+            (0.5, 5.),
+            "../controllers/movie-lens-small/data/ratings.csv",
+        )?;
+
+        assert_eq!(controller.users()?.len(), 610);
+        assert_eq!(
+            controller
+                .all_users_ratings()?
+                .values()
+                .map(|r| r.len())
+                .sum::<usize>(),
+            100836
+        );
+
+        let path = std::env::temp_dir().join("memory-short-record.csv");
+        std::fs::write(&path, "userId,movieId\n1,10\n")?;
+        let controller: Result<MemoryController, _> = MemoryController::from_csv((0.5, 5.), &path);
+        assert!(controller.err().unwrap().to_string().contains("line 2"));
+
+        let path = std::env::temp_dir().join("memory-unequal-record.csv");
+        std::fs::write(&path, "userId,movieId,rating\n1,10,4.0\n1,20\n")?;
+        let controller: Result<MemoryController, _> = MemoryController::from_csv((0.5, 5.), &path);
+        assert!(controller.err().unwrap().to_string().contains("line 3"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn insert_from_prototype() -> Result<(), Error> {
+        let controller = controller();
+
+        let mut proto = HashMap::new();
+        proto.insert("id", Value::String("7".into()));
+        proto.insert("name", Value::String("Chris".into()));
+
+        let user = controller.insert_user(proto.clone())?;
+        assert_eq!(user.get_id(), 7);
+        assert!(controller.insert_user(proto).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,363 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::{
+    consistency::diff_ratings, eid, error::ErrorKind, maped_ratings, ConsistencyReport, Controller,
+    Discrepancy, Entity, MapedRatings, RatingCollection, Ratings, Result, TimedScore,
+};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::{FindOptions, ReplaceOptions};
+use mongodb::sync::{Collection, Database};
+use std::{collections::HashSet, fmt::Display, hash::Hash, str::FromStr};
+
+/// Ids that can own a rating document or be a key of its scores
+pub trait DocumentId: Hash + Eq + Ord + Clone + Display + FromStr {}
+
+impl<T> DocumentId for T where T: Hash + Eq + Ord + Clone + Display + FromStr {}
+
+/// Controllers whose ratings table is copied to the `users_ratings` and
+/// `users_who_rated` mongo collections (see `RatingCollection`), the copies can
+/// be checked against the table, repaired or rebuilt from it.
+///
+/// Controllers only tell how to read their ratings table and how their ids are
+/// stored in mongo, the rest is shared.
+pub trait MirroredRatings: Controller + Sized
+where
+    eid!(Self::User): DocumentId,
+    eid!(Self::Item): DocumentId,
+{
+    /// The database that holds the copies
+    fn mirror_db(&self) -> Result<&Database>;
+
+    /// The collections this controller keeps in mongo, the others are never touched
+    fn mirrored_collections(&self) -> &[RatingCollection];
+
+    /// Ratings of the specified users read from the ratings table, never from mongo
+    #[allow(clippy::type_complexity)]
+    fn table_users_ratings(
+        &self,
+        user_ids: &[eid!(Self::User)],
+    ) -> Result<maped_ratings!(Self::User => Self::Item, TimedScore)>;
+
+    /// Ratings of the specified items read from the ratings table, never from mongo
+    #[allow(clippy::type_complexity)]
+    fn table_items_ratings(
+        &self,
+        item_ids: &[eid!(Self::Item)],
+    ) -> Result<maped_ratings!(Self::Item => Self::User, TimedScore)>;
+
+    /// The value an user id takes in the `user_id` field of its document
+    fn user_document_key(&self, user_id: &eid!(Self::User)) -> Result<Bson>;
+
+    /// The value an item id takes in the `item_id` field of its document
+    fn item_document_key(&self, item_id: &eid!(Self::Item)) -> Result<Bson>;
+
+    /// Diff every document against the ratings table, users and items are checked
+    /// by chunks of `chunk_size`. Documents whose owner isn't in the table anymore
+    /// are reported as extra ratings
+    #[allow(clippy::type_complexity)]
+    fn check_mongo(
+        &self,
+        chunk_size: usize,
+    ) -> Result<ConsistencyReport<eid!(Self::User), eid!(Self::Item)>> {
+        let db = self.mirror_db()?;
+        let mut report = ConsistencyReport::default();
+
+        if let Some(users) = users_mirror(self, db) {
+            report.users = users.check(ids(self.users_by_chunks(chunk_size.max(1))), chunk_size)?;
+        }
+
+        if let Some(items) = items_mirror(self, db) {
+            report.items = items.check(ids(self.items_by_chunks(chunk_size.max(1))), chunk_size)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Rewrite the documents of every drifted user and item from the ratings table
+    fn repair_mongo(
+        &self,
+        report: &ConsistencyReport<eid!(Self::User), eid!(Self::Item)>,
+    ) -> Result<()> {
+        let db = self.mirror_db()?;
+
+        if let Some(users) = users_mirror(self, db) {
+            for user_id in report.drifted_users() {
+                users.rewrite(&user_id)?;
+            }
+        }
+
+        if let Some(items) = items_mirror(self, db) {
+            for item_id in report.drifted_items() {
+                items.rewrite(&item_id)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace every document with the ones built from the ratings table, the
+    /// collections are emptied but not dropped so their indexes are kept
+    fn rebuild_mongo(&self, chunk_size: usize) -> Result<()> {
+        let db = self.mirror_db()?;
+
+        if let Some(users) = users_mirror(self, db) {
+            users.rebuild(ids(self.users_by_chunks(chunk_size.max(1))))?;
+        }
+
+        if let Some(items) = items_mirror(self, db) {
+            items.rebuild(ids(self.items_by_chunks(chunk_size.max(1))))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The `users_ratings` collection if the controller keeps it
+#[allow(clippy::type_complexity)]
+fn users_mirror<'a, C>(
+    controller: &'a C,
+    db: &Database,
+) -> Option<Mirror<'a, eid!(C::User), eid!(C::Item)>>
+where
+    C: MirroredRatings,
+    eid!(C::User): DocumentId,
+    eid!(C::Item): DocumentId,
+{
+    let kind = RatingCollection::UsersRatings;
+    if !controller.mirrored_collections().contains(&kind) {
+        return None;
+    }
+
+    Some(Mirror {
+        collection: db.collection(kind.name()),
+        kind,
+        table: Box::new(move |ids| controller.table_users_ratings(ids)),
+        key: Box::new(move |id| controller.user_document_key(id)),
+    })
+}
+
+/// The `users_who_rated` collection if the controller keeps it
+#[allow(clippy::type_complexity)]
+fn items_mirror<'a, C>(
+    controller: &'a C,
+    db: &Database,
+) -> Option<Mirror<'a, eid!(C::Item), eid!(C::User)>>
+where
+    C: MirroredRatings,
+    eid!(C::User): DocumentId,
+    eid!(C::Item): DocumentId,
+{
+    let kind = RatingCollection::UsersWhoRated;
+    if !controller.mirrored_collections().contains(&kind) {
+        return None;
+    }
+
+    Some(Mirror {
+        collection: db.collection(kind.name()),
+        kind,
+        table: Box::new(move |ids| controller.table_items_ratings(ids)),
+        key: Box::new(move |id| controller.item_document_key(id)),
+    })
+}
+
+/// Ids of the entities of every chunk
+fn ids<E: Entity>(
+    chunks: impl Iterator<Item = Result<Vec<E>>>,
+) -> impl Iterator<Item = Result<Vec<E::Id>>> {
+    chunks.map(|chunk| Ok(chunk?.iter().map(Entity::get_id).collect()))
+}
+
+/// One of the collections along with how to read the ratings table by its owners
+#[allow(clippy::type_complexity)]
+struct Mirror<'a, K, O> {
+    collection: Collection,
+    kind: RatingCollection,
+    table: Box<dyn Fn(&[K]) -> Result<MapedRatings<K, O, TimedScore>> + 'a>,
+    key: Box<dyn Fn(&K) -> Result<Bson> + 'a>,
+}
+
+impl<'a, K, O> Mirror<'a, K, O>
+where
+    K: DocumentId,
+    O: DocumentId,
+{
+    fn check(
+        &self,
+        chunks: impl Iterator<Item = Result<Vec<K>>>,
+        chunk_size: usize,
+    ) -> Result<Vec<Discrepancy<K, O>>> {
+        let mut discrepancies = Vec::new();
+        let mut seen = HashSet::new();
+
+        for ids in chunks {
+            let ids = ids?;
+            let keys = ids.iter().map(&self.key).collect::<Result<Vec<_>>>()?;
+
+            let expected = scores((self.table)(&ids)?);
+            let found = self.documents(keys)?;
+            discrepancies.extend(diff_ratings(&expected, &found));
+            seen.extend(ids);
+        }
+
+        // Documents left behind by removed users (or items)
+        let options = FindOptions::builder()
+            .projection(doc! { self.kind.key(): 1 })
+            .build();
+
+        let mut orphans = Vec::new();
+        for doc in self.collection.find(doc! {}, options)? {
+            let owner = doc?.get(self.kind.key()).cloned();
+            let owner = owner.ok_or(ErrorKind::BsonConvert)?;
+            if !seen.contains(&parse_key::<K>(&owner)?) {
+                orphans.push(owner);
+            }
+        }
+
+        for keys in orphans.chunks(chunk_size.max(1)) {
+            let found = self.documents(keys.to_vec())?;
+            discrepancies.extend(diff_ratings(&MapedRatings::new(), &found));
+        }
+
+        Ok(discrepancies)
+    }
+
+    fn rebuild(&self, chunks: impl Iterator<Item = Result<Vec<K>>>) -> Result<()> {
+        self.collection.delete_many(doc! {}, None)?;
+
+        for ids in chunks {
+            let documents = (self.table)(&ids?)?
+                .iter()
+                .map(|(id, ratings)| Ok(document(self.kind, (self.key)(id)?, ratings)))
+                .collect::<Result<Vec<_>>>()?;
+
+            if !documents.is_empty() {
+                self.collection.insert_many(documents, None)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace the document of `id` with its ratings in the table, or delete it
+    /// when it has none
+    fn rewrite(&self, id: &K) -> Result<()> {
+        let key = (self.key)(id)?;
+        let filter = doc! { self.kind.key(): key.clone() };
+
+        match (self.table)(std::slice::from_ref(id))?.remove(id) {
+            Some(ratings) => {
+                let options = ReplaceOptions::builder().upsert(true).build();
+                let document = document(self.kind, key, &ratings);
+                self.collection.replace_one(filter, document, options)?;
+            }
+
+            None => {
+                self.collection.delete_one(filter, None)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Scores of the documents owned by the given keys
+    fn documents(&self, keys: Vec<Bson>) -> Result<MapedRatings<K, O>> {
+        let filter = doc! { self.kind.key(): { "$in": keys } };
+
+        let mut maped_ratings = MapedRatings::new();
+        for doc in self.collection.find(filter, None)? {
+            let doc = doc?;
+            let owner = doc.get(self.kind.key()).ok_or(ErrorKind::BsonConvert)?;
+
+            let ratings: &mut Ratings<O> = maped_ratings.entry(parse_key(owner)?).or_default();
+            for (other, score) in doc.get_document("scores")? {
+                let score = score.as_f64().ok_or(ErrorKind::BsonConvert)?;
+                ratings.insert(parse(other)?, score);
+            }
+        }
+
+        Ok(maped_ratings)
+    }
+}
+
+fn scores<K, O>(maped_ratings: MapedRatings<K, O, TimedScore>) -> MapedRatings<K, O>
+where
+    K: Hash + Eq,
+    O: Hash + Eq,
+{
+    maped_ratings
+        .into_iter()
+        .map(|(id, ratings)| {
+            let scores = ratings
+                .into_iter()
+                .map(|(other, rating)| (other, rating.score))
+                .collect();
+
+            (id, scores)
+        })
+        .collect()
+}
+
+fn document<O: Display>(
+    collection: RatingCollection,
+    key: Bson,
+    ratings: &Ratings<O, TimedScore>,
+) -> Document {
+    let mut scores = Document::new();
+    let mut times = Document::new();
+
+    for (other, rating) in ratings {
+        scores.insert(other.to_string(), rating.score);
+        if let Some(time) = rating.time {
+            times.insert(other.to_string(), time);
+        }
+    }
+
+    doc! { collection.key(): key, "scores": scores, "times": times }
+}
+
+fn parse<K: FromStr>(key: &str) -> Result<K> {
+    key.parse()
+        .map_err(|_| ErrorKind::ValueConvert(format!("Invalid document key {}", key)).into())
+}
+
+/// Owners are stored as numbers or strings depending on the type of their id
+fn parse_key<K: FromStr>(key: &Bson) -> Result<K> {
+    match key {
+        Bson::Int32(key) => parse(&key.to_string()),
+        Bson::Int64(key) => parse(&key.to_string()),
+        Bson::String(key) => parse(key),
+        _ => Err(ErrorKind::BsonConvert.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_round_trip() -> Result<()> {
+        let mut ratings = Ratings::new();
+        ratings.insert(
+            "0451".to_string(),
+            TimedScore::new(4.5, Some(1_600_000_000)),
+        );
+        ratings.insert("0452".to_string(), TimedScore::new(2., None));
+
+        let doc = document(RatingCollection::UsersRatings, Bson::Int32(7), &ratings);
+        assert_eq!(doc.get_i32("user_id")?, 7);
+        assert_eq!(doc.get_document("scores")?.get_f64("0452")?, 2.);
+        assert_eq!(doc.get_document("times")?.len(), 1);
+
+        assert_eq!(parse_key::<String>(&Bson::Int32(7))?, "7");
+        assert_eq!(parse_key::<i32>(&Bson::String("7".into()))?, 7);
+        assert!(parse_key::<i32>(&Bson::Double(7.)).is_err());
+
+        let maped_ratings = scores(vec![(7, ratings)].into_iter().collect());
+        assert_eq!(maped_ratings[&7]["0451"], 4.5);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::{Result, Timestamp};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::{options::UpdateOptions, sync::Database};
+use std::fmt::Display;
+
+/// Mongo collections that mirror the ratings table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RatingCollection {
+    /// Documents by user, `{ user_id, scores: { item_id: score }, times: { item_id: time } }`
+    UsersRatings,
+
+    /// Documents by item, `{ item_id, scores: { user_id: score }, times: { user_id: time } }`
+    UsersWhoRated,
+}
+
+impl RatingCollection {
+    pub fn name(self) -> &'static str {
+        match self {
+            RatingCollection::UsersRatings => "users_ratings",
+            RatingCollection::UsersWhoRated => "users_who_rated",
+        }
+    }
+
+    pub fn key(self) -> &'static str {
+        match self {
+            RatingCollection::UsersRatings => "user_id",
+            RatingCollection::UsersWhoRated => "item_id",
+        }
+    }
+}
+
+/// A rating mutation recorded in the outbox table within the same transaction
+/// that changed the ratings table, `score` is `None` when the rating was removed
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatingEvent<U, I> {
+    pub id: i64,
+    pub user_id: U,
+    pub item_id: I,
+    pub score: Option<f64>,
+    pub time: Option<Timestamp>,
+}
+
+impl<U, I> RatingEvent<U, I>
+where
+    U: Display + Clone + Into<Bson>,
+    I: Display + Clone + Into<Bson>,
+{
+    /// The filter and update that bring a document of `collection` to the state of
+    /// this event, applying it more than once leaves the document the same
+    pub fn update_for(&self, collection: RatingCollection) -> (Document, Document) {
+        let (owner, other) = match collection {
+            RatingCollection::UsersRatings => {
+                (self.user_id.clone().into(), self.item_id.to_string())
+            }
+            RatingCollection::UsersWhoRated => {
+                (self.item_id.clone().into(), self.user_id.to_string())
+            }
+        };
+
+        let score_key = format!("scores.{}", other);
+        let time_key = format!("times.{}", other);
+
+        let filter = doc! { collection.key(): owner };
+        let update = match (self.score, self.time) {
+            (Some(score), Some(time)) => doc! {
+                "$set": { score_key: score, time_key: time }
+            },
+            (Some(score), None) => doc! {
+                "$set": { score_key: score },
+                "$unset": { time_key: "" }
+            },
+            (None, _) => doc! { "$unset": { score_key: "", time_key: "" } },
+        };
+
+        (filter, update)
+    }
+}
+
+/// Apply the events (in the given order) to the documents of every collection,
+/// documents are created when a rating is set and never when it's removed
+pub fn apply_rating_events<U, I>(
+    db: &Database,
+    events: &[RatingEvent<U, I>],
+    collections: &[RatingCollection],
+) -> Result<()>
+where
+    U: Display + Clone + Into<Bson>,
+    I: Display + Clone + Into<Bson>,
+{
+    for &collection in collections {
+        let mongo = db.collection(collection.name());
+
+        for event in events {
+            let (filter, update) = event.update_for(collection);
+            let options = UpdateOptions::builder()
+                .upsert(event.score.is_some())
+                .build();
+
+            mongo.update_one(filter, update, options)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_become_idempotent_updates() {
+        let set = RatingEvent {
+            id: 1,
+            user_id: 7,
+            item_id: "0451".to_string(),
+            score: Some(4.5),
+            time: Some(1_600_000_000),
+        };
+
+        let (filter, update) = set.update_for(RatingCollection::UsersRatings);
+        assert_eq!(filter, doc! { "user_id": 7 });
+        assert_eq!(
+            update,
+            doc! { "$set": { "scores.0451": 4.5, "times.0451": 1_600_000_000i64 } }
+        );
+
+        let untimed = RatingEvent {
+            time: None,
+            ..set.clone()
+        };
+        let (_, update) = untimed.update_for(RatingCollection::UsersRatings);
+        assert_eq!(
+            update,
+            doc! { "$set": { "scores.0451": 4.5 }, "$unset": { "times.0451": "" } }
+        );
+
+        let removed = RatingEvent { score: None, ..set };
+        let (filter, update) = removed.update_for(RatingCollection::UsersWhoRated);
+        assert_eq!(filter, doc! { "item_id": "0451" });
+        assert_eq!(update, doc! { "$unset": { "scores.7": "", "times.7": "" } });
+    }
+}
@@ -3,13 +3,22 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
+use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::fmt::{self, Display};
 
+/// How many candidates a loose name search returns when no limit is given
+pub const DEFAULT_SEARCH_LIMIT: usize = 10;
+
+/// Minimum share of the searched trigrams a name must have to be a fuzzy match
+pub(crate) const FUZZY_THRESHOLD: f64 = 0.5;
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum SearchBy {
     Id(String),
     Name(String),
     Custom(String, String),
+    NameLike(NameMatch, String, usize),
 }
 
 impl SearchBy {
@@ -24,6 +33,10 @@ impl SearchBy {
     pub fn custom(key: &str, val: &str) -> Self {
         Self::Custom(key.into(), val.into())
     }
+
+    pub fn name_like(mode: NameMatch, name: &str, limit: usize) -> Self {
+        Self::NameLike(mode, name.into(), limit)
+    }
 }
 
 impl Display for SearchBy {
@@ -32,6 +45,192 @@ impl Display for SearchBy {
             SearchBy::Id(id) => write!(f, "id({})", id),
             SearchBy::Name(name) => write!(f, "name({})", name),
             SearchBy::Custom(key, val) => write!(f, "{}({})", key, val),
+            SearchBy::NameLike(mode, name, limit) => write!(f, "{}({}, {})", mode, name, limit),
         }
     }
 }
+
+/// How a name is matched by `SearchBy::NameLike`, case is always ignored
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NameMatch {
+    Contains,
+    Prefix,
+    Fuzzy,
+}
+
+impl NameMatch {
+    /// A `LIKE` pattern over lowercased names that every match satisfies, fuzzy
+    /// matches can't be narrowed down this way. Those matches are ranked first by
+    /// whether they start with the name (see `prefix_pattern`) and then by their
+    /// length, so a query can sort them and apply the limit itself
+    pub fn like_pattern(self, name: &str) -> Option<String> {
+        match self {
+            NameMatch::Contains => Some(format!("%{}%", name.to_lowercase())),
+            NameMatch::Prefix => Some(Self::prefix_pattern(name)),
+            NameMatch::Fuzzy => None,
+        }
+    }
+
+    /// A `LIKE` pattern over lowercased names that start with `name`
+    pub fn prefix_pattern(name: &str) -> String {
+        format!("{}%", name.to_lowercase())
+    }
+
+    /// `LIKE` patterns over lowercased names, every fuzzy match satisfies at least
+    /// one of them. A match shares trigrams with the name, and all of them but the
+    /// ones with the first letter of a word alone hold a pair of letters of a word
+    pub fn fuzzy_patterns(name: &str) -> Vec<String> {
+        let name = name.to_lowercase();
+        let mut patterns = Vec::new();
+
+        for word in words(&name) {
+            let chars: Vec<_> = word.chars().collect();
+            if chars.len() == 1 {
+                patterns.push(format!("%{}%", word));
+            }
+
+            for pair in chars.windows(2) {
+                patterns.push(format!("%{}%", pair.iter().collect::<String>()));
+            }
+        }
+
+        patterns.sort();
+        patterns.dedup();
+        patterns
+    }
+
+    /// How well `candidate` matches the searched `name`, from 0 to 1, `None` if
+    /// it doesn't match at all
+    pub fn score(self, name: &str, candidate: &str) -> Option<f64> {
+        let name = name.to_lowercase();
+        let candidate = candidate.to_lowercase();
+        let coverage = name.chars().count() as f64 / candidate.chars().count().max(1) as f64;
+
+        match self {
+            NameMatch::Contains => {
+                let pos = candidate.find(&name)?;
+                let bonus = if pos == 0 { 1. } else { 0. };
+                Some((coverage + bonus) / 2.)
+            }
+
+            NameMatch::Prefix if candidate.starts_with(&name) => Some(coverage),
+            NameMatch::Prefix => None,
+
+            NameMatch::Fuzzy => {
+                let searched = trigrams(&name);
+                let found = trigrams(&candidate);
+                if searched.is_empty() {
+                    return None;
+                }
+
+                let shared = searched.intersection(&found).count() as f64;
+                let coverage = shared / searched.len() as f64;
+                let similarity = shared / searched.union(&found).count() as f64;
+
+                if coverage < FUZZY_THRESHOLD {
+                    None
+                } else {
+                    Some((coverage + similarity) / 2.)
+                }
+            }
+        }
+    }
+
+    /// Keep the `limit` best matches among `candidates`, best first
+    pub fn rank<T, F>(self, name: &str, candidates: Vec<T>, name_of: F, limit: usize) -> Vec<T>
+    where
+        F: Fn(&T) -> Option<&str>,
+    {
+        let mut scored: Vec<_> = candidates
+            .into_iter()
+            .filter_map(|candidate| {
+                let score = self.score(name, name_of(&candidate)?)?;
+                Some((score, candidate))
+            })
+            .collect();
+
+        scored.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+        scored.into_iter().take(limit).map(|(_, c)| c).collect()
+    }
+}
+
+impl Display for NameMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameMatch::Contains => write!(f, "contains"),
+            NameMatch::Prefix => write!(f, "prefix"),
+            NameMatch::Fuzzy => write!(f, "fuzzy"),
+        }
+    }
+}
+
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+/// Trigrams of every word in `text`, padded like `pg_trgm` does
+fn trigrams(text: &str) -> HashSet<String> {
+    let mut trigrams = HashSet::new();
+
+    for word in words(text) {
+        let padded: Vec<_> = format!("  {} ", word).chars().collect();
+        for window in padded.windows(3) {
+            trigrams.insert(window.iter().collect());
+        }
+    }
+
+    trigrams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranked_name_matches() {
+        let titles = vec![
+            "Toy Story (1995)",
+            "Suture (1993)",
+            "Jumanji (1995)",
+            "Toy Story 2 (1999)",
+            "The Toys (1992)",
+        ];
+
+        let ranked = |mode, name| mode_rank(mode, name, titles.clone());
+
+        assert_eq!(ranked(NameMatch::Contains, "SUTURE"), vec!["Suture (1993)"]);
+        assert_eq!(
+            ranked(NameMatch::Contains, "toy"),
+            vec!["Toy Story (1995)", "Toy Story 2 (1999)", "The Toys (1992)"]
+        );
+        assert_eq!(ranked(NameMatch::Prefix, "the"), vec!["The Toys (1992)"]);
+        assert_eq!(
+            ranked(NameMatch::Fuzzy, "sutur 1993"),
+            vec!["Suture (1993)"]
+        );
+        assert_eq!(ranked(NameMatch::Fuzzy, "jumaji")[0], "Jumanji (1995)");
+        assert!(ranked(NameMatch::Fuzzy, "matrix").is_empty());
+
+        let top = NameMatch::Contains.rank("toy", titles.clone(), |t| Some(*t), 1);
+        assert_eq!(top, vec!["Toy Story (1995)"]);
+
+        // Fuzzy matches all satisfy one of the patterns
+        for name in &["sutur 1993", "jumaji", "toy stroy", "a"] {
+            let patterns = NameMatch::fuzzy_patterns(name);
+            for title in ranked(NameMatch::Fuzzy, name) {
+                let title = title.to_lowercase();
+                assert!(patterns
+                    .iter()
+                    .any(|pattern| title.contains(pattern.trim_matches('%'))));
+            }
+        }
+
+        assert_eq!(NameMatch::fuzzy_patterns("Ab c"), vec!["%ab%", "%c%"]);
+        assert!(NameMatch::fuzzy_patterns("--").is_empty());
+    }
+
+    fn mode_rank<'a>(mode: NameMatch, name: &str, titles: Vec<&'a str>) -> Vec<&'a str> {
+        mode.rank(name, titles, |t| Some(*t), DEFAULT_SEARCH_LIMIT)
+    }
+}
@@ -3,10 +3,17 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
-use crate::error::ErrorKind;
+use crate::{error::ErrorKind, Timestamp};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use regex::Regex;
+use serde::{Serialize, Serializer};
 use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
 
+/// Literal that's read as `Value::Null`, except for strings where it's kept as
+/// it is (a title or a name can be "null")
+pub const NULL_LITERAL: &str = "null";
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Type {
     String,
@@ -15,6 +22,14 @@ pub enum Type {
     Int32,
     Int64,
     Double,
+    /// A calendar date, written as `YYYY-MM-DD`
+    Date,
+    /// Seconds since the unix epoch, written as such or as an RFC 3339 date time
+    Timestamp,
+    /// Comma separated values of the inner type
+    List(&'static Type),
+    /// One of the given strings, read as a `Value::String`
+    Enum(&'static [&'static str]),
 }
 
 impl Display for Type {
@@ -26,30 +41,185 @@ impl Display for Type {
             Type::Int32 => "int32",
             Type::Int64 => "int64",
             Type::Double => "double",
+            Type::Date => "date",
+            Type::Timestamp => "timestamp",
+            Type::List(inner) => return write!(f, "list of {}", inner),
+            Type::Enum(choices) => return write!(f, "one of {}", choices.join(", ")),
         };
 
         write!(f, "{}", o)
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub enum Field<'a> {
-    Required(&'a str, Type),
-    Optional(&'a str, Type),
+/// Regex a field's string values must match, compiled once when it's set
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn is_match(&self, string: &str) -> bool {
+        self.0.is_match(string)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.as_str())
+    }
+}
+
+/// A field of a prototype along with the rules its values must follow, built
+/// with `Field::required` or `Field::optional` and the builder methods.
+///
+/// Fields are declared by the controllers, so the builder panics on rules
+/// that can never hold: a pattern that isn't a valid regex or a default
+/// value the field itself would reject
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field<'a> {
+    pub name: &'a str,
+    pub ty: Type,
+    pub optional: bool,
+    pub description: Option<&'a str>,
+    pub default: Option<Value>,
+    pub range: Option<(f64, f64)>,
+    pub pattern: Option<Pattern>,
+    pub allowed: Vec<Value>,
 }
 
 impl<'a> Field<'a> {
+    pub fn required(name: &'a str, ty: Type) -> Self {
+        Self {
+            name,
+            ty,
+            optional: false,
+            description: None,
+            default: None,
+            range: None,
+            pattern: None,
+            allowed: Vec::new(),
+        }
+    }
+
+    pub fn optional(name: &'a str, ty: Type) -> Self {
+        Self {
+            optional: true,
+            ..Self::required(name, ty)
+        }
+    }
+
+    pub fn describe(mut self, description: &'a str) -> Self {
+        self.description = Some(description);
+        self
+    }
+
+    /// Value taken when the field is left empty
+    pub fn default(mut self, value: Value) -> Self {
+        self.default = Some(value);
+        self.checked()
+    }
+
+    /// Inclusive bounds of numeric values
+    pub fn range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self.checked()
+    }
+
+    /// Regex string values must match
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        let regex = Regex::new(pattern)
+            .unwrap_or_else(|e| panic!("Invalid pattern for field {}: {}", self.name, e));
+        self.pattern = Some(Pattern(regex));
+        self.checked()
+    }
+
+    pub fn allowed(mut self, values: Vec<Value>) -> Self {
+        self.allowed = values;
+        self.checked()
+    }
+
+    /// Make sure the default value, if any, follows the rules set so far
+    fn checked(self) -> Self {
+        if let Some(default) = &self.default {
+            if let Err(e) = self.check(default) {
+                panic!("Invalid default for field {}: {}", self.name, e);
+            }
+        }
+
+        self
+    }
+
     pub fn is_optional(&self) -> bool {
-        match self {
-            Field::Optional(_, _) => true,
-            _ => false,
+        self.optional
+    }
+
+    /// Check a value against the rules of this field, every element of a list
+    /// is checked on its own and nulls are left to the caller
+    pub fn check(&self, value: &Value) -> Result<(), ErrorKind> {
+        let invalid = |reason: String| ErrorKind::InvalidField(self.name.into(), reason);
+
+        match value {
+            Value::Null => return Ok(()),
+            Value::List(values) => return values.iter().try_for_each(|value| self.check(value)),
+            _ => {}
         }
+
+        if let (Some((min, max)), Some(number)) = (self.range, value.as_number()) {
+            if number < min || number > max {
+                return Err(invalid(format!(
+                    "{} isn't between {} and {}",
+                    number, min, max
+                )));
+            }
+        }
+
+        if let (Some(pattern), Value::String(string)) = (&self.pattern, value) {
+            if !pattern.is_match(string) {
+                return Err(invalid(format!("{} doesn't match {}", string, pattern)));
+            }
+        }
+
+        if !self.allowed.is_empty() && !self.allowed.contains(value) {
+            return Err(invalid(format!("{:?} isn't an allowed value", value)));
+        }
+
+        Ok(())
     }
 
-    pub fn into_tuple(self) -> (&'a str, Type) {
-        match self {
-            Field::Required(name, ty) | Field::Optional(name, ty) => (name, ty),
+    /// Short description of the type and rules of this field
+    pub fn hint(&self) -> String {
+        let mut hint = vec![
+            if self.optional {
+                "optional"
+            } else {
+                "required"
+            }
+            .to_string(),
+            self.ty.to_string(),
+        ];
+
+        if let Some((min, max)) = self.range {
+            hint.push(format!("between {} and {}", min, max));
         }
+
+        if let Some(pattern) = &self.pattern {
+            hint.push(format!("matching {}", pattern));
+        }
+
+        if !self.allowed.is_empty() {
+            let allowed: Vec<_> = self.allowed.iter().map(|v| format!("{:?}", v)).collect();
+            hint.push(format!("one of {}", allowed.join(", ")));
+        }
+
+        if let Some(default) = &self.default {
+            hint.push(format!("default {:?}", default));
+        }
+
+        hint.join(", ")
     }
 }
 
@@ -61,10 +231,18 @@ pub enum Value {
     Int32(i32),
     Int64(i64),
     Double(f64),
+    Null,
+    Date(NaiveDate),
+    Timestamp(Timestamp),
+    List(Vec<Value>),
 }
 
 impl Value {
     pub fn from_str(value: &str, tp: Type) -> Result<Self, ErrorKind> {
+        if value == NULL_LITERAL && tp != Type::String {
+            return Ok(Self::Null);
+        }
+
         let value = match tp {
             Type::String => Self::String(value.to_owned()),
             Type::Bool => {
@@ -104,11 +282,78 @@ impl Value {
                     .map_err(|e: <f64 as FromStr>::Err| ErrorKind::ValueConvert(e.to_string()))?;
                 Self::Double(value)
             }
+
+            Type::Date => {
+                let value = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                    .map_err(|e| ErrorKind::ValueConvert(e.to_string()))?;
+                Self::Date(value)
+            }
+
+            Type::Timestamp => {
+                let value = match value.parse::<Timestamp>() {
+                    Ok(value) => value,
+                    Err(_) => DateTime::parse_from_rfc3339(value)
+                        .map(|datetime| datetime.timestamp())
+                        .or_else(|_| {
+                            NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+                                .map(|datetime| datetime.timestamp())
+                        })
+                        .map_err(|e| ErrorKind::ValueConvert(e.to_string()))?,
+                };
+
+                Self::Timestamp(value)
+            }
+
+            Type::List(inner) => {
+                let values = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(|value| Self::from_str(value, *inner))
+                    .collect::<Result<_, _>>()?;
+
+                Self::List(values)
+            }
+
+            Type::Enum(choices) => {
+                let choice = choices
+                    .iter()
+                    .find(|choice| choice.eq_ignore_ascii_case(value))
+                    .ok_or_else(|| {
+                        ErrorKind::ValueConvert(format!("Expected one of {}", choices.join(", ")))
+                    })?;
+
+                Self::String((*choice).to_owned())
+            }
         };
 
         Ok(value)
     }
 
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+
+    /// `None` for `Value::Null`, handy to read optional fields
+    pub fn non_null(&self) -> Option<&Self> {
+        if self.is_null() {
+            None
+        } else {
+            Some(self)
+        }
+    }
+
+    /// Numeric values as a double, `None` for any other value
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Int16(v) => Some(f64::from(*v)),
+            Self::Int32(v) => Some(f64::from(*v)),
+            Self::Int64(v) | Self::Timestamp(v) => Some(*v as f64),
+            Self::Double(v) => Some(*v),
+            _ => None,
+        }
+    }
+
     pub fn as_string(&self) -> Result<&str, ErrorKind> {
         match self {
             Self::String(s) => Ok(s),
@@ -150,6 +395,99 @@ impl Value {
             _ => Err(ErrorKind::CastingValue("f64")),
         }
     }
+
+    pub fn as_date(&self) -> Result<NaiveDate, ErrorKind> {
+        match self {
+            Self::Date(v) => Ok(*v),
+            _ => Err(ErrorKind::CastingValue("NaiveDate")),
+        }
+    }
+
+    pub fn as_timestamp(&self) -> Result<Timestamp, ErrorKind> {
+        match self {
+            Self::Timestamp(v) => Ok(*v),
+            _ => Err(ErrorKind::CastingValue("Timestamp")),
+        }
+    }
+
+    pub fn as_list(&self) -> Result<&[Value], ErrorKind> {
+        match self {
+            Self::List(v) => Ok(v),
+            _ => Err(ErrorKind::CastingValue("List")),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Value::String(v) => write!(f, "{}", v),
+            Value::Bool(v) => write!(f, "{}", v),
+            Value::Int16(v) => write!(f, "{}", v),
+            Value::Int32(v) => write!(f, "{}", v),
+            Value::Int64(v) | Value::Timestamp(v) => write!(f, "{}", v),
+            Value::Double(v) => write!(f, "{}", v),
+            Value::Null => write!(f, "{}", NULL_LITERAL),
+            Value::Date(v) => write!(f, "{}", v.format("%Y-%m-%d")),
+            Value::List(values) => {
+                let values: Vec<_> = values.iter().map(ToString::to_string).collect();
+                write!(f, "{}", values.join(", "))
+            }
+        }
+    }
+}
+
+/// Values are serialized as plain data, dates are written as `YYYY-MM-DD`
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::String(v) => serializer.serialize_str(v),
+            Value::Bool(v) => serializer.serialize_bool(*v),
+            Value::Int16(v) => serializer.serialize_i16(*v),
+            Value::Int32(v) => serializer.serialize_i32(*v),
+            Value::Int64(v) | Value::Timestamp(v) => serializer.serialize_i64(*v),
+            Value::Double(v) => serializer.serialize_f64(*v),
+            Value::Null => serializer.serialize_none(),
+            Value::Date(_) => serializer.serialize_str(&self.to_string()),
+            Value::List(values) => serializer.collect_seq(values),
+        }
+    }
+}
+
+macro_rules! value_from {
+    ($($ty:ty => $variant:ident),*) => {
+        $(
+            impl From<$ty> for Value {
+                fn from(value: $ty) -> Self {
+                    Value::$variant(value.into())
+                }
+            }
+        )*
+    };
+}
+
+value_from! {
+    String => String,
+    &str => String,
+    bool => Bool,
+    i16 => Int16,
+    i32 => Int32,
+    i64 => Int64,
+    u32 => Int64,
+    f64 => Double,
+    NaiveDate => Date
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Self {
+        Value::List(values.into_iter().map(Into::into).collect())
+    }
 }
 
 #[cfg(test)]
@@ -217,4 +555,102 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn casting_null() -> Result<(), Error> {
+        let value = Value::from_str("null", Type::Int16)?;
+
+        assert!(value.is_null());
+        assert!(value.non_null().is_none());
+        assert!(value.as_i16().is_err());
+
+        let value = Value::from_str("null", Type::String)?;
+        assert_eq!(value, Value::String("null".into()));
+
+        let value = Value::from_str("null", Type::List(&Type::String))?;
+        assert!(value.is_null());
+
+        Ok(())
+    }
+
+    #[test]
+    fn casting_date_and_timestamp() -> Result<(), Error> {
+        let value = Value::from_str("2020-06-21", Type::Date)?;
+        assert_eq!(value.as_date()?, NaiveDate::from_ymd(2020, 6, 21));
+
+        let value = Value::from_str("1592697600", Type::Timestamp)?;
+        assert_eq!(value.as_timestamp()?, 1_592_697_600);
+
+        let value = Value::from_str("2020-06-21T00:00:00Z", Type::Timestamp)?;
+        assert_eq!(value.as_timestamp()?, 1_592_697_600);
+
+        assert!(Value::from_str("21/06/2020", Type::Date).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn casting_lists_of_enums() -> Result<(), Error> {
+        const GENRES: &[&str] = &["Comedy", "Horror", "Sci-Fi"];
+        let ty = Type::List(&Type::Enum(GENRES));
+
+        let value = Value::from_str("horror, Sci-Fi", ty)?;
+        let genres: Vec<_> = value
+            .as_list()?
+            .iter()
+            .map(Value::as_string)
+            .collect::<Result<_, _>>()?;
+
+        assert_eq!(genres, vec!["Horror", "Sci-Fi"]);
+        assert!(Value::from_str("Western", ty).is_err());
+        assert_eq!(ty.to_string(), "list of one of Comedy, Horror, Sci-Fi");
+
+        Ok(())
+    }
+
+    #[test]
+    fn field_rules() {
+        let age = Field::optional("age", Type::Int16).range(0., 120.);
+        assert!(age.check(&Value::Int16(31)).is_ok());
+        assert!(age.check(&Value::Int16(244)).is_err());
+        assert!(age.check(&Value::Null).is_ok());
+
+        let title = Field::required("title", Type::String).pattern(r"\(\d{4}\)$");
+        assert!(title.check(&Value::String("Alien (1979)".into())).is_ok());
+        assert!(title.check(&Value::String("Alien".into())).is_err());
+
+        let shelf = Field::required("shelf", Type::List(&Type::String))
+            .allowed(vec![
+                Value::String("read".into()),
+                Value::String("to-read".into()),
+            ])
+            .default(Value::String("to-read".into()));
+        let shelves = Value::List(vec![Value::String("read".into())]);
+        assert!(shelf.check(&shelves).is_ok());
+        assert!(shelf.check(&Value::String("owned".into())).is_err());
+        assert_eq!(age.hint(), "optional, int16, between 0 and 120");
+        assert_eq!(title.hint(), r"required, string, matching \(\d{4}\)$");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid default for field age")]
+    fn default_out_of_range() {
+        Field::optional("age", Type::Int16)
+            .default(Value::Int16(244))
+            .range(0., 120.);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid default for field shelf")]
+    fn default_not_allowed() {
+        Field::required("shelf", Type::String)
+            .default(Value::String("owned".into()))
+            .allowed(vec![Value::String("read".into())]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid pattern for field title")]
+    fn invalid_pattern() {
+        Field::required("title", Type::String).pattern(r"(\d{4}$");
+    }
 }
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+-- Rating mutations waiting to be applied to mongo, a null score means the rating
+-- was removed. Rows are written in the same transaction as the ratings table
+CREATE TABLE rating_outbox (
+    id BIGSERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    book_id VARCHAR NOT NULL,
+    score FLOAT
+)
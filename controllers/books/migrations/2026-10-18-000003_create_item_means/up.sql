@@ -0,0 +1,18 @@
+-- Mean and number of ratings of each item, and the same over every rating in the
+-- single row of global_mean, both are kept up to date by triggers
+CREATE TABLE item_means
+(
+    item_id VARCHAR REFERENCES books(id),
+    val FLOAT NOT NULL,
+    score_number INTEGER NOT NULL,
+    PRIMARY KEY (item_id)
+);
+
+CREATE INDEX item_means_score_number_idx ON item_means(score_number);
+
+CREATE TABLE global_mean
+(
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    val FLOAT NOT NULL,
+    score_number BIGINT NOT NULL
+);
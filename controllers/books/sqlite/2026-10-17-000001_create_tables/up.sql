@@ -0,0 +1,28 @@
+-- Same tables as the postgres migrations, in sqlite dialect
+
+CREATE TABLE users (
+    id INTEGER PRIMARY KEY,
+    location VARCHAR NOT NULL,
+    age SMALLINT DEFAULT NULL
+);
+
+CREATE TABLE books (
+    id VARCHAR PRIMARY KEY NOT NULL,
+    title VARCHAR NOT NULL,
+    author VARCHAR NOT NULL,
+    year SMALLINT NOT NULL,
+    publisher VARCHAR NOT NULL
+);
+
+CREATE TABLE ratings (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    book_id VARCHAR NOT NULL REFERENCES books(id),
+    score FLOAT NOT NULL
+);
+
+CREATE TABLE means (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id),
+    val FLOAT NOT NULL,
+    score_number INTEGER NOT NULL
+);
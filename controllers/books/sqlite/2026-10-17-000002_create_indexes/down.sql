@@ -0,0 +1,2 @@
+DROP INDEX ratings_book_id_idx;
+DROP INDEX ratings_user_id_book_id_idx;
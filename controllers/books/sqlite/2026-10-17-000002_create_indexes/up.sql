@@ -0,0 +1,2 @@
+CREATE UNIQUE INDEX ratings_user_id_book_id_idx on ratings(user_id, book_id);
+CREATE INDEX ratings_book_id_idx on ratings(book_id);
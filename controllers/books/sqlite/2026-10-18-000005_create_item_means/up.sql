@@ -0,0 +1,65 @@
+-- Same tables as the postgres migrations, they start from the ratings already
+-- stored and are kept up to date by triggers
+
+CREATE TABLE item_means (
+    item_id VARCHAR PRIMARY KEY NOT NULL REFERENCES books(id),
+    val FLOAT NOT NULL,
+    score_number INTEGER NOT NULL
+);
+
+CREATE INDEX item_means_score_number_idx ON item_means(score_number);
+
+CREATE TABLE global_mean (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    val FLOAT NOT NULL,
+    score_number BIGINT NOT NULL
+);
+
+INSERT INTO item_means(item_id, val, score_number)
+SELECT book_id, AVG(score), COUNT(*) FROM ratings GROUP BY book_id;
+
+INSERT INTO global_mean(id, val, score_number)
+SELECT 1, COALESCE(AVG(score), 0), COUNT(*) FROM ratings;
+
+CREATE TRIGGER update_item_means_on_new AFTER INSERT ON ratings
+FOR EACH ROW
+BEGIN
+    INSERT OR IGNORE INTO item_means(item_id, val, score_number) VALUES (new.book_id, 0, 0);
+
+    UPDATE item_means
+    SET val = (val * score_number + new.score) / (score_number + 1),
+        score_number = score_number + 1
+    WHERE item_id = new.book_id;
+
+    UPDATE global_mean
+    SET val = (val * score_number + new.score) / (score_number + 1),
+        score_number = score_number + 1;
+END;
+
+CREATE TRIGGER update_item_means_on_del AFTER DELETE ON ratings
+FOR EACH ROW
+BEGIN
+    DELETE FROM item_means WHERE item_id = old.book_id AND score_number <= 1;
+
+    UPDATE item_means
+    SET val = (val * score_number - old.score) / (score_number - 1),
+        score_number = score_number - 1
+    WHERE item_id = old.book_id;
+
+    UPDATE global_mean
+    SET val = CASE WHEN score_number <= 1 THEN 0
+                   ELSE (val * score_number - old.score) / (score_number - 1)
+              END,
+        score_number = MAX(score_number - 1, 0);
+END;
+
+CREATE TRIGGER update_item_means_on_upd AFTER UPDATE OF score ON ratings
+FOR EACH ROW
+BEGIN
+    UPDATE item_means
+    SET val = val + (new.score - old.score) / score_number
+    WHERE item_id = new.book_id;
+
+    UPDATE global_mean
+    SET val = val + (new.score - old.score) / score_number;
+END;
@@ -9,13 +9,12 @@ use books::models::{books::NewBook, ratings::NewRating, users::NewUser};
 use books::schema::{books as books_sc, ratings, users};
 use books::BooksController;
 use config::Config;
-use controller::{Controller, SearchBy};
-use diesel::pg::PgConnection;
+use controller::{with_conn, Controller, DbConnection, SearchBy};
 use diesel::{insert_into, prelude::*};
 use indicatif::ProgressIterator;
 use std::collections::HashMap;
 
-fn insert_users(conn: &PgConnection) -> Result<(), Error> {
+fn insert_users(conn: &DbConnection) -> Result<(), Error> {
     let mut csv = csv::ReaderBuilder::new()
         .has_headers(false)
         .delimiter(b';')
@@ -41,13 +40,13 @@ fn insert_users(conn: &PgConnection) -> Result<(), Error> {
 
     println!("Pushing users by chunks");
     for chunk in users.chunks(10_000).progress() {
-        insert_into(users::table).values(chunk).execute(conn)?;
+        with_conn!(conn, conn => insert_into(users::table).values(chunk).execute(conn))?;
     }
 
     Ok(())
 }
 
-fn insert_books(conn: &PgConnection) -> Result<(), Error> {
+fn insert_books(conn: &DbConnection) -> Result<(), Error> {
     let mut csv = csv::ReaderBuilder::new()
         .has_headers(false)
         .delimiter(b';')
@@ -77,13 +76,13 @@ fn insert_books(conn: &PgConnection) -> Result<(), Error> {
 
     println!("Pushing books by chunks");
     for chunk in books.chunks(10_000).progress() {
-        insert_into(books_sc::table).values(chunk).execute(conn)?;
+        with_conn!(conn, conn => insert_into(books_sc::table).values(chunk).execute(conn))?;
     }
 
     Ok(())
 }
 
-fn insert_ratings(conn: &PgConnection, config: &Config) -> Result<(), Error> {
+fn insert_ratings(conn: &DbConnection, config: &Config) -> Result<(), Error> {
     let mut csv = csv::ReaderBuilder::new()
         .has_headers(false)
         .delimiter(b',')
@@ -110,13 +109,14 @@ fn insert_ratings(conn: &PgConnection, config: &Config) -> Result<(), Error> {
                 score,
                 user_id,
                 book_id,
+                rated_at: None,
             });
         }
     }
 
     println!("Pushing ratings by chunks");
     for chunk in ratings.chunks(10_000).progress() {
-        insert_into(ratings::table).values(chunk).execute(conn)?;
+        with_conn!(conn, conn => insert_into(ratings::table).values(chunk).execute(conn))?;
     }
 
     Ok(())
@@ -127,11 +127,11 @@ fn main() -> Result<(), Error> {
     let mut config = Config::default();
 
     let db = config.databases.get_mut("books").unwrap();
-    db.psql_url = vars["DATABASE_URL"].clone();
+    db.set_database_url(&vars["DATABASE_URL"]);
     db.mongo_url = vars["MONGO_URL"].clone();
     db.mongo_db = vars["MONGO_DB"].clone();
 
-    let conn = establish_connection(&db.psql_url)?;
+    let conn = establish_connection(db.backend, db.database_url())?;
 
     insert_users(&conn)?;
     insert_books(&conn)?;
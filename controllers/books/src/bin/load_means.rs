@@ -8,8 +8,8 @@ use books::establish_connection;
 use books::models::users::NewMean;
 use books::schema::means;
 use books::BooksController;
-use config::Config;
-use controller::Controller;
+use config::{Backend, Config};
+use controller::{with_conn, Controller};
 use diesel::{insert_into, prelude::*};
 use std::collections::HashMap;
 
@@ -35,13 +35,13 @@ fn main() -> Result<(), Error> {
     db.mongo_url = vars["MONGO_URL"].clone();
     db.mongo_db = vars["MONGO_DB"].clone();
 
-    let conn = establish_connection(&db.psql_url)?;
+    let conn = establish_connection(Backend::Postgres, &db.psql_url)?;
     let controller = BooksController::from_config(&config, "books")?;
 
     let users_iterator = controller.users_by_chunks(10000);
     for user_chunk in users_iterator {
         let mut means = Vec::new();
-        let maped_ratings = controller.users_ratings(&user_chunk)?;
+        let maped_ratings = controller.users_ratings(&user_chunk?)?;
 
         for (user_id, ratings) in maped_ratings {
             let mean = compute_mean(&ratings);
@@ -59,7 +59,7 @@ fn main() -> Result<(), Error> {
             }
         }
 
-        insert_into(means::table).values(&means).execute(&conn)?;
+        with_conn!(&conn, conn => insert_into(means::table).values(&means).execute(conn))?;
     }
 
     Ok(())
@@ -29,7 +29,7 @@ fn main() -> Result<(), Error> {
     let mut item_ids = HashSet::new();
 
     for items in controller.items_by_chunks(20000) {
-        for item in items {
+        for item in items? {
             item_ids.insert(item.id);
         }
     }
@@ -5,6 +5,8 @@
 
 #[macro_use]
 extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
 
 pub mod models;
 pub mod schema;
@@ -14,32 +16,67 @@ use crate::models::{
     ratings::Rating,
     users::{Mean, User},
 };
-use crate::schema::{books, ratings, users};
+use crate::schema::{books, global_mean, item_means, means, rating_outbox, ratings, users};
 use anyhow::Error;
-use config::Config;
+use config::{Backend, Config};
+use controller::backend::{length, lower, word_similarity, WordSimilar};
+use controller::outbox::{apply_rating_events, RatingCollection, RatingEvent};
 use controller::{
-    eid, error::ErrorKind, maped_ratings, means, ratings, Controller, Field, SearchBy, Type,
+    counts, eid, error::ErrorKind, insert_returning, maped_ratings, means, now, ratings, with_conn,
+    Controller, DbConnection, Field, Histogram, ItemFeatures, MapedRatings, MirroredRatings,
+    NameMatch, RatingKind, RatingScale, SearchBy, TimedScore, Timestamp, Type,
+};
+use diesel::{
+    delete,
+    dsl::{exists, sql},
+    insert_into,
+    prelude::*,
+    select,
+    sql_types::{BigInt, Bool, Text},
+    update,
+};
+use models::{
+    books::{NewBook, NewUnseenBook},
+    ratings::{NewOutboxEvent, NewRating, OutboxEvent},
+    users::NewUnseenUser,
 };
-use diesel::pg::PgConnection;
-use diesel::{delete, insert_into, prelude::*, update};
-use models::{books::NewUnseenBook, ratings::NewRating, users::NewUnseenUser};
-use mongodb::bson::doc;
+use mongodb::bson::{doc, Bson};
 use mongodb::{
-    options::{FindOptions, UpdateOptions},
+    options::FindOptions,
     sync::{Client, Database},
 };
-use num_traits::Zero;
 use std::collections::HashMap;
 
-pub fn establish_connection(url: &str) -> Result<PgConnection, Error> {
-    Ok(PgConnection::establish(&url)?)
+embed_migrations!("sqlite");
+
+/// Ratings documents kept in mongo, they're written through the outbox
+const MONGO_COLLECTIONS: &[RatingCollection] = &[
+    RatingCollection::UsersRatings,
+    RatingCollection::UsersWhoRated,
+];
+
+const OUTBOX_CHUNK_SIZE: i64 = 1000;
+
+/// Book-Crossing records implicit interactions (the book was read but not rated)
+/// with a score of 0
+const IMPLICIT_SCORE: f64 = 0.;
+
+pub fn establish_connection(backend: Backend, url: &str) -> Result<DbConnection, Error> {
+    let conn = DbConnection::establish(backend, url)?;
+
+    // A sqlite database is created empty, so its tables are created on first use
+    if let DbConnection::Sqlite(conn) = &conn {
+        embedded_migrations::run(conn)?;
+    }
+
+    Ok(conn)
 }
 
 pub struct BooksController {
     users_ratings_mongo: bool,
     users_who_rated_mongo: bool,
-    pg_conn: PgConnection,
-    mongo_db: Database,
+    conn: DbConnection,
+    mongo_db: Option<Database>,
 }
 
 impl BooksController {
@@ -55,22 +92,254 @@ impl BooksController {
             .get(name)
             .ok_or_else(|| ErrorKind::DbConfigError(name.into()))?;
 
-        let users_ratings_mongo = db.users_ratings_mongo;
-        let users_who_rated_mongo = db.users_who_rated_mongo;
-        let psql_url = &db.psql_url;
-        let mongo_url = &db.mongo_url;
-        let mongo_db = &db.mongo_db;
+        let conn = establish_connection(db.backend, db.database_url())?;
+        let mongo_db = match db.backend {
+            Backend::Postgres => Some(Client::with_uri_str(&db.mongo_url)?.database(&db.mongo_db)),
+            Backend::Sqlite => None,
+        };
 
-        let pg_conn = establish_connection(psql_url)?;
-        let client = Client::with_uri_str(mongo_url)?;
-        let mongo_db = client.database(mongo_db);
+        let users_ratings_mongo = db.users_ratings_mongo && mongo_db.is_some();
+        let users_who_rated_mongo = db.users_who_rated_mongo && mongo_db.is_some();
 
-        Ok(Self {
+        let controller = Self {
             users_ratings_mongo,
             users_who_rated_mongo,
-            pg_conn,
+            conn,
             mongo_db,
-        })
+        };
+
+        // Events left behind by a crash or a mongo outage
+        controller.flush_outbox();
+        Ok(controller)
+    }
+
+    fn mongo_db(&self) -> Result<&Database, Error> {
+        Ok(self.mongo_db.as_ref().ok_or(ErrorKind::MongoUnavailable)?)
+    }
+
+    /// Apply the pending events of the outbox to mongo by chunks, events are
+    /// removed once applied so whatever fails is retried on the next replay.
+    /// Returns how many events were applied
+    pub fn replay_outbox(&self) -> Result<usize, Error> {
+        let (conn, mongo_db) = match (&self.conn, &self.mongo_db) {
+            (DbConnection::Postgres(conn), Some(mongo_db)) => (conn, mongo_db),
+            _ => return Ok(0),
+        };
+
+        let mut applied = 0;
+        loop {
+            // Rows stay locked until they're deleted, so concurrent replays
+            // don't apply the same events out of order
+            let replayed = conn.transaction::<_, Error, _>(|| {
+                let events: Vec<RatingEvent<_, _>> = rating_outbox::table
+                    .order(rating_outbox::id)
+                    .limit(OUTBOX_CHUNK_SIZE)
+                    .for_update()
+                    .load::<OutboxEvent>(conn)?
+                    .into_iter()
+                    .map(Into::into)
+                    .collect();
+
+                apply_rating_events(mongo_db, &events, MONGO_COLLECTIONS)?;
+
+                let ids: Vec<_> = events.iter().map(|event| event.id).collect();
+                delete(rating_outbox::table.filter(rating_outbox::id.eq_any(&ids)))
+                    .execute(conn)?;
+
+                Ok(events.len())
+            })?;
+
+            applied += replayed;
+            if replayed < OUTBOX_CHUNK_SIZE as usize {
+                return Ok(applied);
+            }
+        }
+    }
+
+    /// Ratings are written to mongo after the transaction is committed, if mongo
+    /// isn't reachable the events stay in the outbox until the next replay
+    fn flush_outbox(&self) {
+        if let Err(e) = self.replay_outbox() {
+            log::error!("Couldn't replay the rating outbox into mongo: {}", e);
+            match self.pending_events() {
+                Ok(pending) => {
+                    log::warn!("{} rating events are still pending in the outbox", pending)
+                }
+                Err(e) => log::error!("Couldn't count the pending events of the outbox: {}", e),
+            }
+        }
+    }
+
+    /// Number of events in the outbox that haven't been applied to mongo yet
+    pub fn pending_events(&self) -> Result<usize, Error> {
+        with_conn!(&self.conn, conn => rating_outbox::table.count().get_result::<i64>(conn))
+            .map(|count| count as usize)
+            .map_err(Into::into)
+    }
+
+    fn insert_rating_sql(&self, new_rating: &NewRating) -> Result<Rating, Error> {
+        let outbox = self.mongo_db.is_some();
+        let rating = with_conn!(&self.conn, conn => conn.transaction::<_, Error, _>(|| {
+            let rated = ratings::table
+                .filter(ratings::user_id.eq(new_rating.user_id))
+                .filter(ratings::book_id.eq(new_rating.book_id));
+
+            if select(exists(rated)).get_result(conn)? {
+                return Err(ErrorKind::InsertRatingFailed(
+                    new_rating.user_id.to_string(),
+                    new_rating.book_id.to_string(),
+                )
+                .into());
+            }
+
+            insert_into(ratings::table).values(new_rating).execute(conn)?;
+            if outbox {
+                let event = NewOutboxEvent {
+                    user_id: new_rating.user_id,
+                    book_id: new_rating.book_id,
+                    score: Some(new_rating.score),
+                    rated_at: new_rating.rated_at,
+                };
+
+                insert_into(rating_outbox::table).values(&event).execute(conn)?;
+            }
+
+            Ok(rated.first(conn)?)
+        }))?;
+
+        Ok(rating)
+    }
+
+    fn remove_rating_sql(&self, user_id: &i32, item_id: &str) -> Result<Rating, Error> {
+        let outbox = self.mongo_db.is_some();
+        let rating = with_conn!(&self.conn, conn => conn.transaction::<_, Error, _>(|| {
+            let rated = ratings::table
+                .filter(ratings::user_id.eq(user_id))
+                .filter(ratings::book_id.eq(item_id));
+
+            let rating: Rating = rated.first(conn).optional()?.ok_or_else(|| {
+                ErrorKind::RemoveRatingFailed(user_id.to_string(), item_id.to_string())
+            })?;
+
+            delete(rated).execute(conn)?;
+            if outbox {
+                let event = NewOutboxEvent {
+                    user_id: *user_id,
+                    book_id: item_id,
+                    score: None,
+                    rated_at: None,
+                };
+
+                insert_into(rating_outbox::table).values(&event).execute(conn)?;
+            }
+
+            Ok(rating)
+        }))?;
+
+        Ok(rating)
+    }
+
+    fn update_rating_sql(&self, user_id: &i32, item_id: &str, score: f64) -> Result<Rating, Error> {
+        let outbox = self.mongo_db.is_some();
+        let rating = with_conn!(&self.conn, conn => conn.transaction::<_, Error, _>(|| {
+            let rated = ratings::table
+                .filter(ratings::user_id.eq(user_id))
+                .filter(ratings::book_id.eq(item_id));
+
+            // The new score must be different from the actual one
+            rated
+                .select(ratings::score)
+                .first::<f64>(conn)
+                .optional()?
+                .filter(|old_score| *old_score != score)
+                .ok_or_else(|| {
+                    ErrorKind::UpdateRatingFailed(user_id.to_string(), item_id.to_string())
+                })?;
+
+            let rated_at = Some(now());
+            update(rated)
+                .set((ratings::score.eq(score), ratings::rated_at.eq(rated_at)))
+                .execute(conn)?;
+
+            if outbox {
+                let event = NewOutboxEvent {
+                    user_id: *user_id,
+                    book_id: item_id,
+                    score: Some(score),
+                    rated_at,
+                };
+
+                insert_into(rating_outbox::table).values(&event).execute(conn)?;
+            }
+
+            Ok(rated.first(conn)?)
+        }))?;
+
+        Ok(rating)
+    }
+
+    fn update_user_sql(&self, user_id: &i32, changes: &NewUnseenUser) -> Result<User, Error> {
+        let user = with_conn!(&self.conn, conn => conn.transaction(|| {
+            update(users::table.find(user_id)).set(changes).execute(conn)?;
+            users::table.find(user_id).first(conn)
+        }))?;
+
+        Ok(user)
+    }
+
+    fn update_item_sql(&self, item_id: &str, changes: &NewUnseenBook) -> Result<Book, Error> {
+        let item = with_conn!(&self.conn, conn => conn.transaction(|| {
+            update(books::table.find(item_id)).set(changes).execute(conn)?;
+            books::table.find(item_id).first(conn)
+        }))?;
+
+        Ok(item)
+    }
+
+    fn remove_user_sql(&self, user_id: &i32) -> Result<User, Error> {
+        let outbox = self.mongo_db.is_some();
+        let user = with_conn!(&self.conn, conn => conn.transaction::<_, diesel::result::Error, _>(|| {
+            let user = users::table.find(user_id).first::<User>(conn)?;
+
+            let rated = ratings::table.filter(ratings::user_id.eq(user_id));
+            if outbox {
+                insert_into(rating_outbox::table)
+                    .values(rated.select((ratings::user_id, ratings::book_id)))
+                    .into_columns((rating_outbox::user_id, rating_outbox::book_id))
+                    .execute(conn)?;
+            }
+
+            delete(rated).execute(conn)?;
+            delete(means::table.filter(means::user_id.eq(user_id))).execute(conn)?;
+            delete(users::table.find(user_id)).execute(conn)?;
+
+            Ok(user)
+        }))?;
+
+        Ok(user)
+    }
+
+    fn remove_item_sql(&self, item_id: &str) -> Result<Book, Error> {
+        let outbox = self.mongo_db.is_some();
+        let item = with_conn!(&self.conn, conn => conn.transaction::<_, diesel::result::Error, _>(|| {
+            let item = books::table.find(item_id).first::<Book>(conn)?;
+
+            let rated = ratings::table.filter(ratings::book_id.eq(item_id));
+            if outbox {
+                insert_into(rating_outbox::table)
+                    .values(rated.select((ratings::user_id, ratings::book_id)))
+                    .into_columns((rating_outbox::user_id, rating_outbox::book_id))
+                    .execute(conn)?;
+            }
+
+            // The means triggers update the mean of every user that rated it
+            delete(rated).execute(conn)?;
+            delete(books::table.find(item_id)).execute(conn)?;
+
+            Ok(item)
+        }))?;
+
+        Ok(item)
     }
 }
 
@@ -80,7 +349,7 @@ impl Controller for BooksController {
     type Rating = Rating;
 
     fn users(&self) -> Result<Vec<Self::User>, Error> {
-        let users = users::table.load::<User>(&self.pg_conn)?;
+        let users = with_conn!(&self.conn, conn => users::table.load::<User>(conn))?;
         Ok(users)
     }
 
@@ -88,7 +357,7 @@ impl Controller for BooksController {
         match by {
             SearchBy::Id(id) => {
                 let id: i32 = id.parse()?;
-                let users = users::table.filter(users::id.eq(id)).load(&self.pg_conn)?;
+                let users = with_conn!(&self.conn, conn => users::table.filter(users::id.eq(id)).load(conn))?;
 
                 if users.is_empty() {
                     Err(ErrorKind::NotFoundById(id.to_string()).into())
@@ -97,29 +366,80 @@ impl Controller for BooksController {
                 }
             }
 
-            SearchBy::Name(name) => Err(ErrorKind::NotFoundByName(name.clone()).into()),
-            SearchBy::Custom(k, v) => Err(ErrorKind::NotFoundByCustom(k.clone(), v.clone()).into()),
+            SearchBy::Name(name) | SearchBy::NameLike(_, name, _) => {
+                Err(ErrorKind::NotFoundByName(name.clone()).into())
+            }
+            SearchBy::Custom(k, v) => {
+                let users: Vec<User> = match k.as_str() {
+                    // Locations look like "nyc, new york, usa", any of its parts matches
+                    "location" => with_conn!(&self.conn, conn => users::table
+                        .filter(users::location.like(format!("%{}%", v)))
+                        .load::<User>(conn))?
+                    .into_iter()
+                    .filter(|user| {
+                        user.location == *v || user.location.split(", ").any(|part| part == v)
+                    })
+                    .collect(),
+
+                    "age" => {
+                        let age: i16 = v.parse()?;
+                        with_conn!(&self.conn, conn => users::table
+                            .filter(users::age.eq(age))
+                            .load(conn))?
+                    }
+
+                    _ => return Err(ErrorKind::CustomSearchNotSupported(k.clone()).into()),
+                };
+
+                if users.is_empty() {
+                    Err(ErrorKind::NotFoundByCustom(k.clone(), v.clone()).into())
+                } else {
+                    Ok(users)
+                }
+            }
         }
     }
 
     fn users_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<Self::User>, Error> {
-        let users = users::table
+        let users = with_conn!(&self.conn, conn => users::table
+            .order(users::id)
             .offset(offset as i64)
             .limit(limit as i64)
-            .load::<User>(&self.pg_conn)?;
+            .load::<User>(conn))?;
+
+        Ok(users)
+    }
+
+    fn users_after(
+        &self,
+        after: Option<&eid!(Self::User)>,
+        limit: usize,
+    ) -> Result<Vec<Self::User>, Error> {
+        let users = with_conn!(&self.conn, conn => {
+            let mut query = users::table
+                .order(users::id)
+                .limit(limit as i64)
+                .into_boxed();
+
+            if let Some(after) = after {
+                query = query.filter(users::id.gt(after));
+            }
+
+            query.load::<User>(conn)
+        })?;
 
         Ok(users)
     }
 
     fn items(&self) -> Result<Vec<Self::Item>, Error> {
-        let items = books::table.load::<Book>(&self.pg_conn)?;
+        let items = with_conn!(&self.conn, conn => books::table.load::<Book>(conn))?;
         Ok(items)
     }
 
     fn items_by(&self, by: &SearchBy) -> Result<Vec<Self::Item>, Error> {
         match by {
             SearchBy::Id(id) => {
-                let books = books::table.filter(books::id.eq(id)).load(&self.pg_conn)?;
+                let books = with_conn!(&self.conn, conn => books::table.filter(books::id.eq(id)).load(conn))?;
 
                 if books.is_empty() {
                     Err(ErrorKind::NotFoundById(id.to_string()).into())
@@ -129,9 +449,9 @@ impl Controller for BooksController {
             }
 
             SearchBy::Name(name) => {
-                let books = books::table
+                let books = with_conn!(&self.conn, conn => books::table
                     .filter(books::title.eq(name))
-                    .load(&self.pg_conn)?;
+                    .load(conn))?;
 
                 if books.is_empty() {
                     Err(ErrorKind::NotFoundByName(name.clone()).into())
@@ -140,15 +460,103 @@ impl Controller for BooksController {
                 }
             }
 
-            SearchBy::Custom(k, v) => Err(ErrorKind::NotFoundByCustom(k.clone(), v.clone()).into()),
+            SearchBy::NameLike(mode, name, limit) => {
+                let column = lower(books::title);
+                let candidates = match (&self.conn, mode.like_pattern(name)) {
+                    (_, Some(pattern)) => with_conn!(&self.conn, conn => books::table
+                        .filter(column.like(pattern))
+                        .order((
+                            column.like(NameMatch::prefix_pattern(name)).desc(),
+                            length(books::title),
+                        ))
+                        .limit(*limit as i64)
+                        .load::<Book>(conn))?,
+
+                    // Ranked by pg_trgm, the trigram index of the column is used
+                    (DbConnection::Postgres(conn), None) => {
+                        let name = name.to_lowercase();
+                        books::table
+                            .filter(WordSimilar::new(name.as_str().into_sql::<Text>(), column))
+                            .order(word_similarity(&name, column).desc())
+                            .limit(*limit as i64)
+                            .load::<Book>(conn)?
+                    }
+
+                    // Sqlite has no trigrams, only the names that could match are loaded
+                    (DbConnection::Sqlite(conn), None) => {
+                        let mut query = books::table.filter(false.into_sql::<Bool>()).into_boxed();
+                        for pattern in NameMatch::fuzzy_patterns(name) {
+                            query = query.or_filter(column.like(pattern));
+                        }
+
+                        query.load::<Book>(conn)?
+                    }
+                };
+
+                let books = mode.rank(name, candidates, |book| Some(&book.title), *limit);
+                if books.is_empty() {
+                    Err(ErrorKind::NotFoundByName(name.clone()).into())
+                } else {
+                    Ok(books)
+                }
+            }
+
+            SearchBy::Custom(k, v) => {
+                let books: Vec<Book> = match k.as_str() {
+                    "author" => with_conn!(&self.conn, conn => books::table
+                        .filter(books::author.eq(v))
+                        .load(conn))?,
+
+                    "publisher" => with_conn!(&self.conn, conn => books::table
+                        .filter(books::publisher.eq(v))
+                        .load(conn))?,
+
+                    "year" => {
+                        let year: i16 = v.parse()?;
+                        with_conn!(&self.conn, conn => books::table
+                            .filter(books::year.eq(year))
+                            .load(conn))?
+                    }
+
+                    _ => return Err(ErrorKind::CustomSearchNotSupported(k.clone()).into()),
+                };
+
+                if books.is_empty() {
+                    Err(ErrorKind::NotFoundByCustom(k.clone(), v.clone()).into())
+                } else {
+                    Ok(books)
+                }
+            }
         }
     }
 
     fn items_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<Self::Item>, Error> {
-        let items = books::table
+        let items = with_conn!(&self.conn, conn => books::table
+            .order(books::id)
             .offset(offset as i64)
             .limit(limit as i64)
-            .load::<Book>(&self.pg_conn)?;
+            .load::<Book>(conn))?;
+
+        Ok(items)
+    }
+
+    fn items_after(
+        &self,
+        after: Option<&eid!(Self::Item)>,
+        limit: usize,
+    ) -> Result<Vec<Self::Item>, Error> {
+        let items = with_conn!(&self.conn, conn => {
+            let mut query = books::table
+                .order(books::id)
+                .limit(limit as i64)
+                .into_boxed();
+
+            if let Some(after) = after {
+                query = query.filter(books::id.gt(after));
+            }
+
+            query.load::<Book>(conn)
+        })?;
 
         Ok(items)
     }
@@ -189,7 +597,8 @@ impl Controller for BooksController {
         items: &[Self::Item],
     ) -> Result<maped_ratings!(Self::Item => Self::User), Error> {
         if !self.users_who_rated_mongo {
-            let ratings = Rating::belonging_to(items).load::<Rating>(&self.pg_conn)?;
+            let ratings =
+                with_conn!(&self.conn, conn => Rating::belonging_to(items).load::<Rating>(conn))?;
 
             let mut items_users = HashMap::new();
             for rating in ratings {
@@ -201,7 +610,7 @@ impl Controller for BooksController {
 
             Ok(items_users)
         } else {
-            let collection = self.mongo_db.collection("users_who_rated");
+            let collection = self.mongo_db()?.collection("users_who_rated");
             let ids: Vec<_> = items.iter().map(|b| b.id.as_str()).collect();
             let options = FindOptions::builder().show_record_id(false).build();
 
@@ -234,15 +643,15 @@ impl Controller for BooksController {
 
     fn user_ratings(&self, user: &Self::User) -> Result<ratings!(Self::Item), Error> {
         if !self.users_ratings_mongo {
-            let ratings = Rating::belonging_to(user)
-                .load::<Rating>(&self.pg_conn)?
-                .into_iter()
-                .map(|rating| (rating.book_id, rating.score))
-                .collect();
+            let ratings = with_conn!(&self.conn, conn => Rating::belonging_to(user)
+                .load::<Rating>(conn))?
+            .into_iter()
+            .map(|rating| (rating.book_id, rating.score))
+            .collect();
 
             Ok(ratings)
         } else {
-            let collection = self.mongo_db.collection("users_ratings");
+            let collection = self.mongo_db()?.collection("users_ratings");
             let options = FindOptions::builder().show_record_id(false).build();
 
             let cursor = collection.find(
@@ -268,10 +677,49 @@ impl Controller for BooksController {
         }
     }
 
+    fn user_timed_ratings(
+        &self,
+        user: &Self::User,
+    ) -> Result<ratings!(Self::Item, TimedScore), Error> {
+        let ratings = with_conn!(&self.conn, conn => Rating::belonging_to(user)
+            .load::<Rating>(conn))?
+        .into_iter()
+        .map(|rating| {
+            (
+                rating.book_id,
+                TimedScore::new(rating.score, rating.rated_at),
+            )
+        })
+        .collect();
+
+        Ok(ratings)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn users_ratings_since(
+        &self,
+        since: Timestamp,
+    ) -> Result<maped_ratings!(Self::User => Self::Item, TimedScore), Error> {
+        let ratings = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::rated_at.ge(since))
+            .load::<Rating>(conn))?;
+
+        let mut maped_ratings: HashMap<_, HashMap<_, _>> = HashMap::new();
+        for rating in ratings {
+            let score = TimedScore::new(rating.score, rating.rated_at);
+            maped_ratings
+                .entry(rating.user_id)
+                .or_default()
+                .insert(rating.book_id, score);
+        }
+
+        Ok(maped_ratings)
+    }
+
     #[allow(clippy::type_complexity)]
     fn all_users_ratings(&self) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
         if !self.users_ratings_mongo {
-            let ratings = ratings::table.load::<Rating>(&self.pg_conn)?;
+            let ratings = with_conn!(&self.conn, conn => ratings::table.load::<Rating>(conn))?;
 
             let mut maped_ratings = HashMap::new();
             for rating in ratings {
@@ -283,7 +731,7 @@ impl Controller for BooksController {
 
             Ok(maped_ratings)
         } else {
-            let collection = self.mongo_db.collection("users_ratings");
+            let collection = self.mongo_db()?.collection("users_ratings");
             let options = FindOptions::builder().show_record_id(false).build();
             let cursor = collection.find(None, options)?;
 
@@ -313,7 +761,8 @@ impl Controller for BooksController {
         users: &[Self::User],
     ) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
         if !self.users_ratings_mongo {
-            let ratings = Rating::belonging_to(users).load::<Rating>(&self.pg_conn)?;
+            let ratings =
+                with_conn!(&self.conn, conn => Rating::belonging_to(users).load::<Rating>(conn))?;
 
             let mut maped_ratings = HashMap::new();
             for rating in ratings {
@@ -325,7 +774,7 @@ impl Controller for BooksController {
 
             Ok(maped_ratings)
         } else {
-            let collection = self.mongo_db.collection("users_ratings");
+            let collection = self.mongo_db()?.collection("users_ratings");
             let ids: Vec<_> = users.iter().map(|u| u.id).collect();
             let options = FindOptions::builder().show_record_id(false).build();
 
@@ -362,9 +811,9 @@ impl Controller for BooksController {
         user: &Self::User,
     ) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
         if !self.users_ratings_mongo {
-            let ratings = ratings::table
+            let ratings = with_conn!(&self.conn, conn => ratings::table
                 .filter(ratings::user_id.ne(user.id))
-                .load::<Rating>(&self.pg_conn)?;
+                .load::<Rating>(conn))?;
 
             let mut maped_ratings = HashMap::new();
             for rating in ratings {
@@ -376,7 +825,7 @@ impl Controller for BooksController {
 
             Ok(maped_ratings)
         } else {
-            let collection = self.mongo_db.collection("users_ratings");
+            let collection = self.mongo_db()?.collection("users_ratings");
             let options = FindOptions::builder().show_record_id(false).build();
 
             let cursor = collection.find(
@@ -407,7 +856,7 @@ impl Controller for BooksController {
     }
 
     fn users_means(&self, users: &[Self::User]) -> Result<means!(Self::User), Error> {
-        let means = Mean::belonging_to(users).load::<Mean>(&self.pg_conn)?;
+        let means = with_conn!(&self.conn, conn => Mean::belonging_to(users).load::<Mean>(conn))?;
 
         let means_by_user = means
             .into_iter()
@@ -417,130 +866,293 @@ impl Controller for BooksController {
         Ok(means_by_user)
     }
 
+    fn users_count(&self) -> Result<usize, Error> {
+        let count: i64 = with_conn!(&self.conn, conn => users::table.count().get_result(conn))?;
+        Ok(count as usize)
+    }
+
+    fn items_count(&self) -> Result<usize, Error> {
+        let count: i64 = with_conn!(&self.conn, conn => books::table.count().get_result(conn))?;
+        Ok(count as usize)
+    }
+
+    fn ratings_count(&self) -> Result<usize, Error> {
+        let count: i64 = with_conn!(&self.conn, conn => ratings::table.count().get_result(conn))?;
+        Ok(count as usize)
+    }
+
+    fn users_ratings_count(&self, users: &[Self::User]) -> Result<counts!(Self::User), Error> {
+        let ids: Vec<_> = users.iter().map(|user| user.id).collect();
+        let counts: Vec<(i32, i64)> = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::user_id.eq_any(&ids))
+            .group_by(ratings::user_id)
+            .select((ratings::user_id, sql::<BigInt>("COUNT(*)")))
+            .load(conn))?;
+
+        let mut counts_by_user: HashMap<_, _> = ids.into_iter().map(|id| (id, 0)).collect();
+        for (user_id, count) in counts {
+            counts_by_user.insert(user_id, count as usize);
+        }
+
+        Ok(counts_by_user)
+    }
+
+    fn items_ratings_count(&self, items: &[Self::Item]) -> Result<counts!(Self::Item), Error> {
+        let ids: Vec<_> = items.iter().map(|item| item.id.clone()).collect();
+        let counts: Vec<(eid!(Self::Item), i64)> = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::book_id.eq_any(&ids))
+            .group_by(ratings::book_id)
+            .select((ratings::book_id, sql::<BigInt>("COUNT(*)")))
+            .load(conn))?;
+
+        let mut counts_by_item: HashMap<_, _> = ids.into_iter().map(|id| (id, 0)).collect();
+        for (item_id, count) in counts {
+            counts_by_item.insert(item_id, count as usize);
+        }
+
+        Ok(counts_by_item)
+    }
+
+    fn items_features(&self, items: &[Self::Item]) -> Result<ItemFeatures<String>, Error> {
+        let ids: Vec<_> = items.iter().map(|book| book.id.clone()).collect();
+        let books = with_conn!(&self.conn, conn => books::table
+            .filter(books::id.eq_any(&ids))
+            .load::<Book>(conn))?;
+
+        let mut features = ItemFeatures::new();
+        for book in books {
+            let book_features = book.features();
+            features.add(book.id, book_features);
+        }
+
+        Ok(features)
+    }
+
+    fn items_means(&self, items: &[Self::Item]) -> Result<means!(Self::Item), Error> {
+        let ids: Vec<_> = items.iter().map(|item| item.id.clone()).collect();
+        let means: Vec<(eid!(Self::Item), f64)> = with_conn!(&self.conn, conn => item_means::table
+            .filter(item_means::item_id.eq_any(&ids))
+            .select((item_means::item_id, item_means::val))
+            .load(conn))?;
+
+        Ok(means.into_iter().collect())
+    }
+
+    fn global_mean(&self) -> Result<Option<f64>, Error> {
+        let mean: Option<(f64, i64)> = with_conn!(&self.conn, conn => global_mean::table
+            .select((global_mean::val, global_mean::score_number))
+            .first(conn)
+            .optional())?;
+
+        // The row is kept after the last rating is removed
+        Ok(mean.filter(|(_, count)| *count > 0).map(|(mean, _)| mean))
+    }
+
+    fn user_histogram(&self, user: &Self::User) -> Result<Histogram, Error> {
+        let histogram: Vec<(f64, i64)> = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::user_id.eq(user.id))
+            .group_by(ratings::score)
+            .select((ratings::score, sql::<BigInt>("COUNT(*)")))
+            .order(ratings::score)
+            .load(conn))?;
+
+        Ok(histogram
+            .into_iter()
+            .map(|(score, count)| (score, count as usize))
+            .collect())
+    }
+
+    fn item_histogram(&self, item: &Self::Item) -> Result<Histogram, Error> {
+        let histogram: Vec<(f64, i64)> = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::book_id.eq(&item.id))
+            .group_by(ratings::score)
+            .select((ratings::score, sql::<BigInt>("COUNT(*)")))
+            .order(ratings::score)
+            .load(conn))?;
+
+        Ok(histogram
+            .into_iter()
+            .map(|(score, count)| (score, count as usize))
+            .collect())
+    }
+
+    fn most_rated_items(&self, limit: usize) -> Result<Vec<(eid!(Self::Item), usize)>, Error> {
+        let items: Vec<(eid!(Self::Item), i32)> = with_conn!(&self.conn, conn => item_means::table
+            .select((item_means::item_id, item_means::score_number))
+            .order((item_means::score_number.desc(), item_means::item_id))
+            .limit(limit as i64)
+            .load(conn))?;
+
+        Ok(items
+            .into_iter()
+            .map(|(item_id, count)| (item_id, count as usize))
+            .collect())
+    }
+
     fn score_range(&self) -> (f64, f64) {
         (0., 10.)
     }
 
+    fn rating_scale(&self) -> RatingScale {
+        RatingScale::discrete(self.score_range(), 1.)
+    }
+
+    fn rating_kind(&self, score: f64) -> RatingKind {
+        if score == IMPLICIT_SCORE {
+            RatingKind::Implicit
+        } else {
+            RatingKind::Explicit
+        }
+    }
+
     fn fields_for_users(&self) -> Vec<Field> {
         vec![
-            Field::Required("location", Type::String),
-            Field::Optional("age", Type::Int16),
+            Field::required("location", Type::String).describe("City, state, country"),
+            Field::optional("age", Type::Int16).range(0., 120.),
         ]
     }
 
     fn fields_for_items(&self) -> Vec<Field> {
         vec![
-            Field::Required("title", Type::String),
-            Field::Required("author", Type::String),
-            Field::Required("year", Type::Int16),
-            Field::Required("publisher", Type::String),
+            Field::optional("isbn", Type::String).describe("ISBN of the book, needed to insert it"),
+            Field::required("title", Type::String),
+            Field::required("author", Type::String),
+            Field::required("year", Type::Int16)
+                .describe("Year of publication")
+                .range(0., 2100.),
+            Field::required("publisher", Type::String),
         ]
     }
 
+    fn custom_keys_for_users(&self) -> Vec<String> {
+        vec!["location".into(), "age".into()]
+    }
+
+    fn custom_keys_for_items(&self) -> Vec<String> {
+        vec!["author".into(), "publisher".into(), "year".into()]
+    }
+
     fn insert_user<'a>(
         &self,
         proto: HashMap<&'a str, controller::Value>,
     ) -> controller::Result<Self::User> {
         let user = NewUnseenUser {
             location: proto["location"].as_string()?,
-            age: proto.get("age").map(|v| v.as_i16()).transpose()?,
+            age: proto
+                .get("age")
+                .and_then(controller::Value::non_null)
+                .map(|v| v.as_i16())
+                .transpose()?,
         };
 
-        Ok(insert_into(users::table)
-            .values(&user)
-            .get_result(&self.pg_conn)?)
+        let query = insert_into(users::table).values(&user);
+        Ok(insert_returning!(&self.conn, query, users::table)?)
     }
 
     fn insert_item<'a>(
         &self,
         proto: HashMap<&'a str, controller::Value>,
     ) -> controller::Result<Self::Item> {
-        let book = NewUnseenBook {
+        // Books are keyed by their ISBN, there's no key to generate for them
+        let isbn = proto
+            .get("isbn")
+            .and_then(controller::Value::non_null)
+            .ok_or_else(|| {
+                ErrorKind::InvalidField("isbn".into(), "it's needed to insert a book".into())
+            })?;
+
+        let book = NewBook {
+            id: isbn.as_string()?,
             title: proto["title"].as_string()?,
             author: proto["author"].as_string()?,
             year: proto["year"].as_i16()?,
             publisher: proto["publisher"].as_string()?,
         };
 
-        Ok(insert_into(books::table)
-            .values(&book)
-            .get_result(&self.pg_conn)?)
+        let query = insert_into(books::table).values(&book);
+        Ok(insert_returning!(
+            &self.conn,
+            query,
+            books::table,
+            key = book.id
+        )?)
     }
 
-    fn insert_rating(
+    fn update_user(
         &self,
         user_id: &eid!(Self::User),
+        proto: HashMap<&str, controller::Value>,
+    ) -> Result<Self::User, Error> {
+        let changes = NewUnseenUser {
+            location: proto["location"].as_string()?,
+            age: proto
+                .get("age")
+                .and_then(controller::Value::non_null)
+                .map(|v| v.as_i16())
+                .transpose()?,
+        };
+
+        self.update_user_sql(user_id, &changes)
+    }
+
+    fn update_item(
+        &self,
         item_id: &eid!(Self::Item),
-        score: f64,
-    ) -> Result<Self::Rating, Error> {
-        let users_who_rated = self.mongo_db.collection("users_who_rated");
-        let users_ratings = self.mongo_db.collection("users_ratings");
-
-        // Check that this rating doesn't exists on users_who_rated
-        let query = doc! {
-            "item_id": item_id,
-            format!("scores.{}", user_id) : doc!{
-                "$exists": true
-            }
+        proto: HashMap<&str, controller::Value>,
+    ) -> Result<Self::Item, Error> {
+        let changes = NewUnseenBook {
+            title: proto["title"].as_string()?,
+            author: proto["author"].as_string()?,
+            year: proto["year"].as_i16()?,
+            publisher: proto["publisher"].as_string()?,
         };
 
-        let rating = users_who_rated.find_one(query, None)?;
-        if rating.is_some() {
-            return Err(
-                ErrorKind::InsertRatingFailed(user_id.to_string(), item_id.to_string()).into(),
-            );
+        self.update_item_sql(item_id, &changes)
+    }
+
+    fn remove_user(&self, user_id: &eid!(Self::User)) -> Result<Self::User, Error> {
+        let user = self.remove_user_sql(user_id)?;
+
+        // Its ratings are unset from mongo through the outbox, what's left is an
+        // empty document that can be removed once they're applied
+        if let (Ok(_), Some(mongo_db)) = (self.replay_outbox(), &self.mongo_db) {
+            let users_ratings = mongo_db.collection("users_ratings");
+            users_ratings.delete_one(doc! { "user_id": user_id }, None)?;
         }
 
-        let options = UpdateOptions::builder().upsert(true).build();
-        let update = doc! {
-            "$set": doc!{
-                format!("scores.{}", user_id): score
-            }
-        };
+        Ok(user)
+    }
 
-        users_who_rated.update_one(doc! { "item_id": item_id }, update, options)?;
+    fn remove_item(&self, item_id: &eid!(Self::Item)) -> Result<Self::Item, Error> {
+        let item = self.remove_item_sql(item_id)?;
 
-        let options = UpdateOptions::builder().upsert(true).build();
-        let update = doc! {
-            "$set": doc!{
-                format!("scores.{}", item_id): score
-            }
-        };
+        if let (Ok(_), Some(mongo_db)) = (self.replay_outbox(), &self.mongo_db) {
+            let users_who_rated = mongo_db.collection("users_who_rated");
+            users_who_rated.delete_one(doc! { "item_id": item_id }, None)?;
+        }
+
+        Ok(item)
+    }
 
-        users_ratings.update_one(doc! { "user_id": user_id }, update, options)?;
+    fn insert_rating(
+        &self,
+        user_id: &eid!(Self::User),
+        item_id: &eid!(Self::Item),
+        score: f64,
+    ) -> Result<Self::Rating, Error> {
+        self.check_score(score)?;
 
         let new_rating = NewRating {
             user_id: *user_id,
             book_id: item_id,
             score,
+            rated_at: Some(now()),
         };
 
-        let psql_result = insert_into(ratings::table)
-            .values(new_rating)
-            .get_result(&self.pg_conn);
-
-        match psql_result {
-            Ok(rating) => Ok(rating),
-            Err(e) => {
-                let delete_doc = doc! {
-                    "$unset": doc!{
-                        format!("scores.{}", user_id): ""
-                    }
-                };
-
-                users_who_rated.update_one(doc! { "item_id": item_id }, delete_doc, None)?;
-
-                let delete_doc = doc! {
-                    "$unset": doc!{
-                        format!("scores.{}", item_id): ""
-                    }
-                };
-
-                users_ratings.update_one(doc! { "user_id": user_id }, delete_doc, None)?;
+        let rating = self.insert_rating_sql(&new_rating)?;
+        self.flush_outbox();
 
-                Err(e.into())
-            }
-        }
+        Ok(rating)
     }
 
     fn remove_rating(
@@ -548,70 +1160,10 @@ impl Controller for BooksController {
         user_id: &eid!(Self::User),
         item_id: &eid!(Self::Item),
     ) -> Result<Self::Rating, Error> {
-        let users_who_rated = self.mongo_db.collection("users_who_rated");
-        let users_ratings = self.mongo_db.collection("users_ratings");
-
-        let delete_doc = doc! {
-            "$unset": doc!{
-                format!("scores.{}", user_id): ""
-            }
-        };
+        let rating = self.remove_rating_sql(user_id, item_id)?;
+        self.flush_outbox();
 
-        let result = users_who_rated.update_one(doc! { "item_id": item_id }, delete_doc, None)?;
-        if result.matched_count.is_zero() || result.modified_count.is_zero() {
-            return Err(
-                ErrorKind::RemoveRatingFailed(user_id.to_string(), item_id.to_string()).into(),
-            );
-        }
-
-        let delete_doc = doc! {
-            "$unset": doc!{
-                format!("scores.{}", item_id): ""
-            }
-        };
-
-        let result = users_ratings.update_one(doc! { "user_id": user_id }, delete_doc, None)?;
-        if result.matched_count.is_zero() || result.modified_count.is_zero() {
-            return Err(
-                ErrorKind::RemoveRatingFailed(user_id.to_string(), item_id.to_string()).into(),
-            );
-        }
-
-        let old_score: f64 = ratings::table
-            .filter(ratings::user_id.eq(user_id))
-            .filter(ratings::book_id.eq(item_id))
-            .select(ratings::score)
-            .first(&self.pg_conn)?;
-
-        let psql_result = delete(ratings::table)
-            .filter(ratings::user_id.eq(user_id))
-            .filter(ratings::book_id.eq(item_id))
-            .get_result(&self.pg_conn);
-
-        match psql_result {
-            Ok(rating) => Ok(rating),
-            Err(e) => {
-                let options = UpdateOptions::builder().upsert(true).build();
-                let update_doc = doc! {
-                    "$set": doc!{
-                        format!("scores.{}", user_id): old_score
-                    }
-                };
-
-                users_who_rated.update_one(doc! { "item_id": item_id }, update_doc, options)?;
-
-                let options = UpdateOptions::builder().upsert(true).build();
-                let update_doc = doc! {
-                    "$set": doc!{
-                        format!("scores.{}", item_id): old_score
-                    }
-                };
-
-                users_ratings.update_one(doc! { "user_id": user_id }, update_doc, options)?;
-
-                Err(e.into())
-            }
-        }
+        Ok(rating)
     }
 
     fn update_rating(
@@ -620,69 +1172,74 @@ impl Controller for BooksController {
         item_id: &eid!(Self::Item),
         score: f64,
     ) -> Result<Self::Rating, Error> {
-        let users_who_rated = self.mongo_db.collection("users_who_rated");
-        let users_ratings = self.mongo_db.collection("users_ratings");
+        self.check_score(score)?;
 
-        let update_doc = doc! {
-            "$set": doc!{
-                format!("scores.{}", user_id): score
-            }
-        };
+        let rating = self.update_rating_sql(user_id, item_id, score)?;
+        self.flush_outbox();
 
-        let result = users_who_rated.update_one(doc! { "item_id": item_id }, update_doc, None)?;
-        if result.modified_count.is_zero() || result.matched_count.is_zero() {
-            return Err(
-                ErrorKind::UpdateRatingFailed(user_id.to_string(), item_id.to_string()).into(),
-            );
-        }
+        Ok(rating)
+    }
+}
 
-        let update_doc = doc! {
-            "$set": doc!{
-                format!("scores.{}", item_id): score
-            }
-        };
+impl MirroredRatings for BooksController {
+    fn mirror_db(&self) -> Result<&Database, Error> {
+        self.mongo_db()
+    }
 
-        let result = users_ratings.update_one(doc! { "user_id": user_id }, update_doc, None)?;
-        if result.modified_count.is_zero() || result.matched_count.is_zero() {
-            return Err(
-                ErrorKind::UpdateRatingFailed(user_id.to_string(), item_id.to_string()).into(),
-            );
+    fn mirrored_collections(&self) -> &[RatingCollection] {
+        MONGO_COLLECTIONS
+    }
+
+    fn table_users_ratings(
+        &self,
+        user_ids: &[i32],
+    ) -> Result<MapedRatings<i32, String, TimedScore>, Error> {
+        let ratings = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::user_id.eq_any(user_ids))
+            .load::<Rating>(conn))?;
+
+        let mut maped_ratings = HashMap::new();
+        for rating in ratings {
+            maped_ratings
+                .entry(rating.user_id)
+                .or_insert_with(HashMap::new)
+                .insert(
+                    rating.book_id,
+                    TimedScore::new(rating.score, rating.rated_at),
+                );
         }
 
-        let old_score: f64 = ratings::table
-            .filter(ratings::user_id.eq(user_id))
-            .filter(ratings::book_id.eq(item_id))
-            .select(ratings::score)
-            .first(&self.pg_conn)?;
-
-        let psql_res = update(ratings::table)
-            .filter(ratings::user_id.eq(user_id))
-            .filter(ratings::book_id.eq(item_id))
-            .set(ratings::score.eq(score))
-            .get_result::<Rating>(&self.pg_conn);
-
-        match psql_res {
-            Ok(rating) => Ok(rating),
-            Err(e) => {
-                let update_doc = doc! {
-                    "$set": doc! {
-                        format!("score.{}", user_id): old_score
-                    }
-                };
+        Ok(maped_ratings)
+    }
 
-                users_who_rated.update_one(doc! { "item_id": item_id }, update_doc, None)?;
+    fn table_items_ratings(
+        &self,
+        item_ids: &[String],
+    ) -> Result<MapedRatings<String, i32, TimedScore>, Error> {
+        let ratings = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::book_id.eq_any(item_ids))
+            .load::<Rating>(conn))?;
+
+        let mut maped_ratings = HashMap::new();
+        for rating in ratings {
+            maped_ratings
+                .entry(rating.book_id)
+                .or_insert_with(HashMap::new)
+                .insert(
+                    rating.user_id,
+                    TimedScore::new(rating.score, rating.rated_at),
+                );
+        }
 
-                let update_doc = doc! {
-                    "$set": doc! {
-                        format!("score.{}", item_id): old_score
-                    }
-                };
+        Ok(maped_ratings)
+    }
 
-                users_ratings.update_one(doc! { "user_id": user_id }, update_doc, None)?;
+    fn user_document_key(&self, user_id: &i32) -> Result<Bson, Error> {
+        Ok((*user_id).into())
+    }
 
-                Err(e.into())
-            }
-        }
+    fn item_document_key(&self, item_id: &String) -> Result<Bson, Error> {
+        Ok(item_id.clone().into())
     }
 }
 
@@ -718,12 +1275,72 @@ mod tests {
         let controller = BooksController::new()?;
         let mut chunk_iter = controller.users_by_chunks(80000);
 
-        assert_eq!(80000, chunk_iter.next().unwrap().len());
-        assert_eq!(80000, chunk_iter.next().unwrap().len());
-        assert_eq!(80000, chunk_iter.next().unwrap().len());
-        assert_eq!(38858, chunk_iter.next().unwrap().len());
+        assert_eq!(80000, chunk_iter.next().unwrap()?.len());
+        assert_eq!(80000, chunk_iter.next().unwrap()?.len());
+        assert_eq!(80000, chunk_iter.next().unwrap()?.len());
+        assert_eq!(38858, chunk_iter.next().unwrap()?.len());
         assert!(chunk_iter.next().is_none());
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod sqlite_tests {
+    use super::*;
+    use anyhow::Error;
+    use config::DatabaseEntry;
+    use controller::{Entity, Value};
+
+    fn sqlite_controller(file: &str) -> Result<BooksController, Error> {
+        let path = std::env::temp_dir().join(file);
+        let _ = std::fs::remove_file(&path);
+
+        let mut config = Config::default();
+        config.databases.insert(
+            "books-sqlite".into(),
+            DatabaseEntry {
+                kind: None,
+                backend: Backend::Sqlite,
+                sqlite_path: path.to_string_lossy().into(),
+                psql_url: String::new(),
+                mongo_url: String::new(),
+                mongo_db: String::new(),
+                users_ratings_mongo: false,
+                users_who_rated_mongo: false,
+                dataset: None,
+            },
+        );
+
+        BooksController::from_config(&config, "books-sqlite")
+    }
+
+    fn book<'a>(isbn: Option<&str>, title: &str) -> HashMap<&'a str, Value> {
+        let mut proto = HashMap::new();
+        if let Some(isbn) = isbn {
+            proto.insert("isbn", Value::String(isbn.into()));
+        }
+
+        proto.insert("title", Value::String(title.into()));
+        proto.insert("author", Value::String("Anonymous".into()));
+        proto.insert("year", Value::Int16(2000));
+        proto.insert("publisher", Value::String("Nobody".into()));
+        proto
+    }
+
+    #[test]
+    fn sqlite_inserted_books_are_read_back_by_isbn() -> Result<(), Error> {
+        let controller = sqlite_controller("books-insert.db")?;
+
+        let first = controller.insert_item(book(Some("9780000000001"), "First"))?;
+        let second = controller.insert_item(book(Some("0000000002"), "Second"))?;
+        assert_eq!(first.get_id(), "9780000000001");
+        assert_eq!(second.get_id(), "0000000002");
+        assert_eq!(second.title, "Second");
+
+        assert!(controller.insert_item(book(None, "Third")).is_err());
+        assert_eq!(controller.items_count()?, 2);
+
+        Ok(())
+    }
+}
@@ -4,9 +4,7 @@
 // https://opensource.org/licenses/MIT
 
 use crate::schema::books;
-use common_macros::hash_map;
-use controller::Entity;
-use std::collections::HashMap;
+use controller::{data, Data, Entity, Feature};
 
 // To query data from the database
 #[derive(Debug, Clone, Identifiable, Queryable, Default)]
@@ -29,7 +27,7 @@ pub struct NewBook<'a> {
     pub publisher: &'a str,
 }
 
-#[derive(Debug, Clone, Insertable)]
+#[derive(Debug, Clone, Insertable, AsChangeset)]
 #[table_name = "books"]
 pub struct NewUnseenBook<'a> {
     pub title: &'a str,
@@ -38,18 +36,34 @@ pub struct NewUnseenBook<'a> {
     pub publisher: &'a str,
 }
 
+impl Book {
+    /// Author, publisher and decade of publication (unknown years are 0) of this book
+    pub fn features(&self) -> Vec<Feature> {
+        let mut features = vec![
+            Feature::categorical("author", &self.author),
+            Feature::categorical("publisher", &self.publisher),
+        ];
+
+        if self.year > 0 {
+            features.push(Feature::decade(i32::from(self.year)));
+        }
+
+        features
+    }
+}
+
 impl Entity for Book {
     type Id = String;
     fn get_id(&self) -> Self::Id {
         self.id.clone()
     }
 
-    fn get_data(&self) -> HashMap<String, String> {
-        hash_map! {
-            "title".into() => self.title.clone(),
-            "author".into() => self.author.clone(),
-            "year".into() => self.year.to_string(),
-            "publisher".into() => self.publisher.clone()
+    fn get_data(&self) -> Data {
+        data! {
+            "title" => self.title.clone(),
+            "author" => self.author.clone(),
+            "year" => self.year,
+            "publisher" => self.publisher.clone(),
         }
     }
 }
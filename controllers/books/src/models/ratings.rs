@@ -5,10 +5,8 @@
 
 use super::books::Book;
 use super::users::User;
-use crate::schema::ratings;
-use common_macros::hash_map;
-use controller::Entity;
-use std::collections::HashMap;
+use crate::schema::{rating_outbox, ratings};
+use controller::{data, Data, Entity, RatingEvent, Value};
 
 // To query data from the database
 #[derive(Debug, Clone, Identifiable, Queryable, Associations)]
@@ -19,6 +17,7 @@ pub struct Rating {
     pub user_id: i32,
     pub book_id: String,
     pub score: f64,
+    pub rated_at: Option<i64>,
 }
 
 impl Entity for Rating {
@@ -28,11 +27,12 @@ impl Entity for Rating {
         self.id
     }
 
-    fn get_data(&self) -> HashMap<String, String> {
-        hash_map! {
-            "user_id".into() => self.user_id.to_string(),
-            "book_id".into() => self.book_id.clone(),
-            "score".into() => self.score.to_string(),
+    fn get_data(&self) -> Data {
+        data! {
+            "user_id" => self.user_id,
+            "book_id" => self.book_id.clone(),
+            "score" => self.score,
+            "rated_at" => self.rated_at.map_or(Value::Null, Value::Timestamp),
         }
     }
 }
@@ -44,4 +44,37 @@ pub struct NewRating<'a> {
     pub user_id: i32,
     pub book_id: &'a str,
     pub score: f64,
+    pub rated_at: Option<i64>,
+}
+
+// A rating mutation waiting in the outbox to be applied to mongo
+#[derive(Debug, Clone, Queryable)]
+pub struct OutboxEvent {
+    pub id: i64,
+    pub user_id: i32,
+    pub book_id: String,
+    pub score: Option<f64>,
+    pub rated_at: Option<i64>,
+}
+
+impl From<OutboxEvent> for RatingEvent<i32, String> {
+    fn from(event: OutboxEvent) -> Self {
+        Self {
+            id: event.id,
+            user_id: event.user_id,
+            item_id: event.book_id,
+            score: event.score,
+            time: event.rated_at,
+        }
+    }
+}
+
+// To record a rating mutation in the outbox, no score means it was removed
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "rating_outbox"]
+pub struct NewOutboxEvent<'a> {
+    pub user_id: i32,
+    pub book_id: &'a str,
+    pub score: Option<f64>,
+    pub rated_at: Option<i64>,
 }
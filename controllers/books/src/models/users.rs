@@ -4,9 +4,7 @@
 // https://opensource.org/licenses/MIT
 
 use crate::schema::{means, users};
-use common_macros::hash_map;
-use controller::Entity;
-use std::collections::HashMap;
+use controller::{data, Data, Entity};
 
 // To query data from the database
 #[derive(Debug, Clone, Identifiable, Queryable, Default)]
@@ -22,16 +20,11 @@ impl Entity for User {
         self.id
     }
 
-    fn get_data(&self) -> HashMap<String, String> {
-        let mut map = hash_map! {
-            "location".into() => self.location.clone()
-        };
-
-        if let Some(age) = &self.age {
-            map.insert("age".into(), age.to_string());
+    fn get_data(&self) -> Data {
+        data! {
+            "location" => self.location.clone(),
+            "age" => self.age,
         }
-
-        map
     }
 }
 
@@ -44,8 +37,9 @@ pub struct NewUser<'a> {
     pub age: Option<i16>,
 }
 
-#[derive(Debug, Clone, Insertable)]
+#[derive(Debug, Clone, Insertable, AsChangeset)]
 #[table_name = "users"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct NewUnseenUser<'a> {
     pub location: &'a str,
     pub age: Option<i16>,
@@ -8,6 +8,22 @@ table! {
     }
 }
 
+table! {
+    global_mean (id) {
+        id -> Int4,
+        val -> Float8,
+        score_number -> Int8,
+    }
+}
+
+table! {
+    item_means (item_id) {
+        item_id -> Varchar,
+        val -> Float8,
+        score_number -> Int4,
+    }
+}
+
 table! {
     means (user_id) {
         user_id -> Int4,
@@ -16,12 +32,23 @@ table! {
     }
 }
 
+table! {
+    rating_outbox (id) {
+        id -> Int8,
+        user_id -> Int4,
+        book_id -> Varchar,
+        score -> Nullable<Float8>,
+        rated_at -> Nullable<Int8>,
+    }
+}
+
 table! {
     ratings (id) {
         id -> Int4,
         user_id -> Int4,
         book_id -> Varchar,
         score -> Float8,
+        rated_at -> Nullable<Int8>,
     }
 }
 
@@ -33,13 +60,17 @@ table! {
     }
 }
 
+joinable!(item_means -> books (item_id));
 joinable!(means -> users (user_id));
 joinable!(ratings -> books (book_id));
 joinable!(ratings -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
     books,
+    global_mean,
+    item_means,
     means,
+    rating_outbox,
     ratings,
     users,
 );
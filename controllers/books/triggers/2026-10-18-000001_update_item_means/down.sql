@@ -0,0 +1,6 @@
+DROP FUNCTION update_item_mean_on_upd CASCADE;
+DROP FUNCTION update_item_mean_on_del CASCADE;
+DROP FUNCTION update_item_mean_on_new CASCADE;
+
+DELETE FROM global_mean;
+DELETE FROM item_means;
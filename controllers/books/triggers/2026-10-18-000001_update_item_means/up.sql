@@ -0,0 +1,77 @@
+-- Item means and the global mean start from the ratings loaded so far, the
+-- triggers keep them up to date afterwards
+INSERT INTO item_means(item_id, val, score_number)
+SELECT book_id, AVG(score), COUNT(*) FROM ratings GROUP BY book_id;
+
+INSERT INTO global_mean(id, val, score_number)
+SELECT 1, COALESCE(AVG(score), 0), COUNT(*) FROM ratings;
+
+CREATE FUNCTION update_item_mean_on_new() RETURNS TRIGGER AS
+$BODY$
+BEGIN
+    INSERT INTO item_means(item_id, val, score_number)
+    VALUES (new.book_id, new.score, 1)
+    ON CONFLICT (item_id) DO UPDATE
+        SET val = (item_means.val * item_means.score_number + excluded.val) / (item_means.score_number + 1),
+            score_number = item_means.score_number + 1;
+
+    UPDATE global_mean
+    SET val = (val * score_number + new.score) / (score_number + 1),
+        score_number = score_number + 1;
+
+    RETURN new;
+END;
+$BODY$
+LANGUAGE plpgsql;
+
+CREATE FUNCTION update_item_mean_on_del() RETURNS TRIGGER AS
+$BODY$
+BEGIN
+    DELETE FROM item_means
+    WHERE item_id = old.book_id AND score_number <= 1;
+
+    UPDATE item_means
+    SET val = (val * score_number - old.score) / (score_number - 1),
+        score_number = score_number - 1
+    WHERE item_id = old.book_id;
+
+    UPDATE global_mean
+    SET val = CASE WHEN score_number <= 1 THEN 0
+                   ELSE (val * score_number - old.score) / (score_number - 1)
+              END,
+        score_number = GREATEST(score_number - 1, 0);
+
+    RETURN old;
+END;
+$BODY$
+LANGUAGE plpgsql;
+
+CREATE FUNCTION update_item_mean_on_upd() RETURNS TRIGGER AS
+$BODY$
+BEGIN
+    UPDATE item_means
+    SET val = val + (new.score - old.score) / score_number
+    WHERE item_id = new.book_id;
+
+    UPDATE global_mean
+    SET val = val + (new.score - old.score) / score_number;
+
+    RETURN new;
+END;
+$BODY$
+LANGUAGE plpgsql;
+
+CREATE TRIGGER update_item_means_on_new_rating
+AFTER INSERT ON ratings
+FOR EACH ROW
+EXECUTE FUNCTION update_item_mean_on_new();
+
+CREATE TRIGGER update_item_means_on_del_rating
+AFTER DELETE ON ratings
+FOR EACH ROW
+EXECUTE FUNCTION update_item_mean_on_del();
+
+CREATE TRIGGER update_item_means_on_upd_rating
+AFTER UPDATE OF score ON ratings
+FOR EACH ROW
+EXECUTE FUNCTION update_item_mean_on_upd();
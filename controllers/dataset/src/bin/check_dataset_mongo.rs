@@ -0,0 +1,72 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use anyhow::Error;
+use config::Config;
+use controller::MirroredRatings;
+use dataset::DatasetController;
+
+const CHUNK_SIZE: usize = 1000;
+
+fn main() -> Result<(), Error> {
+    let (flags, mut args): (Vec<_>, Vec<_>) = std::env::args()
+        .skip(1)
+        .partition(|arg| arg.starts_with("--"));
+
+    if args.is_empty() {
+        panic!("Usage: check_dataset_mongo <database entry> [config file] [--repair | --rebuild]");
+    }
+
+    let name = args.remove(0);
+    let config = match args.pop() {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    let repair = flags.iter().any(|arg| arg == "--repair");
+    let rebuild = flags.iter().any(|arg| arg == "--rebuild");
+    let controller = DatasetController::from_config(&config, &name)?;
+
+    if rebuild {
+        controller.rebuild_mongo(CHUNK_SIZE)?;
+        println!("Rebuilt the ratings documents of {} from its table", name);
+        return Ok(());
+    }
+
+    let report = controller.check_mongo(CHUNK_SIZE)?;
+    for discrepancy in &report.users {
+        println!(
+            "users_ratings: user({}) item({}) is {}",
+            discrepancy.key, discrepancy.other, discrepancy.drift
+        );
+    }
+
+    for discrepancy in &report.items {
+        println!(
+            "users_who_rated: item({}) user({}) is {}",
+            discrepancy.key, discrepancy.other, discrepancy.drift
+        );
+    }
+
+    if report.is_consistent() {
+        println!("Mongo is consistent with the ratings table");
+        return Ok(());
+    }
+
+    println!(
+        "Found drift for {} users and {} items",
+        report.drifted_users().len(),
+        report.drifted_items().len()
+    );
+
+    if repair {
+        controller.repair_mongo(&report)?;
+        println!("Repaired the documents of drifted users and items");
+    } else {
+        println!("Run with --repair to rewrite them from the ratings table");
+    }
+
+    Ok(())
+}
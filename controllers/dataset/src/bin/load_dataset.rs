@@ -0,0 +1,254 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use anyhow::Error;
+use config::{Backend, Config, DatasetDescription, EntityTable};
+use controller::outbox::RatingCollection;
+use controller::{
+    error::ErrorKind, with_conn, Controller, CsvController, DbConnection, TimedScore,
+};
+use dataset::query::{ident, Bind, Key, Query};
+use dataset::tables::{create_means, create_tables};
+use indicatif::ProgressIterator;
+use mongodb::bson::{doc, Document};
+use mongodb::sync::{Client, Database};
+use std::collections::HashMap;
+
+/// Keeps every insert under the bound parameters limit of the backend
+fn max_binds(backend: Backend) -> usize {
+    match backend {
+        Backend::Postgres => 65535,
+        Backend::Sqlite => 999,
+    }
+}
+
+const MONGO_CHUNK_SIZE: usize = 1000;
+
+/// Ratings documents of a mongo collection by the id of their owner, along with
+/// their scores and times
+type Documents = HashMap<String, (Key, Document, Document)>;
+
+fn insert_rows(
+    conn: &DbConnection,
+    table: &str,
+    columns: &[&String],
+    rows: Vec<Vec<Bind>>,
+) -> Result<(), Error> {
+    let names: Vec<_> = columns.iter().map(|column| ident(column)).collect();
+    let chunk_size = max_binds(conn.backend()) / columns.len();
+
+    for chunk in rows.chunks(chunk_size).progress() {
+        let mut insert = Query::new(format!(
+            "INSERT INTO {} ({}) VALUES ",
+            ident(table),
+            names.join(", ")
+        ));
+
+        for (i, row) in chunk.iter().enumerate() {
+            let open = if i > 0 { ", (" } else { "(" };
+            insert = insert.sql(open).bind_all(row.clone()).sql(")");
+        }
+
+        with_conn!(conn, conn => insert.execute(conn))?;
+    }
+
+    Ok(())
+}
+
+fn entity_columns(table: &EntityTable) -> Vec<&String> {
+    let mut columns = vec![&table.id];
+    columns.extend(&table.name);
+    columns.extend(table.data.keys());
+    columns
+}
+
+fn entity_row(
+    table: &EntityTable,
+    id: &str,
+    name: Option<String>,
+    data: &HashMap<String, String>,
+) -> Result<Vec<Bind>, Error> {
+    let mut row = vec![Key::parse(id, table.id_type)?.into()];
+    if table.name.is_some() {
+        row.push(Bind::from(name.map(Bind::Text)));
+    }
+
+    for (column, ty) in &table.data {
+        let value = data.get(column).map(|value| Bind::parse(value, *ty));
+        row.push(value.transpose()?.unwrap_or(Bind::Null));
+    }
+
+    Ok(row)
+}
+
+fn push_document(documents: &mut Documents, owner: &Key, other: &Key, timed: &TimedScore) {
+    let (_, scores, times) = documents
+        .entry(owner.to_string())
+        .or_insert_with(|| (owner.clone(), Document::new(), Document::new()));
+
+    scores.insert(other.to_string(), timed.score);
+    if let Some(time) = timed.time {
+        times.insert(other.to_string(), time);
+    }
+}
+
+/// Replace the documents of a collection, they're inserted by chunks instead of
+/// being replayed from the outbox one rating at a time
+fn insert_documents(
+    db: &Database,
+    collection: RatingCollection,
+    documents: Documents,
+) -> Result<(), Error> {
+    let mongo = db.collection(collection.name());
+    mongo.drop(None)?;
+
+    let documents: Vec<_> = documents
+        .into_iter()
+        .map(|(_, (owner, scores, times))| {
+            doc! { collection.key(): owner, "scores": scores, "times": times }
+        })
+        .collect();
+
+    for chunk in documents.chunks(MONGO_CHUNK_SIZE).progress() {
+        mongo.insert_many(chunk.to_vec(), None)?;
+    }
+
+    Ok(())
+}
+
+/// Ids of the tables were given explicitly, so their sequences must be moved past them
+fn bump_sequence(conn: &DbConnection, table: &str, id: &str) -> Result<(), Error> {
+    let query = Query::new("SELECT setval(pg_get_serial_sequence(")
+        .bind(Bind::Text(ident(table)))
+        .sql(", ")
+        .bind(Bind::Text(id.into()))
+        .sql(format!(
+            "), COALESCE(MAX({}), 0) + 1, false) FROM {}",
+            ident(id),
+            ident(table)
+        ));
+
+    with_conn!(conn, conn => query.execute(conn))?;
+    Ok(())
+}
+
+fn load_files(
+    conn: &DbConnection,
+    mongo_db: Option<&Database>,
+    description: &DatasetDescription,
+) -> Result<(), Error> {
+    let entry = description
+        .csv_entry()
+        .ok_or_else(|| ErrorKind::CsvColumnNotFound("ratings".into()))?;
+
+    println!("Collecting records from the csv files...");
+    let csv = CsvController::<String, String>::from_csv_entry(&entry)?;
+    let (users, items, ratings) = (&description.users, &description.items, &description.ratings);
+
+    println!("Pushing users by chunks");
+    let mut rows = Vec::new();
+    for user in csv.users()? {
+        rows.push(entity_row(users, &user.id, user.name, &user.data)?);
+    }
+
+    insert_rows(conn, &users.table, &entity_columns(users), rows)?;
+
+    println!("Pushing items by chunks");
+    let mut rows = Vec::new();
+    for item in csv.items()? {
+        rows.push(entity_row(items, &item.id, item.name, &item.data)?);
+    }
+
+    insert_rows(conn, &items.table, &entity_columns(items), rows)?;
+
+    println!("Pushing ratings by chunks");
+    let mut columns = vec![&ratings.user_id, &ratings.item_id, &ratings.score];
+    columns.extend(&ratings.time);
+
+    let mut rows = Vec::new();
+    let mut users_ratings = Documents::new();
+    let mut users_who_rated = Documents::new();
+    for user in csv.users()? {
+        let user_key = Key::parse(&user.id, users.id_type)?;
+        for (item_id, timed) in csv.user_timed_ratings(&user)? {
+            let item_key = Key::parse(&item_id, items.id_type)?;
+            if mongo_db.is_some() {
+                push_document(&mut users_ratings, &user_key, &item_key, &timed);
+                push_document(&mut users_who_rated, &item_key, &user_key, &timed);
+            }
+
+            let mut row = vec![
+                user_key.clone().into(),
+                item_key.into(),
+                Bind::Float(timed.score),
+            ];
+
+            if ratings.time.is_some() {
+                row.push(Bind::from(timed.time));
+            }
+
+            rows.push(row);
+        }
+    }
+
+    insert_rows(conn, &ratings.table, &columns, rows)?;
+
+    if conn.backend() == Backend::Postgres {
+        for table in &[users, items] {
+            if table.id_type == config::IdType::Integer {
+                bump_sequence(conn, &table.table, &table.id)?;
+            }
+        }
+
+        bump_sequence(conn, &ratings.table, &ratings.id)?;
+
+        println!("Computing means and creating their triggers");
+        create_means(conn, description)?;
+    }
+
+    if let Some(mongo_db) = mongo_db {
+        println!("Pushing the ratings documents to mongo by chunks");
+        insert_documents(mongo_db, RatingCollection::UsersRatings, users_ratings)?;
+        insert_documents(mongo_db, RatingCollection::UsersWhoRated, users_who_rated)?;
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<(), Error> {
+    let mut args = std::env::args().skip(1);
+    let name = args
+        .next()
+        .expect("Usage: load_dataset <database entry> [config file]");
+
+    let config = match args.next() {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    let db = config
+        .databases
+        .get(&name)
+        .ok_or_else(|| ErrorKind::DbConfigError(name.clone()))?;
+
+    let path = db
+        .dataset
+        .as_ref()
+        .ok_or_else(|| ErrorKind::DbConfigError(name.clone()))?;
+
+    let description = DatasetDescription::load(path)?;
+    let conn = DbConnection::establish(db.backend, db.database_url())?;
+
+    // Same as the controller, mongo is only used along with an outbox
+    let mongo_db = match (db.backend, &description.ratings.outbox) {
+        (Backend::Postgres, Some(_)) => {
+            Some(Client::with_uri_str(&db.mongo_url)?.database(&db.mongo_db))
+        }
+        _ => None,
+    };
+
+    create_tables(&conn, &description)?;
+    load_files(&conn, mongo_db.as_ref(), &description)
+}
@@ -0,0 +1,1659 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+#[macro_use]
+extern crate diesel;
+
+pub mod query;
+pub mod tables;
+
+use crate::query::{
+    as_bigint, as_float, as_text, data_alias, ident, qualified, Bind, CountRow, EntityRow,
+    IdCountRow, IdRow, IdValueRow, Key, MeanRow, OutboxRow, Query, RatingRow, ScoreCountRow,
+};
+use crate::tables::{ITEM_MEANS_KEY, USER_MEANS_KEY};
+use anyhow::Error;
+use config::{Backend, ColumnType, Config, DatasetDescription, EntityTable, IdType};
+use controller::outbox::{apply_rating_events, RatingCollection, RatingEvent};
+use controller::{
+    counts, eid, error::ErrorKind, maped_ratings, means, now, ratings, with_conn, Controller,
+    DbConnection, DynEntity, Feature, Field, Histogram, ItemFeatures, MapedRatings,
+    MirroredRatings, NameMatch, RatingKind, RatingScale, SearchBy, TimedScore, Timestamp, Type,
+    Value,
+};
+use diesel::{deserialize::QueryableByName, pg::Pg, sqlite::Sqlite, Connection};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::{
+    options::FindOptions,
+    sync::{Client, Database},
+};
+use std::collections::HashMap;
+
+/// Ratings documents kept in mongo, they're written through the outbox
+const MONGO_COLLECTIONS: &[RatingCollection] = &[
+    RatingCollection::UsersRatings,
+    RatingCollection::UsersWhoRated,
+];
+
+const OUTBOX_CHUNK_SIZE: i64 = 1000;
+
+/// Controller for any dataset described by a `DatasetDescription`, its tables
+/// and columns are read from the description instead of being declared with
+/// `table!`, so a new dataset only needs a description and a config entry
+pub struct DatasetController {
+    description: DatasetDescription,
+    users_ratings_mongo: bool,
+    users_who_rated_mongo: bool,
+    conn: DbConnection,
+    mongo_db: Option<Database>,
+}
+
+impl DatasetController {
+    pub fn from_config(config: &Config, name: &str) -> Result<Self, Error> {
+        let db = config
+            .databases
+            .get(name)
+            .ok_or_else(|| ErrorKind::DbConfigError(name.into()))?;
+
+        let path = db
+            .dataset
+            .as_ref()
+            .ok_or_else(|| ErrorKind::DbConfigError(name.into()))?;
+
+        let description = DatasetDescription::load(path)?;
+        let conn = DbConnection::establish(db.backend, db.database_url())?;
+
+        // A sqlite database is created empty, so its tables are created on first use
+        if db.backend == Backend::Sqlite {
+            tables::create_tables(&conn, &description)?;
+        }
+
+        // Mongo documents are written through the outbox, without one they're not used
+        let mongo_db = match (db.backend, &description.ratings.outbox) {
+            (Backend::Postgres, Some(_)) => {
+                Some(Client::with_uri_str(&db.mongo_url)?.database(&db.mongo_db))
+            }
+            _ => None,
+        };
+
+        let users_ratings_mongo = db.users_ratings_mongo && mongo_db.is_some();
+        let users_who_rated_mongo = db.users_who_rated_mongo && mongo_db.is_some();
+
+        let controller = Self {
+            description,
+            users_ratings_mongo,
+            users_who_rated_mongo,
+            conn,
+            mongo_db,
+        };
+
+        // Events left behind by a crash or a mongo outage
+        controller.flush_outbox();
+        Ok(controller)
+    }
+
+    pub fn description(&self) -> &DatasetDescription {
+        &self.description
+    }
+
+    pub fn connection(&self) -> &DbConnection {
+        &self.conn
+    }
+
+    fn mongo_db(&self) -> Result<&Database, Error> {
+        Ok(self.mongo_db.as_ref().ok_or(ErrorKind::MongoUnavailable)?)
+    }
+
+    fn load<T>(&self, query: &Query) -> Result<Vec<T>, Error>
+    where
+        T: QueryableByName<Pg> + QueryableByName<Sqlite>,
+    {
+        Ok(with_conn!(&self.conn, conn => query.load(conn))?)
+    }
+
+    fn user_key(&self, id: &str) -> Result<Key, ErrorKind> {
+        Key::parse(id, self.description.users.id_type)
+    }
+
+    fn item_key(&self, id: &str) -> Result<Key, ErrorKind> {
+        Key::parse(id, self.description.items.id_type)
+    }
+
+    /// Apply the pending events of the outbox to mongo by chunks, events are
+    /// removed once applied so whatever fails is retried on the next replay.
+    /// Returns how many events were applied
+    pub fn replay_outbox(&self) -> Result<usize, Error> {
+        let (conn, mongo_db, outbox) =
+            match (&self.conn, &self.mongo_db, &self.description.ratings.outbox) {
+                (DbConnection::Postgres(conn), Some(mongo_db), Some(outbox)) => {
+                    (conn, mongo_db, outbox)
+                }
+                _ => return Ok(0),
+            };
+
+        let ratings = &self.description.ratings;
+        let select = Query::new(format!(
+            "SELECT id, {} AS user_id, {} AS item_id, score, rated_at FROM {} ORDER BY id LIMIT ",
+            as_text(&ident(&ratings.user_id)),
+            as_text(&ident(&ratings.item_id)),
+            ident(outbox),
+        ))
+        .bind(OUTBOX_CHUNK_SIZE)
+        .sql(" FOR UPDATE");
+
+        let mut applied = 0;
+        loop {
+            // Rows stay locked until they're deleted, so concurrent replays
+            // don't apply the same events out of order
+            let replayed = conn.transaction::<_, Error, _>(|| {
+                let rows: Vec<OutboxRow> = select.load(conn)?;
+                if rows.is_empty() {
+                    return Ok(0);
+                }
+
+                let mut events = Vec::with_capacity(rows.len());
+                for row in rows {
+                    events.push(RatingEvent {
+                        id: row.id,
+                        user_id: self.user_key(&row.user_id)?,
+                        item_id: self.item_key(&row.item_id)?,
+                        score: row.score,
+                        time: row.rated_at,
+                    });
+                }
+
+                apply_rating_events(mongo_db, &events, MONGO_COLLECTIONS)?;
+
+                let ids = events.iter().map(|event| event.id);
+                Query::new(format!("DELETE FROM {} WHERE id IN (", ident(outbox)))
+                    .bind_all(ids)
+                    .sql(")")
+                    .execute(conn)?;
+
+                Ok(events.len())
+            })?;
+
+            applied += replayed;
+            if replayed < OUTBOX_CHUNK_SIZE as usize {
+                return Ok(applied);
+            }
+        }
+    }
+
+    /// Ratings are written to mongo after the transaction is committed, if mongo
+    /// isn't reachable the events stay in the outbox until the next replay
+    fn flush_outbox(&self) {
+        if let Err(e) = self.replay_outbox() {
+            log::error!("Couldn't replay the rating outbox into mongo: {}", e);
+            match self.pending_events() {
+                Ok(pending) => {
+                    log::warn!("{} rating events are still pending in the outbox", pending)
+                }
+                Err(e) => log::error!("Couldn't count the pending events of the outbox: {}", e),
+            }
+        }
+    }
+
+    /// Number of events in the outbox that haven't been applied to mongo yet
+    pub fn pending_events(&self) -> Result<usize, Error> {
+        self.description
+            .ratings
+            .outbox
+            .as_deref()
+            .map_or(Ok(0), |outbox| self.count(outbox))
+    }
+
+    fn select_entities(table: &EntityTable) -> Query {
+        let mut columns = vec![format!("{} AS id", as_text(&ident(&table.id)))];
+        if let Some(name) = &table.name {
+            columns.push(format!("{} AS name", as_text(&ident(name))));
+        }
+
+        for (i, column) in table.data.keys().enumerate() {
+            columns.push(format!("{} AS {}", as_text(&ident(column)), data_alias(i)));
+        }
+
+        Query::new(format!(
+            "SELECT {} FROM {}",
+            columns.join(", "),
+            ident(&table.table)
+        ))
+    }
+
+    /// Data columns are read as text, they get back their type here
+    fn to_entity(table: &EntityTable, row: EntityRow) -> DynEntity {
+        let mut entity = DynEntity::new(&row.id);
+        if let Some(name) = &table.name {
+            entity.data.insert(name.clone(), row.name.into());
+        }
+
+        for ((column, ty), value) in table.data.iter().zip(row.data) {
+            let value = match (value, ty) {
+                (None, _) => Value::Null,
+                (Some(value), ColumnType::Integer) => value
+                    .parse()
+                    .map(Value::Int64)
+                    .unwrap_or(Value::String(value)),
+                (Some(value), ColumnType::Float) => value
+                    .parse()
+                    .map(Value::Double)
+                    .unwrap_or(Value::String(value)),
+                (Some(value), ColumnType::Text) => Value::String(value),
+            };
+
+            entity.data.insert(column.clone(), value);
+        }
+
+        entity
+    }
+
+    fn entities(&self, table: &EntityTable, filter: Query) -> Result<Vec<DynEntity>, Error> {
+        let query = Self::select_entities(table).append(filter);
+        let rows: Vec<EntityRow> = self.load(&query)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Self::to_entity(table, row))
+            .collect())
+    }
+
+    fn entities_by(&self, table: &EntityTable, by: &SearchBy) -> Result<Vec<DynEntity>, Error> {
+        match by {
+            SearchBy::Id(id) => {
+                let key = Key::parse(id, table.id_type)?;
+                let column = qualified(&table.table, &table.id);
+                let filter = Query::new(format!(" WHERE {} = ", column)).bind(key);
+
+                let entities = self.entities(table, filter)?;
+                if entities.is_empty() {
+                    Err(ErrorKind::NotFoundById(id.clone()).into())
+                } else {
+                    Ok(entities)
+                }
+            }
+
+            SearchBy::Name(name) => {
+                let column = table
+                    .name
+                    .as_ref()
+                    .ok_or_else(|| ErrorKind::NotFoundByName(name.clone()))?;
+
+                let filter = Query::new(format!(" WHERE {} = ", qualified(&table.table, column)))
+                    .bind(Bind::Text(name.clone()));
+
+                let entities = self.entities(table, filter)?;
+                if entities.is_empty() {
+                    Err(ErrorKind::NotFoundByName(name.clone()).into())
+                } else {
+                    Ok(entities)
+                }
+            }
+
+            SearchBy::NameLike(mode, name, limit) => {
+                let column = table
+                    .name
+                    .as_ref()
+                    .ok_or_else(|| ErrorKind::NotFoundByName(name.clone()))?;
+
+                // Patterns aren't bound right after LIKE, sqlite would prepare the
+                // statement again on its first step and lose the names of the columns
+                let column = format!("LOWER({})", qualified(&table.table, column));
+                let like = |pattern| {
+                    Query::new(format!("{} LIKE LOWER(", column))
+                        .bind(Bind::Text(pattern))
+                        .sql(")")
+                };
+
+                let query = match (self.conn.backend(), mode.like_pattern(name)) {
+                    (_, Some(pattern)) => Self::select_entities(table)
+                        .sql(" WHERE ")
+                        .append(like(pattern))
+                        .sql(" ORDER BY ")
+                        .append(like(NameMatch::prefix_pattern(name)))
+                        .sql(format!(" DESC, LENGTH({}) LIMIT ", column))
+                        .bind(*limit as i64),
+
+                    // Ranked by pg_trgm, the trigram index of the column is used
+                    (Backend::Postgres, None) => Self::select_entities(table)
+                        .sql(" WHERE LOWER(")
+                        .bind(Bind::Text(name.clone()))
+                        .sql(format!(") <% {} ORDER BY word_similarity(LOWER(", column))
+                        .bind(Bind::Text(name.clone()))
+                        .sql(format!("), {}) DESC LIMIT ", column))
+                        .bind(*limit as i64),
+
+                    // Sqlite has no trigrams, only the names that could match are loaded
+                    (Backend::Sqlite, None) => {
+                        let mut query = Self::select_entities(table).sql(" WHERE 1 = 0");
+                        for pattern in NameMatch::fuzzy_patterns(name) {
+                            query = query.sql(" OR ").append(like(pattern));
+                        }
+
+                        query
+                    }
+                };
+
+                let candidates: Vec<EntityRow> = self.load(&query)?;
+                let rows = mode.rank(name, candidates, |row| row.name.as_deref(), *limit);
+                if rows.is_empty() {
+                    Err(ErrorKind::NotFoundByName(name.clone()).into())
+                } else {
+                    Ok(rows
+                        .into_iter()
+                        .map(|row| Self::to_entity(table, row))
+                        .collect())
+                }
+            }
+
+            SearchBy::Custom(k, v) => {
+                let ty = table
+                    .data
+                    .get(k)
+                    .ok_or_else(|| ErrorKind::CustomSearchNotSupported(k.clone()))?;
+
+                let filter = Query::new(format!(" WHERE {} = ", qualified(&table.table, k)))
+                    .bind(Bind::parse(v, *ty)?);
+
+                let entities = self.entities(table, filter)?;
+                if entities.is_empty() {
+                    Err(ErrorKind::NotFoundByCustom(k.clone(), v.clone()).into())
+                } else {
+                    Ok(entities)
+                }
+            }
+        }
+    }
+
+    fn entities_offset_limit(
+        &self,
+        table: &EntityTable,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<DynEntity>, Error> {
+        let filter = Query::new(format!(
+            " ORDER BY {} LIMIT ",
+            qualified(&table.table, &table.id)
+        ))
+        .bind(limit as i64)
+        .sql(" OFFSET ")
+        .bind(offset as i64);
+
+        self.entities(table, filter)
+    }
+
+    fn entities_after(
+        &self,
+        table: &EntityTable,
+        after: Option<&String>,
+        limit: usize,
+    ) -> Result<Vec<DynEntity>, Error> {
+        let mut filter = Query::default();
+        if let Some(after) = after {
+            filter = filter
+                .sql(format!(" WHERE {} > ", qualified(&table.table, &table.id)))
+                .bind(Key::parse(after, table.id_type)?);
+        }
+
+        let filter = filter
+            .sql(format!(
+                " ORDER BY {} LIMIT ",
+                qualified(&table.table, &table.id)
+            ))
+            .bind(limit as i64);
+
+        self.entities(table, filter)
+    }
+
+    fn fields_for(table: &EntityTable) -> Vec<Field<'_>> {
+        let mut fields = Vec::new();
+        if table.id_type == IdType::Text {
+            fields.push(Field::required("id", Type::String).describe("Unique id"));
+        }
+
+        if let Some(name) = &table.name {
+            fields.push(Field::required(name, Type::String));
+        }
+
+        for (column, ty) in &table.data {
+            let ty = match ty {
+                ColumnType::Integer => Type::Int64,
+                ColumnType::Float => Type::Double,
+                ColumnType::Text => Type::String,
+            };
+
+            if table.required.contains(column) {
+                fields.push(Field::required(column, ty));
+            } else {
+                fields.push(Field::optional(column, ty));
+            }
+        }
+
+        fields
+    }
+
+    /// Columns of the table given in a prototype along with their values, the id
+    /// is only taken when inserting into a table with text ids
+    fn proto_columns(
+        table: &EntityTable,
+        proto: &HashMap<&str, Value>,
+        with_id: bool,
+    ) -> Result<Vec<(String, Bind)>, Error> {
+        let mut columns = Vec::new();
+        if with_id && table.id_type == IdType::Text {
+            let id = proto.get("id").unwrap_or(&Value::Null).as_string()?;
+            columns.push((table.id.clone(), Bind::Text(id.into())));
+        }
+
+        for column in table.name.iter().chain(table.data.keys()) {
+            if let Some(value) = proto.get(column.as_str()) {
+                columns.push((column.clone(), value.into()));
+            }
+        }
+
+        Ok(columns)
+    }
+
+    fn insert_entity(
+        &self,
+        table: &EntityTable,
+        proto: HashMap<&str, Value>,
+    ) -> Result<DynEntity, Error> {
+        let columns = Self::proto_columns(table, &proto, true)?;
+        let mut insert = Query::new(format!("INSERT INTO {}", ident(&table.table)));
+        if columns.is_empty() {
+            insert = insert.sql(" DEFAULT VALUES");
+        } else {
+            let names: Vec<_> = columns.iter().map(|(column, _)| ident(column)).collect();
+            insert = insert
+                .sql(format!(" ({}) VALUES (", names.join(", ")))
+                .bind_all(columns.into_iter().map(|(_, value)| value))
+                .sql(")");
+        }
+
+        // Postgres returns the id of the new row, with sqlite it's read back by the
+        // rowid of the row, whatever the type of the id is
+        let ids: Vec<IdRow> = match &self.conn {
+            DbConnection::Postgres(conn) => insert
+                .sql(format!(" RETURNING {} AS id", as_text(&ident(&table.id))))
+                .load(conn)?,
+            DbConnection::Sqlite(conn) => conn.transaction::<_, Error, _>(|| {
+                insert.execute(conn)?;
+                let inserted = Query::new(format!(
+                    "SELECT {} AS id FROM {} WHERE rowid = last_insert_rowid()",
+                    as_text(&ident(&table.id)),
+                    ident(&table.table),
+                ));
+
+                Ok(inserted.load(conn)?)
+            })?,
+        };
+
+        let id = ids
+            .into_iter()
+            .next()
+            .ok_or_else(|| ErrorKind::NotFoundById(String::new()))?
+            .id;
+
+        Ok(self.entities_by(table, &SearchBy::id(&id))?.remove(0))
+    }
+
+    fn update_entity(
+        &self,
+        table: &EntityTable,
+        id: &str,
+        proto: HashMap<&str, Value>,
+    ) -> Result<DynEntity, Error> {
+        let key = Key::parse(id, table.id_type)?;
+        let columns = Self::proto_columns(table, &proto, false)?;
+
+        if !columns.is_empty() {
+            let mut update = Query::new(format!("UPDATE {} SET ", ident(&table.table)));
+            for (i, (column, value)) in columns.into_iter().enumerate() {
+                let separator = if i > 0 { ", " } else { "" };
+                update = update
+                    .sql(format!("{}{} = ", separator, ident(&column)))
+                    .bind(value);
+            }
+
+            let update = update
+                .sql(format!(" WHERE {} = ", ident(&table.id)))
+                .bind(key);
+
+            with_conn!(&self.conn, conn => update.execute(conn))?;
+        }
+
+        Ok(self.entities_by(table, &SearchBy::id(id))?.remove(0))
+    }
+
+    /// Remove an user or item along with its ratings (recorded in the outbox) and
+    /// its mean, `column` is the column of the ratings table that references it
+    fn remove_entity(
+        &self,
+        table: &EntityTable,
+        id: &str,
+        column: &str,
+        means: Option<&(String, &str)>,
+    ) -> Result<DynEntity, Error> {
+        let key = Key::parse(id, table.id_type)?;
+        let ratings = &self.description.ratings;
+        let outbox = ratings.outbox.as_ref().filter(|_| self.mongo_db.is_some());
+
+        let entity = self.entities_by(table, &SearchBy::id(id))?.remove(0);
+        let rated = format!(" FROM {} WHERE {} = ", ident(&ratings.table), ident(column));
+
+        with_conn!(&self.conn, conn => conn.transaction::<_, Error, _>(|| {
+            if let Some(outbox) = outbox {
+                let user_item = format!("{}, {}", ident(&ratings.user_id), ident(&ratings.item_id));
+                Query::new(format!("INSERT INTO {} ({}) SELECT {}", ident(outbox), user_item, user_item))
+                    .sql(rated.clone())
+                    .bind(key.clone())
+                    .execute(conn)?;
+            }
+
+            Query::new("DELETE")
+                .sql(rated.clone())
+                .bind(key.clone())
+                .execute(conn)?;
+
+            if let Some((means, key_column)) = means {
+                Query::new(format!("DELETE FROM {} WHERE {} = ", ident(means), key_column))
+                    .bind(key.clone())
+                    .execute(conn)?;
+            }
+
+            Query::new(format!("DELETE FROM {} WHERE {} = ", ident(&table.table), ident(&table.id)))
+                .bind(key.clone())
+                .execute(conn)?;
+
+            Ok(())
+        }))?;
+
+        Ok(entity)
+    }
+
+    fn select_ratings(&self) -> Query {
+        let ratings = &self.description.ratings;
+        let rated_at = match &ratings.time {
+            Some(time) => as_bigint(&ident(time)),
+            None => as_bigint("NULL"),
+        };
+
+        Query::new(format!(
+            "SELECT {} AS id, {} AS user_id, {} AS item_id, {} AS score, {} AS rated_at FROM {}",
+            as_text(&ident(&ratings.id)),
+            as_text(&ident(&ratings.user_id)),
+            as_text(&ident(&ratings.item_id)),
+            as_float(&ident(&ratings.score)),
+            rated_at,
+            ident(&ratings.table),
+        ))
+    }
+
+    fn to_rating(&self, row: RatingRow) -> DynEntity {
+        let ratings = &self.description.ratings;
+        let mut rating = DynEntity::new(&row.id);
+
+        rating
+            .data
+            .insert(ratings.user_id.clone(), Value::String(row.user_id));
+        rating
+            .data
+            .insert(ratings.item_id.clone(), Value::String(row.item_id));
+        rating
+            .data
+            .insert(ratings.score.clone(), Value::Double(row.score));
+
+        if let Some(time) = &ratings.time {
+            let rated_at = row.rated_at.map_or(Value::Null, Value::Timestamp);
+            rating.data.insert(time.clone(), rated_at);
+        }
+
+        rating
+    }
+
+    /// Ratings of the ratings table that pass the given filter
+    fn ratings_where(&self, filter: Query) -> Result<Vec<RatingRow>, Error> {
+        self.load(&self.select_ratings().append(filter))
+    }
+
+    fn rating_filter(&self, user: &Key, item: &Key) -> Query {
+        let ratings = &self.description.ratings;
+
+        Query::new(format!(
+            " WHERE {} = ",
+            qualified(&ratings.table, &ratings.user_id)
+        ))
+        .bind(user.clone())
+        .sql(format!(
+            " AND {} = ",
+            qualified(&ratings.table, &ratings.item_id)
+        ))
+        .bind(item.clone())
+    }
+
+    /// Filter of the rows whose `column` (quoted already) is any of the given ids
+    /// of `table`
+    fn keys_in<'a>(
+        column: &str,
+        table: &EntityTable,
+        ids: impl IntoIterator<Item = &'a String>,
+    ) -> Result<Query, Error> {
+        let keys = ids
+            .into_iter()
+            .map(|id| Key::parse(id, table.id_type))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Query::new(format!(" WHERE {} IN (", column))
+            .bind_all(keys)
+            .sql(")"))
+    }
+
+    fn maped_ratings(rows: Vec<RatingRow>, by_user: bool) -> MapedRatings<String, String> {
+        let mut maped_ratings: MapedRatings<String, String> = HashMap::new();
+        for row in rows {
+            let (owner, other) = if by_user {
+                (row.user_id, row.item_id)
+            } else {
+                (row.item_id, row.user_id)
+            };
+
+            maped_ratings
+                .entry(owner)
+                .or_default()
+                .insert(other, row.score);
+        }
+
+        maped_ratings
+    }
+
+    /// Same as `maped_ratings` but keeping when each rating was made
+    fn timed_ratings(
+        rows: Vec<RatingRow>,
+        by_user: bool,
+    ) -> MapedRatings<String, String, TimedScore> {
+        let mut maped_ratings: MapedRatings<String, String, TimedScore> = HashMap::new();
+        for row in rows {
+            let (owner, other) = if by_user {
+                (row.user_id, row.item_id)
+            } else {
+                (row.item_id, row.user_id)
+            };
+
+            maped_ratings
+                .entry(owner)
+                .or_default()
+                .insert(other, TimedScore::new(row.score, row.rated_at));
+        }
+
+        maped_ratings
+    }
+
+    /// Ratings documents of a mongo collection that match the filter
+    fn mongo_ratings(
+        &self,
+        collection: RatingCollection,
+        filter: impl Into<Option<Document>>,
+    ) -> Result<MapedRatings<String, String>, Error> {
+        let mongo = self.mongo_db()?.collection(collection.name());
+        let options = FindOptions::builder().show_record_id(false).build();
+        let cursor = mongo.find(filter, options)?;
+
+        let mut maped_ratings: MapedRatings<String, String> = HashMap::new();
+        for doc in cursor {
+            let doc = doc?;
+            let owner = match doc.get(collection.key()) {
+                Some(Bson::Int32(id)) => id.to_string(),
+                Some(Bson::Int64(id)) => id.to_string(),
+                Some(Bson::String(id)) => id.clone(),
+                _ => return Err(ErrorKind::BsonConvert.into()),
+            };
+
+            let ratings = maped_ratings.entry(owner).or_default();
+            for (other, score) in doc.get_document("scores")? {
+                let score = score.as_f64().ok_or(ErrorKind::BsonConvert)?;
+                ratings.insert(other.clone(), score);
+            }
+        }
+
+        Ok(maped_ratings)
+    }
+
+    fn mongo_keys<'a>(
+        table: &EntityTable,
+        ids: impl IntoIterator<Item = &'a String>,
+    ) -> Result<Vec<Bson>, Error> {
+        Ok(ids
+            .into_iter()
+            .map(|id| Key::parse(id, table.id_type).map(Bson::from))
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Means of the given ids, read from the table of means if the dataset keeps
+    /// one (`key` is its key column) or computed from the ratings otherwise
+    fn means_of(
+        &self,
+        table: &EntityTable,
+        means: Option<&String>,
+        key: &str,
+        column: &str,
+        ids: &[String],
+    ) -> Result<HashMap<String, f64>, Error> {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let query = match means {
+            Some(means) => Query::new(format!(
+                "SELECT {} AS id, {} AS val FROM {}",
+                as_text(key),
+                as_float("val"),
+                ident(means)
+            ))
+            .append(Self::keys_in(&ident(key), table, ids)?),
+
+            None => Query::new(format!(
+                "SELECT {} AS id, {} AS val FROM {}",
+                as_text(&ident(column)),
+                as_float(&format!("AVG({})", ident(&self.description.ratings.score))),
+                ident(&self.description.ratings.table)
+            ))
+            .append(Self::keys_in(&ident(column), table, ids)?)
+            .sql(format!(" GROUP BY {}", ident(column))),
+        };
+
+        let rows: Vec<IdValueRow> = self.load(&query)?;
+        Ok(rows.into_iter().map(|row| (row.id, row.val)).collect())
+    }
+
+    fn counts_of(
+        &self,
+        table: &EntityTable,
+        column: &str,
+        ids: Vec<String>,
+    ) -> Result<HashMap<String, usize>, Error> {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let query = Query::new(format!(
+            "SELECT {} AS id, COUNT(*) AS count FROM {}",
+            as_text(&ident(column)),
+            ident(&self.description.ratings.table)
+        ))
+        .append(Self::keys_in(&ident(column), table, &ids)?)
+        .sql(format!(" GROUP BY {}", ident(column)));
+
+        let rows: Vec<IdCountRow> = self.load(&query)?;
+
+        let mut counts: HashMap<_, _> = ids.into_iter().map(|id| (id, 0)).collect();
+        for row in rows {
+            counts.insert(row.id, row.count as usize);
+        }
+
+        Ok(counts)
+    }
+
+    fn count(&self, table: &str) -> Result<usize, Error> {
+        let query = Query::new(format!("SELECT COUNT(*) AS count FROM {}", ident(table)));
+        let rows: Vec<CountRow> = self.load(&query)?;
+
+        Ok(rows.first().map_or(0, |row| row.count as usize))
+    }
+
+    fn histogram_of(&self, column: &str, key: Key) -> Result<Histogram, Error> {
+        let ratings = &self.description.ratings;
+        let query = Query::new(format!(
+            "SELECT {} AS score, COUNT(*) AS count FROM {} WHERE {} = ",
+            as_float(&ident(&ratings.score)),
+            ident(&ratings.table),
+            ident(column),
+        ))
+        .bind(key)
+        .sql(format!(
+            " GROUP BY {} ORDER BY {}",
+            ident(&ratings.score),
+            ident(&ratings.score)
+        ));
+
+        let rows: Vec<ScoreCountRow> = self.load(&query)?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.score, row.count as usize))
+            .collect())
+    }
+
+    fn insert_outbox_event<C>(
+        &self,
+        conn: &C,
+        user: &Key,
+        item: &Key,
+        score: Option<f64>,
+        rated_at: Option<Timestamp>,
+    ) -> Result<(), Error>
+    where
+        C: Connection,
+        Query: diesel::query_builder::QueryFragment<C::Backend>,
+    {
+        let ratings = &self.description.ratings;
+        if let (Some(outbox), Some(_)) = (&ratings.outbox, &self.mongo_db) {
+            Query::new(format!(
+                "INSERT INTO {} ({}, {}, score, rated_at) VALUES (",
+                ident(outbox),
+                ident(&ratings.user_id),
+                ident(&ratings.item_id),
+            ))
+            .bind_all(vec![
+                user.clone().into(),
+                item.clone().into(),
+                Bind::from(score),
+                Bind::from(rated_at),
+            ])
+            .sql(")")
+            .execute(conn)?;
+        }
+
+        Ok(())
+    }
+
+    fn insert_rating_sql(&self, user: &Key, item: &Key, score: f64) -> Result<RatingRow, Error> {
+        let ratings = &self.description.ratings;
+        let rated = self.rating_filter(user, item);
+
+        let mut columns = vec![&ratings.user_id, &ratings.item_id, &ratings.score];
+        let mut values = vec![user.clone().into(), item.clone().into(), Bind::Float(score)];
+
+        let rated_at = ratings.time.as_ref().map(|_| now());
+        if let (Some(time), Some(rated_at)) = (&ratings.time, rated_at) {
+            columns.push(time);
+            values.push(Bind::Integer(rated_at));
+        }
+
+        let names: Vec<_> = columns.into_iter().map(|column| ident(column)).collect();
+        let insert = Query::new(format!(
+            "INSERT INTO {} ({}) VALUES (",
+            ident(&ratings.table),
+            names.join(", ")
+        ))
+        .bind_all(values)
+        .sql(")");
+
+        let row = with_conn!(&self.conn, conn => conn.transaction::<_, Error, _>(|| {
+            let exists: Vec<RatingRow> = self.select_ratings().append(rated.clone()).load(conn)?;
+            if !exists.is_empty() {
+                return Err(ErrorKind::InsertRatingFailed(user.to_string(), item.to_string()).into());
+            }
+
+            insert.execute(conn)?;
+            self.insert_outbox_event(conn, user, item, Some(score), rated_at)?;
+
+            let mut rows: Vec<RatingRow> = self.select_ratings().append(rated).load(conn)?;
+            Ok(rows.remove(0))
+        }))?;
+
+        Ok(row)
+    }
+
+    fn remove_rating_sql(&self, user: &Key, item: &Key) -> Result<RatingRow, Error> {
+        let ratings = &self.description.ratings;
+        let rated = self.rating_filter(user, item);
+
+        let row = with_conn!(&self.conn, conn => conn.transaction::<_, Error, _>(|| {
+            let rows: Vec<RatingRow> = self.select_ratings().append(rated.clone()).load(conn)?;
+            let row = rows.into_iter().next().ok_or_else(|| {
+                ErrorKind::RemoveRatingFailed(user.to_string(), item.to_string())
+            })?;
+
+            Query::new(format!("DELETE FROM {}", ident(&ratings.table)))
+                .append(rated)
+                .execute(conn)?;
+
+            self.insert_outbox_event(conn, user, item, None, None)?;
+            Ok(row)
+        }))?;
+
+        Ok(row)
+    }
+
+    fn update_rating_sql(&self, user: &Key, item: &Key, score: f64) -> Result<RatingRow, Error> {
+        let ratings = &self.description.ratings;
+        let rated = self.rating_filter(user, item);
+
+        let rated_at = ratings.time.as_ref().map(|_| now());
+        let mut update = Query::new(format!(
+            "UPDATE {} SET {} = ",
+            ident(&ratings.table),
+            ident(&ratings.score)
+        ))
+        .bind(score);
+
+        if let (Some(time), Some(rated_at)) = (&ratings.time, rated_at) {
+            update = update.sql(format!(", {} = ", ident(time))).bind(rated_at);
+        }
+
+        let update = update.append(rated.clone());
+
+        let row = with_conn!(&self.conn, conn => conn.transaction::<_, Error, _>(|| {
+            // The new score must be different from the actual one
+            let rows: Vec<RatingRow> = self.select_ratings().append(rated.clone()).load(conn)?;
+            rows.into_iter()
+                .next()
+                .filter(|row| row.score != score)
+                .ok_or_else(|| {
+                    ErrorKind::UpdateRatingFailed(user.to_string(), item.to_string())
+                })?;
+
+            update.execute(conn)?;
+            self.insert_outbox_event(conn, user, item, Some(score), rated_at)?;
+
+            let mut rows: Vec<RatingRow> = self.select_ratings().append(rated).load(conn)?;
+            Ok(rows.remove(0))
+        }))?;
+
+        Ok(row)
+    }
+}
+
+impl Controller for DatasetController {
+    type User = DynEntity;
+    type Item = DynEntity;
+    type Rating = DynEntity;
+
+    fn users(&self) -> Result<Vec<Self::User>, Error> {
+        self.entities(&self.description.users, Query::default())
+    }
+
+    fn users_by(&self, by: &SearchBy) -> Result<Vec<Self::User>, Error> {
+        self.entities_by(&self.description.users, by)
+    }
+
+    fn users_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<Self::User>, Error> {
+        self.entities_offset_limit(&self.description.users, offset, limit)
+    }
+
+    fn users_after(
+        &self,
+        after: Option<&eid!(Self::User)>,
+        limit: usize,
+    ) -> Result<Vec<Self::User>, Error> {
+        self.entities_after(&self.description.users, after, limit)
+    }
+
+    fn items(&self) -> Result<Vec<Self::Item>, Error> {
+        self.entities(&self.description.items, Query::default())
+    }
+
+    fn items_by(&self, by: &SearchBy) -> Result<Vec<Self::Item>, Error> {
+        self.entities_by(&self.description.items, by)
+    }
+
+    fn items_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<Self::Item>, Error> {
+        self.entities_offset_limit(&self.description.items, offset, limit)
+    }
+
+    fn items_after(
+        &self,
+        after: Option<&eid!(Self::Item)>,
+        limit: usize,
+    ) -> Result<Vec<Self::Item>, Error> {
+        self.entities_after(&self.description.items, after, limit)
+    }
+
+    fn create_partial_users(
+        &self,
+        user_ids: &[eid!(Self::User)],
+    ) -> Result<Vec<Self::User>, Error> {
+        Ok(user_ids.iter().map(|id| DynEntity::new(id)).collect())
+    }
+
+    fn create_partial_items(
+        &self,
+        item_ids: &[eid!(Self::Item)],
+    ) -> Result<Vec<Self::Item>, Error> {
+        Ok(item_ids.iter().map(|id| DynEntity::new(id)).collect())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn users_who_rated(
+        &self,
+        items: &[Self::Item],
+    ) -> Result<maped_ratings!(Self::Item => Self::User), Error> {
+        if items.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let ids = items.iter().map(|item| &item.id);
+        if !self.users_who_rated_mongo {
+            let ratings = &self.description.ratings;
+            let column = qualified(&ratings.table, &ratings.item_id);
+            let rows = self.ratings_where(Self::keys_in(&column, &self.description.items, ids)?)?;
+
+            Ok(Self::maped_ratings(rows, false))
+        } else {
+            let ids = Self::mongo_keys(&self.description.items, ids)?;
+            self.mongo_ratings(
+                RatingCollection::UsersWhoRated,
+                doc! { "item_id": { "$in": ids } },
+            )
+        }
+    }
+
+    fn user_ratings(&self, user: &Self::User) -> Result<ratings!(Self::Item), Error> {
+        let mut maped_ratings = self.users_ratings(std::slice::from_ref(user))?;
+        Ok(maped_ratings.remove(&user.id).unwrap_or_default())
+    }
+
+    fn user_timed_ratings(
+        &self,
+        user: &Self::User,
+    ) -> Result<ratings!(Self::Item, TimedScore), Error> {
+        let ratings = &self.description.ratings;
+        let filter = Self::keys_in(
+            &qualified(&ratings.table, &ratings.user_id),
+            &self.description.users,
+            std::iter::once(&user.id),
+        )?;
+
+        Ok(self
+            .ratings_where(filter)?
+            .into_iter()
+            .map(|row| (row.item_id, TimedScore::new(row.score, row.rated_at)))
+            .collect())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn users_ratings_since(
+        &self,
+        since: Timestamp,
+    ) -> Result<maped_ratings!(Self::User => Self::Item, TimedScore), Error> {
+        // Without a time column no rating is known to be recent
+        let time = match &self.description.ratings.time {
+            Some(time) => time,
+            None => return Ok(HashMap::new()),
+        };
+
+        let time = qualified(&self.description.ratings.table, time);
+        let filter = Query::new(format!(" WHERE {} >= ", time)).bind(since);
+
+        let mut maped_ratings: HashMap<_, HashMap<_, _>> = HashMap::new();
+        for row in self.ratings_where(filter)? {
+            let score = TimedScore::new(row.score, row.rated_at);
+            maped_ratings
+                .entry(row.user_id)
+                .or_default()
+                .insert(row.item_id, score);
+        }
+
+        Ok(maped_ratings)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn all_users_ratings(&self) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
+        if !self.users_ratings_mongo {
+            let rows = self.ratings_where(Query::default())?;
+            Ok(Self::maped_ratings(rows, true))
+        } else {
+            self.mongo_ratings(RatingCollection::UsersRatings, None)
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn users_ratings(
+        &self,
+        users: &[Self::User],
+    ) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
+        if users.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let ids = users.iter().map(|user| &user.id);
+        if !self.users_ratings_mongo {
+            let ratings = &self.description.ratings;
+            let column = qualified(&ratings.table, &ratings.user_id);
+            let rows = self.ratings_where(Self::keys_in(&column, &self.description.users, ids)?)?;
+
+            Ok(Self::maped_ratings(rows, true))
+        } else {
+            let ids = Self::mongo_keys(&self.description.users, ids)?;
+            self.mongo_ratings(
+                RatingCollection::UsersRatings,
+                doc! { "user_id": { "$in": ids } },
+            )
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn users_ratings_except(
+        &self,
+        user: &Self::User,
+    ) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
+        let key = self.user_key(&user.id)?;
+        if !self.users_ratings_mongo {
+            let ratings = &self.description.ratings;
+            let column = qualified(&ratings.table, &ratings.user_id);
+            let filter = Query::new(format!(" WHERE {} <> ", column)).bind(key);
+
+            Ok(Self::maped_ratings(self.ratings_where(filter)?, true))
+        } else {
+            self.mongo_ratings(
+                RatingCollection::UsersRatings,
+                doc! { "user_id": { "$ne": Bson::from(key) } },
+            )
+        }
+    }
+
+    fn users_means(&self, users: &[Self::User]) -> Result<means!(Self::User), Error> {
+        let ids: Vec<_> = users.iter().map(|user| user.id.clone()).collect();
+        self.means_of(
+            &self.description.users,
+            self.description.means.as_ref(),
+            USER_MEANS_KEY,
+            &self.description.ratings.user_id,
+            &ids,
+        )
+    }
+
+    fn users_count(&self) -> Result<usize, Error> {
+        self.count(&self.description.users.table)
+    }
+
+    fn items_count(&self) -> Result<usize, Error> {
+        self.count(&self.description.items.table)
+    }
+
+    fn ratings_count(&self) -> Result<usize, Error> {
+        self.count(&self.description.ratings.table)
+    }
+
+    fn users_ratings_count(&self, users: &[Self::User]) -> Result<counts!(Self::User), Error> {
+        let ids = users.iter().map(|user| user.id.clone()).collect();
+        self.counts_of(
+            &self.description.users,
+            &self.description.ratings.user_id,
+            ids,
+        )
+    }
+
+    fn items_ratings_count(&self, items: &[Self::Item]) -> Result<counts!(Self::Item), Error> {
+        let ids = items.iter().map(|item| item.id.clone()).collect();
+        self.counts_of(
+            &self.description.items,
+            &self.description.ratings.item_id,
+            ids,
+        )
+    }
+
+    fn items_features(&self, items: &[Self::Item]) -> Result<ItemFeatures<String>, Error> {
+        let table = &self.description.items;
+        let mut features = ItemFeatures::new();
+        if items.is_empty() {
+            return Ok(features);
+        }
+
+        let id = qualified(&table.table, &table.id);
+        let filter = Self::keys_in(&id, table, items.iter().map(|item| &item.id))?;
+        for row in self.load::<EntityRow>(&Self::select_entities(table).append(filter))? {
+            let mut item_features = Vec::new();
+            for ((column, ty), value) in table.data.iter().zip(&row.data) {
+                let value = match value {
+                    Some(value) => value,
+                    None => continue,
+                };
+
+                // Numeric columns keep their value, text ones are split in words
+                match ty {
+                    ColumnType::Integer | ColumnType::Float => {
+                        if let Ok(value) = value.parse() {
+                            item_features.push(Feature::numeric(column, value));
+                        }
+                    }
+                    ColumnType::Text => item_features.extend(Feature::words(column, value)),
+                }
+            }
+
+            features.add(row.id, item_features);
+        }
+
+        Ok(features)
+    }
+
+    fn items_means(&self, items: &[Self::Item]) -> Result<means!(Self::Item), Error> {
+        let ids: Vec<_> = items.iter().map(|item| item.id.clone()).collect();
+        self.means_of(
+            &self.description.items,
+            self.description.item_means.as_ref(),
+            ITEM_MEANS_KEY,
+            &self.description.ratings.item_id,
+            &ids,
+        )
+    }
+
+    fn global_mean(&self) -> Result<Option<f64>, Error> {
+        let ratings = &self.description.ratings;
+        let query = match &self.description.global_mean {
+            Some(global_mean) => Query::new(format!(
+                "SELECT {} AS val, {} AS count FROM {}",
+                as_float("val"),
+                as_bigint("score_number"),
+                ident(global_mean)
+            )),
+            None => Query::new(format!(
+                "SELECT {} AS val, COUNT(*) AS count FROM {}",
+                as_float(&format!("AVG({})", ident(&ratings.score))),
+                ident(&ratings.table)
+            )),
+        };
+
+        // The row is kept after the last rating is removed
+        let rows: Vec<MeanRow> = self.load(&query)?;
+        Ok(rows
+            .into_iter()
+            .find(|row| row.count > 0)
+            .and_then(|row| row.val))
+    }
+
+    fn user_histogram(&self, user: &Self::User) -> Result<Histogram, Error> {
+        let key = self.user_key(&user.id)?;
+        self.histogram_of(&self.description.ratings.user_id, key)
+    }
+
+    fn item_histogram(&self, item: &Self::Item) -> Result<Histogram, Error> {
+        let key = self.item_key(&item.id)?;
+        self.histogram_of(&self.description.ratings.item_id, key)
+    }
+
+    fn most_rated_items(&self, limit: usize) -> Result<Vec<(eid!(Self::Item), usize)>, Error> {
+        let ratings = &self.description.ratings;
+        let query = match &self.description.item_means {
+            Some(item_means) => Query::new(format!(
+                "SELECT {} AS id, {} AS count FROM {} ORDER BY score_number DESC, {} LIMIT ",
+                as_text(ITEM_MEANS_KEY),
+                as_bigint("score_number"),
+                ident(item_means),
+                ITEM_MEANS_KEY
+            )),
+            None => Query::new(format!(
+                "SELECT {} AS id, COUNT(*) AS count FROM {} GROUP BY {} ORDER BY COUNT(*) DESC, {} LIMIT ",
+                as_text(&ident(&ratings.item_id)),
+                ident(&ratings.table),
+                ident(&ratings.item_id),
+                ident(&ratings.item_id)
+            )),
+        }
+        .bind(limit as i64);
+
+        let rows: Vec<IdCountRow> = self.load(&query)?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.id, row.count as usize))
+            .collect())
+    }
+
+    fn score_range(&self) -> (f64, f64) {
+        self.description.score_range
+    }
+
+    fn rating_scale(&self) -> RatingScale {
+        match self.description.score_step {
+            Some(step) => RatingScale::discrete(self.description.score_range, step),
+            None => RatingScale::continuous(self.description.score_range),
+        }
+    }
+
+    fn rating_kind(&self, score: f64) -> RatingKind {
+        if Some(score) == self.description.implicit_score {
+            RatingKind::Implicit
+        } else {
+            RatingKind::Explicit
+        }
+    }
+
+    fn fields_for_users(&self) -> Vec<Field<'_>> {
+        Self::fields_for(&self.description.users)
+    }
+
+    fn fields_for_items(&self) -> Vec<Field<'_>> {
+        Self::fields_for(&self.description.items)
+    }
+
+    fn custom_keys_for_users(&self) -> Vec<String> {
+        self.description.users.data.keys().cloned().collect()
+    }
+
+    fn custom_keys_for_items(&self) -> Vec<String> {
+        self.description.items.data.keys().cloned().collect()
+    }
+
+    fn insert_user(&self, proto: HashMap<&str, Value>) -> Result<Self::User, Error> {
+        self.insert_entity(&self.description.users, proto)
+    }
+
+    fn insert_item(&self, proto: HashMap<&str, Value>) -> Result<Self::Item, Error> {
+        self.insert_entity(&self.description.items, proto)
+    }
+
+    fn update_user(
+        &self,
+        user_id: &eid!(Self::User),
+        proto: HashMap<&str, Value>,
+    ) -> Result<Self::User, Error> {
+        self.update_entity(&self.description.users, user_id, proto)
+    }
+
+    fn update_item(
+        &self,
+        item_id: &eid!(Self::Item),
+        proto: HashMap<&str, Value>,
+    ) -> Result<Self::Item, Error> {
+        self.update_entity(&self.description.items, item_id, proto)
+    }
+
+    fn remove_user(&self, user_id: &eid!(Self::User)) -> Result<Self::User, Error> {
+        let means = self
+            .description
+            .means
+            .clone()
+            .map(|means| (means, USER_MEANS_KEY));
+
+        let user = self.remove_entity(
+            &self.description.users,
+            user_id,
+            &self.description.ratings.user_id,
+            means.as_ref(),
+        )?;
+
+        // Its ratings are unset from mongo through the outbox, what's left is an
+        // empty document that can be removed once they're applied
+        if let (Ok(_), Some(mongo_db)) = (self.replay_outbox(), &self.mongo_db) {
+            let users_ratings = mongo_db.collection("users_ratings");
+            let key = self.user_key(user_id)?;
+            users_ratings.delete_one(doc! { "user_id": Bson::from(key) }, None)?;
+        }
+
+        Ok(user)
+    }
+
+    fn remove_item(&self, item_id: &eid!(Self::Item)) -> Result<Self::Item, Error> {
+        let means = self
+            .description
+            .item_means
+            .clone()
+            .map(|means| (means, ITEM_MEANS_KEY));
+
+        let item = self.remove_entity(
+            &self.description.items,
+            item_id,
+            &self.description.ratings.item_id,
+            means.as_ref(),
+        )?;
+
+        if let (Ok(_), Some(mongo_db)) = (self.replay_outbox(), &self.mongo_db) {
+            let users_who_rated = mongo_db.collection("users_who_rated");
+            let key = self.item_key(item_id)?;
+            users_who_rated.delete_one(doc! { "item_id": Bson::from(key) }, None)?;
+        }
+
+        Ok(item)
+    }
+
+    fn insert_rating(
+        &self,
+        user_id: &eid!(Self::User),
+        item_id: &eid!(Self::Item),
+        score: f64,
+    ) -> Result<Self::Rating, Error> {
+        self.check_score(score)?;
+
+        let (user, item) = (self.user_key(user_id)?, self.item_key(item_id)?);
+        let row = self.insert_rating_sql(&user, &item, score)?;
+        self.flush_outbox();
+
+        Ok(self.to_rating(row))
+    }
+
+    fn remove_rating(
+        &self,
+        user_id: &eid!(Self::User),
+        item_id: &eid!(Self::Item),
+    ) -> Result<Self::Rating, Error> {
+        let (user, item) = (self.user_key(user_id)?, self.item_key(item_id)?);
+        let row = self.remove_rating_sql(&user, &item)?;
+        self.flush_outbox();
+
+        Ok(self.to_rating(row))
+    }
+
+    fn update_rating(
+        &self,
+        user_id: &eid!(Self::User),
+        item_id: &eid!(Self::Item),
+        score: f64,
+    ) -> Result<Self::Rating, Error> {
+        self.check_score(score)?;
+
+        let (user, item) = (self.user_key(user_id)?, self.item_key(item_id)?);
+        let row = self.update_rating_sql(&user, &item, score)?;
+        self.flush_outbox();
+
+        Ok(self.to_rating(row))
+    }
+}
+
+impl MirroredRatings for DatasetController {
+    fn mirror_db(&self) -> Result<&Database, Error> {
+        self.mongo_db()
+    }
+
+    fn mirrored_collections(&self) -> &[RatingCollection] {
+        MONGO_COLLECTIONS
+    }
+
+    fn table_users_ratings(
+        &self,
+        user_ids: &[String],
+    ) -> Result<MapedRatings<String, String, TimedScore>, Error> {
+        if user_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let ratings = &self.description.ratings;
+        let column = qualified(&ratings.table, &ratings.user_id);
+        let rows =
+            self.ratings_where(Self::keys_in(&column, &self.description.users, user_ids)?)?;
+
+        Ok(Self::timed_ratings(rows, true))
+    }
+
+    fn table_items_ratings(
+        &self,
+        item_ids: &[String],
+    ) -> Result<MapedRatings<String, String, TimedScore>, Error> {
+        if item_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let ratings = &self.description.ratings;
+        let column = qualified(&ratings.table, &ratings.item_id);
+        let rows =
+            self.ratings_where(Self::keys_in(&column, &self.description.items, item_ids)?)?;
+
+        Ok(Self::timed_ratings(rows, false))
+    }
+
+    fn user_document_key(&self, user_id: &String) -> Result<Bson, Error> {
+        Ok(self.user_key(user_id)?.into())
+    }
+
+    fn item_document_key(&self, item_id: &String) -> Result<Bson, Error> {
+        Ok(self.item_key(item_id)?.into())
+    }
+}
+
+#[cfg(test)]
+mod sqlite_tests {
+    use super::*;
+    use common_macros::hash_map;
+    use config::DatabaseEntry;
+    use controller::NameMatch;
+
+    fn sqlite_controller(file: &str) -> Result<DatasetController, Error> {
+        let path = std::env::temp_dir().join(file);
+        let _ = std::fs::remove_file(&path);
+
+        let description = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../config/example-dataset.toml"
+        );
+
+        let mut config = Config::default();
+        config.databases.insert(
+            "dataset-sqlite".into(),
+            DatabaseEntry {
+                kind: Some("dataset".into()),
+                backend: Backend::Sqlite,
+                sqlite_path: path.to_string_lossy().into(),
+                psql_url: String::new(),
+                mongo_url: String::new(),
+                mongo_db: String::new(),
+                users_ratings_mongo: false,
+                users_who_rated_mongo: false,
+                dataset: Some(description.into()),
+            },
+        );
+
+        DatasetController::from_config(&config, "dataset-sqlite")
+    }
+
+    #[test]
+    fn sqlite_entities() -> Result<(), Error> {
+        let controller = sqlite_controller("dataset-entities.db")?;
+
+        let lima = controller.insert_user(hash_map! {
+            "age" => Value::Int64(31),
+            "location" => Value::String("Lima".into()),
+        })?;
+        let quito =
+            controller.insert_user(hash_map! { "location" => Value::String("Quito".into()) })?;
+        assert_ne!(lima.id, quito.id);
+        assert_eq!(lima.data["age"], Value::Int64(31));
+        assert!(controller
+            .insert_user(hash_map! { "age" => Value::Int64(20) })
+            .is_err());
+
+        let book = controller.insert_item(hash_map! {
+            "id" => Value::String("0446520802".into()),
+            "title" => Value::String("The Notebook".into()),
+            "year" => Value::Int64(1996),
+        })?;
+        assert_eq!(book.id, "0446520802");
+
+        let found =
+            controller.items_by(&SearchBy::name_like(NameMatch::Contains, "notebook", 5))?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].data["title"], Value::String("The Notebook".into()));
+
+        let book = controller.update_item(&book.id, hash_map! { "year" => Value::Int64(1997) })?;
+        assert_eq!(book.data["year"], Value::Int64(1997));
+
+        let users = controller.users_by(&SearchBy::custom("location", "Lima"))?;
+        assert_eq!(users[0].id, lima.id);
+        assert_eq!(controller.custom_keys_for_users(), vec!["age", "location"]);
+        assert_eq!(controller.users_after(Some(&lima.id), 10)?.len(), 1);
+
+        controller.remove_user(&quito.id)?;
+        assert_eq!(controller.users_count()?, 1);
+        assert!(controller.users_by(&SearchBy::id(&quito.id)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn sqlite_ratings_and_means() -> Result<(), Error> {
+        let controller = sqlite_controller("dataset-ratings.db")?;
+
+        let location = || Value::String("Lima".into());
+        let ana = controller.insert_user(hash_map! { "location" => location() })?;
+        let chris = controller.insert_user(hash_map! { "location" => location() })?;
+
+        let notebook = controller.insert_item(hash_map! {
+            "id" => Value::String("0446520802".into()),
+            "title" => Value::String("The Notebook".into()),
+        })?;
+        let dune = controller.insert_item(hash_map! {
+            "id" => Value::String("0441172717".into()),
+            "title" => Value::String("Dune".into()),
+        })?;
+        assert_eq!(dune.id, "0441172717");
+
+        controller.insert_rating(&ana.id, &notebook.id, 4.)?;
+        controller.insert_rating(&ana.id, &dune.id, 8.)?;
+        controller.insert_rating(&chris.id, &dune.id, 10.)?;
+        assert!(controller.insert_rating(&chris.id, &dune.id, 1.).is_err());
+        assert!(controller
+            .insert_rating(&chris.id, &notebook.id, 11.)
+            .is_err());
+        assert!(controller
+            .insert_rating(&chris.id, &notebook.id, 7.5)
+            .is_err());
+
+        assert_eq!(controller.ratings_count()?, 3);
+        assert_eq!(controller.user_ratings(&ana)?[&dune.id], 8.);
+        assert_eq!(controller.most_rated_items(1)?, vec![(dune.id.clone(), 2)]);
+
+        let who_rated = controller.users_who_rated(std::slice::from_ref(&dune))?;
+        assert_eq!(who_rated[&dune.id].len(), 2);
+
+        let users = vec![ana.clone(), chris.clone()];
+        assert_eq!(controller.users_means(&users)?[&ana.id], 6.);
+        assert_eq!(
+            controller.items_means(std::slice::from_ref(&dune))?[&dune.id],
+            9.
+        );
+        assert_eq!(controller.global_mean()?, Some(22. / 3.));
+
+        controller.update_rating(&ana.id, &notebook.id, 6.)?;
+        assert_eq!(controller.users_means(&users)?[&ana.id], 7.);
+
+        controller.remove_rating(&chris.id, &dune.id)?;
+        assert!(!controller.users_means(&users)?.contains_key(&chris.id));
+
+        controller.remove_item(&dune.id)?;
+        assert_eq!(controller.users_means(&users)?[&ana.id], 6.);
+        assert_eq!(controller.ratings_count()?, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sqlite_mirror_reads_the_ratings_table() -> Result<(), Error> {
+        let controller = sqlite_controller("dataset-mirror.db")?;
+
+        let ana =
+            controller.insert_user(hash_map! { "location" => Value::String("Lima".into()) })?;
+        let dune = controller.insert_item(hash_map! {
+            "id" => Value::String("0441172717".into()),
+            "title" => Value::String("Dune".into()),
+        })?;
+        controller.insert_rating(&ana.id, &dune.id, 8.)?;
+
+        let by_user = controller.table_users_ratings(std::slice::from_ref(&ana.id))?;
+        assert_eq!(by_user[&ana.id][&dune.id].score, 8.);
+
+        let by_item = controller.table_items_ratings(std::slice::from_ref(&dune.id))?;
+        assert!(by_item[&dune.id].contains_key(&ana.id));
+        assert!(controller.table_items_ratings(&[])?.is_empty());
+
+        // Keys take the type of the ids in the description
+        assert_eq!(
+            controller.item_document_key(&dune.id)?,
+            Bson::String(dune.id.clone())
+        );
+        assert!(controller.user_document_key(&"ana".to_string()).is_err());
+
+        // There's no mongo to check against with sqlite
+        assert!(controller.check_mongo(10).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use config::{ColumnType, IdType};
+use controller::{error::ErrorKind, Value};
+use diesel::{
+    backend::Backend,
+    deserialize::{self, FromSql, QueryableByName},
+    query_builder::{AstPass, QueryFragment, QueryId},
+    row::NamedRow,
+    serialize::ToSql,
+    sql_types::{BigInt, Double, HasSqlType, Nullable, Text},
+    Connection, QueryResult,
+};
+use mongodb::bson::Bson;
+use std::fmt::{self, Display};
+
+/// Quote a table or column name of the description, so it's taken as is
+pub fn ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Column of a table, columns in filters are qualified since the selected
+/// columns are aliased with names that could shadow them
+pub fn qualified(table: &str, column: &str) -> String {
+    format!("{}.{}", ident(table), ident(column))
+}
+
+/// Column or expression read as text, ids and data columns are selected this way
+pub fn as_text(expr: &str) -> String {
+    format!("CAST({} AS TEXT)", expr)
+}
+
+/// Column or expression read as a float
+pub fn as_float(expr: &str) -> String {
+    format!("CAST({} AS DOUBLE PRECISION)", expr)
+}
+
+/// Column or expression read as a big integer
+pub fn as_bigint(expr: &str) -> String {
+    format!("CAST({} AS BIGINT)", expr)
+}
+
+/// Alias of the n-th data column in an entity query
+pub fn data_alias(index: usize) -> String {
+    format!("d{}", index)
+}
+
+/// A value bound to a query, its sql type is given by the variant
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bind {
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    Null,
+}
+
+impl Bind {
+    /// Parse a value of a data column
+    pub fn parse(value: &str, ty: ColumnType) -> Result<Self, ErrorKind> {
+        let convert = |e: &dyn Display| ErrorKind::ValueConvert(e.to_string());
+
+        match ty {
+            _ if value.is_empty() => Ok(Bind::Null),
+            ColumnType::Integer => Ok(Bind::Integer(value.parse().map_err(|e| convert(&e))?)),
+            ColumnType::Float => Ok(Bind::Float(value.parse().map_err(|e| convert(&e))?)),
+            ColumnType::Text => Ok(Bind::Text(value.into())),
+        }
+    }
+}
+
+impl From<i64> for Bind {
+    fn from(value: i64) -> Self {
+        Bind::Integer(value)
+    }
+}
+
+impl From<f64> for Bind {
+    fn from(value: f64) -> Self {
+        Bind::Float(value)
+    }
+}
+
+impl<T: Into<Bind>> From<Option<T>> for Bind {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Bind::Null, Into::into)
+    }
+}
+
+impl From<&Value> for Bind {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Null => Bind::Null,
+            Value::String(value) => Bind::Text(value.clone()),
+            Value::Bool(value) => Bind::Integer(*value as i64),
+            Value::Int16(value) => Bind::Integer(*value as i64),
+            Value::Int32(value) => Bind::Integer(*value as i64),
+            Value::Int64(value) => Bind::Integer(*value),
+            Value::Double(value) => Bind::Float(*value),
+            Value::Timestamp(value) => Bind::Integer(*value),
+            other => Bind::Text(other.to_string()),
+        }
+    }
+}
+
+/// Id of an user or item as it's stored, in the database and in the mongo documents
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    Integer(i32),
+    Text(String),
+}
+
+impl Key {
+    pub fn parse(id: &str, ty: IdType) -> Result<Self, ErrorKind> {
+        match ty {
+            IdType::Integer => id
+                .parse()
+                .map(Key::Integer)
+                .map_err(|_| ErrorKind::NotFoundById(id.into())),
+            IdType::Text => Ok(Key::Text(id.into())),
+        }
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::Integer(id) => write!(f, "{}", id),
+            Key::Text(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+impl From<Key> for Bind {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Integer(id) => Bind::Integer(id as i64),
+            Key::Text(id) => Bind::Text(id),
+        }
+    }
+}
+
+impl From<Key> for Bson {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Integer(id) => Bson::Int32(id),
+            Key::Text(id) => Bson::String(id),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Sql(String),
+    Bind(Bind),
+}
+
+/// A query written at runtime, table and column names come from the dataset
+/// description so they can't be checked like the ones declared with `table!`.
+/// Values are always bound, never written in the sql
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    parts: Vec<Part>,
+}
+
+impl Query {
+    pub fn new(sql: impl Into<String>) -> Self {
+        Self {
+            parts: vec![Part::Sql(sql.into())],
+        }
+    }
+
+    pub fn sql(mut self, sql: impl Into<String>) -> Self {
+        self.parts.push(Part::Sql(sql.into()));
+        self
+    }
+
+    pub fn bind(mut self, value: impl Into<Bind>) -> Self {
+        self.parts.push(Part::Bind(value.into()));
+        self
+    }
+
+    /// Bind every value separated by commas, ex. within `IN (...)`
+    pub fn bind_all<B: Into<Bind>>(mut self, values: impl IntoIterator<Item = B>) -> Self {
+        for (i, value) in values.into_iter().enumerate() {
+            if i > 0 {
+                self.parts.push(Part::Sql(", ".into()));
+            }
+
+            self.parts.push(Part::Bind(value.into()));
+        }
+
+        self
+    }
+
+    pub fn append(mut self, other: Query) -> Self {
+        self.parts.extend(other.parts);
+        self
+    }
+
+    pub fn load<C, T>(&self, conn: &C) -> QueryResult<Vec<T>>
+    where
+        C: Connection,
+        T: QueryableByName<C::Backend>,
+        Self: QueryFragment<C::Backend>,
+    {
+        conn.query_by_name(self)
+    }
+
+    pub fn execute<C>(&self, conn: &C) -> QueryResult<usize>
+    where
+        C: Connection,
+        Self: QueryFragment<C::Backend>,
+    {
+        conn.execute_returning_count(self)
+    }
+}
+
+impl<DB> QueryFragment<DB> for Query
+where
+    DB: Backend + HasSqlType<BigInt> + HasSqlType<Double> + HasSqlType<Text>,
+    i64: ToSql<BigInt, DB>,
+    f64: ToSql<Double, DB>,
+    String: ToSql<Text, DB>,
+{
+    fn walk_ast(&self, mut out: AstPass<DB>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        for part in &self.parts {
+            match part {
+                Part::Sql(sql) => out.push_sql(sql),
+                Part::Bind(Bind::Integer(value)) => out.push_bind_param::<BigInt, _>(value)?,
+                Part::Bind(Bind::Float(value)) => out.push_bind_param::<Double, _>(value)?,
+                Part::Bind(Bind::Text(value)) => out.push_bind_param::<Text, _>(value)?,
+                Part::Bind(Bind::Null) => out.push_sql("NULL"),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl QueryId for Query {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+/// An user or item, its data columns are selected as `d0`, `d1`... in the order
+/// of the description
+#[derive(Debug, Clone)]
+pub struct EntityRow {
+    pub id: String,
+    pub name: Option<String>,
+    pub data: Vec<Option<String>>,
+}
+
+impl<DB> QueryableByName<DB> for EntityRow
+where
+    DB: Backend,
+    String: FromSql<Text, DB>,
+{
+    fn build<R: NamedRow<DB>>(row: &R) -> deserialize::Result<Self> {
+        let id = row.get::<Text, String>("id")?;
+        let name = match row.index_of("name") {
+            Some(_) => row.get::<Nullable<Text>, Option<String>>("name")?,
+            None => None,
+        };
+
+        let mut data = Vec::new();
+        while row.index_of(&data_alias(data.len())).is_some() {
+            data.push(row.get::<Nullable<Text>, Option<String>>(&data_alias(data.len()))?);
+        }
+
+        Ok(Self { id, name, data })
+    }
+}
+
+#[derive(Debug, Clone, QueryableByName)]
+pub struct RatingRow {
+    #[sql_type = "Text"]
+    pub id: String,
+    #[sql_type = "Text"]
+    pub user_id: String,
+    #[sql_type = "Text"]
+    pub item_id: String,
+    #[sql_type = "Double"]
+    pub score: f64,
+    #[sql_type = "Nullable<BigInt>"]
+    pub rated_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, QueryableByName)]
+pub struct OutboxRow {
+    #[sql_type = "BigInt"]
+    pub id: i64,
+    #[sql_type = "Text"]
+    pub user_id: String,
+    #[sql_type = "Text"]
+    pub item_id: String,
+    #[sql_type = "Nullable<Double>"]
+    pub score: Option<f64>,
+    #[sql_type = "Nullable<BigInt>"]
+    pub rated_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, QueryableByName)]
+pub struct IdRow {
+    #[sql_type = "Text"]
+    pub id: String,
+}
+
+#[derive(Debug, Clone, QueryableByName)]
+pub struct IdValueRow {
+    #[sql_type = "Text"]
+    pub id: String,
+    #[sql_type = "Double"]
+    pub val: f64,
+}
+
+#[derive(Debug, Clone, QueryableByName)]
+pub struct IdCountRow {
+    #[sql_type = "Text"]
+    pub id: String,
+    #[sql_type = "BigInt"]
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, QueryableByName)]
+pub struct ScoreCountRow {
+    #[sql_type = "Double"]
+    pub score: f64,
+    #[sql_type = "BigInt"]
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, QueryableByName)]
+pub struct MeanRow {
+    #[sql_type = "Nullable<Double>"]
+    pub val: Option<f64>,
+    #[sql_type = "BigInt"]
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, QueryableByName)]
+pub struct CountRow {
+    #[sql_type = "BigInt"]
+    pub count: i64,
+}
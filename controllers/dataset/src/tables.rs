@@ -0,0 +1,449 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::query::{ident, Bind, CountRow, Query};
+use anyhow::Error;
+use config::{Backend, ColumnType, DatasetDescription, EntityTable, IdType};
+use controller::{with_conn, DbConnection};
+use diesel::connection::SimpleConnection;
+
+/// Key column of the users means table. Tables of means have the same layout as
+/// the ones of the other controllers whatever the dataset is, so databases of those
+/// can be described too: the key (`id` for the global mean), the mean in `val` and
+/// the number of scores it was computed from in `score_number`
+pub const USER_MEANS_KEY: &str = "user_id";
+
+/// Key column of the items means table, see `USER_MEANS_KEY`
+pub const ITEM_MEANS_KEY: &str = "item_id";
+
+fn id_column(table: &EntityTable, backend: Backend) -> String {
+    let ty = match (table.id_type, backend) {
+        (IdType::Integer, Backend::Postgres) => "SERIAL",
+        (IdType::Integer, Backend::Sqlite) => "INTEGER",
+        (IdType::Text, _) => "VARCHAR",
+    };
+
+    format!("{} {} PRIMARY KEY", ident(&table.id), ty)
+}
+
+fn key_type(table: &EntityTable) -> &'static str {
+    match table.id_type {
+        IdType::Integer => "INTEGER",
+        IdType::Text => "VARCHAR",
+    }
+}
+
+fn reference(table: &EntityTable) -> String {
+    format!(
+        "{} NOT NULL REFERENCES {}({})",
+        key_type(table),
+        ident(&table.table),
+        ident(&table.id)
+    )
+}
+
+fn column_type(ty: ColumnType) -> &'static str {
+    match ty {
+        ColumnType::Integer => "BIGINT",
+        ColumnType::Float => "FLOAT",
+        ColumnType::Text => "VARCHAR",
+    }
+}
+
+fn entity_table(table: &EntityTable, backend: Backend) -> String {
+    let mut columns = vec![id_column(table, backend)];
+    if let Some(name) = &table.name {
+        columns.push(format!("{} VARCHAR", ident(name)));
+    }
+
+    for (column, ty) in &table.data {
+        let not_null = if table.required.contains(column) {
+            " NOT NULL"
+        } else {
+            ""
+        };
+
+        columns.push(format!(
+            "{} {}{}",
+            ident(column),
+            column_type(*ty),
+            not_null
+        ));
+    }
+
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (\n    {}\n);\n",
+        ident(&table.table),
+        columns.join(",\n    ")
+    )
+}
+
+/// Create the tables (and indexes) of a dataset that don't exist yet, with sqlite
+/// the tables of means are created too so they're kept up to date from the start
+pub fn create_tables(conn: &DbConnection, description: &DatasetDescription) -> Result<(), Error> {
+    let backend = conn.backend();
+    let (users, items, ratings) = (&description.users, &description.items, &description.ratings);
+
+    let mut sql = entity_table(users, backend);
+    sql += &entity_table(items, backend);
+
+    let rating_id = match backend {
+        Backend::Postgres => "SERIAL",
+        Backend::Sqlite => "INTEGER",
+    };
+
+    let time = match &ratings.time {
+        Some(time) => format!(",\n    {} BIGINT", ident(time)),
+        None => String::new(),
+    };
+
+    sql += &format!(
+        "CREATE TABLE IF NOT EXISTS {table} (
+    {id} {rating_id} PRIMARY KEY,
+    {user} {user_ref},
+    {item} {item_ref},
+    {score} FLOAT NOT NULL{time}
+);
+CREATE UNIQUE INDEX IF NOT EXISTS {user_item_idx} ON {table}({user}, {item});
+CREATE INDEX IF NOT EXISTS {item_idx} ON {table}({item});
+",
+        table = ident(&ratings.table),
+        id = ident(&ratings.id),
+        rating_id = rating_id,
+        user = ident(&ratings.user_id),
+        user_ref = reference(users),
+        item = ident(&ratings.item_id),
+        item_ref = reference(items),
+        score = ident(&ratings.score),
+        time = time,
+        user_item_idx = ident(&format!(
+            "{}_{}_{}_idx",
+            ratings.table, ratings.user_id, ratings.item_id
+        )),
+        item_idx = ident(&format!("{}_{}_idx", ratings.table, ratings.item_id)),
+    );
+
+    // Mongo is only used along with postgres, so is the outbox. It outlives the
+    // removed users and items, so its columns don't reference them
+    if let (Some(outbox), Backend::Postgres) = (&ratings.outbox, backend) {
+        sql += &format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+    id BIGSERIAL PRIMARY KEY,
+    {} {} NOT NULL,
+    {} {} NOT NULL,
+    score FLOAT,
+    rated_at BIGINT
+);
+",
+            ident(outbox),
+            ident(&ratings.user_id),
+            key_type(users),
+            ident(&ratings.item_id),
+            key_type(items),
+        );
+    }
+
+    // Fuzzy searches by name are ranked by pg_trgm
+    if backend == Backend::Postgres {
+        sql += "CREATE EXTENSION IF NOT EXISTS pg_trgm;\n";
+        for table in &[users, items] {
+            if let Some(name) = &table.name {
+                sql += &format!(
+                    "CREATE INDEX IF NOT EXISTS {} ON {} USING gin (LOWER({}) gin_trgm_ops);\n",
+                    ident(&format!("{}_{}_trgm_idx", table.table, name)),
+                    ident(&table.table),
+                    ident(name),
+                );
+            }
+        }
+    }
+
+    with_conn!(conn, conn => conn.batch_execute(&sql))?;
+
+    if backend == Backend::Sqlite {
+        create_means(conn, description)?;
+    }
+
+    Ok(())
+}
+
+/// Tables of means by user or item along with their key column and the table
+/// the key references
+fn mean_tables(description: &DatasetDescription) -> Vec<(&String, &'static str, &EntityTable)> {
+    let mut tables = Vec::new();
+    if let Some(means) = &description.means {
+        tables.push((means, USER_MEANS_KEY, &description.users));
+    }
+
+    if let Some(item_means) = &description.item_means {
+        tables.push((item_means, ITEM_MEANS_KEY, &description.items));
+    }
+
+    tables
+}
+
+const PG_MEANS_TRIGGERS: &str = "
+INSERT INTO {means}({key}, val, score_number)
+SELECT {column}, AVG({score}), COUNT(*) FROM {ratings} GROUP BY {column};
+
+CREATE OR REPLACE FUNCTION {on_new}() RETURNS TRIGGER AS
+$BODY$
+BEGIN
+    INSERT INTO {means}({key}, val, score_number)
+    VALUES (new.{column}, new.{score}, 1)
+    ON CONFLICT ({key}) DO UPDATE
+        SET val = ({means}.val * {means}.score_number + excluded.val) / ({means}.score_number + 1),
+            score_number = {means}.score_number + 1;
+
+    RETURN new;
+END;
+$BODY$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION {on_del}() RETURNS TRIGGER AS
+$BODY$
+BEGIN
+    DELETE FROM {means} WHERE {key} = old.{column} AND score_number <= 1;
+
+    UPDATE {means}
+    SET val = (val * score_number - old.{score}) / (score_number - 1),
+        score_number = score_number - 1
+    WHERE {key} = old.{column};
+
+    RETURN old;
+END;
+$BODY$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION {on_upd}() RETURNS TRIGGER AS
+$BODY$
+BEGIN
+    UPDATE {means}
+    SET val = val + (new.{score} - old.{score}) / score_number
+    WHERE {key} = new.{column};
+
+    RETURN new;
+END;
+$BODY$
+LANGUAGE plpgsql;
+";
+
+const PG_GLOBAL_MEAN_TRIGGERS: &str = "
+INSERT INTO {means}(id, val, score_number)
+SELECT 1, COALESCE(AVG({score}), 0), COUNT(*) FROM {ratings};
+
+CREATE OR REPLACE FUNCTION {on_new}() RETURNS TRIGGER AS
+$BODY$
+BEGIN
+    UPDATE {means}
+    SET val = (val * score_number + new.{score}) / (score_number + 1),
+        score_number = score_number + 1;
+
+    RETURN new;
+END;
+$BODY$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION {on_del}() RETURNS TRIGGER AS
+$BODY$
+BEGIN
+    UPDATE {means}
+    SET val = CASE WHEN score_number <= 1 THEN 0
+                   ELSE (val * score_number - old.{score}) / (score_number - 1)
+              END,
+        score_number = GREATEST(score_number - 1, 0);
+
+    RETURN old;
+END;
+$BODY$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION {on_upd}() RETURNS TRIGGER AS
+$BODY$
+BEGIN
+    UPDATE {means}
+    SET val = val + (new.{score} - old.{score}) / score_number;
+
+    RETURN new;
+END;
+$BODY$
+LANGUAGE plpgsql;
+";
+
+const PG_CREATE_TRIGGERS: &str = "
+CREATE TRIGGER {on_new} AFTER INSERT ON {ratings}
+FOR EACH ROW EXECUTE FUNCTION {on_new}();
+
+CREATE TRIGGER {on_del} AFTER DELETE ON {ratings}
+FOR EACH ROW EXECUTE FUNCTION {on_del}();
+
+CREATE TRIGGER {on_upd} AFTER UPDATE OF {score} ON {ratings}
+FOR EACH ROW EXECUTE FUNCTION {on_upd}();
+";
+
+const SQLITE_MEANS_TRIGGERS: &str = "
+INSERT INTO {means}({key}, val, score_number)
+SELECT {column}, AVG({score}), COUNT(*) FROM {ratings} GROUP BY {column};
+
+CREATE TRIGGER {on_new} AFTER INSERT ON {ratings}
+FOR EACH ROW
+BEGIN
+    INSERT OR IGNORE INTO {means}({key}, val, score_number) VALUES (new.{column}, 0, 0);
+
+    UPDATE {means}
+    SET val = (val * score_number + new.{score}) / (score_number + 1),
+        score_number = score_number + 1
+    WHERE {key} = new.{column};
+END;
+
+CREATE TRIGGER {on_del} AFTER DELETE ON {ratings}
+FOR EACH ROW
+BEGIN
+    DELETE FROM {means} WHERE {key} = old.{column} AND score_number <= 1;
+
+    UPDATE {means}
+    SET val = (val * score_number - old.{score}) / (score_number - 1),
+        score_number = score_number - 1
+    WHERE {key} = old.{column};
+END;
+
+CREATE TRIGGER {on_upd} AFTER UPDATE OF {score} ON {ratings}
+FOR EACH ROW
+BEGIN
+    UPDATE {means}
+    SET val = val + (new.{score} - old.{score}) / score_number
+    WHERE {key} = new.{column};
+END;
+";
+
+const SQLITE_GLOBAL_MEAN_TRIGGERS: &str = "
+INSERT INTO {means}(id, val, score_number)
+SELECT 1, COALESCE(AVG({score}), 0), COUNT(*) FROM {ratings};
+
+CREATE TRIGGER {on_new} AFTER INSERT ON {ratings}
+FOR EACH ROW
+BEGIN
+    UPDATE {means}
+    SET val = (val * score_number + new.{score}) / (score_number + 1),
+        score_number = score_number + 1;
+END;
+
+CREATE TRIGGER {on_del} AFTER DELETE ON {ratings}
+FOR EACH ROW
+BEGIN
+    UPDATE {means}
+    SET val = CASE WHEN score_number <= 1 THEN 0
+                   ELSE (val * score_number - old.{score}) / (score_number - 1)
+              END,
+        score_number = MAX(score_number - 1, 0);
+END;
+
+CREATE TRIGGER {on_upd} AFTER UPDATE OF {score} ON {ratings}
+FOR EACH ROW
+BEGIN
+    UPDATE {means}
+    SET val = val + (new.{score} - old.{score}) / score_number;
+END;
+";
+
+fn fill(
+    template: &str,
+    means: &str,
+    key: &str,
+    column: &str,
+    description: &DatasetDescription,
+) -> String {
+    template
+        .replace("{means}", &ident(means))
+        .replace("{on_new}", &ident(&format!("{}_on_new", means)))
+        .replace("{on_del}", &ident(&format!("{}_on_del", means)))
+        .replace("{on_upd}", &ident(&format!("{}_on_upd", means)))
+        .replace("{ratings}", &ident(&description.ratings.table))
+        .replace("{score}", &ident(&description.ratings.score))
+        .replace("{column}", &ident(column))
+        .replace("{key}", key)
+}
+
+fn table_exists(conn: &DbConnection, table: &str) -> Result<bool, Error> {
+    let tables = match conn.backend() {
+        Backend::Postgres => {
+            "SELECT COUNT(*) AS count FROM information_schema.tables \
+             WHERE table_schema = current_schema() AND table_name = "
+        }
+        Backend::Sqlite => {
+            "SELECT COUNT(*) AS count FROM sqlite_master WHERE type = 'table' AND name = "
+        }
+    };
+
+    let query = Query::new(tables).bind(Bind::Text(table.into()));
+    let rows: Vec<CountRow> = with_conn!(conn, conn => query.load(conn))?;
+
+    Ok(rows.iter().any(|row| row.count > 0))
+}
+
+/// Create the tables of means that don't exist yet, they're filled from the ratings
+/// already stored and kept up to date by triggers afterwards. Tables that exist
+/// already are left as they are, along with whatever keeps them up to date. With
+/// postgres it's done after loading the ratings
+pub fn create_means(conn: &DbConnection, description: &DatasetDescription) -> Result<(), Error> {
+    let (means_triggers, global_mean_triggers) = match conn.backend() {
+        Backend::Postgres => (PG_MEANS_TRIGGERS, PG_GLOBAL_MEAN_TRIGGERS),
+        Backend::Sqlite => (SQLITE_MEANS_TRIGGERS, SQLITE_GLOBAL_MEAN_TRIGGERS),
+    };
+
+    let ratings = &description.ratings;
+    let mut sql = String::new();
+    for (means, key, table) in mean_tables(description) {
+        if table_exists(conn, means)? {
+            continue;
+        }
+
+        sql += &format!(
+            "CREATE TABLE {} (
+    {} {} PRIMARY KEY REFERENCES {}({}),
+    val FLOAT NOT NULL,
+    score_number INTEGER NOT NULL
+);
+",
+            ident(means),
+            key,
+            key_type(table),
+            ident(&table.table),
+            ident(&table.id),
+        );
+
+        let column = match key {
+            USER_MEANS_KEY => &ratings.user_id,
+            _ => &ratings.item_id,
+        };
+
+        sql += &fill(means_triggers, means, key, column, description);
+        if conn.backend() == Backend::Postgres {
+            sql += &fill(PG_CREATE_TRIGGERS, means, key, column, description);
+        }
+    }
+
+    if let Some(global_mean) = &description.global_mean {
+        if !table_exists(conn, global_mean)? {
+            sql += &format!(
+                "CREATE TABLE {} (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    val FLOAT NOT NULL,
+    score_number BIGINT NOT NULL
+);
+",
+                ident(global_mean)
+            );
+
+            sql += &fill(global_mean_triggers, global_mean, "id", "", description);
+            if conn.backend() == Backend::Postgres {
+                sql += &fill(PG_CREATE_TRIGGERS, global_mean, "id", "", description);
+            }
+        }
+    }
+
+    with_conn!(conn, conn => conn.batch_execute(&sql))?;
+    Ok(())
+}
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE rating_outbox DROP COLUMN rated_at;
+ALTER TABLE ratings DROP COLUMN rated_at;
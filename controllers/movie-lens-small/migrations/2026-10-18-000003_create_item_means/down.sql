@@ -0,0 +1,2 @@
+DROP TABLE global_mean;
+DROP TABLE item_means;
@@ -0,0 +1,4 @@
+-- Seconds since the unix epoch, null for ratings that were loaded without a time
+
+ALTER TABLE ratings ADD COLUMN rated_at BIGINT;
+CREATE INDEX ratings_rated_at_idx on ratings(rated_at);
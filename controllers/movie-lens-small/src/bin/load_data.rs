@@ -5,8 +5,7 @@
 
 use anyhow::Error;
 use config::Config;
-use controller::{Controller, SearchBy};
-use diesel::pg::PgConnection;
+use controller::{with_conn, Controller, DbConnection, SearchBy};
 use diesel::{insert_into, prelude::*};
 use indicatif::ProgressIterator;
 use movie_lens_small::establish_connection;
@@ -15,16 +14,16 @@ use movie_lens_small::schema::{movies, ratings, users};
 use movie_lens_small::MovieLensSmallController;
 use std::collections::HashMap;
 
-fn insert_users(conn: &PgConnection) -> Result<(), Error> {
+fn insert_users(conn: &DbConnection) -> Result<(), Error> {
     println!("Collecting records for users...");
 
     let users: Vec<_> = (1..=610).map(|id| NewUser { id }).collect();
-    insert_into(users::table).values(&users).execute(conn)?;
+    with_conn!(conn, conn => insert_into(users::table).values(&users).execute(conn))?;
 
     Ok(())
 }
 
-fn insert_movies(conn: &PgConnection) -> Result<(), Error> {
+fn insert_movies(conn: &DbConnection) -> Result<(), Error> {
     let mut csv = csv::ReaderBuilder::new()
         .has_headers(true)
         .delimiter(b',')
@@ -48,12 +47,12 @@ fn insert_movies(conn: &PgConnection) -> Result<(), Error> {
     }
 
     println!("Pushing into the database");
-    insert_into(movies::table).values(&movies).execute(conn)?;
+    with_conn!(conn, conn => insert_into(movies::table).values(&movies).execute(conn))?;
 
     Ok(())
 }
 
-fn insert_ratings(conn: &PgConnection, config: &Config) -> Result<(), Error> {
+fn insert_ratings(conn: &DbConnection, config: &Config) -> Result<(), Error> {
     let mut csv = csv::ReaderBuilder::new()
         .has_headers(true)
         .delimiter(b',')
@@ -69,6 +68,7 @@ fn insert_ratings(conn: &PgConnection, config: &Config) -> Result<(), Error> {
             let user_id: i32 = record[0].parse()?;
             let movie_id: i32 = record[1].parse()?;
             let score: f64 = record[2].parse()?;
+            let rated_at: i64 = record[3].parse()?;
 
             match controller.items_by(&SearchBy::id(&movie_id.to_string())) {
                 Ok(movies) if movies.is_empty() => continue,
@@ -80,13 +80,14 @@ fn insert_ratings(conn: &PgConnection, config: &Config) -> Result<(), Error> {
                 score,
                 user_id,
                 movie_id,
+                rated_at: Some(rated_at),
             });
         }
     }
 
     println!("Pushing ratings by chunks");
     for chunk in ratings.chunks(10_000).progress() {
-        insert_into(ratings::table).values(chunk).execute(conn)?;
+        with_conn!(conn, conn => insert_into(ratings::table).values(chunk).execute(conn))?;
     }
 
     Ok(())
@@ -97,11 +98,11 @@ fn main() -> Result<(), Error> {
     let mut config = Config::default();
 
     let db = config.databases.get_mut("movie-lens-small").unwrap();
-    db.psql_url = vars["DATABASE_URL"].clone();
+    db.set_database_url(&vars["DATABASE_URL"]);
     db.mongo_url = vars["MONGO_URL"].clone();
     db.mongo_db = vars["MONGO_DB"].clone();
 
-    let conn = establish_connection(&db.psql_url)?;
+    let conn = establish_connection(db.backend, db.database_url())?;
 
     insert_users(&conn)?;
     insert_movies(&conn)?;
@@ -4,8 +4,8 @@
 // https://opensource.org/licenses/MIT
 
 use anyhow::Error;
-use config::Config;
-use controller::Controller;
+use config::{Backend, Config};
+use controller::{with_conn, Controller};
 use diesel::{insert_into, prelude::*};
 use movie_lens_small::establish_connection;
 use movie_lens_small::models::users::NewMean;
@@ -35,7 +35,7 @@ fn main() -> Result<(), Error> {
     db.mongo_url = vars["MONGO_URL"].clone();
     db.mongo_db = vars["MONGO_DB"].clone();
 
-    let conn = establish_connection(&db.psql_url)?;
+    let conn = establish_connection(Backend::Postgres, &db.psql_url)?;
     let controller = MovieLensSmallController::from_config(&config, "movie-lens-small")?;
 
     let mut means = Vec::new();
@@ -59,7 +59,7 @@ fn main() -> Result<(), Error> {
         }
     }
 
-    insert_into(means::table).values(&means).execute(&conn)?;
+    with_conn!(&conn, conn => insert_into(means::table).values(&means).execute(conn))?;
 
     Ok(())
 }
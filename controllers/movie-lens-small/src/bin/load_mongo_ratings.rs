@@ -30,34 +30,45 @@ fn main() -> Result<(), Error> {
 
     let mut current_item = None;
     let mut current_ratings = HashMap::new();
+    let mut current_times = HashMap::new();
 
     for record in csv.records().progress() {
         if let Ok(record) = record {
             let user_id: i32 = record[0].parse()?;
             let movie_id: i32 = record[1].parse()?;
             let score: f64 = record[2].parse()?;
+            let rated_at: i64 = record[3].parse()?;
 
             if let Some(current_item) = &mut current_item {
                 if *current_item != movie_id {
                     let data = to_bson(&current_ratings)?;
-                    collection
-                        .insert_one(doc! { "item_id": *current_item, "scores": data }, None)?;
+                    let times = to_bson(&current_times)?;
+                    collection.insert_one(
+                        doc! { "item_id": *current_item, "scores": data, "times": times },
+                        None,
+                    )?;
 
                     *current_item = movie_id;
                     current_ratings.clear();
+                    current_times.clear();
                 }
             } else {
                 current_item = Some(movie_id);
             }
 
             current_ratings.insert(user_id.to_string(), Bson::Double(score));
+            current_times.insert(user_id.to_string(), Bson::Int64(rated_at));
         }
     }
 
     if let Some(current_item) = current_item {
         if !current_ratings.is_empty() {
             let data = to_bson(&current_ratings)?;
-            collection.insert_one(doc! { "item_id": current_item, "scores": data}, None)?;
+            let times = to_bson(&current_times)?;
+            collection.insert_one(
+                doc! { "item_id": current_item, "scores": data, "times": times },
+                None,
+            )?;
         }
     }
 
@@ -72,33 +83,44 @@ fn main() -> Result<(), Error> {
 
     let mut current_user = None;
     let mut current_ratings = HashMap::new();
+    let mut current_times = HashMap::new();
     for record in csv.records().progress() {
         if let Ok(record) = record {
             let user_id: i32 = record[0].parse()?;
             let movie_id: i32 = record[1].parse()?;
             let score: f64 = record[2].parse()?;
+            let rated_at: i64 = record[3].parse()?;
 
             if let Some(current_user) = &mut current_user {
                 if *current_user != user_id {
                     let data = to_bson(&current_ratings)?;
-                    collection
-                        .insert_one(doc! { "user_id": *current_user, "scores": data }, None)?;
+                    let times = to_bson(&current_times)?;
+                    collection.insert_one(
+                        doc! { "user_id": *current_user, "scores": data, "times": times },
+                        None,
+                    )?;
 
                     *current_user = user_id;
                     current_ratings.clear();
+                    current_times.clear();
                 }
             } else {
                 current_user = Some(user_id);
             }
 
             current_ratings.insert(movie_id.to_string(), Bson::Double(score));
+            current_times.insert(movie_id.to_string(), Bson::Int64(rated_at));
         }
     }
 
     if let Some(current_user) = current_user {
         if !current_ratings.is_empty() {
             let data = to_bson(&current_ratings)?;
-            collection.insert_one(doc! { "user_id": current_user, "scores": data}, None)?;
+            let times = to_bson(&current_times)?;
+            collection.insert_one(
+                doc! { "user_id": current_user, "scores": data, "times": times },
+                None,
+            )?;
         }
     }
 
@@ -5,10 +5,8 @@
 
 use super::movies::Movie;
 use super::users::User;
-use crate::schema::ratings;
-use common_macros::hash_map;
-use controller::Entity;
-use std::collections::HashMap;
+use crate::schema::{rating_outbox, ratings};
+use controller::{data, Data, Entity, RatingEvent, Value};
 
 // To query data from the database
 #[derive(Debug, Clone, Identifiable, Queryable, Associations)]
@@ -19,6 +17,7 @@ pub struct Rating {
     pub user_id: i32,
     pub movie_id: i32,
     pub score: f64,
+    pub rated_at: Option<i64>,
 }
 
 impl Entity for Rating {
@@ -28,11 +27,12 @@ impl Entity for Rating {
         self.id
     }
 
-    fn get_data(&self) -> HashMap<String, String> {
-        hash_map! {
-            "user_id".into() => self.user_id.to_string(),
-            "movie_id".into() => self.movie_id.to_string(),
-            "score".into() => self.score.to_string(),
+    fn get_data(&self) -> Data {
+        data! {
+            "user_id" => self.user_id,
+            "movie_id" => self.movie_id,
+            "score" => self.score,
+            "rated_at" => self.rated_at.map_or(Value::Null, Value::Timestamp),
         }
     }
 }
@@ -44,4 +44,37 @@ pub struct NewRating {
     pub user_id: i32,
     pub movie_id: i32,
     pub score: f64,
+    pub rated_at: Option<i64>,
+}
+
+// A rating mutation waiting in the outbox to be applied to mongo
+#[derive(Debug, Clone, Queryable)]
+pub struct OutboxEvent {
+    pub id: i64,
+    pub user_id: i32,
+    pub movie_id: i32,
+    pub score: Option<f64>,
+    pub rated_at: Option<i64>,
+}
+
+impl From<OutboxEvent> for RatingEvent<i32, i32> {
+    fn from(event: OutboxEvent) -> Self {
+        Self {
+            id: event.id,
+            user_id: event.user_id,
+            item_id: event.movie_id,
+            score: event.score,
+            time: event.rated_at,
+        }
+    }
+}
+
+// To record a rating mutation in the outbox, no score means it was removed
+#[derive(Debug, Clone, Insertable)]
+#[table_name = "rating_outbox"]
+pub struct NewOutboxEvent {
+    pub user_id: i32,
+    pub movie_id: i32,
+    pub score: Option<f64>,
+    pub rated_at: Option<i64>,
 }
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX movies_title_trgm_idx on movies USING gin (lower(title) gin_trgm_ops);
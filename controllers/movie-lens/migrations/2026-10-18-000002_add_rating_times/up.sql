@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- Seconds since the unix epoch, null for ratings that were loaded without a time
+ALTER TABLE ratings ADD COLUMN rated_at BIGINT;
+ALTER TABLE rating_outbox ADD COLUMN rated_at BIGINT;
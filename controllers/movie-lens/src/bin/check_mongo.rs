@@ -0,0 +1,69 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use anyhow::Error;
+use config::Config;
+use controller::MirroredRatings;
+use movie_lens::MovieLensController;
+use std::collections::HashMap;
+
+const CHUNK_SIZE: usize = 1000;
+
+fn main() -> Result<(), Error> {
+    let vars: HashMap<String, String> = dotenv::vars().collect();
+    let mut config = Config::default();
+
+    let db = config.databases.get_mut("movie-lens").unwrap();
+    db.psql_url = vars["DATABASE_URL"].clone();
+    db.mongo_url = vars["MONGO_URL"].clone();
+    db.mongo_db = vars["MONGO_DB"].clone();
+
+    let repair = std::env::args().any(|arg| arg == "--repair");
+    let rebuild = std::env::args().any(|arg| arg == "--rebuild");
+    let controller = MovieLensController::from_config(&config, "movie-lens")?;
+
+    if rebuild {
+        controller.rebuild_mongo(CHUNK_SIZE)?;
+        println!("Rebuilt users_ratings and users_who_rated from postgres");
+        return Ok(());
+    }
+
+    let report = controller.check_mongo(CHUNK_SIZE)?;
+    for discrepancy in &report.users {
+        println!(
+            "users_ratings: user({}) item({}) is {}",
+            discrepancy.key, discrepancy.other, discrepancy.drift
+        );
+    }
+
+    for discrepancy in &report.items {
+        println!(
+            "users_who_rated: item({}) user({}) is {}",
+            discrepancy.key, discrepancy.other, discrepancy.drift
+        );
+    }
+
+    if report.is_consistent() {
+        println!("Mongo is consistent with postgres");
+        return Ok(());
+    }
+
+    let users = report.drifted_users();
+    let items = report.drifted_items();
+    println!(
+        "Found drift for {} users and {} items",
+        users.len(),
+        items.len()
+    );
+
+    if repair {
+        controller.repair_mongo(&report)?;
+        println!("Repaired the documents of drifted users and items");
+    } else {
+        println!("Run with --repair to rewrite them from postgres");
+    }
+
+    Ok(())
+}
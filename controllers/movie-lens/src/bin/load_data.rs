@@ -4,7 +4,8 @@
 // https://opensource.org/licenses/MIT
 
 use anyhow::Error;
-use diesel::pg::PgConnection;
+use config::Backend;
+use controller::{with_conn, DbConnection};
 use diesel::{insert_into, prelude::*};
 use indicatif::ProgressIterator;
 use movie_lens::establish_connection;
@@ -13,7 +14,7 @@ use movie_lens::schema::{movies, ratings, users};
 use std::fs::File;
 use std::{collections::HashMap, io::BufReader};
 
-fn insert_users(conn: &PgConnection) -> Result<(), Error> {
+fn insert_users(conn: &DbConnection) -> Result<(), Error> {
     let mut users = Vec::new();
     println!("Collecting records for users...");
 
@@ -23,13 +24,13 @@ fn insert_users(conn: &PgConnection) -> Result<(), Error> {
 
     println!("Pushing users by chunks");
     for chunk in users.chunks(10_000).progress() {
-        insert_into(users::table).values(chunk).execute(conn)?;
+        with_conn!(conn, conn => insert_into(users::table).values(chunk).execute(conn))?;
     }
 
     Ok(())
 }
 
-fn insert_movies(conn: &PgConnection) -> Result<(), Error> {
+fn insert_movies(conn: &DbConnection) -> Result<(), Error> {
     let mut csv = csv::ReaderBuilder::new()
         .has_headers(true)
         .delimiter(b',')
@@ -54,13 +55,13 @@ fn insert_movies(conn: &PgConnection) -> Result<(), Error> {
 
     println!("Pushing movies by chunks");
     for chunk in movies.chunks(10_000).progress() {
-        insert_into(movies::table).values(chunk).execute(conn)?;
+        with_conn!(conn, conn => insert_into(movies::table).values(chunk).execute(conn))?;
     }
 
     Ok(())
 }
 
-fn insert_ratings(conn: &PgConnection) -> Result<(), Error> {
+fn insert_ratings(conn: &DbConnection) -> Result<(), Error> {
     let file = File::open("data/ratings.csv")?;
     let reader = BufReader::new(file);
 
@@ -77,17 +78,19 @@ fn insert_ratings(conn: &PgConnection) -> Result<(), Error> {
             let user_id: i32 = record[0].parse()?;
             let movie_id: i32 = record[1].parse()?;
             let score: f64 = record[2].parse()?;
+            let rated_at: i64 = record[3].parse()?;
 
             ratings.push(NewRating {
                 score,
                 user_id,
                 movie_id,
+                rated_at: Some(rated_at),
             });
         }
 
         // Push the ratings vec when it's 10K length
         if !ratings.is_empty() && ratings.len() % 10_000 == 0 {
-            insert_into(ratings::table).values(&ratings).execute(conn)?;
+            with_conn!(conn, conn => insert_into(ratings::table).values(&ratings).execute(conn))?;
 
             // Clear ratings for the following iterations
             ratings.clear();
@@ -95,7 +98,7 @@ fn insert_ratings(conn: &PgConnection) -> Result<(), Error> {
     }
 
     if !ratings.is_empty() {
-        insert_into(ratings::table).values(&ratings).execute(conn)?;
+        with_conn!(conn, conn => insert_into(ratings::table).values(&ratings).execute(conn))?;
     }
 
     Ok(())
@@ -105,7 +108,7 @@ fn main() -> Result<(), Error> {
     let vars: HashMap<String, String> = dotenv::vars().collect();
 
     let url = &vars["DATABASE_URL"];
-    let conn = establish_connection(url)?;
+    let conn = establish_connection(Backend::from_url(url), url)?;
 
     insert_users(&conn)?;
     insert_movies(&conn)?;
@@ -4,8 +4,8 @@
 // https://opensource.org/licenses/MIT
 
 use anyhow::Error;
-use config::Config;
-use controller::Controller;
+use config::{Backend, Config};
+use controller::{with_conn, Controller};
 use diesel::{insert_into, prelude::*};
 use movie_lens::establish_connection;
 use movie_lens::models::users::NewMean;
@@ -35,7 +35,7 @@ fn main() -> Result<(), Error> {
     db.mongo_url = vars["MONGO_URL"].clone();
     db.mongo_db = vars["MONGO_DB"].clone();
 
-    let conn = establish_connection(&db.psql_url)?;
+    let conn = establish_connection(Backend::Postgres, &db.psql_url)?;
     let controller = MovieLensController::from_config(&config, "movie-lens")?;
 
     let users_iterator = controller.users_by_chunks(10000);
@@ -43,7 +43,7 @@ fn main() -> Result<(), Error> {
         println!("Inserting new chunk");
 
         let mut means = Vec::new();
-        let maped_ratings = controller.users_ratings(&user_chunk)?;
+        let maped_ratings = controller.users_ratings(&user_chunk?)?;
         for (user_id, ratings) in maped_ratings {
             let mean = compute_mean(&ratings);
 
@@ -60,7 +60,7 @@ fn main() -> Result<(), Error> {
             }
         }
 
-        insert_into(means::table).values(&means).execute(&conn)?;
+        with_conn!(&conn, conn => insert_into(means::table).values(&means).execute(conn))?;
     }
 
     Ok(())
@@ -4,9 +4,8 @@
 // https://opensource.org/licenses/MIT
 
 use crate::schema::movies;
-use common_macros::hash_map;
-use controller::Entity;
-use std::collections::HashMap;
+use crate::NO_GENRES;
+use controller::{data, Data, Entity, Feature};
 
 #[derive(Debug, Clone, Identifiable, Queryable, Default)]
 pub struct Movie {
@@ -15,6 +14,31 @@ pub struct Movie {
     pub genres: String,
 }
 
+impl Movie {
+    /// Genres and release decade (taken from the title) of this movie
+    pub fn features(&self) -> Vec<Feature> {
+        let mut features: Vec<_> = self
+            .genres
+            .split('|')
+            .filter(|&genre| genre != NO_GENRES)
+            .map(|genre| Feature::categorical("genre", genre))
+            .collect();
+
+        let year = self
+            .title
+            .trim_end()
+            .strip_suffix(')')
+            .and_then(|title| title.rsplit('(').next())
+            .and_then(|year| year.parse().ok());
+
+        if let Some(year) = year {
+            features.push(Feature::decade(year));
+        }
+
+        features
+    }
+}
+
 impl Entity for Movie {
     type Id = i32;
 
@@ -22,10 +46,16 @@ impl Entity for Movie {
         self.id
     }
 
-    fn get_data(&self) -> HashMap<String, String> {
-        hash_map! {
-            "title".into() => self.title.clone(),
-            "genres".into() => self.genres.clone(),
+    fn get_data(&self) -> Data {
+        let genres: Vec<_> = self
+            .genres
+            .split('|')
+            .filter(|&genre| genre != NO_GENRES)
+            .collect();
+
+        data! {
+            "title" => self.title.clone(),
+            "genres" => genres,
         }
     }
 }
@@ -38,7 +68,7 @@ pub struct NewMovie<'a> {
     pub genres: &'a str,
 }
 
-#[derive(Debug, Clone, Insertable)]
+#[derive(Debug, Clone, Insertable, AsChangeset)]
 #[table_name = "movies"]
 pub struct NewUnseenMovie<'a> {
     pub title: &'a str,
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+CREATE INDEX ratings_rated_at_idx on ratings(rated_at);
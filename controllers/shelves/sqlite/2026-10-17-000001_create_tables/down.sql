@@ -0,0 +1,4 @@
+DROP TABLE means;
+DROP TABLE ratings;
+DROP TABLE books;
+DROP TABLE users;
@@ -0,0 +1,22 @@
+-- Same tables as the postgres migrations, in sqlite dialect
+
+CREATE TABLE users (
+    id INTEGER PRIMARY KEY
+);
+
+CREATE TABLE books (
+    id INTEGER PRIMARY KEY
+);
+
+CREATE TABLE ratings (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    book_id INTEGER NOT NULL REFERENCES books(id),
+    score FLOAT NOT NULL
+);
+
+CREATE TABLE means (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id),
+    val FLOAT NOT NULL,
+    score_number INTEGER NOT NULL
+);
@@ -0,0 +1,32 @@
+-- Means are kept up to date from the start, there's no need to load them
+-- after the ratings like with postgres
+
+CREATE TRIGGER update_means_on_new AFTER INSERT ON ratings
+FOR EACH ROW
+BEGIN
+    INSERT OR IGNORE INTO means(user_id, val, score_number) VALUES (new.user_id, 0, 0);
+
+    UPDATE means
+    SET val = (val * score_number + new.score) / (score_number + 1),
+        score_number = score_number + 1
+    WHERE user_id = new.user_id;
+END;
+
+CREATE TRIGGER update_means_on_del AFTER DELETE ON ratings
+FOR EACH ROW
+BEGIN
+    DELETE FROM means WHERE user_id = old.user_id AND score_number <= 1;
+
+    UPDATE means
+    SET val = (val * score_number - old.score) / (score_number - 1),
+        score_number = score_number - 1
+    WHERE user_id = old.user_id;
+END;
+
+CREATE TRIGGER update_means_on_upd AFTER UPDATE OF score ON ratings
+FOR EACH ROW
+BEGIN
+    UPDATE means
+    SET val = val + (new.score - old.score) / score_number
+    WHERE user_id = new.user_id;
+END;
@@ -0,0 +1,2 @@
+DROP INDEX ratings_rated_at_idx;
+ALTER TABLE ratings DROP COLUMN rated_at;
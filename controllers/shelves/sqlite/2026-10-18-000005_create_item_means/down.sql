@@ -0,0 +1,5 @@
+DROP TRIGGER update_item_means_on_upd;
+DROP TRIGGER update_item_means_on_del;
+DROP TRIGGER update_item_means_on_new;
+DROP TABLE global_mean;
+DROP TABLE item_means;
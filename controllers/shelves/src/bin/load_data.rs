@@ -4,7 +4,8 @@
 // https://opensource.org/licenses/MIT
 
 use anyhow::Error;
-use diesel::pg::PgConnection;
+use config::Backend;
+use controller::{with_conn, DbConnection};
 use diesel::{insert_into, prelude::*};
 use indicatif::ProgressIterator;
 use shelves::establish_connection;
@@ -12,7 +13,7 @@ use shelves::models::{books::NewBook, ratings::NewRating, users::NewUser};
 use shelves::schema::{books, ratings, users};
 use std::collections::HashMap;
 
-fn insert_users(conn: &PgConnection) -> Result<(), Error> {
+fn insert_users(conn: &DbConnection) -> Result<(), Error> {
     let mut csv = csv::ReaderBuilder::new()
         .has_headers(true)
         .delimiter(b',')
@@ -35,13 +36,13 @@ fn insert_users(conn: &PgConnection) -> Result<(), Error> {
 
     println!("Pushing users by chunks");
     for chunk in users.chunks(10_000).progress() {
-        insert_into(users::table).values(chunk).execute(conn)?;
+        with_conn!(conn, conn => insert_into(users::table).values(chunk).execute(conn))?;
     }
 
     Ok(())
 }
 
-fn insert_books(conn: &PgConnection) -> Result<(), Error> {
+fn insert_books(conn: &DbConnection) -> Result<(), Error> {
     let mut csv = csv::ReaderBuilder::new()
         .has_headers(true)
         .delimiter(b',')
@@ -64,13 +65,13 @@ fn insert_books(conn: &PgConnection) -> Result<(), Error> {
 
     println!("Pushing books by chunks");
     for chunk in books.chunks(10_000).progress() {
-        insert_into(books::table).values(chunk).execute(conn)?;
+        with_conn!(conn, conn => insert_into(books::table).values(chunk).execute(conn))?;
     }
 
     Ok(())
 }
 
-fn insert_ratings(conn: &PgConnection) -> Result<(), Error> {
+fn insert_ratings(conn: &DbConnection) -> Result<(), Error> {
     let mut csv = csv::ReaderBuilder::new()
         .has_headers(true)
         .delimiter(b',')
@@ -79,6 +80,8 @@ fn insert_ratings(conn: &PgConnection) -> Result<(), Error> {
     let mut ratings = Vec::new();
     println!("Collecting records for ratings...");
 
+    // Columns are user_id, book_id, is_read, rating and is_reviewed, only the rating
+    // is kept (a 0 is an interaction without a rating, see `rating_kind`)
     for record in csv.records().progress() {
         if let Ok(record) = record {
             let user_id: i32 = record[0].parse()?;
@@ -89,17 +92,18 @@ fn insert_ratings(conn: &PgConnection) -> Result<(), Error> {
                 user_id,
                 book_id,
                 score,
+                rated_at: None,
             });
         }
 
         if !ratings.is_empty() && ratings.len() % 10_000 == 0 {
-            insert_into(ratings::table).values(&ratings).execute(conn)?;
+            with_conn!(conn, conn => insert_into(ratings::table).values(&ratings).execute(conn))?;
             ratings.clear();
         }
     }
 
     if !ratings.is_empty() {
-        insert_into(ratings::table).values(&ratings).execute(conn)?;
+        with_conn!(conn, conn => insert_into(ratings::table).values(&ratings).execute(conn))?;
     }
 
     Ok(())
@@ -109,7 +113,7 @@ fn main() -> Result<(), Error> {
     let vars: HashMap<String, String> = dotenv::vars().collect();
 
     let url = &vars["DATABASE_URL"];
-    let conn = establish_connection(url)?;
+    let conn = establish_connection(Backend::from_url(url), url)?;
 
     insert_users(&conn)?;
     insert_books(&conn)?;
@@ -4,9 +4,8 @@
 // https://opensource.org/licenses/MIT
 
 use anyhow::Error;
-use config::Config;
-use controller::Controller;
-use diesel::pg::PgConnection;
+use config::{Backend, Config};
+use controller::{with_conn, Controller, DbConnection};
 use diesel::{insert_into, prelude::*};
 use shelves::establish_connection;
 use shelves::models::users::NewMean;
@@ -15,8 +14,8 @@ use shelves::ShelvesController;
 use std::collections::HashMap;
 use std::time::Instant;
 
-fn insert_means(conn: &PgConnection, new_means: &[NewMean]) -> Result<(), Error> {
-    insert_into(means::table).values(new_means).execute(conn)?;
+fn insert_means(conn: &DbConnection, new_means: &[NewMean]) -> Result<(), Error> {
+    with_conn!(conn, conn => insert_into(means::table).values(new_means).execute(conn))?;
 
     Ok(())
 }
@@ -42,7 +41,7 @@ fn main() -> Result<(), Error> {
     db.mongo_url = vars["MONGO_URL"].clone();
     db.mongo_db = vars["MONGO_DB"].clone();
 
-    let conn = establish_connection(&db.psql_url)?;
+    let conn = establish_connection(Backend::Postgres, &db.psql_url)?;
     let controller = ShelvesController::from_config(&config, "shelves")?;
 
     let users_iterator = controller.users_by_chunks(10000);
@@ -50,7 +49,7 @@ fn main() -> Result<(), Error> {
         println!("Inserting new chunk");
         let now = Instant::now();
         let mut mean_chunk = Vec::new();
-        let maped_ratings = controller.users_ratings(&user_chunk)?;
+        let maped_ratings = controller.users_ratings(&user_chunk?)?;
         for (user_id, ratings) in maped_ratings {
             let mean = compute_mean(&ratings);
             if let Some(mean) = mean {
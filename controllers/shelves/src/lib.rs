@@ -5,6 +5,8 @@
 
 #[macro_use]
 extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
 
 pub mod models;
 pub mod schema;
@@ -14,30 +16,58 @@ use crate::models::{
     ratings::Rating,
     users::{Mean, User},
 };
-use crate::schema::{books, ratings, users};
+use crate::schema::{books, global_mean, item_means, means, rating_outbox, ratings, users};
 use anyhow::Error;
-use config::Config;
-use controller::{eid, error::ErrorKind, maped_ratings, means, ratings, Controller, SearchBy};
-use diesel::pg::PgConnection;
-use diesel::{delete, insert_into, prelude::*, update};
-use models::ratings::NewRating;
-use mongodb::bson::doc;
+use config::{Backend, Config};
+use controller::outbox::{apply_rating_events, RatingCollection, RatingEvent};
+use controller::{
+    counts, eid, error::ErrorKind, insert_returning, maped_ratings, means, now, ratings, with_conn,
+    Controller, DbConnection, Histogram, MapedRatings, MirroredRatings, RatingKind, RatingScale,
+    SearchBy, TimedScore, Timestamp,
+};
+use diesel::{
+    delete,
+    dsl::{exists, sql},
+    insert_into,
+    prelude::*,
+    select,
+    sql_types::BigInt,
+    update,
+};
+use models::ratings::{NewOutboxEvent, NewRating, OutboxEvent};
+use mongodb::bson::{doc, Bson};
 use mongodb::{
-    options::{FindOptions, UpdateOptions},
+    options::FindOptions,
     sync::{Client, Database},
 };
-
-use num_traits::Zero;
 use std::collections::HashMap;
 
-pub fn establish_connection(url: &str) -> Result<PgConnection, Error> {
-    Ok(PgConnection::establish(&url)?)
+embed_migrations!("sqlite");
+
+/// Ratings documents kept in mongo, they're written through the outbox
+const MONGO_COLLECTIONS: &[RatingCollection] = &[RatingCollection::UsersWhoRated];
+
+const OUTBOX_CHUNK_SIZE: i64 = 1000;
+
+/// Goodreads interactions without a rating (the book was only shelved, read or
+/// reviewed) are loaded with a score of 0
+const IMPLICIT_SCORE: f64 = 0.;
+
+pub fn establish_connection(backend: Backend, url: &str) -> Result<DbConnection, Error> {
+    let conn = DbConnection::establish(backend, url)?;
+
+    // A sqlite database is created empty, so its tables are created on first use
+    if let DbConnection::Sqlite(conn) = &conn {
+        embedded_migrations::run(conn)?;
+    }
+
+    Ok(conn)
 }
 
 pub struct ShelvesController {
     users_who_rated_mongo: bool,
-    pg_conn: PgConnection,
-    mongo_db: Database,
+    conn: DbConnection,
+    mongo_db: Option<Database>,
 }
 
 impl ShelvesController {
@@ -53,20 +83,234 @@ impl ShelvesController {
             .get(name)
             .ok_or_else(|| ErrorKind::DbConfigError(name.into()))?;
 
-        let users_who_rated_mongo = db.users_who_rated_mongo;
-        let psql_url = &db.psql_url;
-        let mongo_url = &db.mongo_url;
-        let mongo_db = &db.mongo_db;
+        let conn = establish_connection(db.backend, db.database_url())?;
+        let mongo_db = match db.backend {
+            Backend::Postgres => Some(Client::with_uri_str(&db.mongo_url)?.database(&db.mongo_db)),
+            Backend::Sqlite => None,
+        };
 
-        let pg_conn = establish_connection(psql_url)?;
-        let client = Client::with_uri_str(mongo_url)?;
-        let mongo_db = client.database(mongo_db);
+        let users_who_rated_mongo = db.users_who_rated_mongo && mongo_db.is_some();
 
-        Ok(Self {
+        let controller = Self {
             users_who_rated_mongo,
-            pg_conn,
+            conn,
             mongo_db,
-        })
+        };
+
+        // Events left behind by a crash or a mongo outage
+        controller.flush_outbox();
+        Ok(controller)
+    }
+
+    fn mongo_db(&self) -> Result<&Database, Error> {
+        Ok(self.mongo_db.as_ref().ok_or(ErrorKind::MongoUnavailable)?)
+    }
+
+    /// Apply the pending events of the outbox to mongo by chunks, events are
+    /// removed once applied so whatever fails is retried on the next replay.
+    /// Returns how many events were applied
+    pub fn replay_outbox(&self) -> Result<usize, Error> {
+        let (conn, mongo_db) = match (&self.conn, &self.mongo_db) {
+            (DbConnection::Postgres(conn), Some(mongo_db)) => (conn, mongo_db),
+            _ => return Ok(0),
+        };
+
+        let mut applied = 0;
+        loop {
+            // Rows stay locked until they're deleted, so concurrent replays
+            // don't apply the same events out of order
+            let replayed = conn.transaction::<_, Error, _>(|| {
+                let events: Vec<RatingEvent<_, _>> = rating_outbox::table
+                    .order(rating_outbox::id)
+                    .limit(OUTBOX_CHUNK_SIZE)
+                    .for_update()
+                    .load::<OutboxEvent>(conn)?
+                    .into_iter()
+                    .map(Into::into)
+                    .collect();
+
+                apply_rating_events(mongo_db, &events, MONGO_COLLECTIONS)?;
+
+                let ids: Vec<_> = events.iter().map(|event| event.id).collect();
+                delete(rating_outbox::table.filter(rating_outbox::id.eq_any(&ids)))
+                    .execute(conn)?;
+
+                Ok(events.len())
+            })?;
+
+            applied += replayed;
+            if replayed < OUTBOX_CHUNK_SIZE as usize {
+                return Ok(applied);
+            }
+        }
+    }
+
+    /// Ratings are written to mongo after the transaction is committed, if mongo
+    /// isn't reachable the events stay in the outbox until the next replay
+    fn flush_outbox(&self) {
+        if let Err(e) = self.replay_outbox() {
+            log::error!("Couldn't replay the rating outbox into mongo: {}", e);
+            match self.pending_events() {
+                Ok(pending) => {
+                    log::warn!("{} rating events are still pending in the outbox", pending)
+                }
+                Err(e) => log::error!("Couldn't count the pending events of the outbox: {}", e),
+            }
+        }
+    }
+
+    /// Number of events in the outbox that haven't been applied to mongo yet
+    pub fn pending_events(&self) -> Result<usize, Error> {
+        with_conn!(&self.conn, conn => rating_outbox::table.count().get_result::<i64>(conn))
+            .map(|count| count as usize)
+            .map_err(Into::into)
+    }
+
+    fn insert_rating_sql(&self, new_rating: &NewRating) -> Result<Rating, Error> {
+        let outbox = self.mongo_db.is_some();
+        let rating = with_conn!(&self.conn, conn => conn.transaction::<_, Error, _>(|| {
+            let rated = ratings::table
+                .filter(ratings::user_id.eq(new_rating.user_id))
+                .filter(ratings::book_id.eq(new_rating.book_id));
+
+            if select(exists(rated)).get_result(conn)? {
+                return Err(ErrorKind::InsertRatingFailed(
+                    new_rating.user_id.to_string(),
+                    new_rating.book_id.to_string(),
+                )
+                .into());
+            }
+
+            insert_into(ratings::table).values(new_rating).execute(conn)?;
+            if outbox {
+                let event = NewOutboxEvent {
+                    user_id: new_rating.user_id,
+                    book_id: new_rating.book_id,
+                    score: Some(new_rating.score),
+                    rated_at: new_rating.rated_at,
+                };
+
+                insert_into(rating_outbox::table).values(&event).execute(conn)?;
+            }
+
+            Ok(rated.first(conn)?)
+        }))?;
+
+        Ok(rating)
+    }
+
+    fn remove_rating_sql(&self, user_id: &i32, item_id: &i32) -> Result<Rating, Error> {
+        let outbox = self.mongo_db.is_some();
+        let rating = with_conn!(&self.conn, conn => conn.transaction::<_, Error, _>(|| {
+            let rated = ratings::table
+                .filter(ratings::user_id.eq(user_id))
+                .filter(ratings::book_id.eq(item_id));
+
+            let rating: Rating = rated.first(conn).optional()?.ok_or_else(|| {
+                ErrorKind::RemoveRatingFailed(user_id.to_string(), item_id.to_string())
+            })?;
+
+            delete(rated).execute(conn)?;
+            if outbox {
+                let event = NewOutboxEvent {
+                    user_id: *user_id,
+                    book_id: *item_id,
+                    score: None,
+                    rated_at: None,
+                };
+
+                insert_into(rating_outbox::table).values(&event).execute(conn)?;
+            }
+
+            Ok(rating)
+        }))?;
+
+        Ok(rating)
+    }
+
+    fn update_rating_sql(&self, user_id: &i32, item_id: &i32, score: f64) -> Result<Rating, Error> {
+        let outbox = self.mongo_db.is_some();
+        let rating = with_conn!(&self.conn, conn => conn.transaction::<_, Error, _>(|| {
+            let rated = ratings::table
+                .filter(ratings::user_id.eq(user_id))
+                .filter(ratings::book_id.eq(item_id));
+
+            // The new score must be different from the actual one
+            rated
+                .select(ratings::score)
+                .first::<f64>(conn)
+                .optional()?
+                .filter(|old_score| *old_score != score)
+                .ok_or_else(|| {
+                    ErrorKind::UpdateRatingFailed(user_id.to_string(), item_id.to_string())
+                })?;
+
+            let rated_at = Some(now());
+            update(rated)
+                .set((ratings::score.eq(score), ratings::rated_at.eq(rated_at)))
+                .execute(conn)?;
+
+            if outbox {
+                let event = NewOutboxEvent {
+                    user_id: *user_id,
+                    book_id: *item_id,
+                    score: Some(score),
+                    rated_at,
+                };
+
+                insert_into(rating_outbox::table).values(&event).execute(conn)?;
+            }
+
+            Ok(rated.first(conn)?)
+        }))?;
+
+        Ok(rating)
+    }
+
+    fn remove_user_sql(&self, user_id: &i32) -> Result<User, Error> {
+        let outbox = self.mongo_db.is_some();
+        let user = with_conn!(&self.conn, conn => conn.transaction::<_, diesel::result::Error, _>(|| {
+            let user = users::table.find(user_id).first::<User>(conn)?;
+
+            let rated = ratings::table.filter(ratings::user_id.eq(user_id));
+            if outbox {
+                insert_into(rating_outbox::table)
+                    .values(rated.select((ratings::user_id, ratings::book_id)))
+                    .into_columns((rating_outbox::user_id, rating_outbox::book_id))
+                    .execute(conn)?;
+            }
+
+            delete(rated).execute(conn)?;
+            delete(means::table.filter(means::user_id.eq(user_id))).execute(conn)?;
+            delete(users::table.find(user_id)).execute(conn)?;
+
+            Ok(user)
+        }))?;
+
+        Ok(user)
+    }
+
+    fn remove_item_sql(&self, item_id: &i32) -> Result<Book, Error> {
+        let outbox = self.mongo_db.is_some();
+        let item = with_conn!(&self.conn, conn => conn.transaction::<_, diesel::result::Error, _>(|| {
+            let item = books::table.find(item_id).first::<Book>(conn)?;
+
+            let rated = ratings::table.filter(ratings::book_id.eq(item_id));
+            if outbox {
+                insert_into(rating_outbox::table)
+                    .values(rated.select((ratings::user_id, ratings::book_id)))
+                    .into_columns((rating_outbox::user_id, rating_outbox::book_id))
+                    .execute(conn)?;
+            }
+
+            // The means triggers update the mean of every user that rated it
+            delete(rated).execute(conn)?;
+            delete(books::table.find(item_id)).execute(conn)?;
+
+            Ok(item)
+        }))?;
+
+        Ok(item)
     }
 }
 
@@ -76,7 +320,7 @@ impl Controller for ShelvesController {
     type Rating = Rating;
 
     fn users(&self) -> Result<Vec<Self::User>, Error> {
-        let users = users::table.load::<User>(&self.pg_conn)?;
+        let users = with_conn!(&self.conn, conn => users::table.load::<User>(conn))?;
         Ok(users)
     }
 
@@ -84,7 +328,7 @@ impl Controller for ShelvesController {
         match by {
             SearchBy::Id(id) => {
                 let id: i32 = id.parse()?;
-                let users = users::table.filter(users::id.eq(id)).load(&self.pg_conn)?;
+                let users = with_conn!(&self.conn, conn => users::table.filter(users::id.eq(id)).load(conn))?;
 
                 if users.is_empty() {
                     Err(ErrorKind::NotFoundById(id.to_string()).into())
@@ -93,22 +337,46 @@ impl Controller for ShelvesController {
                 }
             }
 
-            SearchBy::Name(name) => Err(ErrorKind::NotFoundByName(name.clone()).into()),
-            SearchBy::Custom(k, v) => Err(ErrorKind::NotFoundByCustom(k.clone(), v.clone()).into()),
+            SearchBy::Name(name) | SearchBy::NameLike(_, name, _) => {
+                Err(ErrorKind::NotFoundByName(name.clone()).into())
+            }
+            SearchBy::Custom(k, _) => Err(ErrorKind::CustomSearchNotSupported(k.clone()).into()),
         }
     }
 
     fn users_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<Self::User>, Error> {
-        let users = users::table
+        let users = with_conn!(&self.conn, conn => users::table
+            .order(users::id)
             .offset(offset as i64)
             .limit(limit as i64)
-            .load::<User>(&self.pg_conn)?;
+            .load::<User>(conn))?;
+
+        Ok(users)
+    }
+
+    fn users_after(
+        &self,
+        after: Option<&eid!(Self::User)>,
+        limit: usize,
+    ) -> Result<Vec<Self::User>, Error> {
+        let users = with_conn!(&self.conn, conn => {
+            let mut query = users::table
+                .order(users::id)
+                .limit(limit as i64)
+                .into_boxed();
+
+            if let Some(after) = after {
+                query = query.filter(users::id.gt(after));
+            }
+
+            query.load::<User>(conn)
+        })?;
 
         Ok(users)
     }
 
     fn items(&self) -> Result<Vec<Self::Item>, Error> {
-        let items = books::table.load::<Book>(&self.pg_conn)?;
+        let items = with_conn!(&self.conn, conn => books::table.load::<Book>(conn))?;
         Ok(items)
     }
 
@@ -116,7 +384,7 @@ impl Controller for ShelvesController {
         match by {
             SearchBy::Id(id) => {
                 let id: i32 = id.parse()?;
-                let books = books::table.filter(books::id.eq(id)).load(&self.pg_conn)?;
+                let books = with_conn!(&self.conn, conn => books::table.filter(books::id.eq(id)).load(conn))?;
 
                 if books.is_empty() {
                     Err(ErrorKind::NotFoundById(id.to_string()).into())
@@ -125,16 +393,40 @@ impl Controller for ShelvesController {
                 }
             }
 
-            SearchBy::Name(name) => Err(ErrorKind::NotFoundByName(name.clone()).into()),
-            SearchBy::Custom(k, v) => Err(ErrorKind::NotFoundByCustom(k.clone(), v.clone()).into()),
+            SearchBy::Name(name) | SearchBy::NameLike(_, name, _) => {
+                Err(ErrorKind::NotFoundByName(name.clone()).into())
+            }
+            SearchBy::Custom(k, _) => Err(ErrorKind::CustomSearchNotSupported(k.clone()).into()),
         }
     }
 
     fn items_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<Self::Item>, Error> {
-        let items = books::table
+        let items = with_conn!(&self.conn, conn => books::table
+            .order(books::id)
             .offset(offset as i64)
             .limit(limit as i64)
-            .load::<Book>(&self.pg_conn)?;
+            .load::<Book>(conn))?;
+
+        Ok(items)
+    }
+
+    fn items_after(
+        &self,
+        after: Option<&eid!(Self::Item)>,
+        limit: usize,
+    ) -> Result<Vec<Self::Item>, Error> {
+        let items = with_conn!(&self.conn, conn => {
+            let mut query = books::table
+                .order(books::id)
+                .limit(limit as i64)
+                .into_boxed();
+
+            if let Some(after) = after {
+                query = query.filter(books::id.gt(after));
+            }
+
+            query.load::<Book>(conn)
+        })?;
 
         Ok(items)
     }
@@ -165,7 +457,8 @@ impl Controller for ShelvesController {
         items: &[Self::Item],
     ) -> Result<maped_ratings!(Self::Item => Self::User), Error> {
         if !self.users_who_rated_mongo {
-            let ratings = Rating::belonging_to(items).load::<Rating>(&self.pg_conn)?;
+            let ratings =
+                with_conn!(&self.conn, conn => Rating::belonging_to(items).load::<Rating>(conn))?;
 
             let mut items_users = HashMap::new();
             for rating in ratings {
@@ -177,7 +470,7 @@ impl Controller for ShelvesController {
 
             Ok(items_users)
         } else {
-            let collection = self.mongo_db.collection("users_who_rated");
+            let collection = self.mongo_db()?.collection("users_who_rated");
             let ids: Vec<_> = items.iter().map(|m| m.id).collect();
             let options = FindOptions::builder().show_record_id(false).build();
 
@@ -208,18 +501,57 @@ impl Controller for ShelvesController {
     }
 
     fn user_ratings(&self, user: &Self::User) -> Result<ratings!(Self::Item), Error> {
-        let ratings = Rating::belonging_to(user)
-            .load::<Rating>(&self.pg_conn)?
-            .into_iter()
-            .map(|rating| (rating.book_id, rating.score))
-            .collect();
+        let ratings = with_conn!(&self.conn, conn => Rating::belonging_to(user)
+            .load::<Rating>(conn))?
+        .into_iter()
+        .map(|rating| (rating.book_id, rating.score))
+        .collect();
 
         Ok(ratings)
     }
 
+    fn user_timed_ratings(
+        &self,
+        user: &Self::User,
+    ) -> Result<ratings!(Self::Item, TimedScore), Error> {
+        let ratings = with_conn!(&self.conn, conn => Rating::belonging_to(user)
+            .load::<Rating>(conn))?
+        .into_iter()
+        .map(|rating| {
+            (
+                rating.book_id,
+                TimedScore::new(rating.score, rating.rated_at),
+            )
+        })
+        .collect();
+
+        Ok(ratings)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn users_ratings_since(
+        &self,
+        since: Timestamp,
+    ) -> Result<maped_ratings!(Self::User => Self::Item, TimedScore), Error> {
+        let ratings = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::rated_at.ge(since))
+            .load::<Rating>(conn))?;
+
+        let mut maped_ratings: HashMap<_, HashMap<_, _>> = HashMap::new();
+        for rating in ratings {
+            let score = TimedScore::new(rating.score, rating.rated_at);
+            maped_ratings
+                .entry(rating.user_id)
+                .or_default()
+                .insert(rating.book_id, score);
+        }
+
+        Ok(maped_ratings)
+    }
+
     #[allow(clippy::type_complexity)]
     fn all_users_ratings(&self) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
-        let ratings = ratings::table.load::<Rating>(&self.pg_conn)?;
+        let ratings = with_conn!(&self.conn, conn => ratings::table.load::<Rating>(conn))?;
 
         let mut maped_ratings = HashMap::new();
         for rating in ratings {
@@ -237,7 +569,8 @@ impl Controller for ShelvesController {
         &self,
         users: &[Self::User],
     ) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
-        let ratings = Rating::belonging_to(users).load::<Rating>(&self.pg_conn)?;
+        let ratings =
+            with_conn!(&self.conn, conn => Rating::belonging_to(users).load::<Rating>(conn))?;
 
         let mut maped_ratings = HashMap::new();
         for rating in ratings {
@@ -255,9 +588,9 @@ impl Controller for ShelvesController {
         &self,
         user: &Self::User,
     ) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
-        let ratings = ratings::table
+        let ratings = with_conn!(&self.conn, conn => ratings::table
             .filter(ratings::user_id.ne(user.id))
-            .load::<Rating>(&self.pg_conn)?;
+            .load::<Rating>(conn))?;
 
         let mut maped_ratings = HashMap::new();
         for rating in ratings {
@@ -271,7 +604,7 @@ impl Controller for ShelvesController {
     }
 
     fn users_means(&self, users: &[Self::User]) -> Result<means!(Self::User), Error> {
-        let means = Mean::belonging_to(users).load::<Mean>(&self.pg_conn)?;
+        let means = with_conn!(&self.conn, conn => Mean::belonging_to(users).load::<Mean>(conn))?;
 
         let means_by_user = means
             .into_iter()
@@ -281,10 +614,130 @@ impl Controller for ShelvesController {
         Ok(means_by_user)
     }
 
+    fn users_count(&self) -> Result<usize, Error> {
+        let count: i64 = with_conn!(&self.conn, conn => users::table.count().get_result(conn))?;
+        Ok(count as usize)
+    }
+
+    fn items_count(&self) -> Result<usize, Error> {
+        let count: i64 = with_conn!(&self.conn, conn => books::table.count().get_result(conn))?;
+        Ok(count as usize)
+    }
+
+    fn ratings_count(&self) -> Result<usize, Error> {
+        let count: i64 = with_conn!(&self.conn, conn => ratings::table.count().get_result(conn))?;
+        Ok(count as usize)
+    }
+
+    fn users_ratings_count(&self, users: &[Self::User]) -> Result<counts!(Self::User), Error> {
+        let ids: Vec<_> = users.iter().map(|user| user.id).collect();
+        let counts: Vec<(i32, i64)> = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::user_id.eq_any(&ids))
+            .group_by(ratings::user_id)
+            .select((ratings::user_id, sql::<BigInt>("COUNT(*)")))
+            .load(conn))?;
+
+        let mut counts_by_user: HashMap<_, _> = ids.into_iter().map(|id| (id, 0)).collect();
+        for (user_id, count) in counts {
+            counts_by_user.insert(user_id, count as usize);
+        }
+
+        Ok(counts_by_user)
+    }
+
+    fn items_ratings_count(&self, items: &[Self::Item]) -> Result<counts!(Self::Item), Error> {
+        let ids: Vec<_> = items.iter().map(|item| item.id).collect();
+        let counts: Vec<(eid!(Self::Item), i64)> = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::book_id.eq_any(&ids))
+            .group_by(ratings::book_id)
+            .select((ratings::book_id, sql::<BigInt>("COUNT(*)")))
+            .load(conn))?;
+
+        let mut counts_by_item: HashMap<_, _> = ids.into_iter().map(|id| (id, 0)).collect();
+        for (item_id, count) in counts {
+            counts_by_item.insert(item_id, count as usize);
+        }
+
+        Ok(counts_by_item)
+    }
+
+    fn items_means(&self, items: &[Self::Item]) -> Result<means!(Self::Item), Error> {
+        let ids: Vec<_> = items.iter().map(|item| item.id).collect();
+        let means: Vec<(eid!(Self::Item), f64)> = with_conn!(&self.conn, conn => item_means::table
+            .filter(item_means::item_id.eq_any(&ids))
+            .select((item_means::item_id, item_means::val))
+            .load(conn))?;
+
+        Ok(means.into_iter().collect())
+    }
+
+    fn global_mean(&self) -> Result<Option<f64>, Error> {
+        let mean: Option<(f64, i64)> = with_conn!(&self.conn, conn => global_mean::table
+            .select((global_mean::val, global_mean::score_number))
+            .first(conn)
+            .optional())?;
+
+        // The row is kept after the last rating is removed
+        Ok(mean.filter(|(_, count)| *count > 0).map(|(mean, _)| mean))
+    }
+
+    fn user_histogram(&self, user: &Self::User) -> Result<Histogram, Error> {
+        let histogram: Vec<(f64, i64)> = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::user_id.eq(user.id))
+            .group_by(ratings::score)
+            .select((ratings::score, sql::<BigInt>("COUNT(*)")))
+            .order(ratings::score)
+            .load(conn))?;
+
+        Ok(histogram
+            .into_iter()
+            .map(|(score, count)| (score, count as usize))
+            .collect())
+    }
+
+    fn item_histogram(&self, item: &Self::Item) -> Result<Histogram, Error> {
+        let histogram: Vec<(f64, i64)> = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::book_id.eq(item.id))
+            .group_by(ratings::score)
+            .select((ratings::score, sql::<BigInt>("COUNT(*)")))
+            .order(ratings::score)
+            .load(conn))?;
+
+        Ok(histogram
+            .into_iter()
+            .map(|(score, count)| (score, count as usize))
+            .collect())
+    }
+
+    fn most_rated_items(&self, limit: usize) -> Result<Vec<(eid!(Self::Item), usize)>, Error> {
+        let items: Vec<(eid!(Self::Item), i32)> = with_conn!(&self.conn, conn => item_means::table
+            .select((item_means::item_id, item_means::score_number))
+            .order((item_means::score_number.desc(), item_means::item_id))
+            .limit(limit as i64)
+            .load(conn))?;
+
+        Ok(items
+            .into_iter()
+            .map(|(item_id, count)| (item_id, count as usize))
+            .collect())
+    }
+
     fn score_range(&self) -> (f64, f64) {
         (0., 5.)
     }
 
+    fn rating_scale(&self) -> RatingScale {
+        RatingScale::discrete(self.score_range(), 1.)
+    }
+
+    fn rating_kind(&self, score: f64) -> RatingKind {
+        if score == IMPLICIT_SCORE {
+            RatingKind::Implicit
+        } else {
+            RatingKind::Explicit
+        }
+    }
+
     fn fields_for_users(&self) -> Vec<controller::Field> {
         vec![]
     }
@@ -293,78 +746,99 @@ impl Controller for ShelvesController {
         vec![]
     }
 
+    fn custom_keys_for_users(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn custom_keys_for_items(&self) -> Vec<String> {
+        Vec::new()
+    }
+
     fn insert_user<'a>(
         &self,
         _: HashMap<&'a str, controller::Value>,
     ) -> controller::Result<Self::User> {
-        Ok(insert_into(users::table)
-            .default_values()
-            .get_result(&self.pg_conn)?)
+        let query = insert_into(users::table).default_values();
+        Ok(insert_returning!(&self.conn, query, users::table)?)
     }
 
     fn insert_item<'a>(
         &self,
         _: HashMap<&'a str, controller::Value>,
     ) -> controller::Result<Self::Item> {
-        Ok(insert_into(books::table)
-            .default_values()
-            .get_result(&self.pg_conn)?)
+        let query = insert_into(books::table).default_values();
+        Ok(insert_returning!(&self.conn, query, books::table)?)
     }
 
-    fn insert_rating(
+    fn update_user(
         &self,
         user_id: &eid!(Self::User),
+        proto: HashMap<&str, controller::Value>,
+    ) -> Result<Self::User, Error> {
+        // There's nothing to update besides the id, so no field is accepted and
+        // the entity is only checked to exist
+        if let Some(field) = proto.keys().next() {
+            return Err(ErrorKind::UpdateNotSupported(field.to_string()).into());
+        }
+
+        Ok(with_conn!(&self.conn, conn => users::table.find(user_id).first(conn))?)
+    }
+
+    fn update_item(
+        &self,
         item_id: &eid!(Self::Item),
-        score: f64,
-    ) -> Result<Self::Rating, Error> {
-        let users_who_rated = self.mongo_db.collection("users_who_rated");
+        proto: HashMap<&str, controller::Value>,
+    ) -> Result<Self::Item, Error> {
+        // There's nothing to update besides the id, so no field is accepted and
+        // the entity is only checked to exist
+        if let Some(field) = proto.keys().next() {
+            return Err(ErrorKind::UpdateNotSupported(field.to_string()).into());
+        }
 
-        let query = doc! {
-            "item_id": item_id,
-            format!("scores.{}", user_id) : doc!{
-                "$exists": true
-            }
-        };
+        Ok(with_conn!(&self.conn, conn => books::table.find(item_id).first(conn))?)
+    }
+
+    fn remove_user(&self, user_id: &eid!(Self::User)) -> Result<Self::User, Error> {
+        let user = self.remove_user_sql(user_id)?;
+
+        // Its ratings are unset from mongo through the outbox
+        self.flush_outbox();
+
+        Ok(user)
+    }
+
+    fn remove_item(&self, item_id: &eid!(Self::Item)) -> Result<Self::Item, Error> {
+        let item = self.remove_item_sql(item_id)?;
 
-        let rating = users_who_rated.find_one(query, None)?;
-        if rating.is_some() {
-            return Err(
-                ErrorKind::InsertRatingFailed(user_id.to_string(), item_id.to_string()).into(),
-            );
+        // Its ratings are unset from mongo through the outbox, what's left is an
+        // empty document that can be removed once they're applied
+        if let (Ok(_), Some(mongo_db)) = (self.replay_outbox(), &self.mongo_db) {
+            let users_who_rated = mongo_db.collection("users_who_rated");
+            users_who_rated.delete_one(doc! { "item_id": item_id }, None)?;
         }
 
-        let update = doc! {
-            "$set": doc!{
-                format!("scores.{}",user_id): score
-            }
-        };
+        Ok(item)
+    }
 
-        let options = UpdateOptions::builder().upsert(true).build();
-        users_who_rated.update_one(doc! { "item_id": item_id }, update, options)?;
+    fn insert_rating(
+        &self,
+        user_id: &eid!(Self::User),
+        item_id: &eid!(Self::Item),
+        score: f64,
+    ) -> Result<Self::Rating, Error> {
+        self.check_score(score)?;
 
         let new_rating = NewRating {
             user_id: *user_id,
             book_id: *item_id,
             score,
+            rated_at: Some(now()),
         };
 
-        let psql_result = insert_into(ratings::table)
-            .values(new_rating)
-            .get_result(&self.pg_conn);
-
-        match psql_result {
-            Ok(rating) => Ok(rating),
-            Err(e) => {
-                let delete_doc = doc! {
-                    "$unset": doc!{
-                        format!("scores.{}", user_id): ""
-                    }
-                };
+        let rating = self.insert_rating_sql(&new_rating)?;
+        self.flush_outbox();
 
-                users_who_rated.update_one(doc! { "item_id": item_id }, delete_doc, None)?;
-                Err(e.into())
-            }
-        }
+        Ok(rating)
     }
 
     fn remove_rating(
@@ -372,47 +846,10 @@ impl Controller for ShelvesController {
         user_id: &eid!(Self::User),
         item_id: &eid!(Self::Item),
     ) -> Result<Self::Rating, Error> {
-        let users_who_rated = self.mongo_db.collection("users_who_rated");
-
-        let delete_doc = doc! {
-            "$unset": doc!{
-                format!("scores.{}", user_id): ""
-            }
-        };
-
-        let result = users_who_rated.update_one(doc! { "item_id": item_id }, delete_doc, None)?;
-        if result.matched_count.is_zero() || result.modified_count.is_zero() {
-            return Err(
-                ErrorKind::RemoveRatingFailed(user_id.to_string(), item_id.to_string()).into(),
-            );
-        }
+        let rating = self.remove_rating_sql(user_id, item_id)?;
+        self.flush_outbox();
 
-        let old_score: f64 = ratings::table
-            .filter(ratings::user_id.eq(user_id))
-            .filter(ratings::book_id.eq(item_id))
-            .select(ratings::score)
-            .first(&self.pg_conn)?;
-
-        let psql_result = delete(ratings::table)
-            .filter(ratings::user_id.eq(user_id))
-            .filter(ratings::book_id.eq(item_id))
-            .get_result(&self.pg_conn);
-
-        match psql_result {
-            Ok(rating) => Ok(rating),
-            Err(e) => {
-                let update_doc = doc! {
-                    "$set": doc!{
-                        format!("scores.{}",user_id): old_score
-                    }
-                };
-
-                let options = UpdateOptions::builder().upsert(true).build();
-                users_who_rated.update_one(doc! { "item_id": item_id }, update_doc, options)?;
-
-                Err(e.into())
-            }
-        }
+        Ok(rating)
     }
 
     fn update_rating(
@@ -421,47 +858,74 @@ impl Controller for ShelvesController {
         item_id: &eid!(Self::Item),
         score: f64,
     ) -> Result<Self::Rating, Error> {
-        let users_who_rated = self.mongo_db.collection("users_who_rated");
+        self.check_score(score)?;
 
-        let update_doc = doc! {
-            "$set": doc!{
-                format!("scores.{}", user_id): score
-            }
-        };
+        let rating = self.update_rating_sql(user_id, item_id, score)?;
+        self.flush_outbox();
+
+        Ok(rating)
+    }
+}
+
+impl MirroredRatings for ShelvesController {
+    fn mirror_db(&self) -> Result<&Database, Error> {
+        self.mongo_db()
+    }
+
+    fn mirrored_collections(&self) -> &[RatingCollection] {
+        MONGO_COLLECTIONS
+    }
 
-        let result = users_who_rated.update_one(doc! { "item_id": item_id }, update_doc, None)?;
-        if result.modified_count.is_zero() || result.matched_count.is_zero() {
-            return Err(
-                ErrorKind::UpdateRatingFailed(user_id.to_string(), item_id.to_string()).into(),
-            );
+    fn table_users_ratings(
+        &self,
+        user_ids: &[i32],
+    ) -> Result<MapedRatings<i32, i32, TimedScore>, Error> {
+        let ratings = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::user_id.eq_any(user_ids))
+            .load::<Rating>(conn))?;
+
+        let mut maped_ratings = HashMap::new();
+        for rating in ratings {
+            maped_ratings
+                .entry(rating.user_id)
+                .or_insert_with(HashMap::new)
+                .insert(
+                    rating.book_id,
+                    TimedScore::new(rating.score, rating.rated_at),
+                );
         }
 
-        let old_score: f64 = ratings::table
-            .filter(ratings::user_id.eq(user_id))
-            .filter(ratings::book_id.eq(item_id))
-            .select(ratings::score)
-            .first(&self.pg_conn)?;
-
-        let psql_res = update(ratings::table)
-            .filter(ratings::user_id.eq(user_id))
-            .filter(ratings::book_id.eq(item_id))
-            .set(ratings::score.eq(score))
-            .get_result::<Rating>(&self.pg_conn);
-
-        match psql_res {
-            Ok(rating) => Ok(rating),
-            Err(e) => {
-                let update_doc = doc! {
-                    "$set": doc! {
-                        format!("score.{}", user_id): old_score
-                    }
-                };
+        Ok(maped_ratings)
+    }
 
-                users_who_rated.update_one(doc! { "item_id": item_id }, update_doc, None)?;
+    fn table_items_ratings(
+        &self,
+        item_ids: &[i32],
+    ) -> Result<MapedRatings<i32, i32, TimedScore>, Error> {
+        let ratings = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::book_id.eq_any(item_ids))
+            .load::<Rating>(conn))?;
 
-                Err(e.into())
-            }
+        let mut maped_ratings = HashMap::new();
+        for rating in ratings {
+            maped_ratings
+                .entry(rating.book_id)
+                .or_insert_with(HashMap::new)
+                .insert(
+                    rating.user_id,
+                    TimedScore::new(rating.score, rating.rated_at),
+                );
         }
+
+        Ok(maped_ratings)
+    }
+
+    fn user_document_key(&self, user_id: &i32) -> Result<Bson, Error> {
+        Ok((*user_id).into())
+    }
+
+    fn item_document_key(&self, item_id: &i32) -> Result<Bson, Error> {
+        Ok((*item_id).into())
     }
 }
 
@@ -497,12 +961,56 @@ mod tests {
         let controller = ShelvesController::new()?;
         let mut chunk_iter = controller.users_by_chunks(80000);
 
-        assert_eq!(80000, chunk_iter.next().unwrap().len());
-        assert_eq!(80000, chunk_iter.next().unwrap().len());
-        assert_eq!(80000, chunk_iter.next().unwrap().len());
-        assert_eq!(38858, chunk_iter.next().unwrap().len());
+        assert_eq!(80000, chunk_iter.next().unwrap()?.len());
+        assert_eq!(80000, chunk_iter.next().unwrap()?.len());
+        assert_eq!(80000, chunk_iter.next().unwrap()?.len());
+        assert_eq!(38858, chunk_iter.next().unwrap()?.len());
         assert!(chunk_iter.next().is_none());
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod sqlite_tests {
+    use super::*;
+    use anyhow::Error;
+
+    #[test]
+    fn sqlite_default_ids_and_means() -> Result<(), Error> {
+        let path = std::env::temp_dir().join("shelves-means.db");
+        let _ = std::fs::remove_file(&path);
+
+        let mut config = Config::default();
+        let db = config.databases.get_mut("shelves").unwrap();
+        db.set_database_url(&path.to_string_lossy());
+
+        let controller = ShelvesController::from_config(&config, "shelves")?;
+
+        let first = controller.insert_user(HashMap::new())?;
+        let second = controller.insert_user(HashMap::new())?;
+        assert_eq!(second.id, first.id + 1);
+
+        // Users have nothing to update but can't silently drop fields either
+        assert_eq!(
+            controller.update_user(&first.id, HashMap::new())?.id,
+            first.id
+        );
+        let mut proto = HashMap::new();
+        proto.insert("name", controller::Value::from("Ann"));
+        assert!(controller.update_user(&first.id, proto).is_err());
+
+        let book = controller.insert_item(HashMap::new())?;
+        controller.insert_rating(&first.id, &book.id, 3.)?;
+        controller.insert_rating(&second.id, &book.id, 5.)?;
+
+        let who_rated = controller.users_who_rated(std::slice::from_ref(&book))?;
+        assert_eq!(who_rated[&book.id].len(), 2);
+
+        controller.update_rating(&first.id, &book.id, 1.)?;
+        let means = controller.users_means(&[first, second])?;
+        assert_eq!(means.values().sum::<f64>(), 6.);
+
+        Ok(())
+    }
+}
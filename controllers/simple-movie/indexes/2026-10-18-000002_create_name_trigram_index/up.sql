@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX users_name_trgm_idx on users USING gin (lower(name) gin_trgm_ops);
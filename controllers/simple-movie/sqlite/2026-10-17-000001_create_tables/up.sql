@@ -0,0 +1,24 @@
+-- Same tables as the postgres migrations, in sqlite dialect
+
+CREATE TABLE users (
+    id INTEGER PRIMARY KEY,
+    name VARCHAR NOT NULL
+);
+
+CREATE TABLE movies (
+    id INTEGER PRIMARY KEY,
+    name VARCHAR NOT NULL
+);
+
+CREATE TABLE ratings (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    movie_id INTEGER NOT NULL REFERENCES movies(id),
+    score FLOAT NOT NULL
+);
+
+CREATE TABLE means (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id),
+    val FLOAT NOT NULL,
+    score_number INTEGER NOT NULL
+);
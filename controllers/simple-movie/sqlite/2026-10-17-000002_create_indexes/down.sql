@@ -0,0 +1,2 @@
+DROP INDEX ratings_movie_id_idx;
+DROP INDEX ratings_user_id_movie_id_idx;
@@ -0,0 +1,2 @@
+CREATE UNIQUE INDEX ratings_user_id_movie_id_idx on ratings(user_id, movie_id);
+CREATE INDEX ratings_movie_id_idx on ratings(movie_id);
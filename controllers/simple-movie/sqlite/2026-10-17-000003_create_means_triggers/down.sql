@@ -0,0 +1,3 @@
+DROP TRIGGER update_means_on_upd;
+DROP TRIGGER update_means_on_del;
+DROP TRIGGER update_means_on_new;
@@ -4,7 +4,8 @@
 // https://opensource.org/licenses/MIT
 
 use anyhow::Error;
-use diesel::pg::PgConnection;
+use config::Backend;
+use controller::{insert_returning, with_conn, DbConnection};
 use diesel::{insert_into, prelude::*};
 use simple_movie::establish_connection;
 use simple_movie::models::{
@@ -15,28 +16,26 @@ use simple_movie::models::{
 use simple_movie::schema::{movies, ratings, users};
 use std::collections::HashMap;
 
-fn create_movie(conn: &PgConnection, name: &str) -> Result<Movie, Error> {
+fn create_movie(conn: &DbConnection, name: &str) -> Result<Movie, Error> {
     let new_movie = NewMovie { name };
 
-    let movie = insert_into(movies::table)
-        .values(&new_movie)
-        .get_result(conn)?;
+    let query = insert_into(movies::table).values(&new_movie);
+    let movie = insert_returning!(conn, query, movies::table)?;
 
     Ok(movie)
 }
 
-fn create_user(conn: &PgConnection, name: &str) -> Result<User, Error> {
+fn create_user(conn: &DbConnection, name: &str) -> Result<User, Error> {
     let new_user = NewUser { name };
 
-    let user = insert_into(users::table)
-        .values(&new_user)
-        .get_result(conn)?;
+    let query = insert_into(users::table).values(&new_user);
+    let user = insert_returning!(conn, query, users::table)?;
 
     Ok(user)
 }
 
 fn create_rating(
-    conn: &PgConnection,
+    conn: &DbConnection,
     score: f64,
     user_id: i32,
     movie_id: i32,
@@ -45,11 +44,10 @@ fn create_rating(
         score,
         user_id,
         movie_id,
+        rated_at: None,
     };
 
-    insert_into(ratings::table)
-        .values(&new_rating)
-        .execute(conn)?;
+    with_conn!(conn, conn => insert_into(ratings::table).values(&new_rating).execute(conn))?;
 
     Ok(())
 }
@@ -58,7 +56,7 @@ fn main() -> Result<(), Error> {
     let vars: HashMap<String, String> = dotenv::vars().collect();
 
     let url = &vars["DATABASE_URL"];
-    let conn = establish_connection(url)?;
+    let conn = establish_connection(Backend::from_url(url), url)?;
 
     let mut csv = csv::ReaderBuilder::new()
         .has_headers(false)
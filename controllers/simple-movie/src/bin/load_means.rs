@@ -4,8 +4,8 @@
 // https://opensource.org/licenses/MIT
 
 use anyhow::Error;
-use config::Config;
-use controller::Controller;
+use config::{Backend, Config};
+use controller::{with_conn, Controller};
 use diesel::{insert_into, prelude::*};
 use simple_movie::establish_connection;
 use simple_movie::models::users::NewMean;
@@ -35,7 +35,7 @@ fn main() -> Result<(), Error> {
     db.mongo_url = vars["MONGO_URL"].clone();
     db.mongo_db = vars["MONGO_DB"].clone();
 
-    let conn = establish_connection(&db.psql_url)?;
+    let conn = establish_connection(Backend::Postgres, &db.psql_url)?;
     let controller = SimpleMovieController::from_config(&config, "simple-movie")?;
 
     let mut means = Vec::new();
@@ -58,7 +58,7 @@ fn main() -> Result<(), Error> {
         }
     }
 
-    insert_into(means::table).values(&means).execute(&conn)?;
+    with_conn!(&conn, conn => insert_into(means::table).values(&means).execute(conn))?;
 
     Ok(())
 }
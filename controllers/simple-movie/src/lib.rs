@@ -5,6 +5,8 @@
 
 #[macro_use]
 extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
 
 pub mod models;
 pub mod schema;
@@ -14,32 +16,63 @@ use crate::models::{
     ratings::Rating,
     users::{Mean, User},
 };
-use crate::schema::{movies, ratings, users};
+use crate::schema::{global_mean, item_means, means, movies, rating_outbox, ratings, users};
 use anyhow::Error;
-use config::Config;
+use config::{Backend, Config};
+use controller::backend::{length, lower, word_similarity, WordSimilar};
+use controller::outbox::{apply_rating_events, RatingCollection, RatingEvent};
 use controller::{
-    eid, error::ErrorKind, maped_ratings, means, ratings, Controller, Field, SearchBy, Type, Value,
+    counts, eid, error::ErrorKind, insert_returning, maped_ratings, means, now, ratings, with_conn,
+    Controller, DbConnection, Field, Histogram, ItemFeatures, MapedRatings, MirroredRatings,
+    NameMatch, RatingScale, SearchBy, TimedScore, Timestamp, Type, Value,
+};
+use diesel::{
+    delete,
+    dsl::{exists, sql},
+    insert_into,
+    prelude::*,
+    select,
+    sql_types::{BigInt, Bool, Text},
+    update,
+};
+use models::{
+    movies::NewMovie,
+    ratings::{NewOutboxEvent, NewRating, OutboxEvent},
+    users::NewUser,
 };
-use diesel::pg::PgConnection;
-use diesel::{delete, insert_into, prelude::*, update};
-use models::{movies::NewMovie, ratings::NewRating, users::NewUser};
-use mongodb::bson::doc;
+use mongodb::bson::{doc, Bson};
 use mongodb::{
-    options::{FindOptions, UpdateOptions},
+    options::FindOptions,
     sync::{Client, Database},
 };
-use num_traits::Zero;
 use std::collections::HashMap;
 
-pub fn establish_connection(url: &str) -> Result<PgConnection, Error> {
-    Ok(PgConnection::establish(&url)?)
+embed_migrations!("sqlite");
+
+/// Ratings documents kept in mongo, they're written through the outbox
+const MONGO_COLLECTIONS: &[RatingCollection] = &[
+    RatingCollection::UsersRatings,
+    RatingCollection::UsersWhoRated,
+];
+
+const OUTBOX_CHUNK_SIZE: i64 = 1000;
+
+pub fn establish_connection(backend: Backend, url: &str) -> Result<DbConnection, Error> {
+    let conn = DbConnection::establish(backend, url)?;
+
+    // A sqlite database is created empty, so its tables are created on first use
+    if let DbConnection::Sqlite(conn) = &conn {
+        embedded_migrations::run(conn)?;
+    }
+
+    Ok(conn)
 }
 
 pub struct SimpleMovieController {
     users_ratings_mongo: bool,
     users_who_rated_mongo: bool,
-    pg_conn: PgConnection,
-    mongo_db: Database,
+    conn: DbConnection,
+    mongo_db: Option<Database>,
 }
 
 impl SimpleMovieController {
@@ -55,22 +88,254 @@ impl SimpleMovieController {
             .get(name)
             .ok_or_else(|| ErrorKind::DbConfigError(name.into()))?;
 
-        let users_ratings_mongo = db.users_ratings_mongo;
-        let users_who_rated_mongo = db.users_who_rated_mongo;
-        let psql_url = &db.psql_url;
-        let mongo_url = &db.mongo_url;
-        let mongo_db = &db.mongo_db;
+        let conn = establish_connection(db.backend, db.database_url())?;
+        let mongo_db = match db.backend {
+            Backend::Postgres => Some(Client::with_uri_str(&db.mongo_url)?.database(&db.mongo_db)),
+            Backend::Sqlite => None,
+        };
 
-        let pg_conn = establish_connection(psql_url)?;
-        let client = Client::with_uri_str(mongo_url)?;
-        let mongo_db = client.database(mongo_db);
+        let users_ratings_mongo = db.users_ratings_mongo && mongo_db.is_some();
+        let users_who_rated_mongo = db.users_who_rated_mongo && mongo_db.is_some();
 
-        Ok(Self {
+        let controller = Self {
             users_ratings_mongo,
             users_who_rated_mongo,
-            pg_conn,
+            conn,
             mongo_db,
-        })
+        };
+
+        // Events left behind by a crash or a mongo outage
+        controller.flush_outbox();
+        Ok(controller)
+    }
+
+    fn mongo_db(&self) -> Result<&Database, Error> {
+        Ok(self.mongo_db.as_ref().ok_or(ErrorKind::MongoUnavailable)?)
+    }
+
+    /// Apply the pending events of the outbox to mongo by chunks, events are
+    /// removed once applied so whatever fails is retried on the next replay.
+    /// Returns how many events were applied
+    pub fn replay_outbox(&self) -> Result<usize, Error> {
+        let (conn, mongo_db) = match (&self.conn, &self.mongo_db) {
+            (DbConnection::Postgres(conn), Some(mongo_db)) => (conn, mongo_db),
+            _ => return Ok(0),
+        };
+
+        let mut applied = 0;
+        loop {
+            // Rows stay locked until they're deleted, so concurrent replays
+            // don't apply the same events out of order
+            let replayed = conn.transaction::<_, Error, _>(|| {
+                let events: Vec<RatingEvent<_, _>> = rating_outbox::table
+                    .order(rating_outbox::id)
+                    .limit(OUTBOX_CHUNK_SIZE)
+                    .for_update()
+                    .load::<OutboxEvent>(conn)?
+                    .into_iter()
+                    .map(Into::into)
+                    .collect();
+
+                apply_rating_events(mongo_db, &events, MONGO_COLLECTIONS)?;
+
+                let ids: Vec<_> = events.iter().map(|event| event.id).collect();
+                delete(rating_outbox::table.filter(rating_outbox::id.eq_any(&ids)))
+                    .execute(conn)?;
+
+                Ok(events.len())
+            })?;
+
+            applied += replayed;
+            if replayed < OUTBOX_CHUNK_SIZE as usize {
+                return Ok(applied);
+            }
+        }
+    }
+
+    /// Ratings are written to mongo after the transaction is committed, if mongo
+    /// isn't reachable the events stay in the outbox until the next replay
+    fn flush_outbox(&self) {
+        if let Err(e) = self.replay_outbox() {
+            log::error!("Couldn't replay the rating outbox into mongo: {}", e);
+            match self.pending_events() {
+                Ok(pending) => {
+                    log::warn!("{} rating events are still pending in the outbox", pending)
+                }
+                Err(e) => log::error!("Couldn't count the pending events of the outbox: {}", e),
+            }
+        }
+    }
+
+    /// Number of events in the outbox that haven't been applied to mongo yet
+    pub fn pending_events(&self) -> Result<usize, Error> {
+        with_conn!(&self.conn, conn => rating_outbox::table.count().get_result::<i64>(conn))
+            .map(|count| count as usize)
+            .map_err(Into::into)
+    }
+
+    fn insert_rating_sql(&self, new_rating: &NewRating) -> Result<Rating, Error> {
+        let outbox = self.mongo_db.is_some();
+        let rating = with_conn!(&self.conn, conn => conn.transaction::<_, Error, _>(|| {
+            let rated = ratings::table
+                .filter(ratings::user_id.eq(new_rating.user_id))
+                .filter(ratings::movie_id.eq(new_rating.movie_id));
+
+            if select(exists(rated)).get_result(conn)? {
+                return Err(ErrorKind::InsertRatingFailed(
+                    new_rating.user_id.to_string(),
+                    new_rating.movie_id.to_string(),
+                )
+                .into());
+            }
+
+            insert_into(ratings::table).values(new_rating).execute(conn)?;
+            if outbox {
+                let event = NewOutboxEvent {
+                    user_id: new_rating.user_id,
+                    movie_id: new_rating.movie_id,
+                    score: Some(new_rating.score),
+                    rated_at: new_rating.rated_at,
+                };
+
+                insert_into(rating_outbox::table).values(&event).execute(conn)?;
+            }
+
+            Ok(rated.first(conn)?)
+        }))?;
+
+        Ok(rating)
+    }
+
+    fn remove_rating_sql(&self, user_id: &i32, item_id: &i32) -> Result<Rating, Error> {
+        let outbox = self.mongo_db.is_some();
+        let rating = with_conn!(&self.conn, conn => conn.transaction::<_, Error, _>(|| {
+            let rated = ratings::table
+                .filter(ratings::user_id.eq(user_id))
+                .filter(ratings::movie_id.eq(item_id));
+
+            let rating: Rating = rated.first(conn).optional()?.ok_or_else(|| {
+                ErrorKind::RemoveRatingFailed(user_id.to_string(), item_id.to_string())
+            })?;
+
+            delete(rated).execute(conn)?;
+            if outbox {
+                let event = NewOutboxEvent {
+                    user_id: *user_id,
+                    movie_id: *item_id,
+                    score: None,
+                    rated_at: None,
+                };
+
+                insert_into(rating_outbox::table).values(&event).execute(conn)?;
+            }
+
+            Ok(rating)
+        }))?;
+
+        Ok(rating)
+    }
+
+    fn update_rating_sql(&self, user_id: &i32, item_id: &i32, score: f64) -> Result<Rating, Error> {
+        let outbox = self.mongo_db.is_some();
+        let rating = with_conn!(&self.conn, conn => conn.transaction::<_, Error, _>(|| {
+            let rated = ratings::table
+                .filter(ratings::user_id.eq(user_id))
+                .filter(ratings::movie_id.eq(item_id));
+
+            // The new score must be different from the actual one
+            rated
+                .select(ratings::score)
+                .first::<f64>(conn)
+                .optional()?
+                .filter(|old_score| *old_score != score)
+                .ok_or_else(|| {
+                    ErrorKind::UpdateRatingFailed(user_id.to_string(), item_id.to_string())
+                })?;
+
+            let rated_at = Some(now());
+            update(rated)
+                .set((ratings::score.eq(score), ratings::rated_at.eq(rated_at)))
+                .execute(conn)?;
+
+            if outbox {
+                let event = NewOutboxEvent {
+                    user_id: *user_id,
+                    movie_id: *item_id,
+                    score: Some(score),
+                    rated_at,
+                };
+
+                insert_into(rating_outbox::table).values(&event).execute(conn)?;
+            }
+
+            Ok(rated.first(conn)?)
+        }))?;
+
+        Ok(rating)
+    }
+
+    fn update_user_sql(&self, user_id: &i32, changes: &NewUser) -> Result<User, Error> {
+        let user = with_conn!(&self.conn, conn => conn.transaction(|| {
+            update(users::table.find(user_id)).set(changes).execute(conn)?;
+            users::table.find(user_id).first(conn)
+        }))?;
+
+        Ok(user)
+    }
+
+    fn update_item_sql(&self, item_id: &i32, changes: &NewMovie) -> Result<Movie, Error> {
+        let item = with_conn!(&self.conn, conn => conn.transaction(|| {
+            update(movies::table.find(item_id)).set(changes).execute(conn)?;
+            movies::table.find(item_id).first(conn)
+        }))?;
+
+        Ok(item)
+    }
+
+    fn remove_user_sql(&self, user_id: &i32) -> Result<User, Error> {
+        let outbox = self.mongo_db.is_some();
+        let user = with_conn!(&self.conn, conn => conn.transaction::<_, diesel::result::Error, _>(|| {
+            let user = users::table.find(user_id).first::<User>(conn)?;
+
+            let rated = ratings::table.filter(ratings::user_id.eq(user_id));
+            if outbox {
+                insert_into(rating_outbox::table)
+                    .values(rated.select((ratings::user_id, ratings::movie_id)))
+                    .into_columns((rating_outbox::user_id, rating_outbox::movie_id))
+                    .execute(conn)?;
+            }
+
+            delete(rated).execute(conn)?;
+            delete(means::table.filter(means::user_id.eq(user_id))).execute(conn)?;
+            delete(users::table.find(user_id)).execute(conn)?;
+
+            Ok(user)
+        }))?;
+
+        Ok(user)
+    }
+
+    fn remove_item_sql(&self, item_id: &i32) -> Result<Movie, Error> {
+        let outbox = self.mongo_db.is_some();
+        let item = with_conn!(&self.conn, conn => conn.transaction::<_, diesel::result::Error, _>(|| {
+            let item = movies::table.find(item_id).first::<Movie>(conn)?;
+
+            let rated = ratings::table.filter(ratings::movie_id.eq(item_id));
+            if outbox {
+                insert_into(rating_outbox::table)
+                    .values(rated.select((ratings::user_id, ratings::movie_id)))
+                    .into_columns((rating_outbox::user_id, rating_outbox::movie_id))
+                    .execute(conn)?;
+            }
+
+            // The means triggers update the mean of every user that rated it
+            delete(rated).execute(conn)?;
+            delete(movies::table.find(item_id)).execute(conn)?;
+
+            Ok(item)
+        }))?;
+
+        Ok(item)
     }
 }
 
@@ -80,7 +345,7 @@ impl Controller for SimpleMovieController {
     type Rating = Rating;
 
     fn users(&self) -> Result<Vec<Self::User>, Error> {
-        let users = users::table.load::<User>(&self.pg_conn)?;
+        let users = with_conn!(&self.conn, conn => users::table.load::<User>(conn))?;
         Ok(users)
     }
 
@@ -89,7 +354,7 @@ impl Controller for SimpleMovieController {
             SearchBy::Id(id) => {
                 let id: i32 = id.parse()?;
 
-                let users = users::table.filter(users::id.eq(id)).load(&self.pg_conn)?;
+                let users = with_conn!(&self.conn, conn => users::table.filter(users::id.eq(id)).load(conn))?;
 
                 if users.is_empty() {
                     Err(ErrorKind::NotFoundById(id.to_string()).into())
@@ -99,9 +364,9 @@ impl Controller for SimpleMovieController {
             }
 
             SearchBy::Name(name) => {
-                let users = users::table
+                let users = with_conn!(&self.conn, conn => users::table
                     .filter(users::name.eq(name))
-                    .load(&self.pg_conn)?;
+                    .load(conn))?;
 
                 if users.is_empty() {
                     Err(ErrorKind::NotFoundByName(name.clone()).into())
@@ -110,21 +375,84 @@ impl Controller for SimpleMovieController {
                 }
             }
 
-            SearchBy::Custom(k, v) => Err(ErrorKind::NotFoundByCustom(k.clone(), v.clone()).into()),
+            SearchBy::NameLike(mode, name, limit) => {
+                let column = lower(users::name);
+                let candidates = match (&self.conn, mode.like_pattern(name)) {
+                    (_, Some(pattern)) => with_conn!(&self.conn, conn => users::table
+                        .filter(column.like(pattern))
+                        .order((
+                            column.like(NameMatch::prefix_pattern(name)).desc(),
+                            length(users::name),
+                        ))
+                        .limit(*limit as i64)
+                        .load::<User>(conn))?,
+
+                    // Ranked by pg_trgm, the trigram index of the column is used
+                    (DbConnection::Postgres(conn), None) => {
+                        let name = name.to_lowercase();
+                        users::table
+                            .filter(WordSimilar::new(name.as_str().into_sql::<Text>(), column))
+                            .order(word_similarity(&name, column).desc())
+                            .limit(*limit as i64)
+                            .load::<User>(conn)?
+                    }
+
+                    // Sqlite has no trigrams, only the names that could match are loaded
+                    (DbConnection::Sqlite(conn), None) => {
+                        let mut query = users::table.filter(false.into_sql::<Bool>()).into_boxed();
+                        for pattern in NameMatch::fuzzy_patterns(name) {
+                            query = query.or_filter(column.like(pattern));
+                        }
+
+                        query.load::<User>(conn)?
+                    }
+                };
+
+                let users = mode.rank(name, candidates, |user| Some(&user.name), *limit);
+                if users.is_empty() {
+                    Err(ErrorKind::NotFoundByName(name.clone()).into())
+                } else {
+                    Ok(users)
+                }
+            }
+
+            SearchBy::Custom(k, _) => Err(ErrorKind::CustomSearchNotSupported(k.clone()).into()),
         }
     }
 
     fn users_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<Self::User>, Error> {
-        let users = users::table
+        let users = with_conn!(&self.conn, conn => users::table
+            .order(users::id)
             .limit(limit as i64)
             .offset(offset as i64)
-            .load::<User>(&self.pg_conn)?;
+            .load::<User>(conn))?;
+
+        Ok(users)
+    }
+
+    fn users_after(
+        &self,
+        after: Option<&eid!(Self::User)>,
+        limit: usize,
+    ) -> Result<Vec<Self::User>, Error> {
+        let users = with_conn!(&self.conn, conn => {
+            let mut query = users::table
+                .order(users::id)
+                .limit(limit as i64)
+                .into_boxed();
+
+            if let Some(after) = after {
+                query = query.filter(users::id.gt(after));
+            }
+
+            query.load::<User>(conn)
+        })?;
 
         Ok(users)
     }
 
     fn items(&self) -> Result<Vec<Self::Item>, Error> {
-        let movies = movies::table.load::<Movie>(&self.pg_conn)?;
+        let movies = with_conn!(&self.conn, conn => movies::table.load::<Movie>(conn))?;
         Ok(movies)
     }
 
@@ -133,9 +461,9 @@ impl Controller for SimpleMovieController {
             SearchBy::Id(id) => {
                 let id: i32 = id.parse()?;
 
-                let movies = movies::table
+                let movies = with_conn!(&self.conn, conn => movies::table
                     .filter(movies::id.eq(id))
-                    .load(&self.pg_conn)?;
+                    .load(conn))?;
 
                 if movies.is_empty() {
                     Err(ErrorKind::NotFoundById(id.to_string()).into())
@@ -145,9 +473,9 @@ impl Controller for SimpleMovieController {
             }
 
             SearchBy::Name(name) => {
-                let movies = movies::table
+                let movies = with_conn!(&self.conn, conn => movies::table
                     .filter(movies::name.eq(name))
-                    .load(&self.pg_conn)?;
+                    .load(conn))?;
 
                 if movies.is_empty() {
                     Err(ErrorKind::NotFoundByName(name.clone()).into())
@@ -156,15 +484,55 @@ impl Controller for SimpleMovieController {
                 }
             }
 
-            SearchBy::Custom(k, v) => Err(ErrorKind::NotFoundByCustom(k.clone(), v.clone()).into()),
+            SearchBy::NameLike(mode, name, limit) => {
+                let candidates = with_conn!(&self.conn, conn => {
+                    let mut query = movies::table.into_boxed();
+                    if let Some(pattern) = mode.like_pattern(name) {
+                        query = query.filter(lower(movies::name).like(pattern));
+                    }
+
+                    query.load::<Movie>(conn)
+                })?;
+
+                let movies = mode.rank(name, candidates, |movie| Some(&movie.name), *limit);
+                if movies.is_empty() {
+                    Err(ErrorKind::NotFoundByName(name.clone()).into())
+                } else {
+                    Ok(movies)
+                }
+            }
+
+            SearchBy::Custom(k, _) => Err(ErrorKind::CustomSearchNotSupported(k.clone()).into()),
         }
     }
 
     fn items_offset_limit(&self, offset: usize, limit: usize) -> Result<Vec<Self::Item>, Error> {
-        let items = movies::table
+        let items = with_conn!(&self.conn, conn => movies::table
+            .order(movies::id)
             .limit(limit as i64)
             .offset(offset as i64)
-            .load::<Movie>(&self.pg_conn)?;
+            .load::<Movie>(conn))?;
+
+        Ok(items)
+    }
+
+    fn items_after(
+        &self,
+        after: Option<&eid!(Self::Item)>,
+        limit: usize,
+    ) -> Result<Vec<Self::Item>, Error> {
+        let items = with_conn!(&self.conn, conn => {
+            let mut query = movies::table
+                .order(movies::id)
+                .limit(limit as i64)
+                .into_boxed();
+
+            if let Some(after) = after {
+                query = query.filter(movies::id.gt(after));
+            }
+
+            query.load::<Movie>(conn)
+        })?;
 
         Ok(items)
     }
@@ -205,7 +573,8 @@ impl Controller for SimpleMovieController {
         items: &[Self::Item],
     ) -> Result<maped_ratings!(Self::Item => Self::User), Error> {
         if !self.users_who_rated_mongo {
-            let ratings = Rating::belonging_to(items).load::<Rating>(&self.pg_conn)?;
+            let ratings =
+                with_conn!(&self.conn, conn => Rating::belonging_to(items).load::<Rating>(conn))?;
 
             let mut items_users = HashMap::new();
             for rating in ratings {
@@ -217,7 +586,7 @@ impl Controller for SimpleMovieController {
 
             Ok(items_users)
         } else {
-            let collection = self.mongo_db.collection("users_who_rated");
+            let collection = self.mongo_db()?.collection("users_who_rated");
             let ids: Vec<_> = items.iter().map(|m| m.id).collect();
             let options = FindOptions::builder().show_record_id(false).build();
 
@@ -250,15 +619,15 @@ impl Controller for SimpleMovieController {
 
     fn user_ratings(&self, user: &Self::User) -> Result<ratings!(Self::Item), Error> {
         if !self.users_ratings_mongo {
-            let ratings = Rating::belonging_to(user)
-                .load::<Rating>(&self.pg_conn)?
-                .into_iter()
-                .map(|rating| (rating.movie_id, rating.score))
-                .collect();
+            let ratings = with_conn!(&self.conn, conn => Rating::belonging_to(user)
+                .load::<Rating>(conn))?
+            .into_iter()
+            .map(|rating| (rating.movie_id, rating.score))
+            .collect();
 
             Ok(ratings)
         } else {
-            let collection = self.mongo_db.collection("users_ratings");
+            let collection = self.mongo_db()?.collection("users_ratings");
             let options = FindOptions::builder().show_record_id(false).build();
 
             let cursor = collection.find(
@@ -284,10 +653,49 @@ impl Controller for SimpleMovieController {
         }
     }
 
+    fn user_timed_ratings(
+        &self,
+        user: &Self::User,
+    ) -> Result<ratings!(Self::Item, TimedScore), Error> {
+        let ratings = with_conn!(&self.conn, conn => Rating::belonging_to(user)
+            .load::<Rating>(conn))?
+        .into_iter()
+        .map(|rating| {
+            (
+                rating.movie_id,
+                TimedScore::new(rating.score, rating.rated_at),
+            )
+        })
+        .collect();
+
+        Ok(ratings)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn users_ratings_since(
+        &self,
+        since: Timestamp,
+    ) -> Result<maped_ratings!(Self::User => Self::Item, TimedScore), Error> {
+        let ratings = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::rated_at.ge(since))
+            .load::<Rating>(conn))?;
+
+        let mut maped_ratings: HashMap<_, HashMap<_, _>> = HashMap::new();
+        for rating in ratings {
+            let score = TimedScore::new(rating.score, rating.rated_at);
+            maped_ratings
+                .entry(rating.user_id)
+                .or_default()
+                .insert(rating.movie_id, score);
+        }
+
+        Ok(maped_ratings)
+    }
+
     #[allow(clippy::type_complexity)]
     fn all_users_ratings(&self) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
         if !self.users_ratings_mongo {
-            let ratings = ratings::table.load::<Rating>(&self.pg_conn)?;
+            let ratings = with_conn!(&self.conn, conn => ratings::table.load::<Rating>(conn))?;
 
             let mut maped_ratings = HashMap::new();
             for rating in ratings {
@@ -299,7 +707,7 @@ impl Controller for SimpleMovieController {
 
             Ok(maped_ratings)
         } else {
-            let collection = self.mongo_db.collection("users_ratings");
+            let collection = self.mongo_db()?.collection("users_ratings");
             let options = FindOptions::builder().show_record_id(false).build();
             let cursor = collection.find(None, options)?;
 
@@ -329,7 +737,8 @@ impl Controller for SimpleMovieController {
         users: &[Self::User],
     ) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
         if !self.users_ratings_mongo {
-            let ratings = Rating::belonging_to(users).load::<Rating>(&self.pg_conn)?;
+            let ratings =
+                with_conn!(&self.conn, conn => Rating::belonging_to(users).load::<Rating>(conn))?;
 
             let mut maped_ratings = HashMap::new();
             for rating in ratings {
@@ -341,7 +750,7 @@ impl Controller for SimpleMovieController {
 
             Ok(maped_ratings)
         } else {
-            let collection = self.mongo_db.collection("users_ratings");
+            let collection = self.mongo_db()?.collection("users_ratings");
             let ids: Vec<_> = users.iter().map(|u| u.id).collect();
             let options = FindOptions::builder().show_record_id(false).build();
 
@@ -378,9 +787,9 @@ impl Controller for SimpleMovieController {
         user: &Self::User,
     ) -> Result<maped_ratings!(Self::User => Self::Item), Error> {
         if !self.users_ratings_mongo {
-            let ratings = ratings::table
-                .filter(ratings::user_id.is_distinct_from(user.id))
-                .load::<Rating>(&self.pg_conn)?;
+            let ratings = with_conn!(&self.conn, conn => ratings::table
+                .filter(ratings::user_id.ne(user.id))
+                .load::<Rating>(conn))?;
 
             let mut maped_ratings = HashMap::new();
             for rating in ratings {
@@ -392,7 +801,7 @@ impl Controller for SimpleMovieController {
 
             Ok(maped_ratings)
         } else {
-            let collection = self.mongo_db.collection("users_ratings");
+            let collection = self.mongo_db()?.collection("users_ratings");
             let options = FindOptions::builder().show_record_id(false).build();
 
             let cursor = collection.find(
@@ -423,7 +832,7 @@ impl Controller for SimpleMovieController {
     }
 
     fn users_means(&self, users: &[Self::User]) -> Result<means!(Self::User), Error> {
-        let means = Mean::belonging_to(users).load::<Mean>(&self.pg_conn)?;
+        let means = with_conn!(&self.conn, conn => Mean::belonging_to(users).load::<Mean>(conn))?;
 
         let means_by_user = means
             .into_iter()
@@ -433,16 +842,150 @@ impl Controller for SimpleMovieController {
         Ok(means_by_user)
     }
 
+    fn users_count(&self) -> Result<usize, Error> {
+        let count: i64 = with_conn!(&self.conn, conn => users::table.count().get_result(conn))?;
+        Ok(count as usize)
+    }
+
+    fn items_count(&self) -> Result<usize, Error> {
+        let count: i64 = with_conn!(&self.conn, conn => movies::table.count().get_result(conn))?;
+        Ok(count as usize)
+    }
+
+    fn ratings_count(&self) -> Result<usize, Error> {
+        let count: i64 = with_conn!(&self.conn, conn => ratings::table.count().get_result(conn))?;
+        Ok(count as usize)
+    }
+
+    fn users_ratings_count(&self, users: &[Self::User]) -> Result<counts!(Self::User), Error> {
+        let ids: Vec<_> = users.iter().map(|user| user.id).collect();
+        let counts: Vec<(i32, i64)> = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::user_id.eq_any(&ids))
+            .group_by(ratings::user_id)
+            .select((ratings::user_id, sql::<BigInt>("COUNT(*)")))
+            .load(conn))?;
+
+        let mut counts_by_user: HashMap<_, _> = ids.into_iter().map(|id| (id, 0)).collect();
+        for (user_id, count) in counts {
+            counts_by_user.insert(user_id, count as usize);
+        }
+
+        Ok(counts_by_user)
+    }
+
+    fn items_ratings_count(&self, items: &[Self::Item]) -> Result<counts!(Self::Item), Error> {
+        let ids: Vec<_> = items.iter().map(|item| item.id).collect();
+        let counts: Vec<(eid!(Self::Item), i64)> = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::movie_id.eq_any(&ids))
+            .group_by(ratings::movie_id)
+            .select((ratings::movie_id, sql::<BigInt>("COUNT(*)")))
+            .load(conn))?;
+
+        let mut counts_by_item: HashMap<_, _> = ids.into_iter().map(|id| (id, 0)).collect();
+        for (item_id, count) in counts {
+            counts_by_item.insert(item_id, count as usize);
+        }
+
+        Ok(counts_by_item)
+    }
+
+    fn items_features(&self, items: &[Self::Item]) -> Result<ItemFeatures<i32>, Error> {
+        let ids: Vec<_> = items.iter().map(|movie| movie.id).collect();
+        let movies = with_conn!(&self.conn, conn => movies::table
+            .filter(movies::id.eq_any(&ids))
+            .load::<Movie>(conn))?;
+
+        let mut features = ItemFeatures::new();
+        for movie in movies {
+            features.add(movie.id, movie.features());
+        }
+
+        Ok(features)
+    }
+
+    fn items_means(&self, items: &[Self::Item]) -> Result<means!(Self::Item), Error> {
+        let ids: Vec<_> = items.iter().map(|item| item.id).collect();
+        let means: Vec<(eid!(Self::Item), f64)> = with_conn!(&self.conn, conn => item_means::table
+            .filter(item_means::item_id.eq_any(&ids))
+            .select((item_means::item_id, item_means::val))
+            .load(conn))?;
+
+        Ok(means.into_iter().collect())
+    }
+
+    fn global_mean(&self) -> Result<Option<f64>, Error> {
+        let mean: Option<(f64, i64)> = with_conn!(&self.conn, conn => global_mean::table
+            .select((global_mean::val, global_mean::score_number))
+            .first(conn)
+            .optional())?;
+
+        // The row is kept after the last rating is removed
+        Ok(mean.filter(|(_, count)| *count > 0).map(|(mean, _)| mean))
+    }
+
+    fn user_histogram(&self, user: &Self::User) -> Result<Histogram, Error> {
+        let histogram: Vec<(f64, i64)> = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::user_id.eq(user.id))
+            .group_by(ratings::score)
+            .select((ratings::score, sql::<BigInt>("COUNT(*)")))
+            .order(ratings::score)
+            .load(conn))?;
+
+        Ok(histogram
+            .into_iter()
+            .map(|(score, count)| (score, count as usize))
+            .collect())
+    }
+
+    fn item_histogram(&self, item: &Self::Item) -> Result<Histogram, Error> {
+        let histogram: Vec<(f64, i64)> = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::movie_id.eq(item.id))
+            .group_by(ratings::score)
+            .select((ratings::score, sql::<BigInt>("COUNT(*)")))
+            .order(ratings::score)
+            .load(conn))?;
+
+        Ok(histogram
+            .into_iter()
+            .map(|(score, count)| (score, count as usize))
+            .collect())
+    }
+
+    fn most_rated_items(&self, limit: usize) -> Result<Vec<(eid!(Self::Item), usize)>, Error> {
+        let items: Vec<(eid!(Self::Item), i32)> = with_conn!(&self.conn, conn => item_means::table
+            .select((item_means::item_id, item_means::score_number))
+            .order((item_means::score_number.desc(), item_means::item_id))
+            .limit(limit as i64)
+            .load(conn))?;
+
+        Ok(items
+            .into_iter()
+            .map(|(item_id, count)| (item_id, count as usize))
+            .collect())
+    }
+
     fn score_range(&self) -> (f64, f64) {
         (1., 5.)
     }
 
+    fn rating_scale(&self) -> RatingScale {
+        RatingScale::discrete(self.score_range(), 1.)
+    }
+
     fn fields_for_users(&self) -> Vec<Field> {
-        vec![Field::Required("name", Type::String)]
+        vec![Field::required("name", Type::String)]
     }
 
     fn fields_for_items(&self) -> Vec<Field> {
-        vec![Field::Required("name", Type::String)]
+        vec![Field::required("name", Type::String)]
+    }
+
+    fn custom_keys_for_users(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn custom_keys_for_items(&self) -> Vec<String> {
+        Vec::new()
     }
 
     fn insert_user<'a>(&self, proto: HashMap<&'a str, Value>) -> Result<User, Error> {
@@ -450,9 +993,8 @@ impl Controller for SimpleMovieController {
             name: proto["name"].as_string()?,
         };
 
-        Ok(insert_into(users::table)
-            .values(&user)
-            .get_result(&self.pg_conn)?)
+        let query = insert_into(users::table).values(&user);
+        Ok(insert_returning!(&self.conn, query, users::table)?)
     }
 
     fn insert_item<'a>(&self, proto: HashMap<&'a str, Value>) -> Result<Movie, Error> {
@@ -460,85 +1002,77 @@ impl Controller for SimpleMovieController {
             name: proto["name"].as_string()?,
         };
 
-        Ok(insert_into(movies::table)
-            .values(&movie)
-            .get_result(&self.pg_conn)?)
+        let query = insert_into(movies::table).values(&movie);
+        Ok(insert_returning!(&self.conn, query, movies::table)?)
     }
 
-    fn insert_rating(
+    fn update_user(
         &self,
         user_id: &eid!(Self::User),
+        proto: HashMap<&str, Value>,
+    ) -> Result<Self::User, Error> {
+        let changes = NewUser {
+            name: proto["name"].as_string()?,
+        };
+
+        self.update_user_sql(user_id, &changes)
+    }
+
+    fn update_item(
+        &self,
         item_id: &eid!(Self::Item),
-        score: f64,
-    ) -> Result<Self::Rating, Error> {
-        let users_who_rated = self.mongo_db.collection("users_who_rated");
-        let users_ratings = self.mongo_db.collection("users_ratings");
-
-        // Check that this rating doesn't exists on users_who_rated
-        let query = doc! {
-            "item_id": item_id,
-            format!("scores.{}", user_id) : doc!{
-                "$exists": true
-            }
+        proto: HashMap<&str, Value>,
+    ) -> Result<Self::Item, Error> {
+        let changes = NewMovie {
+            name: proto["name"].as_string()?,
         };
 
-        let rating = users_who_rated.find_one(query, None)?;
-        if rating.is_some() {
-            return Err(
-                ErrorKind::InsertRatingFailed(user_id.to_string(), item_id.to_string()).into(),
-            );
+        self.update_item_sql(item_id, &changes)
+    }
+
+    fn remove_user(&self, user_id: &eid!(Self::User)) -> Result<Self::User, Error> {
+        let user = self.remove_user_sql(user_id)?;
+
+        // Its ratings are unset from mongo through the outbox, what's left is an
+        // empty document that can be removed once they're applied
+        if let (Ok(_), Some(mongo_db)) = (self.replay_outbox(), &self.mongo_db) {
+            let users_ratings = mongo_db.collection("users_ratings");
+            users_ratings.delete_one(doc! { "user_id": user_id }, None)?;
         }
 
-        let options = UpdateOptions::builder().upsert(true).build();
-        let update = doc! {
-            "$set": doc!{
-                format!("scores.{}", user_id): score
-            }
-        };
+        Ok(user)
+    }
 
-        users_who_rated.update_one(doc! { "item_id": item_id }, update, options)?;
+    fn remove_item(&self, item_id: &eid!(Self::Item)) -> Result<Self::Item, Error> {
+        let item = self.remove_item_sql(item_id)?;
 
-        let options = UpdateOptions::builder().upsert(true).build();
-        let update = doc! {
-            "$set": doc!{
-                format!("scores.{}", item_id): score
-            }
-        };
+        if let (Ok(_), Some(mongo_db)) = (self.replay_outbox(), &self.mongo_db) {
+            let users_who_rated = mongo_db.collection("users_who_rated");
+            users_who_rated.delete_one(doc! { "item_id": item_id }, None)?;
+        }
+
+        Ok(item)
+    }
 
-        users_ratings.update_one(doc! { "user_id": user_id }, update, options)?;
+    fn insert_rating(
+        &self,
+        user_id: &eid!(Self::User),
+        item_id: &eid!(Self::Item),
+        score: f64,
+    ) -> Result<Self::Rating, Error> {
+        self.check_score(score)?;
 
         let new_rating = NewRating {
             user_id: *user_id,
             movie_id: *item_id,
             score,
+            rated_at: Some(now()),
         };
 
-        let psql_result = insert_into(ratings::table)
-            .values(new_rating)
-            .get_result(&self.pg_conn);
-
-        match psql_result {
-            Ok(rating) => Ok(rating),
-            Err(e) => {
-                let delete_doc = doc! {
-                    "$unset": doc!{
-                        format!("scores.{}", user_id): ""
-                    }
-                };
+        let rating = self.insert_rating_sql(&new_rating)?;
+        self.flush_outbox();
 
-                users_who_rated.update_one(doc! { "item_id": item_id }, delete_doc, None)?;
-
-                let delete_doc = doc! {
-                    "$unset": doc!{
-                        format!("scores.{}", item_id): ""
-                    }
-                };
-
-                users_ratings.update_one(doc! { "user_id": user_id }, delete_doc, None)?;
-
-                Err(e.into())
-            }
-        }
+        Ok(rating)
     }
 
     fn remove_rating(
@@ -546,70 +1080,10 @@ impl Controller for SimpleMovieController {
         user_id: &eid!(Self::User),
         item_id: &eid!(Self::Item),
     ) -> Result<Self::Rating, Error> {
-        let users_who_rated = self.mongo_db.collection("users_who_rated");
-        let users_ratings = self.mongo_db.collection("users_ratings");
-
-        let delete_doc = doc! {
-            "$unset": doc!{
-                format!("scores.{}", user_id): ""
-            }
-        };
-
-        let result = users_who_rated.update_one(doc! { "item_id": item_id }, delete_doc, None)?;
-        if result.matched_count.is_zero() || result.modified_count.is_zero() {
-            return Err(
-                ErrorKind::RemoveRatingFailed(user_id.to_string(), item_id.to_string()).into(),
-            );
-        }
-
-        let delete_doc = doc! {
-            "$unset": doc!{
-                format!("scores.{}", item_id): ""
-            }
-        };
-
-        let result = users_ratings.update_one(doc! { "user_id": user_id }, delete_doc, None)?;
-        if result.matched_count.is_zero() || result.modified_count.is_zero() {
-            return Err(
-                ErrorKind::RemoveRatingFailed(user_id.to_string(), item_id.to_string()).into(),
-            );
-        }
+        let rating = self.remove_rating_sql(user_id, item_id)?;
+        self.flush_outbox();
 
-        let old_score: f64 = ratings::table
-            .filter(ratings::user_id.eq(user_id))
-            .filter(ratings::movie_id.eq(item_id))
-            .select(ratings::score)
-            .first(&self.pg_conn)?;
-
-        let psql_result = delete(ratings::table)
-            .filter(ratings::user_id.eq(user_id))
-            .filter(ratings::movie_id.eq(item_id))
-            .get_result(&self.pg_conn);
-
-        match psql_result {
-            Ok(rating) => Ok(rating),
-            Err(e) => {
-                let options = UpdateOptions::builder().upsert(true).build();
-                let update_doc = doc! {
-                    "$set": doc!{
-                        format!("scores.{}", user_id): old_score
-                    }
-                };
-
-                users_who_rated.update_one(doc! { "item_id": item_id }, update_doc, options)?;
-
-                let options = UpdateOptions::builder().upsert(true).build();
-                let update_doc = doc! {
-                    "$set": doc!{
-                        format!("scores.{}", item_id): old_score
-                    }
-                };
-
-                users_ratings.update_one(doc! { "user_id": user_id }, update_doc, options)?;
-
-                Err(e.into())
-            }
-        }
+        Ok(rating)
     }
 
     fn update_rating(
@@ -618,69 +1092,74 @@ impl Controller for SimpleMovieController {
         item_id: &eid!(Self::Item),
         score: f64,
     ) -> Result<Self::Rating, Error> {
-        let users_who_rated = self.mongo_db.collection("users_who_rated");
-        let users_ratings = self.mongo_db.collection("users_ratings");
+        self.check_score(score)?;
 
-        let update_doc = doc! {
-            "$set": doc!{
-                format!("scores.{}", user_id): score
-            }
-        };
+        let rating = self.update_rating_sql(user_id, item_id, score)?;
+        self.flush_outbox();
 
-        let result = users_who_rated.update_one(doc! { "item_id": item_id }, update_doc, None)?;
-        if result.modified_count.is_zero() || result.matched_count.is_zero() {
-            return Err(
-                ErrorKind::UpdateRatingFailed(user_id.to_string(), item_id.to_string()).into(),
-            );
-        }
+        Ok(rating)
+    }
+}
 
-        let update_doc = doc! {
-            "$set": doc!{
-                format!("scores.{}", item_id): score
-            }
-        };
+impl MirroredRatings for SimpleMovieController {
+    fn mirror_db(&self) -> Result<&Database, Error> {
+        self.mongo_db()
+    }
 
-        let result = users_ratings.update_one(doc! { "user_id": user_id }, update_doc, None)?;
-        if result.modified_count.is_zero() || result.matched_count.is_zero() {
-            return Err(
-                ErrorKind::UpdateRatingFailed(user_id.to_string(), item_id.to_string()).into(),
-            );
+    fn mirrored_collections(&self) -> &[RatingCollection] {
+        MONGO_COLLECTIONS
+    }
+
+    fn table_users_ratings(
+        &self,
+        user_ids: &[i32],
+    ) -> Result<MapedRatings<i32, i32, TimedScore>, Error> {
+        let ratings = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::user_id.eq_any(user_ids))
+            .load::<Rating>(conn))?;
+
+        let mut maped_ratings = HashMap::new();
+        for rating in ratings {
+            maped_ratings
+                .entry(rating.user_id)
+                .or_insert_with(HashMap::new)
+                .insert(
+                    rating.movie_id,
+                    TimedScore::new(rating.score, rating.rated_at),
+                );
         }
 
-        let old_score: f64 = ratings::table
-            .filter(ratings::user_id.eq(user_id))
-            .filter(ratings::movie_id.eq(item_id))
-            .select(ratings::score)
-            .first(&self.pg_conn)?;
-
-        let psql_res = update(ratings::table)
-            .filter(ratings::user_id.eq(user_id))
-            .filter(ratings::movie_id.eq(item_id))
-            .set(ratings::score.eq(score))
-            .get_result::<Rating>(&self.pg_conn);
-
-        match psql_res {
-            Ok(rating) => Ok(rating),
-            Err(e) => {
-                let update_doc = doc! {
-                    "$set": doc! {
-                        format!("score.{}", user_id): old_score
-                    }
-                };
+        Ok(maped_ratings)
+    }
 
-                users_who_rated.update_one(doc! { "item_id": item_id }, update_doc, None)?;
+    fn table_items_ratings(
+        &self,
+        item_ids: &[i32],
+    ) -> Result<MapedRatings<i32, i32, TimedScore>, Error> {
+        let ratings = with_conn!(&self.conn, conn => ratings::table
+            .filter(ratings::movie_id.eq_any(item_ids))
+            .load::<Rating>(conn))?;
+
+        let mut maped_ratings = HashMap::new();
+        for rating in ratings {
+            maped_ratings
+                .entry(rating.movie_id)
+                .or_insert_with(HashMap::new)
+                .insert(
+                    rating.user_id,
+                    TimedScore::new(rating.score, rating.rated_at),
+                );
+        }
 
-                let update_doc = doc! {
-                    "$set": doc! {
-                        format!("score.{}", item_id): old_score
-                    }
-                };
+        Ok(maped_ratings)
+    }
 
-                users_ratings.update_one(doc! { "user_id": user_id }, update_doc, None)?;
+    fn user_document_key(&self, user_id: &i32) -> Result<Bson, Error> {
+        Ok((*user_id).into())
+    }
 
-                Err(e.into())
-            }
-        }
+    fn item_document_key(&self, item_id: &i32) -> Result<Bson, Error> {
+        Ok((*item_id).into())
     }
 }
 
@@ -711,3 +1190,160 @@ mod tests {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod sqlite_tests {
+    use super::*;
+    use anyhow::Error;
+    use common_macros::hash_map;
+    use config::DatabaseEntry;
+    use controller::Entity;
+
+    fn sqlite_controller(file: &str) -> Result<SimpleMovieController, Error> {
+        let path = std::env::temp_dir().join(file);
+        let _ = std::fs::remove_file(&path);
+
+        let mut config = Config::default();
+        config.databases.insert(
+            "simple-movie-sqlite".into(),
+            DatabaseEntry {
+                kind: None,
+                backend: Backend::Sqlite,
+                sqlite_path: path.to_string_lossy().into(),
+                psql_url: String::new(),
+                mongo_url: String::new(),
+                mongo_db: String::new(),
+                users_ratings_mongo: false,
+                users_who_rated_mongo: true,
+                dataset: None,
+            },
+        );
+
+        SimpleMovieController::from_config(&config, "simple-movie-sqlite")
+    }
+
+    #[test]
+    fn sqlite_ratings_and_means() -> Result<(), Error> {
+        let controller = sqlite_controller("simple-movie-ratings.db")?;
+
+        let chris =
+            controller.insert_user(hash_map! { "name" => Value::String("Chris".into()) })?;
+        let ana = controller.insert_user(hash_map! { "name" => Value::String("Ana".into()) })?;
+        assert_ne!(chris.get_id(), ana.get_id());
+
+        let alien =
+            controller.insert_item(hash_map! { "name" => Value::String("Alien".into()) })?;
+        let heat = controller.insert_item(hash_map! { "name" => Value::String("Heat".into()) })?;
+
+        controller.insert_rating(&chris.id, &alien.id, 4.)?;
+        controller.insert_rating(&chris.id, &heat.id, 2.)?;
+        controller.insert_rating(&ana.id, &heat.id, 5.)?;
+        assert!(controller.insert_rating(&ana.id, &heat.id, 1.).is_err());
+
+        assert_eq!(controller.users_count()?, 2);
+        assert_eq!(controller.items_count()?, 2);
+        assert_eq!(controller.ratings_count()?, 3);
+
+        let items = controller.items_after(Some(&alien.id), 10)?;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].id, heat.id);
+        assert_eq!(controller.users_by_chunks(1).count(), 2);
+
+        let counts = controller.items_ratings_count(&[alien.clone(), heat.clone()])?;
+        assert_eq!(counts[&alien.id], 1);
+        assert_eq!(counts[&heat.id], 2);
+
+        let users = controller.users_by(&SearchBy::name("Chris"))?;
+        assert_eq!(controller.user_ratings(&users[0])?.len(), 2);
+        assert_eq!(controller.users_means(&users)?[&chris.id], 3.);
+
+        let rating = controller.update_rating(&chris.id, &heat.id, 5.)?;
+        assert_eq!(rating.score, 5.);
+        assert_eq!(controller.users_means(&users)?[&chris.id], 4.5);
+
+        controller.remove_rating(&chris.id, &alien.id)?;
+        assert_eq!(controller.users_means(&users)?[&chris.id], 5.);
+
+        controller.remove_rating(&chris.id, &heat.id)?;
+        assert!(controller.users_means(&users)?.is_empty());
+
+        let who_rated = controller.users_who_rated(std::slice::from_ref(&heat))?;
+        assert_eq!(who_rated[&heat.id].len(), 1);
+        assert_eq!(controller.users_ratings_except(&chris)?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sqlite_update_and_remove() -> Result<(), Error> {
+        let controller = sqlite_controller("simple-movie-crud.db")?;
+
+        let chris =
+            controller.insert_user(hash_map! { "name" => Value::String("Chris".into()) })?;
+        let ana = controller.insert_user(hash_map! { "name" => Value::String("Ana".into()) })?;
+        let alien =
+            controller.insert_item(hash_map! { "name" => Value::String("Alein".into()) })?;
+        let heat = controller.insert_item(hash_map! { "name" => Value::String("Heat".into()) })?;
+
+        controller.insert_rating(&chris.id, &alien.id, 4.)?;
+        controller.insert_rating(&chris.id, &heat.id, 2.)?;
+        controller.insert_rating(&ana.id, &alien.id, 5.)?;
+        controller.insert_rating(&ana.id, &heat.id, 3.)?;
+
+        let proto = hash_map! { "name" => Value::String("Alien".into()) };
+        let updated = controller.update_item(&alien.id, proto.clone())?;
+        assert_eq!(updated.name, "Alien");
+        assert_eq!(
+            controller.items_by(&SearchBy::name("Alien"))?[0].id,
+            alien.id
+        );
+        assert!(controller.update_item(&999, proto).is_err());
+
+        let removed = controller.remove_item(&heat.id)?;
+        assert_eq!(removed.name, "Heat");
+        assert_eq!(controller.items_count()?, 1);
+        assert_eq!(controller.ratings_count()?, 2);
+        assert_eq!(
+            controller.users_means(std::slice::from_ref(&chris))?[&chris.id],
+            4.
+        );
+
+        controller.remove_user(&ana.id)?;
+        assert!(controller.remove_user(&ana.id).is_err());
+        assert_eq!(controller.users_count()?, 1);
+        assert_eq!(controller.ratings_count()?, 1);
+        assert!(controller.users_means(&[ana])?.is_empty());
+
+        let who_rated = controller.users_who_rated(std::slice::from_ref(&alien))?;
+        assert_eq!(who_rated[&alien.id].len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sqlite_name_like_searches() -> Result<(), Error> {
+        let controller = sqlite_controller("simple-movie-names.db")?;
+        for name in &["Johnny", "John", "Ana Johnson", "Peter"] {
+            controller.insert_user(hash_map! { "name" => Value::String(name.to_string()) })?;
+        }
+
+        let names = |mode, name, limit| -> Result<Vec<String>, Error> {
+            let users = controller.users_by(&SearchBy::name_like(mode, name, limit))?;
+            Ok(users.into_iter().map(|user| user.name).collect())
+        };
+
+        assert_eq!(
+            names(NameMatch::Contains, "JOHN", 10)?,
+            vec!["John", "Johnny", "Ana Johnson"]
+        );
+        assert_eq!(
+            names(NameMatch::Contains, "john", 2)?,
+            vec!["John", "Johnny"]
+        );
+        assert_eq!(names(NameMatch::Prefix, "ana", 10)?, vec!["Ana Johnson"]);
+        assert_eq!(names(NameMatch::Fuzzy, "jonny", 10)?[0], "Johnny");
+        assert!(names(NameMatch::Fuzzy, "xyz", 10).is_err());
+
+        Ok(())
+    }
+}
@@ -4,9 +4,7 @@
 // https://opensource.org/licenses/MIT
 
 use crate::schema::movies;
-use common_macros::hash_map;
-use controller::Entity;
-use std::collections::HashMap;
+use controller::{data, Data, Entity, Feature};
 
 // To query data from the database
 #[derive(Debug, Clone, Identifiable, Queryable, Default)]
@@ -15,6 +13,13 @@ pub struct Movie {
     pub name: String,
 }
 
+impl Movie {
+    /// Words in the name of this movie, it's the only content there is
+    pub fn features(&self) -> Vec<Feature> {
+        Feature::words("word", &self.name)
+    }
+}
+
 impl Entity for Movie {
     type Id = i32;
 
@@ -22,15 +27,15 @@ impl Entity for Movie {
         self.id
     }
 
-    fn get_data(&self) -> HashMap<String, String> {
-        hash_map! {
-            "name".into() => self.name.clone()
+    fn get_data(&self) -> Data {
+        data! {
+            "name" => self.name.clone(),
         }
     }
 }
 
 // To insert a new movie into the database
-#[derive(Debug, Clone, Insertable)]
+#[derive(Debug, Clone, Insertable, AsChangeset)]
 #[table_name = "movies"]
 pub struct NewMovie<'a> {
     pub name: &'a str,
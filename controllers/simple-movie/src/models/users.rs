@@ -4,9 +4,7 @@
 // https://opensource.org/licenses/MIT
 
 use crate::schema::{means, users};
-use common_macros::hash_map;
-use controller::Entity;
-use std::collections::HashMap;
+use controller::{data, Data, Entity};
 
 // To query data from the database
 #[derive(Debug, Clone, Identifiable, Queryable, Default)]
@@ -23,14 +21,14 @@ impl Entity for User {
         self.id
     }
 
-    fn get_data(&self) -> HashMap<String, String> {
-        hash_map! {
-            "name".into() => self.name.clone(),
+    fn get_data(&self) -> Data {
+        data! {
+            "name" => self.name.clone(),
         }
     }
 }
 
-#[derive(Debug, Clone, Insertable)]
+#[derive(Debug, Clone, Insertable, AsChangeset)]
 #[table_name = "users"]
 pub struct NewUser<'a> {
     pub name: &'a str,
@@ -1,3 +1,19 @@
+table! {
+    global_mean (id) {
+        id -> Int4,
+        val -> Float8,
+        score_number -> Int8,
+    }
+}
+
+table! {
+    item_means (item_id) {
+        item_id -> Int4,
+        val -> Float8,
+        score_number -> Int4,
+    }
+}
+
 table! {
     means (user_id) {
         user_id -> Int4,
@@ -13,12 +29,23 @@ table! {
     }
 }
 
+table! {
+    rating_outbox (id) {
+        id -> Int8,
+        user_id -> Int4,
+        movie_id -> Int4,
+        score -> Nullable<Float8>,
+        rated_at -> Nullable<Int8>,
+    }
+}
+
 table! {
     ratings (id) {
         id -> Int4,
         user_id -> Int4,
         movie_id -> Int4,
         score -> Float8,
+        rated_at -> Nullable<Int8>,
     }
 }
 
@@ -29,13 +56,17 @@ table! {
     }
 }
 
+joinable!(item_means -> movies (item_id));
 joinable!(means -> users (user_id));
 joinable!(ratings -> movies (movie_id));
 joinable!(ratings -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
+    global_mean,
+    item_means,
     means,
     movies,
+    rating_outbox,
     ratings,
     users,
 );
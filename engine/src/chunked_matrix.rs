@@ -6,6 +6,7 @@
 use crate::{
     distances::items::{slope_one, AdjCosine},
     error::ErrorKind,
+    implicit,
 };
 use anyhow::Error;
 use config::Config;
@@ -22,12 +23,28 @@ where
     C: Controller<Item = I>,
     I: Entity,
 {
-    fn approximate_chunk_size(&self) -> usize;
-    fn optimize_chunks_size(&mut self);
+    fn chunks_size(&self) -> (usize, usize);
+    fn approximate_chunk_size(&self) -> Result<usize, Error>;
+    fn optimize_chunks_size(&mut self) -> Result<(), Error>;
     fn calculate_chunk(&mut self, i: usize, j: usize) -> Result<(), Error>;
     fn get_value(&self, id_a: &eid!(I), id_b: &eid!(I)) -> Option<f64>;
 }
 
+/// Approximate how many values are held in memory to calculate a chunk of `m` x `n`
+/// items, that's the ratings of both groups of items plus the values of the chunk
+fn approximate_size<C: Controller>(controller: &C, m: usize, n: usize) -> Result<usize, Error> {
+    let items_count = controller.items_count()?;
+    if items_count == 0 {
+        return Ok(0);
+    }
+
+    let ratings_per_item = controller.ratings_count()? as f64 / items_count as f64;
+    let m = m.min(items_count);
+    let n = n.min(items_count);
+
+    Ok(((m + n) as f64 * ratings_per_item) as usize + m * n)
+}
+
 pub struct SimilarityMatrix<'a, C, U, I>
 where
     C: Controller<User = U, Item = I>,
@@ -97,52 +114,60 @@ where
     eid!(U): Hash + Eq + Clone + Default,
     eid!(I): Hash + Eq + Clone,
 {
-    fn approximate_chunk_size(&self) -> usize {
-        todo!("Implement for each controller a 'counter' method for ratings")
+    fn chunks_size(&self) -> (usize, usize) {
+        (self.ver_chunk_size, self.hor_chunk_size)
+    }
+
+    fn approximate_chunk_size(&self) -> Result<usize, Error> {
+        approximate_size(self.controller, self.ver_chunk_size, self.hor_chunk_size)
     }
 
-    fn optimize_chunks_size(&mut self) {
+    fn optimize_chunks_size(&mut self) -> Result<(), Error> {
         if !self.config.matrix.allow_chunk_optimization {
-            return;
+            return Ok(());
         }
 
+        // The threshold is relative to the size of the whole matrix
         let threshold = self.config.matrix.chunk_size_threshold;
-        let original_size = self.approximate_chunk_size();
+        let items_count = self.controller.items_count()?;
+        let original_size = approximate_size(self.controller, items_count, items_count)?;
         let target_size = (original_size as f64 * threshold) as usize;
 
-        while self.approximate_chunk_size() > target_size {
-            self.ver_chunk_size /= 2;
-            self.hor_chunk_size /= 2;
+        while self.approximate_chunk_size()? > target_size
+            && (self.ver_chunk_size > 1 || self.hor_chunk_size > 1)
+        {
+            self.ver_chunk_size = (self.ver_chunk_size / 2).max(1);
+            self.hor_chunk_size = (self.hor_chunk_size / 2).max(1);
 
             self.ver_iter = self.controller.items_by_chunks(self.ver_chunk_size);
             self.hor_iter = self.controller.items_by_chunks(self.hor_chunk_size);
         }
+
+        Ok(())
     }
 
     fn calculate_chunk(&mut self, i: usize, j: usize) -> Result<(), Error> {
-        let ver_items = self
-            .ver_iter
-            .nth(i)
-            .ok_or_else(|| ErrorKind::IndexOutOfBound)?;
-
-        let hor_items = self
-            .hor_iter
-            .nth(j)
-            .ok_or_else(|| ErrorKind::IndexOutOfBound)?;
-
-        let ver_items_users: maped_ratings!(I => U) = self
-            .controller
-            .users_who_rated(&ver_items)?
-            .into_iter()
-            .filter(|(_, ratings)| !ratings.is_empty())
-            .collect();
-
-        let hor_items_users: maped_ratings!(I => U) = self
-            .controller
-            .users_who_rated(&hor_items)?
-            .into_iter()
-            .filter(|(_, ratings)| !ratings.is_empty())
-            .collect();
+        let ver_items = self.ver_iter.nth(i).ok_or(ErrorKind::IndexOutOfBound)??;
+
+        let hor_items = self.hor_iter.nth(j).ok_or(ErrorKind::IndexOutOfBound)??;
+
+        let ver_items_users: maped_ratings!(I => U) = implicit::adjust_maped_ratings(
+            self.controller,
+            self.config.engine.implicit_ratings,
+            self.controller.users_who_rated(&ver_items)?,
+        )
+        .into_iter()
+        .filter(|(_, ratings)| !ratings.is_empty())
+        .collect();
+
+        let hor_items_users: maped_ratings!(I => U) = implicit::adjust_maped_ratings(
+            self.controller,
+            self.config.engine.implicit_ratings,
+            self.controller.users_who_rated(&hor_items)?,
+        )
+        .into_iter()
+        .filter(|(_, ratings)| !ratings.is_empty())
+        .collect();
 
         let all_users_iter = ver_items_users.values().chain(hor_items_users.values());
         let mut all_users = HashSet::new();
@@ -165,7 +190,11 @@ where
 
         let partial_users_chunk_size = self.config.matrix.partial_users_chunk_size;
         for partial_users_chunk in all_partial_users.chunks(partial_users_chunk_size) {
-            let mean_chunk = self.controller.users_means(partial_users_chunk)?;
+            let mean_chunk = implicit::users_means(
+                self.controller,
+                self.config.engine.implicit_ratings,
+                partial_users_chunk,
+            )?;
             self.adj_cosine.borrow_mut().push_means(&mean_chunk);
         }
 
@@ -261,52 +290,60 @@ where
     eid!(U): Hash + Eq,
     eid!(I): Hash + Eq + Clone,
 {
-    fn approximate_chunk_size(&self) -> usize {
-        todo!("Implement for each controller a 'counter' method for ratings")
+    fn chunks_size(&self) -> (usize, usize) {
+        (self.ver_chunk_size, self.hor_chunk_size)
+    }
+
+    fn approximate_chunk_size(&self) -> Result<usize, Error> {
+        approximate_size(self.controller, self.ver_chunk_size, self.hor_chunk_size)
     }
 
-    fn optimize_chunks_size(&mut self) {
+    fn optimize_chunks_size(&mut self) -> Result<(), Error> {
         if !self.config.matrix.allow_chunk_optimization {
-            return;
+            return Ok(());
         }
 
+        // The threshold is relative to the size of the whole matrix
         let threshold = self.config.matrix.chunk_size_threshold;
-        let original_size = self.approximate_chunk_size();
+        let items_count = self.controller.items_count()?;
+        let original_size = approximate_size(self.controller, items_count, items_count)?;
         let target_size = (original_size as f64 * threshold) as usize;
 
-        while self.approximate_chunk_size() > target_size {
-            self.ver_chunk_size /= 2;
-            self.hor_chunk_size /= 2;
+        while self.approximate_chunk_size()? > target_size
+            && (self.ver_chunk_size > 1 || self.hor_chunk_size > 1)
+        {
+            self.ver_chunk_size = (self.ver_chunk_size / 2).max(1);
+            self.hor_chunk_size = (self.hor_chunk_size / 2).max(1);
 
             self.ver_iter = self.controller.items_by_chunks(self.ver_chunk_size);
             self.hor_iter = self.controller.items_by_chunks(self.hor_chunk_size);
         }
+
+        Ok(())
     }
 
     fn calculate_chunk(&mut self, i: usize, j: usize) -> Result<(), Error> {
-        let ver_items = self
-            .ver_iter
-            .nth(i)
-            .ok_or_else(|| ErrorKind::IndexOutOfBound)?;
-
-        let hor_items = self
-            .hor_iter
-            .nth(j)
-            .ok_or_else(|| ErrorKind::IndexOutOfBound)?;
-
-        let ver_items_users: maped_ratings!(I => U) = self
-            .controller
-            .users_who_rated(&ver_items)?
-            .into_iter()
-            .filter(|(_, ratings)| !ratings.is_empty())
-            .collect();
-
-        let hor_items_users: maped_ratings!(I => U) = self
-            .controller
-            .users_who_rated(&hor_items)?
-            .into_iter()
-            .filter(|(_, ratings)| !ratings.is_empty())
-            .collect();
+        let ver_items = self.ver_iter.nth(i).ok_or(ErrorKind::IndexOutOfBound)??;
+
+        let hor_items = self.hor_iter.nth(j).ok_or(ErrorKind::IndexOutOfBound)??;
+
+        let ver_items_users: maped_ratings!(I => U) = implicit::adjust_maped_ratings(
+            self.controller,
+            self.config.engine.implicit_ratings,
+            self.controller.users_who_rated(&ver_items)?,
+        )
+        .into_iter()
+        .filter(|(_, ratings)| !ratings.is_empty())
+        .collect();
+
+        let hor_items_users: maped_ratings!(I => U) = implicit::adjust_maped_ratings(
+            self.controller,
+            self.config.engine.implicit_ratings,
+            self.controller.users_who_rated(&hor_items)?,
+        )
+        .into_iter()
+        .filter(|(_, ratings)| !ratings.is_empty())
+        .collect();
 
         let mut matrix = HashMap::new();
         for (item_a, item_a_ratings) in ver_items_users.into_iter() {
@@ -30,4 +30,7 @@ pub enum ErrorKind {
 
     #[error("Indices out of bounds")]
     IndexOutOfBound,
+
+    #[error("The matrix factorization model hasn't been trained yet")]
+    UntrainedModel,
 }
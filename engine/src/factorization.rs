@@ -0,0 +1,265 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::error::ErrorKind;
+use anyhow::Error;
+use config::FactorizationConfig;
+use controller::{MapedRatings, Ratings};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{cmp::Ordering, collections::HashMap, hash::Hash};
+
+/// Ratings a model is trained with, users and items are given an index as their
+/// ratings are pushed so ratings can be gathered by chunks
+#[derive(Debug, Clone)]
+pub struct TrainingSet<U, I> {
+    users: HashMap<U, usize>,
+    items: HashMap<I, usize>,
+    ratings: Vec<(usize, usize, f64)>,
+}
+
+impl<U, I> Default for TrainingSet<U, I> {
+    fn default() -> Self {
+        Self {
+            users: HashMap::new(),
+            items: HashMap::new(),
+            ratings: Vec::new(),
+        }
+    }
+}
+
+impl<U, I> TrainingSet<U, I>
+where
+    U: Hash + Eq,
+    I: Hash + Eq,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, maped_ratings: MapedRatings<U, I>) {
+        for (user_id, ratings) in maped_ratings {
+            let next = self.users.len();
+            let user = *self.users.entry(user_id).or_insert(next);
+
+            for (item_id, score) in ratings {
+                let next = self.items.len();
+                let item = *self.items.entry(item_id).or_insert(next);
+                self.ratings.push((user, item, score));
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ratings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ratings.is_empty()
+    }
+}
+
+/// Latent factors model of the ratings (biased matrix factorization, also known as
+/// Funk SVD), a score is predicted as the global mean plus the user and item biases
+/// plus the dot product of their factors
+#[derive(Debug, Clone)]
+pub struct MatrixFactorization<U, I> {
+    factors: usize,
+    global_mean: f64,
+    rmse: f64,
+
+    users: HashMap<U, usize>,
+    items: HashMap<I, usize>,
+
+    user_biases: Vec<f64>,
+    item_biases: Vec<f64>,
+
+    // Factors of every user (or item) one after the other
+    user_factors: Vec<f64>,
+    item_factors: Vec<f64>,
+}
+
+impl<U, I> MatrixFactorization<U, I>
+where
+    U: Hash + Eq,
+    I: Hash + Eq + Clone,
+{
+    /// Train a model with stochastic gradient descent, ratings are visited in a
+    /// different (seeded) order on every epoch
+    pub fn train(set: TrainingSet<U, I>, config: &FactorizationConfig) -> Result<Self, Error> {
+        if set.is_empty() {
+            return Err(ErrorKind::EmptyRatings.into());
+        }
+
+        let k = config.factors;
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let mut init =
+            |len: usize| -> Vec<f64> { (0..len).map(|_| rng.gen_range(-0.1, 0.1)).collect() };
+
+        let mut model = Self {
+            factors: k,
+            global_mean: set.ratings.iter().map(|(_, _, score)| score).sum::<f64>()
+                / set.len() as f64,
+            rmse: 0.0,
+            user_biases: vec![0.0; set.users.len()],
+            item_biases: vec![0.0; set.items.len()],
+            user_factors: init(set.users.len() * k),
+            item_factors: init(set.items.len() * k),
+            users: set.users,
+            items: set.items,
+        };
+
+        let (lr, reg) = (config.learning_rate, config.regularization);
+        let mut ratings = set.ratings;
+
+        for epoch in 0..config.epochs {
+            ratings.shuffle(&mut rng);
+
+            let mut squared_error = 0.0;
+            for &(user, item, score) in &ratings {
+                let err = score - model.score(user, item);
+                squared_error += err * err;
+
+                model.user_biases[user] += lr * (err - reg * model.user_biases[user]);
+                model.item_biases[item] += lr * (err - reg * model.item_biases[item]);
+
+                for f in 0..k {
+                    let pu = model.user_factors[user * k + f];
+                    let qi = model.item_factors[item * k + f];
+
+                    model.user_factors[user * k + f] += lr * (err * qi - reg * pu);
+                    model.item_factors[item * k + f] += lr * (err * pu - reg * qi);
+                }
+            }
+
+            model.rmse = (squared_error / ratings.len() as f64).sqrt();
+            log::info!(
+                "Epoch {} finished with a training rmse of {}",
+                epoch + 1,
+                model.rmse
+            );
+        }
+
+        Ok(model)
+    }
+
+    fn score(&self, user: usize, item: usize) -> f64 {
+        let k = self.factors;
+        let user_factors = &self.user_factors[user * k..(user + 1) * k];
+        let item_factors = &self.item_factors[item * k..(item + 1) * k];
+
+        let dot: f64 = user_factors
+            .iter()
+            .zip(item_factors)
+            .map(|(pu, qi)| pu * qi)
+            .sum();
+
+        self.global_mean + self.user_biases[user] + self.item_biases[item] + dot
+    }
+
+    /// Root mean squared error over the training ratings during the last epoch
+    pub fn rmse(&self) -> f64 {
+        self.rmse
+    }
+
+    /// Predict the score of an user for an item, unknown users or items (not seen
+    /// while training) fall back to the biases that are known
+    pub fn predict(&self, user_id: &U, item_id: &I) -> f64 {
+        match (self.users.get(user_id), self.items.get(item_id)) {
+            (Some(&user), Some(&item)) => self.score(user, item),
+            (Some(&user), None) => self.global_mean + self.user_biases[user],
+            (None, Some(&item)) => self.global_mean + self.item_biases[item],
+            (None, None) => self.global_mean,
+        }
+    }
+
+    /// The `n` items with the greatest predicted scores for an user, leaving out the
+    /// items in `rated`
+    pub fn top_n(&self, user_id: &U, n: usize, rated: &Ratings<I>) -> Vec<(I, f64)> {
+        let mut scores: Vec<_> = self
+            .items
+            .keys()
+            .filter(|item_id| !rated.contains_key(item_id))
+            .map(|item_id| (item_id.clone(), self.predict(user_id, item_id)))
+            .collect();
+
+        scores.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+        scores.truncate(n);
+        scores
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::*;
+    use common_macros::hash_map;
+
+    fn training_set() -> TrainingSet<i32, i32> {
+        let mut set = TrainingSet::new();
+        set.push(hash_map! {
+            1 => hash_map! { 10 => 5., 20 => 4., 30 => 1. },
+            2 => hash_map! { 10 => 4., 20 => 5., 40 => 1. },
+        });
+
+        set.push(hash_map! {
+            3 => hash_map! { 10 => 1., 30 => 5., 40 => 4. },
+            4 => hash_map! { 20 => 1., 30 => 4., 40 => 5. },
+        });
+
+        set
+    }
+
+    fn config() -> FactorizationConfig {
+        FactorizationConfig {
+            factors: 2,
+            learning_rate: 0.05,
+            regularization: 0.01,
+            epochs: 500,
+            ..FactorizationConfig::default()
+        }
+    }
+
+    #[test]
+    fn fits_the_training_ratings() -> Result<(), Error> {
+        let set = training_set();
+        assert_eq!(set.len(), 12);
+
+        let model = MatrixFactorization::train(set, &config())?;
+        assert!(model.rmse() < 0.2);
+        assert_approx_eq!(model.predict(&1, &10), 5., 0.5);
+        assert_approx_eq!(model.predict(&4, &20), 1., 0.5);
+
+        // Users 1 and 2 like the same items, as do users 3 and 4
+        assert!(model.predict(&1, &40) < 3.);
+        assert!(model.predict(&2, &30) < 3.);
+        assert!(model.predict(&3, &20) < 3.);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_users_and_items() -> Result<(), Error> {
+        let model = MatrixFactorization::train(training_set(), &config())?;
+        assert_approx_eq!(model.predict(&99, &99), 40. / 12.);
+        assert!(model.predict(&99, &10).is_finite());
+
+        assert!(MatrixFactorization::<i32, i32>::train(TrainingSet::new(), &config()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn top_n_leaves_rated_items_out() -> Result<(), Error> {
+        let model = MatrixFactorization::train(training_set(), &config())?;
+
+        let top = model.top_n(&1, 5, &hash_map! { 10 => 5., 20 => 4. });
+        let ids: Vec<_> = top.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(!ids.contains(&10) && !ids.contains(&20));
+        assert!(top[0].1 >= top[1].1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use anyhow::Error;
+use config::ImplicitRatings;
+use controller::{eid, Controller, Entity, MapedRatings, Means, RatingKind, Ratings};
+use std::{collections::HashMap, hash::Hash};
+
+/// The score a rating is taken with under `policy`, `None` if it's left out
+pub fn adjusted_score(
+    policy: ImplicitRatings,
+    kind: RatingKind,
+    score: f64,
+    (min, max): (f64, f64),
+) -> Option<f64> {
+    match (kind, policy) {
+        (RatingKind::Implicit, ImplicitRatings::Exclude) => None,
+        (RatingKind::Implicit, ImplicitRatings::Weight(weight)) => Some(min + weight * (max - min)),
+        _ => Some(score),
+    }
+}
+
+/// Apply the implicit ratings `policy` to some ratings gathered from `controller`
+pub fn adjust_ratings<C, K>(
+    controller: &C,
+    policy: ImplicitRatings,
+    ratings: Ratings<K>,
+) -> Ratings<K>
+where
+    C: Controller,
+    K: Hash + Eq,
+{
+    if policy == ImplicitRatings::Include {
+        return ratings;
+    }
+
+    let range = controller.score_range();
+    ratings
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let kind = controller.rating_kind(score);
+            Some((id, adjusted_score(policy, kind, score, range)?))
+        })
+        .collect()
+}
+
+/// Same as `adjust_ratings` for maped ratings, owners whose ratings are all left
+/// out are kept with no ratings
+pub fn adjust_maped_ratings<C, K, I>(
+    controller: &C,
+    policy: ImplicitRatings,
+    maped_ratings: MapedRatings<K, I>,
+) -> MapedRatings<K, I>
+where
+    C: Controller,
+    K: Hash + Eq,
+    I: Hash + Eq,
+{
+    if policy == ImplicitRatings::Include {
+        return maped_ratings;
+    }
+
+    maped_ratings
+        .into_iter()
+        .map(|(id, ratings)| (id, adjust_ratings(controller, policy, ratings)))
+        .collect()
+}
+
+/// Means of the specified users under `policy`, the stored means already include
+/// every rating so they're computed again from the adjusted ratings otherwise
+pub fn users_means<C, U>(
+    controller: &C,
+    policy: ImplicitRatings,
+    users: &[U],
+) -> Result<Means<eid!(U)>, Error>
+where
+    C: Controller<User = U>,
+    U: Entity,
+    eid!(U): Hash + Eq,
+{
+    if policy == ImplicitRatings::Include {
+        return controller.users_means(users);
+    }
+
+    let range = controller.score_range();
+    let mut means = HashMap::new();
+
+    for (user_id, ratings) in controller.users_ratings(users)? {
+        let scores: Vec<_> = ratings
+            .values()
+            .filter_map(|&score| {
+                let kind = controller.rating_kind(score);
+                adjusted_score(policy, kind, score, range)
+            })
+            .collect();
+
+        if !scores.is_empty() {
+            let mean = scores.iter().sum::<f64>() / scores.len() as f64;
+            means.insert(user_id, mean);
+        }
+    }
+
+    Ok(means)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn implicit_scores_by_policy() {
+        let range = (0., 10.);
+
+        let explicit = adjusted_score(ImplicitRatings::Exclude, RatingKind::Explicit, 7., range);
+        assert_eq!(explicit, Some(7.));
+
+        let explicit = adjusted_score(
+            ImplicitRatings::Weight(0.2),
+            RatingKind::Explicit,
+            10.,
+            range,
+        );
+        assert_eq!(explicit, Some(10.));
+
+        let implicit = |policy| adjusted_score(policy, RatingKind::Implicit, 0., range);
+        assert_eq!(implicit(ImplicitRatings::Include), Some(0.));
+        assert_eq!(implicit(ImplicitRatings::Exclude), None);
+        assert_eq!(implicit(ImplicitRatings::Weight(0.7)), Some(7.));
+    }
+}
@@ -6,6 +6,8 @@
 pub mod chunked_matrix;
 pub mod distances;
 pub mod error;
+pub mod factorization;
+pub mod implicit;
 pub mod knn;
 pub mod maped_distance;
 pub mod utils;
@@ -15,10 +17,11 @@ use crate::{
     maped_distance::MapedDistance,
 };
 use anyhow::Error;
-use config::Config;
-use controller::{eid, maped_ratings, Controller, Entity, Ratings};
+use config::{Config, ImplicitRatings};
+use controller::{eid, maped_ratings, means, ratings, Controller, Entity, Ratings};
 use distances::items::{denormalize_user_rating, normalize_user_ratings, slope_one, AdjCosine};
 use error::ErrorKind;
+use factorization::{MatrixFactorization, TrainingSet};
 use knn::{Knn, MaxHeapKnn, MinHeapKnn};
 use num_traits::Zero;
 use std::cell::RefCell;
@@ -36,6 +39,7 @@ where
     controller: &'a C,
 
     adj_cosine: Rc<RefCell<AdjCosine<eid!(U), f64>>>,
+    factorization: Option<MatrixFactorization<eid!(U), eid!(I)>>,
 
     user_type: PhantomData<U>,
     item_type: PhantomData<I>,
@@ -54,6 +58,7 @@ where
             config,
             controller,
             adj_cosine: Rc::new(RefCell::new(AdjCosine::new())),
+            factorization: None,
             user_type: PhantomData,
             item_type: PhantomData,
         }
@@ -71,9 +76,62 @@ where
         Rc::clone(&self.adj_cosine)
     }
 
+    fn user_ratings(&self, user: &U) -> Result<ratings!(I), Error> {
+        let ratings = self.controller.user_ratings(user)?;
+        Ok(implicit::adjust_ratings(
+            self.controller,
+            self.implicit_ratings(),
+            ratings,
+        ))
+    }
+
+    fn users_ratings(&self, users: &[U]) -> Result<maped_ratings!(U => I), Error> {
+        let maped_ratings = self.controller.users_ratings(users)?;
+        Ok(implicit::adjust_maped_ratings(
+            self.controller,
+            self.implicit_ratings(),
+            maped_ratings,
+        ))
+    }
+
+    fn users_ratings_except(&self, user: &U) -> Result<maped_ratings!(U => I), Error> {
+        let maped_ratings = self.controller.users_ratings_except(user)?;
+        Ok(implicit::adjust_maped_ratings(
+            self.controller,
+            self.implicit_ratings(),
+            maped_ratings,
+        ))
+    }
+
+    fn users_who_rated(&self, items: &[I]) -> Result<maped_ratings!(I => U), Error> {
+        let maped_ratings = self.controller.users_who_rated(items)?;
+        Ok(implicit::adjust_maped_ratings(
+            self.controller,
+            self.implicit_ratings(),
+            maped_ratings,
+        ))
+    }
+
+    fn users_means(&self, users: &[U]) -> Result<means!(U), Error> {
+        implicit::users_means(self.controller, self.implicit_ratings(), users)
+    }
+
+    fn implicit_ratings(&self) -> ImplicitRatings {
+        self.config.engine.implicit_ratings
+    }
+
+    /// Snap a prediction onto the rating scale if the config asks so
+    fn prediction(&self, predicted: f64) -> f64 {
+        if self.config.engine.snap_predictions {
+            self.controller.rating_scale().snap(predicted)
+        } else {
+            predicted
+        }
+    }
+
     pub fn user_distance(&self, user_a: U, user_b: U, method: UserMethod) -> Result<f64, Error> {
-        let rating_a = self.controller.user_ratings(&user_a)?;
-        let rating_b = self.controller.user_ratings(&user_b)?;
+        let rating_a = self.user_ratings(&user_a)?;
+        let rating_b = self.user_ratings(&user_b)?;
 
         distances::users::distance(&rating_a, &rating_b, method).map_err(Into::into)
     }
@@ -89,7 +147,7 @@ where
                 let item_a_id = item_a.get_id();
                 let item_b_id = item_b.get_id();
 
-                let users_who_rated = self.controller.users_who_rated(&[item_a, item_b])?;
+                let users_who_rated = self.users_who_rated(&[item_a, item_b])?;
 
                 let all_users_iter = users_who_rated.values();
                 let mut all_users = HashSet::new();
@@ -111,7 +169,7 @@ where
 
                 let partial_users_chunk_size = self.config.engine.partial_users_chunk_size;
                 for partial_users_chunk in all_partial_users.chunks(partial_users_chunk_size) {
-                    let mean_chunk = self.controller.users_means(partial_users_chunk)?;
+                    let mean_chunk = self.users_means(partial_users_chunk)?;
                     self.adj_cosine.borrow_mut().push_means(&mean_chunk);
                 }
 
@@ -126,7 +184,7 @@ where
             ItemMethod::SlopeOne => {
                 let item_a_id = item_a.get_id();
                 let item_b_id = item_b.get_id();
-                let users_who_rated = self.controller.users_who_rated(&[item_a, item_b])?;
+                let users_who_rated = self.users_who_rated(&[item_a, item_b])?;
                 let (dev, _) =
                     slope_one(&users_who_rated[&item_a_id], &users_who_rated[&item_b_id])?;
 
@@ -146,7 +204,7 @@ where
             return Err(ErrorKind::EmptyKNearestNeighbors.into());
         }
 
-        let user_ratings = self.controller.user_ratings(&user)?;
+        let user_ratings = self.user_ratings(&user)?;
         let mut knn: Box<dyn Knn<eid!(U), eid!(I)>> = if method.is_similarity() {
             Box::new(MinHeapKnn::new(k, method))
         } else {
@@ -156,11 +214,11 @@ where
         if let Some(chunk_size) = chunk_size {
             let users_chunks = self.controller.users_by_chunks(chunk_size);
             for users in users_chunks {
-                let maped_ratings = self.controller.users_ratings(&users)?;
+                let maped_ratings = self.users_ratings(&users?)?;
                 knn.update(&user_ratings, maped_ratings);
             }
         } else {
-            let maped_ratings = self.controller.users_ratings_except(&user)?;
+            let maped_ratings = self.users_ratings_except(&user)?;
             knn.update(&user_ratings, maped_ratings);
         }
 
@@ -186,7 +244,7 @@ where
         chunk_size: Option<usize>,
     ) -> Result<f64, Error> {
         let item_id = item.get_id();
-        let user_ratings = self.controller.user_ratings(&user)?;
+        let user_ratings = self.user_ratings(&user)?;
 
         let mut knn: Box<dyn Knn<eid!(U), eid!(I)>> = if method.is_similarity() {
             Box::new(MinHeapKnn::new(k, method))
@@ -198,8 +256,7 @@ where
             let users_chunks = self.controller.users_by_chunks(chunk_size);
             for users in users_chunks {
                 let maped_ratings = self
-                    .controller
-                    .users_ratings(&users)?
+                    .users_ratings(&users?)?
                     .into_iter()
                     .filter(|(_, ratings)| ratings.contains_key(&item_id))
                     .collect();
@@ -208,7 +265,6 @@ where
             }
         } else {
             let maped_ratings = self
-                .controller
                 .users_ratings_except(&user)?
                 .into_iter()
                 .filter(|(_id, ratings)| ratings.contains_key(&item_id))
@@ -250,7 +306,9 @@ where
             *prediction.get_or_insert(0.0) += nn_rating * (maped_distance.dist() / total);
         }
 
-        prediction.ok_or_else(|| ErrorKind::EmptyKNearestNeighbors.into())
+        prediction
+            .map(|predicted| self.prediction(predicted))
+            .ok_or_else(|| ErrorKind::EmptyKNearestNeighbors.into())
     }
 
     fn adj_cosine_predict(&self, user: U, item: I, chunk_size: usize) -> Result<f64, Error> {
@@ -264,13 +322,13 @@ where
         );
 
         log::info!("Gathering user({:?}) ratings", user_id);
-        let user_ratings = self.controller.user_ratings(&user)?;
+        let user_ratings = self.user_ratings(&user)?;
         let (min_rating, max_rating) = self.controller.score_range();
         log::info!("Normalizing user({:?}) ratings", user_id);
         let normalized_ratings = normalize_user_ratings(&user_ratings, min_rating, max_rating)?;
 
         log::info!("Gathering users who rated for target item");
-        let target_items_users = self.controller.users_who_rated(&[item])?;
+        let target_items_users = self.users_who_rated(&[item])?;
         log::info!(
             "Gathered {} scores for this item",
             target_items_users[&item_id].len()
@@ -288,6 +346,7 @@ where
         log::info!("Iterating items by chunks of size {}", chunk_size);
         let items_chunks = self.controller.items_by_chunks(chunk_size);
         for item_chunk_base in items_chunks {
+            let item_chunk_base = item_chunk_base?;
             log::info!("Initial chunk size: {}", item_chunk_base.len());
             let now = Instant::now();
             let item_chunk: Vec<_> = item_chunk_base
@@ -307,7 +366,6 @@ where
             log::info!("Gathering users who rated for {} items", item_chunk.len());
             let now = Instant::now();
             let mut users_who_rated: maped_ratings!(I => U) = self
-                .controller
                 .users_who_rated(&item_chunk)?
                 .into_iter()
                 .filter(|(_, ratings)| ratings.contains_key(&user_id))
@@ -349,7 +407,7 @@ where
             let now = Instant::now();
             let partial_users_chunk_size = self.config.engine.partial_users_chunk_size;
             for partial_users_chunk in all_partial_users.chunks(partial_users_chunk_size) {
-                let mean_chunk = self.controller.users_means(partial_users_chunk)?;
+                let mean_chunk = self.users_means(partial_users_chunk)?;
                 adj_cosine.push_means(&mean_chunk);
             }
             let mean_time = now.elapsed().as_secs_f64();
@@ -389,15 +447,15 @@ where
         }
 
         log::info!("Denormalizing the final score");
-        Ok(denormalize_user_rating(num / dem, min_rating, max_rating)?)
+        let predicted = denormalize_user_rating(num / dem, min_rating, max_rating)?;
+        Ok(self.prediction(predicted))
     }
 
     pub fn slope_one_predict(&self, user: U, item: I, chunk_size: usize) -> Result<f64, Error> {
         let target_item_id = item.get_id();
-        let target_item_ratings = &self.controller.users_who_rated(&[item])?[&target_item_id];
+        let target_item_ratings = &self.users_who_rated(&[item])?[&target_item_id];
 
         let user_ratings: Ratings<_, _> = self
-            .controller
             .user_ratings(&user)?
             .into_iter()
             .filter(|(id, _)| id != &target_item_id)
@@ -410,7 +468,7 @@ where
         let mut den = 0.0;
 
         for partial_items_chunk in all_partial_items.chunks(chunk_size) {
-            let users_who_rated = self.controller.users_who_rated(partial_items_chunk)?;
+            let users_who_rated = self.users_who_rated(partial_items_chunk)?;
             for (item_id, ratings) in users_who_rated {
                 if let Ok((dev, card)) = slope_one(target_item_ratings, &ratings) {
                     num += (dev + user_ratings[&item_id]) * card as f64;
@@ -422,7 +480,7 @@ where
         if den.is_zero() {
             Err(ErrorKind::DivisionByZero.into())
         } else {
-            Ok(num / den)
+            Ok(self.prediction(num / den))
         }
     }
 
@@ -438,6 +496,68 @@ where
             ItemMethod::SlopeOne => self.slope_one_predict(user, item, chunk_size),
         }
     }
+
+    /// Train the matrix factorization model with the ratings of every user, read
+    /// by chunks if configured so. The model isn't updated along with the ratings,
+    /// it must be trained again to take new ratings into account. Returns the rmse
+    /// over the training ratings
+    pub fn train_factorization(&mut self) -> Result<f64, Error> {
+        let config = &self.config.engine.factorization;
+        let mut set = TrainingSet::new();
+
+        match config.users_chunk_size {
+            Some(chunk_size) => {
+                for users in self.controller.users_by_chunks(chunk_size) {
+                    set.push(self.users_ratings(&users?)?);
+                }
+            }
+
+            None => set.push(implicit::adjust_maped_ratings(
+                self.controller,
+                self.implicit_ratings(),
+                self.controller.all_users_ratings()?,
+            )),
+        }
+
+        log::info!("Training with {} ratings", set.len());
+        let model = MatrixFactorization::train(set, config)?;
+        let rmse = model.rmse();
+
+        self.factorization = Some(model);
+        Ok(rmse)
+    }
+
+    fn trained_factorization(&self) -> Result<&MatrixFactorization<eid!(U), eid!(I)>, Error> {
+        self.factorization
+            .as_ref()
+            .ok_or_else(|| ErrorKind::UntrainedModel.into())
+    }
+
+    /// Scores predicted by the model can lie outside the score range
+    fn factorization_prediction(&self, predicted: f64) -> f64 {
+        let (min, max) = self.controller.score_range();
+        self.prediction(predicted.max(min).min(max))
+    }
+
+    pub fn factorization_predict(&self, user: U, item: I) -> Result<f64, Error> {
+        let model = self.trained_factorization()?;
+        let predicted = model.predict(&user.get_id(), &item.get_id());
+
+        Ok(self.factorization_prediction(predicted))
+    }
+
+    /// The `n` items with the greatest predicted scores for an user, among the ones
+    /// the user hasn't rated yet
+    pub fn factorization_top_n(&self, user: U, n: usize) -> Result<Vec<(eid!(I), f64)>, Error> {
+        let model = self.trained_factorization()?;
+        let rated = self.controller.user_ratings(&user)?;
+
+        Ok(model
+            .top_n(&user.get_id(), n, &rated)
+            .into_iter()
+            .map(|(item_id, predicted)| (item_id, self.factorization_prediction(predicted)))
+            .collect())
+    }
 }
 
 #[cfg(feature = "test-engine")]
@@ -757,3 +877,164 @@ mod tests {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod memory_tests {
+    use super::distances::users::Method;
+    use super::*;
+    use anyhow::Error;
+    use assert_approx_eq::assert_approx_eq;
+    use common_macros::hash_map;
+    use config::Config;
+    use controller::{MemoryController, SearchBy};
+
+    fn controller() -> MemoryController {
+        MemoryController::from_maped_ratings(
+            (1., 5.),
+            hash_map! {
+                1 => hash_map! { 10 => 5., 20 => 3., 30 => 4. },
+                2 => hash_map! { 10 => 3., 20 => 1., 30 => 2. },
+                3 => hash_map! { 10 => 4., 20 => 3., 30 => 5., 40 => 4. },
+                4 => hash_map! { 10 => 1., 30 => 1., 40 => 2. },
+            },
+        )
+    }
+
+    #[test]
+    fn knn_with_manhattan() -> Result<(), Error> {
+        let config = Config::default();
+        let controller = controller();
+        let engine = Engine::with_controller(&controller, &config);
+
+        let user = controller.users_by(&SearchBy::id("1"))?.remove(0);
+        let knn = engine.user_knn(1, user.clone(), Method::Manhattan, None)?;
+        assert_eq!(knn[0].0, 3);
+
+        // Scanning by chunks doesn't exclude the user itself
+        let knn = engine.user_knn(2, user, Method::Manhattan, Some(2))?;
+        assert!(knn.iter().any(|(id, _)| *id == 3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn slope_one_prediction() -> Result<(), Error> {
+        let config = Config::default();
+        let controller = controller();
+        let engine = Engine::with_controller(&controller, &config);
+
+        let user = controller.users_by(&SearchBy::id("1"))?.remove(0);
+        let item = controller.items_by(&SearchBy::id("40"))?.remove(0);
+
+        // dev(40, 10) = 0.5 (card 2), dev(40, 20) = 1.0 (card 1), dev(40, 30) = 0.0 (card 2)
+        let predicted = engine.item_based_predict(user, item, ItemMethod::SlopeOne, 2)?;
+        assert_approx_eq!(predicted, 4.6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn factorization_prediction() -> Result<(), Error> {
+        let mut config = Config::default();
+        config.engine.snap_predictions = true;
+        config.engine.factorization.epochs = 200;
+        config.engine.factorization.users_chunk_size = Some(3);
+
+        let controller = controller().with_score_step(0.5);
+        let mut engine = Engine::with_controller(&controller, &config);
+
+        let user = controller.users_by(&SearchBy::id("2"))?.remove(0);
+        let item = controller.items_by(&SearchBy::id("40"))?.remove(0);
+        assert!(engine
+            .factorization_predict(user.clone(), item.clone())
+            .is_err());
+
+        assert!(engine.train_factorization()? < 1.);
+
+        // User 2 rates below everyone else, so does it with item 40
+        let predicted = engine.factorization_predict(user.clone(), item)?;
+        assert!((1. ..3.).contains(&predicted));
+        assert_approx_eq!(predicted % 0.5, 0.);
+
+        let top = engine.factorization_top_n(user, 10)?;
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].0, 40);
+
+        Ok(())
+    }
+
+    #[test]
+    fn optimized_chunks_size() -> Result<(), Error> {
+        use super::chunked_matrix::{ChunkedMatrix, DeviationMatrix};
+
+        let mut config = Config::default();
+        let controller = controller();
+
+        config.matrix.allow_chunk_optimization = false;
+        let mut matrix = DeviationMatrix::new(&controller, &config, 100, 100);
+        matrix.optimize_chunks_size()?;
+        assert_eq!(matrix.chunks_size(), (100, 100));
+
+        // 4 items and 13 ratings, the whole matrix takes 8 * 3.25 + 16 = 42 values
+        config.matrix.allow_chunk_optimization = true;
+        config.matrix.chunk_size_threshold = 0.3;
+        let mut matrix = DeviationMatrix::new(&controller, &config, 100, 100);
+        matrix.optimize_chunks_size()?;
+        assert_eq!(matrix.chunks_size(), (1, 1));
+        assert!(matrix.approximate_chunk_size()? <= 12);
+
+        matrix.calculate_chunk(0, 1)?;
+        assert_approx_eq!(matrix.get_value(&10, &20).unwrap(), 5. / 3.);
+
+        Ok(())
+    }
+
+    #[test]
+    fn failing_chunks_are_propagated() -> Result<(), Error> {
+        use super::chunked_matrix::{ChunkedMatrix, DeviationMatrix, SimilarityMatrix};
+
+        let mut config = Config::default();
+        config.engine.factorization.users_chunk_size = Some(2);
+        let controller = controller().with_failing_pages();
+        let mut engine = Engine::with_controller(&controller, &config);
+
+        // The scans fail on their second chunk instead of stopping at the first one
+        let user = controller.users_by(&SearchBy::id("1"))?.remove(0);
+        let knn = engine.user_knn(2, user.clone(), Method::Manhattan, Some(2));
+        assert_eq!(
+            knn.err().unwrap().to_string(),
+            "Failed to read the users after 2"
+        );
+
+        let item = controller.items_by(&SearchBy::id("40"))?.remove(0);
+        let predicted = engine.item_based_predict(user, item, ItemMethod::AdjCosine, 2);
+        assert_eq!(
+            predicted.err().unwrap().to_string(),
+            "Failed to read the items after 20"
+        );
+
+        let trained = engine.train_factorization();
+        assert_eq!(
+            trained.err().unwrap().to_string(),
+            "Failed to read the users after 2"
+        );
+
+        // Chunks past the failing one aren't reported as out of bounds
+        let mut matrix = DeviationMatrix::new(&controller, &config, 2, 2);
+        matrix.calculate_chunk(0, 0)?;
+        let chunk = matrix.calculate_chunk(1, 0);
+        assert_eq!(
+            chunk.err().unwrap().to_string(),
+            "Failed to read the items after 20"
+        );
+
+        let mut matrix = SimilarityMatrix::new(&controller, &config, 2, 2);
+        let chunk = matrix.calculate_chunk(0, 1);
+        assert_eq!(
+            chunk.err().unwrap().to_string(),
+            "Failed to read the items after 20"
+        );
+
+        Ok(())
+    }
+}
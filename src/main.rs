@@ -4,24 +4,21 @@
 // https://opensource.org/licenses/MIT
 
 pub mod parser;
+pub mod registry;
 pub mod utils;
 
 use anyhow::Error;
-use books::BooksController;
 use clap::{App, Arg};
 use config::Config;
-use controller::{eid, Controller, Entity, ToTable};
+use controller::{eid, Controller, DynController, Entity, InstrumentedController, ToTable};
 use engine::{
     chunked_matrix::{ChunkedMatrix, DeviationMatrix, SimilarityMatrix},
     distances::items::Method as ItemMethod,
     Engine,
 };
-use movie_lens::MovieLensController;
-use movie_lens_small::MovieLensSmallController;
-use parser::{Database, Statement};
+use parser::Statement;
+use registry::Registry;
 use rustyline::Editor;
-use shelves::ShelvesController;
-use simple_movie::SimpleMovieController;
 use simplelog::{
     CombinedLogger, Config as LogConfig, ConfigBuilder as LogConfigBuilder, LevelFilter,
     TermLogger, TerminalMode, WriteLogger,
@@ -90,6 +87,14 @@ where
     let mut curr_i = 0;
     let mut curr_j = 0;
 
+    if let Err(e) = matrix.optimize_chunks_size() {
+        log::error!("{}", e);
+        return Ok(());
+    }
+
+    let (m, n) = matrix.chunks_size();
+    println!("Using chunks of {}x{} items", m, n);
+
     let now = Instant::now();
     match matrix.calculate_chunk(curr_i, curr_j) {
         Ok(chunk) => chunk,
@@ -176,21 +181,13 @@ where
     Ok(())
 }
 
-fn database_connected_prompt<C, U, I, R>(
+fn database_connected_prompt(
     config: &Config,
-    controller: C,
+    controller: Box<dyn DynController>,
     name: &str,
     rl: &mut Editor<()>,
-) -> Result<(), Error>
-where
-    C: Controller<User = U, Item = I, Rating = R>,
-    U: Entity,
-    I: Entity,
-    R: Entity,
-    eid!(U): Hash + Eq + Display + Clone + Debug + Default,
-    eid!(I): Hash + Eq + Display + Clone + Debug,
-    eid!(R): Display,
-{
+) -> Result<(), Error> {
+    let controller = InstrumentedController::new(controller);
     let mut engine = Engine::with_controller(&controller, config);
 
     loop {
@@ -222,6 +219,31 @@ where
                         log::error!("Enter the matrix first!");
                     }
 
+                    Statement::Searches => {
+                        let keys_for = |keys: Vec<String>| {
+                            if keys.is_empty() {
+                                "none".to_string()
+                            } else {
+                                keys.join(", ")
+                            }
+                        };
+
+                        println!("users: {}", keys_for(controller.custom_keys_for_users()));
+                        println!("items: {}", keys_for(controller.custom_keys_for_items()));
+                    }
+
+                    Statement::Stats => println!("{}", controller.stats().to_table()),
+
+                    Statement::ResetStats => controller.reset_stats(),
+
+                    Statement::DumpStats(path) => match controller.stats().write_csv(&path) {
+                        Ok(()) => println!("Stats written to {}", path),
+                        Err(e) => {
+                            log::error!("Failed to write stats to {}", path);
+                            log::error!("Reason: {}", e);
+                        }
+                    },
+
                     Statement::QueryUser(searchby) => match controller.users_by(&searchby) {
                         Ok(users) => {
                             for user in users {
@@ -243,7 +265,7 @@ where
                     Statement::QueryRatings(searchby) => match controller.users_by(&searchby) {
                         Ok(users) => {
                             for user in users {
-                                if let Ok(ratings) = controller.user_ratings(&user) {
+                                if let Ok(ratings) = controller.user_timed_ratings(&user) {
                                     if !ratings.is_empty() {
                                         println!("{}", ratings.to_table());
                                     } else {
@@ -310,11 +332,146 @@ where
                         }
                     }
 
-                    Statement::InsertRating(searchby_user, searchby_item, score) => {
-                        let (lower_limit, upper_limit) = controller.score_range();
+                    Statement::UpdateUser(searchby) => {
+                        let user = match controller
+                            .users_by(&searchby)
+                            .map(|mut users| users.drain(..1).next().unwrap())
+                        {
+                            Ok(user) => user,
+                            Err(e) => {
+                                log::error!("{}", e);
+                                continue;
+                            }
+                        };
+
+                        let fields = controller.fields_for_users();
+                        let prototype = match build_prototype(rl, fields) {
+                            Ok(p) => p,
+                            Err(e) => {
+                                log::error!("Error creating prototype");
+                                log::error!("Reason: {}", e);
+                                continue;
+                            }
+                        };
+
+                        match controller.update_user(&user.get_id(), prototype) {
+                            Ok(user) => {
+                                println!("Successfully updated! Yay!");
+                                println!("{}", user.to_table());
+                            }
+
+                            Err(e) => {
+                                log::error!("Failed to update user!");
+                                log::error!("Reason: {}", e);
+                            }
+                        }
+                    }
+
+                    Statement::UpdateItem(searchby) => {
+                        let item = match controller
+                            .items_by(&searchby)
+                            .map(|mut items| items.drain(..1).next().unwrap())
+                        {
+                            Ok(item) => item,
+                            Err(e) => {
+                                log::error!("{}", e);
+                                continue;
+                            }
+                        };
+
+                        let fields = controller.fields_for_items();
+                        let prototype = match build_prototype(rl, fields) {
+                            Ok(p) => p,
+                            Err(e) => {
+                                log::error!("Error creating prototype");
+                                log::error!("Reason: {}", e);
+                                continue;
+                            }
+                        };
+
+                        match controller.update_item(&item.get_id(), prototype) {
+                            Ok(item) => {
+                                println!("Successfully updated! Yay!");
+                                println!("{}", item.to_table());
+                            }
+
+                            Err(e) => {
+                                log::error!("Failed to update item!");
+                                log::error!("Reason: {}", e);
+                            }
+                        }
+                    }
+
+                    Statement::RemoveUser(searchby) => {
+                        let user_id = match controller
+                            .users_by(&searchby)
+                            .map(|mut users| users.drain(..1).next().unwrap())
+                        {
+                            Ok(user) => user.get_id(),
+                            Err(e) => {
+                                log::error!("{}", e);
+                                continue;
+                            }
+                        };
+
+                        match controller.remove_user(&user_id) {
+                            Ok(user) => {
+                                println!("Successfully removed! Yay?");
+                                println!("{}", user.to_table());
+                                engine.maybe_delete_mean_for(&user_id);
+                            }
+
+                            Err(e) => {
+                                log::error!("Failed to remove user!");
+                                log::error!("Reason: {}", e);
+                            }
+                        }
+                    }
+
+                    Statement::RemoveItem(searchby) => {
+                        let item = match controller
+                            .items_by(&searchby)
+                            .map(|mut items| items.drain(..1).next().unwrap())
+                        {
+                            Ok(item) => item,
+                            Err(e) => {
+                                log::error!("{}", e);
+                                continue;
+                            }
+                        };
+
+                        // Every user that rated the item gets a new mean
+                        let item_id = item.get_id();
+                        let raters = match controller.users_who_rated(&[item]) {
+                            Ok(mut users_who_rated) => {
+                                users_who_rated.remove(&item_id).unwrap_or_default()
+                            }
+                            Err(e) => {
+                                log::error!("{}", e);
+                                continue;
+                            }
+                        };
 
-                        if score < lower_limit || score > upper_limit {
-                            log::error!("Given score is not between database bounds");
+                        match controller.remove_item(&item_id) {
+                            Ok(item) => {
+                                println!("Successfully removed! Yay?");
+                                println!("{}", item.to_table());
+
+                                for user_id in raters.keys() {
+                                    engine.maybe_delete_mean_for(user_id);
+                                }
+                            }
+
+                            Err(e) => {
+                                log::error!("Failed to remove item!");
+                                log::error!("Reason: {}", e);
+                            }
+                        }
+                    }
+
+                    Statement::InsertRating(searchby_user, searchby_item, score) => {
+                        if let Err(e) = controller.check_score(score) {
+                            log::error!("{}", e);
                             continue;
                         }
 
@@ -383,9 +540,8 @@ where
                     }
 
                     Statement::UpdateRating(searchby_user, searchby_item, score) => {
-                        let (lower_limit, upper_limit) = controller.score_range();
-                        if score < lower_limit || score > upper_limit {
-                            log::error!("Given score is not between database bounds");
+                        if let Err(e) = controller.check_score(score) {
+                            log::error!("{}", e);
                             continue;
                         }
 
@@ -713,6 +869,90 @@ where
                         println!("Operation took {:.4} seconds", now.elapsed().as_secs_f64());
                     }
 
+                    Statement::TrainMf => {
+                        let now = Instant::now();
+                        match engine.train_factorization() {
+                            Ok(rmse) => {
+                                println!("Trained the model, its training rmse is {}", rmse)
+                            }
+                            Err(e) => {
+                                log::error!("Failed to train the model");
+                                log::error!("Reason: {}", e);
+                            }
+                        }
+
+                        println!("Operation took {:.4} seconds", now.elapsed().as_secs_f64());
+                    }
+
+                    Statement::MfPredict(searchby_user, searchby_item) => {
+                        let user = match controller
+                            .users_by(&searchby_user)
+                            .map(|mut users| users.drain(..1).next().unwrap())
+                        {
+                            Ok(user) => user,
+                            Err(e) => {
+                                log::error!("{}", e);
+                                continue;
+                            }
+                        };
+
+                        let item = match controller
+                            .items_by(&searchby_item)
+                            .map(|mut items| items.drain(..1).next().unwrap())
+                        {
+                            Ok(item) => item,
+                            Err(e) => {
+                                log::error!("{}", e);
+                                continue;
+                            }
+                        };
+
+                        let item_id = item.get_id();
+                        match engine.factorization_predict(user, item) {
+                            Ok(predicted) => println!(
+                                "Predicted score for item with id({}) is {}",
+                                item_id, predicted
+                            ),
+
+                            Err(e) => {
+                                log::error!("Failed to predict the score");
+                                log::error!("Reason: {}", e);
+                            }
+                        }
+                    }
+
+                    Statement::MfTopN(searchby, n) => {
+                        let user = match controller
+                            .users_by(&searchby)
+                            .map(|mut users| users.drain(..1).next().unwrap())
+                        {
+                            Ok(user) => user,
+                            Err(e) => {
+                                log::error!("{}", e);
+                                continue;
+                            }
+                        };
+
+                        let now = Instant::now();
+                        match engine.factorization_top_n(user, n) {
+                            Ok(top) => {
+                                for (item_id, predicted) in top {
+                                    println!(
+                                        "Predicted score for item with id({}) is {}",
+                                        item_id, predicted
+                                    );
+                                }
+                            }
+
+                            Err(e) => {
+                                log::error!("Failed to find the top {} items", n);
+                                log::error!("Reason: {}", e);
+                            }
+                        }
+
+                        println!("Operation took {:.4} seconds", now.elapsed().as_secs_f64());
+                    }
+
                     Statement::EnterMatrix(m, n, method) => match method {
                         ItemMethod::AdjCosine => {
                             let adj_cosine = engine.clone_rc_adj_cosine();
@@ -795,6 +1035,7 @@ fn main() -> Result<(), Error> {
 
     println!("Welcome to recommendation-system {}", VERSION);
     let mut rl = rustyline::Editor::<()>::new();
+    let registry = Registry::default();
 
     loop {
         let opt: String = prompt!(rl)?;
@@ -815,45 +1056,17 @@ fn main() -> Result<(), Error> {
 
             line => match parser::parse_line(line) {
                 Some(stmt) => {
-                    if let Statement::Connect(db) = stmt {
-                        let name = db.to_string();
-
-                        match db {
-                            Database::Books => database_connected_prompt(
-                                &config,
-                                BooksController::from_config(&config, &name)?,
-                                &name,
-                                &mut rl,
-                            )?,
-
-                            Database::Shelves => database_connected_prompt(
-                                &config,
-                                ShelvesController::from_config(&config, &name)?,
-                                &name,
-                                &mut rl,
-                            )?,
-
-                            Database::SimpleMovie => database_connected_prompt(
-                                &config,
-                                SimpleMovieController::from_config(&config, &name)?,
-                                &name,
-                                &mut rl,
-                            )?,
-
-                            Database::MovieLens => database_connected_prompt(
-                                &config,
-                                MovieLensController::from_config(&config, &name)?,
-                                &name,
-                                &mut rl,
-                            )?,
+                    if let Statement::Connect(name) = stmt {
+                        let controller = match registry.connect(&config, &name) {
+                            Ok(controller) => controller,
+                            Err(e) => {
+                                log::error!("Couldn't connect to {}", name);
+                                log::error!("Reason: {}", e);
+                                continue;
+                            }
+                        };
 
-                            Database::MovieLensSmall => database_connected_prompt(
-                                &config,
-                                MovieLensSmallController::from_config(&config, &name)?,
-                                &name,
-                                &mut rl,
-                            )?,
-                        }
+                        database_connected_prompt(&config, controller, &name, &mut rl)?;
                     } else {
                         log::error!("Invalid statement in this context.");
                         log::error!("Connect to a database first!");
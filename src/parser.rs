@@ -5,56 +5,19 @@
 
 pub mod basics;
 
-use crate::parser::basics::{parse_ident, parse_int, parse_separator, parse_string};
+use crate::parser::basics::{parse_ident, parse_int, parse_separator, parse_string, parse_usize};
 use basics::parse_float;
-use controller::SearchBy;
+use controller::{NameMatch, SearchBy, DEFAULT_SEARCH_LIMIT};
 use engine::distances::items::Method as ItemMethod;
 use engine::distances::users::Method as UserMethod;
 use nom::combinator::opt;
 use nom::sequence::{delimited, tuple};
 use nom::{branch::alt, character::complete::char};
 use nom::{bytes::complete::tag, IResult};
-use std::fmt::{self, Display, Formatter};
-
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub enum Database {
-    Books,
-    Shelves,
-    SimpleMovie,
-    MovieLens,
-    MovieLensSmall,
-}
-
-impl From<&str> for Database {
-    fn from(s: &str) -> Self {
-        match s {
-            "books" => Self::Books,
-            "shelves" => Self::Shelves,
-            "simple-movie" => Self::SimpleMovie,
-            "movie-lens" => Self::MovieLens,
-            "movie-lens-small" => Self::MovieLensSmall,
-            _ => unreachable!(),
-        }
-    }
-}
-
-impl Display for Database {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let name = match self {
-            Database::Books => "books",
-            Database::Shelves => "shelves",
-            Database::SimpleMovie => "simple-movie",
-            Database::MovieLens => "movie-lens",
-            Database::MovieLensSmall => "movie-lens-small",
-        };
-
-        write!(f, "{}", name)
-    }
-}
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
-    Connect(Database),
+    Connect(String),
     QueryUser(SearchBy),
     QueryItem(SearchBy),
     QueryRatings(SearchBy),
@@ -64,6 +27,11 @@ pub enum Statement {
     UserBasedPredict(usize, SearchBy, SearchBy, UserMethod, Option<usize>),
     ItemBasedPredict(SearchBy, SearchBy, ItemMethod, usize),
 
+    // Matrix factorization model
+    TrainMf,
+    MfPredict(SearchBy, SearchBy),
+    MfTopN(SearchBy, usize),
+
     // Specific for similarity matrix
     EnterMatrix(usize, usize, ItemMethod),
     MatrixGet(SearchBy, SearchBy),
@@ -72,9 +40,21 @@ pub enum Statement {
     // Specific for insertion
     InsertUser,
     InsertItem,
+    UpdateUser(SearchBy),
+    UpdateItem(SearchBy),
+    RemoveUser(SearchBy),
+    RemoveItem(SearchBy),
     InsertRating(SearchBy, SearchBy, f64),
     UpdateRating(SearchBy, SearchBy, f64),
     RemoveRating(SearchBy, SearchBy),
+
+    // Custom keys to search users and items by
+    Searches,
+
+    // Controller instrumentation
+    Stats,
+    ResetStats,
+    DumpStats(String),
 }
 
 fn parse_user_method(input: &str) -> IResult<&str, UserMethod> {
@@ -121,6 +101,24 @@ fn parse_item_method(input: &str) -> IResult<&str, ItemMethod> {
 
 fn parse_searchby(input: &str) -> IResult<&str, SearchBy> {
     let (input, ident) = parse_ident(input)?;
+    let mode = match ident {
+        "contains" => Some(NameMatch::Contains),
+        "prefix" => Some(NameMatch::Prefix),
+        "fuzzy" => Some(NameMatch::Fuzzy),
+        _ => None,
+    };
+
+    if let Some(mode) = mode {
+        let (input, (value, limit)) = delimited(
+            char('('),
+            tuple((parse_string, opt(tuple((parse_separator, parse_usize))))),
+            char(')'),
+        )(input)?;
+
+        let limit = limit.map_or(DEFAULT_SEARCH_LIMIT, |(_, limit)| limit);
+        return Ok((input, SearchBy::name_like(mode, value, limit)));
+    }
+
     let (input, value) = delimited(char('('), parse_string, char(')'))(input)?;
 
     let index = match ident {
@@ -133,24 +131,40 @@ fn parse_searchby(input: &str) -> IResult<&str, SearchBy> {
 }
 
 fn parse_statement(input: &str) -> IResult<&str, Statement> {
+    // Writes are matched on their own, a single alt can't take as many tags
     let (input, statement_type) = alt((
-        tag("get"),
-        tag("move_to"),
-        tag("connect"),
-        tag("user_knn"),
-        tag("query_user"),
-        tag("query_item"),
-        tag("insert_user"),
-        tag("insert_item"),
-        tag("enter_matrix"),
-        tag("insert_rating"),
-        tag("update_rating"),
-        tag("remove_rating"),
-        tag("query_ratings"),
-        tag("user_distance"),
-        tag("item_distance"),
-        tag("user_based_predict"),
-        tag("item_based_predict"),
+        alt((
+            tag("insert_user"),
+            tag("insert_item"),
+            tag("update_user"),
+            tag("update_item"),
+            tag("remove_user"),
+            tag("remove_item"),
+            tag("insert_rating"),
+            tag("update_rating"),
+            tag("remove_rating"),
+        )),
+        alt((
+            tag("get"),
+            tag("stats"),
+            tag("move_to"),
+            tag("searches"),
+            tag("connect"),
+            tag("user_knn"),
+            tag("query_user"),
+            tag("query_item"),
+            tag("enter_matrix"),
+            tag("dump_stats"),
+            tag("reset_stats"),
+            tag("query_ratings"),
+            tag("user_distance"),
+            tag("item_distance"),
+            tag("user_based_predict"),
+            tag("item_based_predict"),
+            tag("train_mf"),
+            tag("mf_predict"),
+            tag("mf_top_n"),
+        )),
     ))(input)?;
 
     let (input, statement) = match statement_type {
@@ -340,6 +354,26 @@ fn parse_statement(input: &str) -> IResult<&str, Statement> {
         "insert_user" => (input, Statement::InsertUser),
         "insert_item" => (input, Statement::InsertItem),
 
+        "update_user" => {
+            let (input, user_searchby) = delimited(char('('), parse_searchby, char(')'))(input)?;
+            (input, Statement::UpdateUser(user_searchby))
+        }
+
+        "update_item" => {
+            let (input, item_searchby) = delimited(char('('), parse_searchby, char(')'))(input)?;
+            (input, Statement::UpdateItem(item_searchby))
+        }
+
+        "remove_user" => {
+            let (input, user_searchby) = delimited(char('('), parse_searchby, char(')'))(input)?;
+            (input, Statement::RemoveUser(user_searchby))
+        }
+
+        "remove_item" => {
+            let (input, item_searchby) = delimited(char('('), parse_searchby, char(')'))(input)?;
+            (input, Statement::RemoveItem(item_searchby))
+        }
+
         "insert_rating" => {
             let (input, (searchby_user, _, searchby_item, _, score)) = delimited(
                 char('('),
@@ -388,6 +422,35 @@ fn parse_statement(input: &str) -> IResult<&str, Statement> {
             (input, Statement::RemoveRating(searchby_user, searchby_item))
         }
 
+        "train_mf" => (input, Statement::TrainMf),
+        "mf_predict" => {
+            let (input, (user_searchby, _, item_searchby)) = delimited(
+                char('('),
+                tuple((parse_searchby, parse_separator, parse_searchby)),
+                char(')'),
+            )(input)?;
+
+            (input, Statement::MfPredict(user_searchby, item_searchby))
+        }
+
+        "mf_top_n" => {
+            let (input, (user_searchby, _, n)) = delimited(
+                char('('),
+                tuple((parse_searchby, parse_separator, parse_usize)),
+                char(')'),
+            )(input)?;
+
+            (input, Statement::MfTopN(user_searchby, n))
+        }
+
+        "searches" => (input, Statement::Searches),
+        "stats" => (input, Statement::Stats),
+        "reset_stats" => (input, Statement::ResetStats),
+        "dump_stats" => {
+            let (input, path) = delimited(char('('), parse_string, char(')'))(input)?;
+            (input, Statement::DumpStats(path.into()))
+        }
+
         function => unimplemented!("Unimplemented parser for {}", function),
     };
 
@@ -425,7 +488,12 @@ mod tests {
     #[test]
     fn connect_statement() {
         let parsed = parse_statement("connect(simple-movie)");
-        let expected = ("", Statement::Connect(Database::SimpleMovie));
+        let expected = ("", Statement::Connect("simple-movie".into()));
+
+        assert_eq!(parsed, Ok(expected));
+
+        let parsed = parse_statement("connect(some-csv)");
+        let expected = ("", Statement::Connect("some-csv".into()));
 
         assert_eq!(parsed, Ok(expected));
     }
@@ -571,6 +639,26 @@ mod tests {
         assert_eq!(parsed, Ok(expected));
     }
 
+    #[test]
+    fn matrix_factorization_statements() {
+        assert_eq!(parse_statement("train_mf"), Ok(("", Statement::TrainMf)));
+
+        let parsed = parse_statement("mf_predict(id('324x'), name('Alien'))");
+        let expected = (
+            "",
+            Statement::MfPredict(SearchBy::id("324x"), SearchBy::name("Alien")),
+        );
+
+        assert_eq!(parsed, Ok(expected));
+
+        let parsed = parse_statement("mf_top_n(id('324x'), 10)");
+        let expected = ("", Statement::MfTopN(SearchBy::id("324x"), 10));
+
+        assert_eq!(parsed, Ok(expected));
+
+        assert!(parse_statement("mf_top_n(id('324x'), -10)").is_err());
+    }
+
     #[test]
     fn enter_matrix_statement() {
         let parsed = parse_statement("enter_matrix(100, 100, adj_cosine)");
@@ -598,6 +686,75 @@ mod tests {
         assert_eq!(parsed, Ok(expected));
     }
 
+    #[test]
+    fn update_and_remove_entity_statements() {
+        let parsed = parse_statement("update_user(id('3'))");
+        let expected = ("", Statement::UpdateUser(SearchBy::id("3")));
+
+        assert_eq!(parsed, Ok(expected));
+
+        let parsed = parse_statement("update_item(name('Alien'))");
+        let expected = ("", Statement::UpdateItem(SearchBy::name("Alien")));
+
+        assert_eq!(parsed, Ok(expected));
+
+        let parsed = parse_statement("remove_user(name('Patrick C'))");
+        let expected = ("", Statement::RemoveUser(SearchBy::name("Patrick C")));
+
+        assert_eq!(parsed, Ok(expected));
+
+        let parsed = parse_statement("remove_item(id('bx32a'))");
+        let expected = ("", Statement::RemoveItem(SearchBy::id("bx32a")));
+
+        assert_eq!(parsed, Ok(expected));
+    }
+
+    #[test]
+    fn searches_statement() {
+        assert_eq!(parse_statement("searches"), Ok(("", Statement::Searches)));
+        assert_eq!(
+            parse_statement("query_item(genre('Comedy'))"),
+            Ok((
+                "",
+                Statement::QueryItem(SearchBy::custom("genre", "Comedy"))
+            ))
+        );
+    }
+
+    #[test]
+    fn name_like_searches() {
+        let parsed = parse_statement("query_item(fuzzy('sutur'))");
+        let expected = SearchBy::name_like(NameMatch::Fuzzy, "sutur", DEFAULT_SEARCH_LIMIT);
+
+        assert_eq!(parsed, Ok(("", Statement::QueryItem(expected))));
+
+        let parsed = parse_statement("query_user(prefix('Pat', 3))");
+        let expected = SearchBy::name_like(NameMatch::Prefix, "Pat", 3);
+
+        assert_eq!(parsed, Ok(("", Statement::QueryUser(expected))));
+
+        let parsed = parse_statement("query_item(contains('story' , 20))");
+        let expected = SearchBy::name_like(NameMatch::Contains, "story", 20);
+
+        assert_eq!(parsed, Ok(("", Statement::QueryItem(expected))));
+
+        assert!(parse_statement("query_item(contains('story', -20))").is_err());
+    }
+
+    #[test]
+    fn stats_statements() {
+        assert_eq!(parse_statement("stats"), Ok(("", Statement::Stats)));
+        assert_eq!(
+            parse_statement("reset_stats"),
+            Ok(("", Statement::ResetStats))
+        );
+
+        let parsed = parse_statement("dump_stats('/tmp/stats.csv')");
+        let expected = ("", Statement::DumpStats("/tmp/stats.csv".into()));
+
+        assert_eq!(parsed, Ok(expected));
+    }
+
     #[test]
     fn parse_invalid_line() {
         let parsed = parse_line("query_user(id())xx");
@@ -20,6 +20,11 @@ pub(crate) fn parse_int(input: &str) -> IResult<&str, i64> {
     map_res(digit1, |s: &str| s.parse::<i64>())(input)
 }
 
+/// Counts and limits, there's no sign to parse so negative values are rejected
+pub(crate) fn parse_usize(input: &str) -> IResult<&str, usize> {
+    map_res(digit1, |s: &str| s.parse::<usize>())(input)
+}
+
 pub(crate) fn parse_float(input: &str) -> IResult<&str, f64> {
     double(input)
 }
@@ -72,5 +77,8 @@ mod tests {
         let parsed = parse_int("12c3");
         let expected = ("c3", 12);
         assert_eq!(parsed, Ok(expected));
+
+        assert_eq!(parse_usize("20"), Ok(("", 20)));
+        assert!(parse_usize("-20").is_err());
     }
 }
@@ -0,0 +1,177 @@
+// Copyright (c) 2020 White Leaf
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use anyhow::Error;
+use books::BooksController;
+use config::Config;
+use controller::{
+    eid, error::ErrorKind, CachedController, Controller, CsvController, DynAdapter, DynController,
+    Entity,
+};
+use dataset::DatasetController;
+use movie_lens::MovieLensController;
+use movie_lens_small::MovieLensSmallController;
+use shelves::ShelvesController;
+use simple_movie::SimpleMovieController;
+use std::{collections::HashMap, fmt::Display, hash::Hash, str::FromStr};
+
+/// Wrap a database controller in the read caches configured in `[cache]`
+fn cached<C, U, I, R>(config: &Config, controller: C) -> Box<dyn DynController>
+where
+    C: Controller<User = U, Item = I, Rating = R> + 'static,
+    U: Entity + Clone,
+    I: Entity + Clone,
+    R: Entity,
+    eid!(U): ToString + FromStr + Hash + Eq + Clone,
+    eid!(I): ToString + FromStr + Hash + Eq + Clone,
+    eid!(R): ToString,
+    <eid!(U) as FromStr>::Err: Display,
+    <eid!(I) as FromStr>::Err: Display,
+{
+    DynAdapter::boxed(CachedController::from_config(controller, config))
+}
+
+/// Builds a controller for the entry with the given name in the config
+pub type Constructor = fn(&Config, &str) -> Result<Box<dyn DynController>, Error>;
+
+/// Maps controller kinds (ex. "movie-lens") to their constructors, any
+/// `[databases.*]` entry can be connected as long as its kind is registered
+pub struct Registry {
+    constructors: HashMap<&'static str, Constructor>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+
+        registry.register("books", |config, name| {
+            Ok(cached(config, BooksController::from_config(config, name)?))
+        });
+
+        registry.register("shelves", |config, name| {
+            Ok(cached(
+                config,
+                ShelvesController::from_config(config, name)?,
+            ))
+        });
+
+        registry.register("simple-movie", |config, name| {
+            Ok(cached(
+                config,
+                SimpleMovieController::from_config(config, name)?,
+            ))
+        });
+
+        registry.register("movie-lens", |config, name| {
+            Ok(cached(
+                config,
+                MovieLensController::from_config(config, name)?,
+            ))
+        });
+
+        registry.register("movie-lens-small", |config, name| {
+            Ok(cached(
+                config,
+                MovieLensSmallController::from_config(config, name)?,
+            ))
+        });
+
+        registry.register("dataset", |config, name| {
+            Ok(cached(
+                config,
+                DatasetController::from_config(config, name)?,
+            ))
+        });
+
+        registry
+    }
+}
+
+impl Registry {
+    /// An empty registry, use `Registry::default` to get the built-in controllers
+    pub fn new() -> Self {
+        Self {
+            constructors: HashMap::new(),
+        }
+    }
+
+    /// Register (or replace) the constructor for a kind of controller
+    pub fn register(&mut self, kind: &'static str, constructor: Constructor) {
+        self.constructors.insert(kind, constructor);
+    }
+
+    /// Registered kinds of controllers
+    pub fn kinds(&self) -> Vec<&'static str> {
+        let mut kinds: Vec<_> = self.constructors.keys().copied().collect();
+        kinds.sort_unstable();
+        kinds
+    }
+
+    /// Connect to the entry named `name`, it's looked up in `[databases.*]` first
+    /// and then in `[csv.*]`
+    pub fn connect(&self, config: &Config, name: &str) -> Result<Box<dyn DynController>, Error> {
+        if let Some(entry) = config.databases.get(name) {
+            let kind = entry.kind_or(name);
+            let constructor = self
+                .constructors
+                .get(kind)
+                .ok_or_else(|| ErrorKind::UnknownControllerKind(kind.into()))?;
+
+            return constructor(config, name);
+        }
+
+        if config.csv.contains_key(name) {
+            let controller = CsvController::<String, String>::from_config(config, name)?;
+            return Ok(DynAdapter::boxed(controller));
+        }
+
+        Err(ErrorKind::DbConfigError(name.into()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{Backend, DatabaseEntry};
+
+    #[test]
+    fn connect_by_kind() -> Result<(), Error> {
+        let path = std::env::temp_dir().join("registry-simple-movie.db");
+        let _ = std::fs::remove_file(&path);
+
+        let mut config = Config::default();
+        let mut entry = config.databases["simple-movie"].clone();
+        entry.kind = Some("simple-movie".into());
+        entry.backend = Backend::Sqlite;
+        entry.sqlite_path = path.to_string_lossy().into();
+        config.databases.insert("my-simple-movie".into(), entry);
+
+        let registry = Registry::default();
+        let controller = registry.connect(&config, "my-simple-movie")?;
+        assert_eq!(controller.users_count()?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn connect_unknown_entries() {
+        let mut config = Config::default();
+        let registry = Registry::default();
+
+        assert!(registry.connect(&config, "nowhere").is_err());
+
+        let entry = DatabaseEntry {
+            kind: Some("movie-lens-25m".into()),
+            ..config.databases["movie-lens"].clone()
+        };
+        config.databases.insert("my-movielens-25m".into(), entry);
+
+        let err = registry.connect(&config, "my-movielens-25m").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Unknown controller kind movie-lens-25m for a database entry"
+        );
+    }
+}
@@ -9,15 +9,14 @@ use rustyline::Editor;
 use std::collections::HashMap;
 
 macro_rules! field {
-    ($ed:ident, $name:expr, $opt:expr, $ty:expr) => {{
+    ($ed:ident, $field:expr) => {{
         use rustyline::error::ReadlineError;
 
-        let msg = if $opt {
-            format!("{}{} (optional, {}): ", $crate::PROMPT, $name, $ty)
-        } else {
-            format!("{}{} (required, {}): ", $crate::PROMPT, $name, $ty)
-        };
+        if let Some(description) = $field.description {
+            println!("{}: {}", $field.name, description);
+        }
 
+        let msg = format!("{}{} ({}): ", $crate::PROMPT, $field.name, $field.hint());
         match $ed.readline(&msg) {
             Ok(line) => Ok(Some(line)),
 
@@ -34,20 +33,28 @@ pub(crate) fn build_prototype<'a>(
     rl: &mut Editor<()>,
     fields: Vec<Field<'a>>,
 ) -> Result<HashMap<&'a str, Value>, Error> {
-    println!("Press CTRL-D to leave a field as 'empty'");
+    println!(
+        "Press CTRL-D to leave a field as 'empty', type 'null' to set a non-string field as null"
+    );
     let mut prototype = HashMap::new();
 
     for field in fields {
+        let name = field.name;
         let is_optional = field.is_optional();
-        let (name, ty) = field.into_tuple();
 
         loop {
-            let input: Option<String> = field!(rl, name, is_optional, ty)?;
+            let input: Option<String> = field!(rl, field)?;
 
             match input {
                 Some(input) => {
-                    let value = Value::from_str(&input, ty);
+                    let value = Value::from_str(&input, field.ty)
+                        .and_then(|value| field.check(&value).map(|_| value));
+
                     match value {
+                        Ok(value) if value.is_null() && !is_optional => {
+                            log::error!("Field '{}' is required, cannot be null!", name);
+                        }
+
                         Ok(value) => {
                             prototype.insert(name, value);
                             break;
@@ -60,6 +67,11 @@ pub(crate) fn build_prototype<'a>(
                     }
                 }
 
+                None if field.default.is_some() => {
+                    prototype.insert(name, field.default.clone().unwrap());
+                    break;
+                }
+
                 None if is_optional => {
                     break;
                 }